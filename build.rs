@@ -26,6 +26,9 @@ fn main() {
       .arg(&format!("{}/libuserspace.a", out_dir))
       .arg(&format!("{}/user_image.aarch64.o", out_dir))
       .status().unwrap();
+  } else {
+    // Note: host build of `cargo test`, no user image
+    return;
   }
   println!("cargo:rustc-link-search=native={}", out_dir);
   println!("cargo:rustc-link-lib=static=userspace");
@@ -1,5 +1,10 @@
 use core::fmt::Formatter;
 
+// Note: SPSR_EL1.SS, software step state of the returned context
+const SPSR_SS: u64 = 1 << 21;
+// Note: only condition flags (NZCV) of a user spsr are trusted
+const SPSR_NZCV: u64 = 0xf << 28;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Aarch64ContextFrame {
@@ -24,13 +29,20 @@ impl core::fmt::Display for Aarch64ContextFrame {
   }
 }
 
+impl Aarch64ContextFrame {
+  pub fn single_step(&self) -> bool {
+    self.spsr & SPSR_SS != 0
+  }
+}
+
 impl crate::arch::traits::ContextFrameTrait for Aarch64ContextFrame {
+  // Note: kernel threads run on their kernel stack (SP_EL1), `sp` goes to SP_EL0 regardless
   fn new(pc: usize, sp: usize, arg: usize, privileged: bool) -> Self {
     use cortex_a::regs::*;
     let mut r = Aarch64ContextFrame {
       gpr: [0; 31],
       spsr: (
-        if privileged { SPSR_EL1::M::EL1t } else { SPSR_EL1::M::EL0t }
+        if privileged { SPSR_EL1::M::EL1h } else { SPSR_EL1::M::EL0t }
           + SPSR_EL1::I::Unmasked + SPSR_EL1::F::Masked).value as u64,
       elr: pc as u64,
       sp: sp as u64,
@@ -46,6 +58,13 @@ impl crate::arch::traits::ContextFrameTrait for Aarch64ContextFrame {
     self.gpr[i] as usize
   }
 
+  fn set_syscall_argument(&mut self, i: usize, v: usize) {
+    const AARCH64_SYSCALL_ARG_LIMIT: usize = 8;
+    assert!(i < AARCH64_SYSCALL_ARG_LIMIT);
+    // x0 ~ x7
+    self.gpr[i] = v as u64;
+  }
+
   fn syscall_number(&self) -> usize {
     // x8
     self.gpr[8] as usize
@@ -72,7 +91,55 @@ impl crate::arch::traits::ContextFrameTrait for Aarch64ContextFrame {
     self.sp = sp as u64;
   }
 
+  fn frame_pointer(&self) -> usize {
+    // x29
+    self.gpr[29] as usize
+  }
+
+  fn link_register(&self) -> usize {
+    // x30
+    self.gpr[30] as usize
+  }
+
   fn set_argument(&mut self, arg: usize) {
     self.gpr[0] = arg as u64;
   }
+
+  fn restore_from_user(&mut self, saved: &Self) {
+    let spsr = (self.spsr & !SPSR_NZCV) | (saved.spsr & SPSR_NZCV);
+    *self = *saved;
+    self.spsr = spsr;
+  }
+
+  fn gdb_register(&self, i: usize) -> Option<(u64, usize)> {
+    // x0 ~ x30, sp, pc, cpsr
+    match i {
+      0..=30 => Some((self.gpr[i], 8)),
+      31 => Some((self.sp, 8)),
+      32 => Some((self.elr, 8)),
+      33 => Some((self.spsr & 0xffff_ffff, 4)),
+      _ => None,
+    }
+  }
+
+  fn set_gdb_register(&mut self, i: usize, v: u64) -> bool {
+    match i {
+      0..=30 => { self.gpr[i] = v; }
+      31 => { self.sp = v; }
+      32 => { self.elr = v; }
+      33 => { self.spsr = (self.spsr & !SPSR_NZCV) | (v & SPSR_NZCV); }
+      _ => { return false; }
+    }
+    true
+  }
+
+  fn set_single_step(&mut self, step: bool) -> bool {
+    // Note: MDSCR_EL1.SS follows this bit on exception return (see `exception.rs`)
+    if step {
+      self.spsr |= SPSR_SS;
+    } else {
+      self.spsr &= !SPSR_SS;
+    }
+    true
+  }
 }
@@ -35,13 +35,34 @@ unsafe extern "C" fn current_el0_serror() {
 
 /// Asynchronous exception taken from the current EL, using SP of the current EL.
 #[no_mangle]
-unsafe extern "C" fn current_elx_synchronous() {
-  panic!("current_elx_synchronous {:016x}", cortex_a::regs::FAR_EL1.get());
+unsafe extern "C" fn current_elx_synchronous(ctx: *mut ContextFrame) {
+  let core = crate::lib::core::current();
+  core.set_context(ctx);
+  println!("current_elx_synchronous: {}", Aarch64FaultSyndrome::read());
+  println!("FAR_EL1 {:016x}", FAR_EL1.get());
+  crate::lib::backtrace::print_context(&*ctx);
+  if crate::mm::kernel_stack::is_guard(FAR_EL1.get() as usize) {
+    panic!("kernel stack overflow {:016x}", FAR_EL1.get());
+  }
+  panic!("current_elx_synchronous {:016x}", FAR_EL1.get());
 }
 
+// Note: from `VECTOR_CHECKED` on the emergency stack, no trap frame was pushed
 #[no_mangle]
-unsafe extern "C" fn current_elx_irq() {
-  panic!("current_elx_irq");
+unsafe extern "C" fn kernel_stack_overflow(sp: usize) -> ! {
+  println!("current_elx_synchronous: {}", Aarch64FaultSyndrome::read());
+  panic!("kernel stack overflow: sp {:016x} FAR_EL1 {:016x} ELR_EL1 {:016x}", sp, FAR_EL1.get(), ELR_EL1.get());
+}
+
+// Note: only kernel threads run at EL1 with irq unmasked
+#[no_mangle]
+unsafe extern "C" fn current_elx_irq(ctx: *mut ContextFrame) {
+  use crate::lib::isr::*;
+  let core = crate::lib::core::current();
+  core.set_context(ctx);
+  Isr::interrupt_request();
+  crate::lib::scheduler::leave_trap();
+  crate::lib::core::current().clear_context();
 }
 
 #[no_mangle]
@@ -62,12 +83,15 @@ unsafe extern "C" fn lower_aarch64_synchronous(ctx: *mut ContextFrame) {
     Isr::system_call();
   } else if ESR_EL1.matches_all(ESR_EL1::EC::InstrAbortLowerEL) | ESR_EL1.matches_all(ESR_EL1::EC::DataAbortLowerEL) {
     Isr::page_fault();
+  } else if Aarch64FaultSyndrome::read().is_debug() {
+    Isr::breakpoint();
   } else {
-    let ec = ESR_EL1.read(ESR_EL1::EC);
-    println!("lower_aarch64_synchronous: ec {:06b}", ec);
     Isr::default();
   }
-  core.clear_context();
+  crate::lib::signal::handle_pending();
+  crate::lib::scheduler::leave_trap();
+  sync_single_step(&*ctx);
+  crate::lib::core::current().clear_context();
 }
 
 #[no_mangle]
@@ -76,7 +100,10 @@ unsafe extern "C" fn lower_aarch64_irq(ctx: *mut ContextFrame) {
   let core = crate::lib::core::current();
   core.set_context(ctx);
   Isr::interrupt_request();
-  core.clear_context();
+  crate::lib::signal::handle_pending();
+  crate::lib::scheduler::leave_trap();
+  sync_single_step(&*ctx);
+  crate::lib::core::current().clear_context();
 }
 
 #[no_mangle]
@@ -85,7 +112,125 @@ unsafe extern "C" fn lower_aarch64_serror(ctx: *mut ContextFrame) {
   let core = crate::lib::core::current();
   core.set_context(ctx);
   Isr::default();
-  core.clear_context();
+  crate::lib::signal::handle_pending();
+  crate::lib::scheduler::leave_trap();
+  sync_single_step(&*ctx);
+  crate::lib::core::current().clear_context();
+}
+
+//--------------------------------------------------------------------------------------------------
+// Software step
+//--------------------------------------------------------------------------------------------------
+
+// Note: MDSCR_EL1.SS is global, while SPSR_EL1.SS of the returned context is per thread
+//       an armed MDSCR_EL1.SS with a clear SPSR_EL1.SS traps before the first instruction,
+//       so keep MDSCR_EL1.SS in line with the context about to be returned to
+fn sync_single_step(ctx: &ContextFrame) {
+  const MDSCR_SS: u64 = 1 << 0;
+  let step = ctx.single_step();
+  unsafe {
+    let mut mdscr: u64;
+    llvm_asm!("mrs $0, mdscr_el1" : "=r"(mdscr) ::: "volatile");
+    if step {
+      mdscr |= MDSCR_SS;
+    } else {
+      mdscr &= !MDSCR_SS;
+    }
+    llvm_asm!("msr mdscr_el1, $0" :: "r"(mdscr) :: "volatile");
+    barrier::isb(barrier::SY);
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Fault syndrome (ESR_EL1) decoding
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aarch64FaultSyndrome {
+  esr: u64,
+}
+
+impl Aarch64FaultSyndrome {
+  pub fn read() -> Self {
+    Aarch64FaultSyndrome {
+      esr: ESR_EL1.get() as u64,
+    }
+  }
+
+  fn ec(&self) -> u64 {
+    (self.esr >> 26) & 0x3f
+  }
+
+  fn iss(&self) -> u64 {
+    self.esr & 0x1ff_ffff
+  }
+
+  // Note: BRK instruction or software step from lower EL
+  pub fn is_debug(&self) -> bool {
+    match self.ec() {
+      0x32 | 0x3c => true,
+      _ => false,
+    }
+  }
+
+  fn is_abort(&self) -> bool {
+    match self.ec() {
+      0x20 | 0x21 | 0x24 | 0x25 => true,
+      _ => false,
+    }
+  }
+}
+
+fn exception_class(ec: u64) -> &'static str {
+  match ec {
+    0x00 => "unknown reason",
+    0x01 => "trapped WFI/WFE",
+    0x07 => "SIMD/FP access",
+    0x0e => "illegal execution state",
+    0x15 => "SVC instruction",
+    0x18 => "trapped MSR/MRS/system instruction",
+    0x20 => "instruction abort from lower EL",
+    0x21 => "instruction abort from current EL",
+    0x22 => "PC alignment fault",
+    0x24 => "data abort from lower EL",
+    0x25 => "data abort from current EL",
+    0x26 => "SP alignment fault",
+    0x2c => "floating point exception",
+    0x2f => "SError interrupt",
+    0x30 | 0x31 => "breakpoint",
+    0x32 | 0x33 => "software step",
+    0x34 | 0x35 => "watchpoint",
+    0x3c => "BRK instruction",
+    _ => "reserved",
+  }
+}
+
+fn fault_status(fsc: u64) -> &'static str {
+  match fsc {
+    0b000000..=0b000011 => "address size fault",
+    0b000100..=0b000111 => "translation fault",
+    0b001001..=0b001011 => "access flag fault",
+    0b001101..=0b001111 => "permission fault",
+    0b010000 => "synchronous external abort",
+    0b100001 => "alignment fault",
+    0b110000 => "TLB conflict abort",
+    _ => "other fault",
+  }
+}
+
+impl core::fmt::Display for Aarch64FaultSyndrome {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+    write!(f, "ESR_EL1 {:08x} EC {:06b} ({}) ISS {:07x}", self.esr, self.ec(), exception_class(self.ec()), self.iss())?;
+    if self.is_abort() {
+      let fsc = self.iss() & 0x3f;
+      write!(f, "\n  {} level {}", fault_status(fsc), fsc & 0b11)?;
+      if self.ec() == 0x24 || self.ec() == 0x25 {
+        let wnr = (self.iss() >> 6) & 0b1 == 1;
+        write!(f, " on {}", if wnr { "write" } else { "read" })?;
+      }
+    }
+    Ok(())
+  }
 }
 
 pub fn init() {
@@ -95,6 +240,8 @@ pub fn init() {
   unsafe {
     let addr: u64 = vectors as usize as u64;
     VBAR_EL1.set(addr);
+    // Note: clear OS lock, debug exceptions (software step) are disabled while it is set
+    llvm_asm!("msr oslar_el1, $0" :: "r"(0u64) :: "volatile");
     barrier::isb(barrier::SY);
   }
 }
@@ -29,6 +29,15 @@ pub type ArchPageTableEntry = super::page_table::Aarch64PageTableEntry;
 
 pub type AddressSpaceId = u16;
 
+pub type FaultSyndrome = super::exception::Aarch64FaultSyndrome;
+
+// Note: AAPCS64 frame record at fp: [fp] previous fp, [fp + 8] lr
+pub const FRAME_RECORD_FP_OFFSET: isize = 0;
+pub const FRAME_RECORD_RA_OFFSET: isize = 8;
+
+// Note: `brk #0`, little endian
+pub const BREAKPOINT_INSTRUCTIONS: &[&[u8]] = &[&[0x00, 0x00, 0x20, 0xd4]];
+
 pub struct Aarch64Arch;
 
 impl crate::arch::ArchTrait for Aarch64Arch {
@@ -57,7 +66,84 @@ impl crate::arch::ArchTrait for Aarch64Arch {
     FAR_EL1.get() as usize
   }
 
+  fn fault_syndrome() -> FaultSyndrome {
+    FaultSyndrome::read()
+  }
+
   fn core_id() -> usize {
     MPIDR_EL1.get() as usize & (BOARD_CORE_NUMBER - 1)
   }
-}
\ No newline at end of file
+
+  #[inline(always)]
+  fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+      llvm_asm!("mov $0, x29" : "=r"(fp) ::: "volatile");
+    }
+    fp
+  }
+
+  fn sync_instruction_cache(kva: usize) {
+    unsafe {
+      llvm_asm!("dc cvau, $0" :: "r"(kva) :: "volatile");
+      llvm_asm!("dsb ish");
+      llvm_asm!("ic ialluis");
+      llvm_asm!("dsb ish");
+      llvm_asm!("isb");
+    }
+  }
+
+  fn interrupt_save_disable() -> usize {
+    let daif: usize;
+    unsafe {
+      llvm_asm!("mrs $0, daif" : "=r"(daif) ::: "volatile");
+      llvm_asm!("msr daifset, #2" :::: "volatile");
+    }
+    daif
+  }
+
+  fn interrupt_restore(state: usize) {
+    unsafe {
+      llvm_asm!("msr daif, $0" :: "r"(state) :: "volatile");
+    }
+  }
+
+  fn interrupt_pending() -> bool {
+    // Note: ISR_EL1.I (bit 7)
+    let isr: usize;
+    unsafe {
+      llvm_asm!("mrs $0, isr_el1" : "=r"(isr) ::: "volatile");
+    }
+    isr & (1 << 7) != 0
+  }
+
+  fn switch_context(from_sp: *mut usize, to_sp: usize) {
+    extern "C" {
+      fn switch_context(from_sp: *mut usize, to_sp: usize);
+    }
+    unsafe { switch_context(from_sp, to_sp) }
+  }
+
+  fn kernel_stack_init(top: usize) -> usize {
+    extern "C" {
+      fn thread_entry();
+    }
+    // Note: x19 ~ x30 as pushed by `switch_context` in `exception.S`, fp (x29) 0 ends backtraces
+    const SWITCH_FRAME_SIZE: usize = 12 * MACHINE_SIZE;
+    let sp = top - core::mem::size_of::<ContextFrame>() - SWITCH_FRAME_SIZE;
+    unsafe {
+      core::intrinsics::volatile_set_memory(sp as *mut u8, 0, SWITCH_FRAME_SIZE);
+      core::intrinsics::volatile_store((sp + 11 * MACHINE_SIZE) as *mut usize, thread_entry as usize);
+    }
+    sp
+  }
+
+  // Note: cortex-a53 (ARMv8.0) has no PAN, EL1 can always access user pages
+  fn user_access_begin() {}
+
+  fn user_access_end() {}
+
+  fn device_tree() -> Option<usize> {
+    super::start::device_tree()
+  }
+}
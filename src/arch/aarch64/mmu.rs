@@ -4,7 +4,6 @@ use super::interface::PAGE_SHIFT;
 use super::interface::PAGE_SIZE;
 use super::page_table::PAGE_TABLE_L1_SHIFT;
 use super::page_table::PAGE_TABLE_L2_SHIFT;
-use super::page_table::PAGE_TABLE_L3_SHIFT;
 use super::vm_descriptor::*;
 
 const PHYSICAL_ADDRESS_LIMIT_GB: usize = BOARD_PHYSICAL_ADDRESS_LIMIT >> 30;
@@ -29,18 +28,19 @@ impl TableDescriptor {
   }
 }
 
+// Note: level 2 block, 2 MiB of the kernel linear map
 #[derive(Copy, Clone)]
 #[repr(transparent)]
-struct PageDescriptor(u64);
+struct BlockDescriptor(u64);
 
-impl PageDescriptor {
-  fn new(output_addr: usize, t: MemoryType) -> PageDescriptor {
-    PageDescriptor((
+impl BlockDescriptor {
+  fn new(output_addr: usize, t: MemoryType) -> BlockDescriptor {
+    BlockDescriptor((
       PAGE_DESCRIPTOR::PXN::False
         + PAGE_DESCRIPTOR::OUTPUT_PPN.val((output_addr >> PAGE_SHIFT) as u64)
         + PAGE_DESCRIPTOR::AF::True
         + PAGE_DESCRIPTOR::AP::RW_EL1
-        + PAGE_DESCRIPTOR::TYPE::Table
+        + PAGE_DESCRIPTOR::TYPE::Block
         + PAGE_DESCRIPTOR::VALID::True
         +
         if t == MemoryType::Device {
@@ -55,16 +55,14 @@ impl PageDescriptor {
 #[repr(C)]
 #[repr(align(4096))]
 struct PageTables {
-  lvl3: [[[PageDescriptor; ENTRY_PER_PAGE]; ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
-  lvl2: [[TableDescriptor; ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
+  lvl2: [[BlockDescriptor; ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
   lvl1: [TableDescriptor; ENTRY_PER_PAGE],
 }
 
 #[no_mangle]
 #[link_section = ".data.kvm"]
 static mut KERNEL_PAGE_TABLES: PageTables = PageTables {
-  lvl3: [[[PageDescriptor(0); ENTRY_PER_PAGE]; ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
-  lvl2: [[TableDescriptor(0); ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
+  lvl2: [[BlockDescriptor(0); ENTRY_PER_PAGE]; PHYSICAL_ADDRESS_LIMIT_GB],
   lvl1: [TableDescriptor(0); ENTRY_PER_PAGE],
 };
 
@@ -97,16 +95,13 @@ pub unsafe extern "C" fn init(create_table: bool) {
     for i in 0..PHYSICAL_ADDRESS_LIMIT_GB {
       let output_addr = KERNEL_PAGE_TABLES.lvl2[i].base_addr_usize();
       KERNEL_PAGE_TABLES.lvl1[i] = TableDescriptor::new(output_addr);
+      // Note: board memory ranges are 2 MiB aligned
       for j in 0..ENTRY_PER_PAGE {
-        let output_addr = KERNEL_PAGE_TABLES.lvl3[i][j].base_addr_usize();
-        KERNEL_PAGE_TABLES.lvl2[i][j] = TableDescriptor::new(output_addr);
-        for k in 0..ENTRY_PER_PAGE {
-          let output_addr = (i << PAGE_TABLE_L1_SHIFT) | (j << PAGE_TABLE_L2_SHIFT) | (k << PAGE_TABLE_L3_SHIFT);
-          if crate::board::BOARD_NORMAL_MEMORY_RANGE.contains(&output_addr) {
-            KERNEL_PAGE_TABLES.lvl3[i][j][k] = PageDescriptor::new(output_addr, MemoryType::Normal);
-          } else if crate::board::BOARD_DEVICE_MEMORY_RANGE.contains(&output_addr) {
-            KERNEL_PAGE_TABLES.lvl3[i][j][k] = PageDescriptor::new(output_addr, MemoryType::Device);
-          }
+        let output_addr = (i << PAGE_TABLE_L1_SHIFT) | (j << PAGE_TABLE_L2_SHIFT);
+        if crate::board::BOARD_NORMAL_MEMORY_RANGE.contains(&output_addr) {
+          KERNEL_PAGE_TABLES.lvl2[i][j] = BlockDescriptor::new(output_addr, MemoryType::Normal);
+        } else if crate::board::BOARD_DEVICE_MEMORY_RANGE.contains(&output_addr) {
+          KERNEL_PAGE_TABLES.lvl2[i][j] = BlockDescriptor::new(output_addr, MemoryType::Device);
         }
       }
     }
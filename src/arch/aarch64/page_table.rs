@@ -1,5 +1,5 @@
 use crate::arch::*;
-use crate::lib::page_table::{Entry, EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::page_table::{BLOCK_SIZE_1G, BLOCK_SIZE_2M, Entry, EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
 use crate::mm::PageFrame;
 
 use super::vm_descriptor::*;
@@ -17,6 +17,17 @@ pub struct Aarch64PageTable {
 #[derive(Copy, Clone, Debug)]
 pub struct Aarch64PageTableEntry(usize);
 
+impl Aarch64PageTableEntry {
+  // Note: level 1/2 block, tables and level 3 pages have TYPE set
+  fn block(&self) -> bool {
+    self.0 & 0b11 == 0b01
+  }
+
+  fn to_block(self) -> Self {
+    Aarch64PageTableEntry(self.0 & !0b10)
+  }
+}
+
 impl ArchPageTableEntryTrait for Aarch64PageTableEntry {
   fn from_pte(value: usize) -> Self {
     Aarch64PageTableEntry(value)
@@ -53,8 +64,15 @@ impl ArchPageTableEntryTrait for Aarch64PageTableEntry {
   }
 
   fn alloc_table() -> Self {
-    let frame = crate::mm::page_pool::alloc();
-    crate::mm::page_pool::increase_rc(frame);
+    use crate::mm::page_pool::{FRAME_PAGE_TABLE, Owner};
+    let frame = crate::mm::page_pool::try_alloc_tagged(FRAME_PAGE_TABLE, Owner::Unowned)
+      .and_then(|frame| crate::mm::page_pool::increase_rc(frame).map(|_| frame));
+    let frame = match frame {
+      Ok(frame) => { frame }
+      Err(e) => { panic!("page_table: alloc_table: {:?}", e) }
+    };
+    // Note: frames come back from the pool dirty
+    frame.zero();
     Aarch64PageTableEntry::from(Entry::new(EntryAttribute::user_readonly(), frame.pa()))
   }
 }
@@ -148,18 +166,55 @@ impl PageTableTrait for Aarch64PageTable {
     l2e.set_entry(va.l3x(), Aarch64PageTableEntry::from(Entry::new(attr, pa)));
   }
 
+  fn map_block(&self, va: usize, pa: usize, size: usize, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
+    assert_eq!(va % size, 0);
+    assert_eq!(pa % size, 0);
+    let directory = Aarch64PageTableEntry::from_pa(self.directory.pa());
+    let (table, index) = match size {
+      BLOCK_SIZE_1G => { (directory, va.l1x()) }
+      BLOCK_SIZE_2M => {
+        let mut l1e = directory.entry(va.l1x());
+        if !l1e.valid() {
+          l1e = Aarch64PageTableEntry::alloc_table();
+          directory.set_entry(va.l1x(), l1e);
+        } else if l1e.block() {
+          return Err(crate::lib::page_table::Error::BlockConflictError);
+        }
+        (l1e, va.l2x())
+      }
+      _ => { panic!("page_table: map_block: size 0x{:x}", size) }
+    };
+    let old = table.entry(index);
+    if old.valid() && !old.block() {
+      return Err(crate::lib::page_table::Error::BlockConflictError);
+    }
+    table.set_entry(index, Aarch64PageTableEntry::from(Entry::new(attr, pa)).to_block());
+    Ok(())
+  }
+
   fn unmap(&self, va: usize) {
     let directory = Aarch64PageTableEntry::from_pa(self.directory.pa());
     let l1e = directory.entry(va.l1x());
     assert!(l1e.valid());
+    if l1e.block() {
+      directory.set_entry(va.l1x(), Aarch64PageTableEntry(0));
+      return;
+    }
     let l2e = l1e.entry(va.l2x());
     assert!(l2e.valid());
+    if l2e.block() {
+      l1e.set_entry(va.l2x(), Aarch64PageTableEntry(0));
+      return;
+    }
     l2e.set_entry(va.l3x(), Aarch64PageTableEntry(0));
   }
 
   fn insert_page(&self, va: usize, frame: PageFrame, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
     let pa = frame.pa();
-    if let Some(p) = self.lookup_page(va) {
+    if let Some((p, size)) = self.lookup(va) {
+      if size != PAGE_SIZE {
+        return Err(crate::lib::page_table::Error::BlockConflictError);
+      }
       if p.pa() != pa {
         // replace mapped frame
         self.remove_page(va)?;
@@ -171,35 +226,42 @@ impl PageTableTrait for Aarch64PageTable {
       }
     }
     crate::arch::Arch::invalidate_tlb();
+    crate::mm::page_pool::increase_rc(frame)?;
     self.map(va, pa, attr);
-    crate::mm::page_pool::increase_rc(frame);
     Ok(())
   }
 
-  fn lookup_page(&self, va: usize) -> Option<Entry> {
+  fn lookup(&self, va: usize) -> Option<(Entry, usize)> {
     let directory = Aarch64PageTableEntry::from_pa(self.directory.pa());
     let l1e = directory.entry(va.l1x());
     if !l1e.valid() {
       return None;
     }
+    if l1e.block() {
+      return Some((Entry::from(l1e), BLOCK_SIZE_1G));
+    }
     let l2e = l1e.entry(va.l2x());
     if !l2e.valid() {
       return None;
     }
+    if l2e.block() {
+      return Some((Entry::from(l2e), BLOCK_SIZE_2M));
+    }
     let l3e = l2e.entry(va.l3x());
     if l3e.valid() {
-      Some(Entry::from(l3e))
+      Some((Entry::from(l3e), PAGE_SIZE))
     } else {
       None
     }
   }
 
   fn remove_page(&self, va: usize) -> Result<(), crate::lib::page_table::Error> {
-    if let Some(pte) = self.lookup_page(va) {
+    if let Some((pte, _)) = self.lookup(va) {
       let frame = PageFrame::new(pte.pa());
-      crate::mm::page_pool::decrease_rc(frame);
       self.unmap(va);
       crate::arch::Arch::invalidate_tlb();
+      // Note: released once unmapped, the frame may be freed here
+      crate::mm::page_pool::decrease_rc(frame)?;
       Ok(())
     } else {
       Err(crate::lib::page_table::Error::AddressNotMappedError)
@@ -216,11 +278,20 @@ impl PageTableTrait for Aarch64PageTable {
     )));
   }
 
+  // Note: user entries are cleared, the directory may still be installed (see `process::ControlBlock::drop`)
   fn destroy(&self) {
+    use crate::lib::page_table::release;
     let directory = Aarch64PageTableEntry::from_pa(self.directory.pa());
     for l1x in 0..(PAGE_SIZE / MACHINE_SIZE) {
       let l1e = directory.entry(l1x);
-      if !l1e.valid() {
+      // Note: the recursive mapping holds no reference to the directory
+      //       walking it would release every table a second time
+      if !l1e.valid() || l1e.to_pa() == self.directory.pa() {
+        continue;
+      }
+      if l1e.block() {
+        release(l1e.to_pa());
+        directory.set_entry(l1x, Aarch64PageTableEntry(0));
         continue;
       }
       for l2x in 0..(PAGE_SIZE / MACHINE_SIZE) {
@@ -228,28 +299,21 @@ impl PageTableTrait for Aarch64PageTable {
         if !l2e.valid() {
           continue;
         }
+        if l2e.block() {
+          release(l2e.to_pa());
+          continue;
+        }
         for l3x in 0..(PAGE_SIZE / MACHINE_SIZE) {
           let l3e = l2e.entry(l3x);
           if !l3e.valid() {
             continue;
           }
-          let pa = l3e.to_pa();
-          if crate::mm::config::paged_range().contains(&pa) {
-            let frame = PageFrame::new(pa);
-            crate::mm::page_pool::decrease_rc(frame);
-          }
-        }
-        let pa = l2e.to_pa();
-        if crate::mm::config::paged_range().contains(&pa) {
-          let frame = PageFrame::new(pa);
-          crate::mm::page_pool::decrease_rc(frame);
+          release(l3e.to_pa());
         }
+        release(l2e.to_pa());
       }
-      let pa = l1e.to_pa();
-      if crate::mm::config::paged_range().contains(&pa) {
-        let frame = PageFrame::new(pa);
-        crate::mm::page_pool::decrease_rc(frame);
-      }
+      release(l1e.to_pa());
+      directory.set_entry(l1x, Aarch64PageTableEntry(0));
     }
   }
 
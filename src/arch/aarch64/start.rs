@@ -3,11 +3,25 @@ use crate::arch::Address;
 const CORE_MASK: u64 = 0x3;
 const BOOT_CORE_ID: u64 = 0;
 
+// Note: physical address of the device tree, x0 from firmware
+//       written with MMU off, so placed in the identity mapped `.data.kvm`
+#[link_section = ".data.kvm"]
+static mut BOOT_DEVICE_TREE: usize = 0;
+
+pub fn device_tree() -> Option<usize> {
+  let pa = unsafe { &BOOT_DEVICE_TREE as *const usize as usize };
+  match unsafe { core::intrinsics::volatile_load(pa.pa2kva() as *const usize) } {
+    0 => { None }
+    pa => { Some(pa) }
+  }
+}
+
 #[no_mangle]
 #[link_section = ".text.start"]
-unsafe extern "C" fn _start() -> ! {
+unsafe extern "C" fn _start(device_tree: usize) -> ! {
   use cortex_a::{*, regs::*};
   if BOOT_CORE_ID == MPIDR_EL1.get() & CORE_MASK {
+    core::intrinsics::volatile_store(&mut BOOT_DEVICE_TREE as *mut usize, device_tree);
     CPACR_EL1.set(3 << 20); // enable neon over EL1
     HCR_EL2.write(HCR_EL2::RW::EL1IsAarch64);
     SPSR_EL2.write(
@@ -0,0 +1,113 @@
+use core::fmt::Formatter;
+
+// Note: register convention follows aarch64
+//       x0 ~ x7 arguments, x8 system call number, x29 fp, x30 lr
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MockContextFrame {
+  gpr: [u64; 31],
+  privileged: u64,
+  pc: u64,
+  sp: u64,
+}
+
+impl core::fmt::Display for MockContextFrame {
+  fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), core::fmt::Error> {
+    for i in 0..31 {
+      write!(f, "x{:02}: {:016x}   ", i, self.gpr[i])?;
+      if (i + 1) % 2 == 0 {
+        write!(f, "\n")?;
+      }
+    }
+    writeln!(f, "pc:  {:016x}   sp:  {:016x}", self.pc, self.sp)?;
+    Ok(())
+  }
+}
+
+impl crate::arch::traits::ContextFrameTrait for MockContextFrame {
+  fn new(pc: usize, sp: usize, arg: usize, privileged: bool) -> Self {
+    let mut r = MockContextFrame {
+      gpr: [0; 31],
+      privileged: privileged as u64,
+      pc: pc as u64,
+      sp: sp as u64,
+    };
+    r.set_argument(arg);
+    r
+  }
+
+  fn syscall_argument(&self, i: usize) -> usize {
+    assert!(i < 8);
+    self.gpr[i] as usize
+  }
+
+  fn set_syscall_argument(&mut self, i: usize, v: usize) {
+    assert!(i < 8);
+    self.gpr[i] = v as u64;
+  }
+
+  fn syscall_number(&self) -> usize {
+    self.gpr[8] as usize
+  }
+
+  fn set_syscall_return_value(&mut self, v: usize) {
+    self.gpr[0] = v as u64;
+  }
+
+  fn exception_pc(&self) -> usize {
+    self.pc as usize
+  }
+
+  fn set_exception_pc(&mut self, pc: usize) {
+    self.pc = pc as u64;
+  }
+
+  fn stack_pointer(&self) -> usize {
+    self.sp as usize
+  }
+
+  fn set_stack_pointer(&mut self, sp: usize) {
+    self.sp = sp as u64;
+  }
+
+  fn frame_pointer(&self) -> usize {
+    self.gpr[29] as usize
+  }
+
+  fn link_register(&self) -> usize {
+    self.gpr[30] as usize
+  }
+
+  fn set_argument(&mut self, arg: usize) {
+    self.gpr[0] = arg as u64;
+  }
+
+  fn restore_from_user(&mut self, saved: &Self) {
+    let privileged = self.privileged;
+    *self = *saved;
+    self.privileged = privileged;
+  }
+
+  fn gdb_register(&self, i: usize) -> Option<(u64, usize)> {
+    match i {
+      0..=30 => Some((self.gpr[i], 8)),
+      31 => Some((self.sp, 8)),
+      32 => Some((self.pc, 8)),
+      _ => None,
+    }
+  }
+
+  fn set_gdb_register(&mut self, i: usize, v: u64) -> bool {
+    match i {
+      0..=30 => { self.gpr[i] = v; }
+      31 => { self.sp = v; }
+      32 => { self.pc = v; }
+      _ => { return false; }
+    }
+    true
+  }
+
+  fn set_single_step(&mut self, _step: bool) -> bool {
+    false
+  }
+}
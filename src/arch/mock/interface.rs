@@ -0,0 +1,108 @@
+use crate::arch::Address;
+use crate::board::BOARD_PHYSICAL_ADDRESS_LIMIT;
+
+pub const PAGE_SIZE: usize = 4096;
+pub const PAGE_SHIFT: usize = 12;
+pub const MACHINE_SIZE: usize = 8;
+
+lazy_static! {
+  // Note: backing store of the whole mock physical address space
+  static ref PHYSICAL_MEMORY_BASE: usize = {
+    let layout = std::alloc::Layout::from_size_align(BOARD_PHYSICAL_ADDRESS_LIMIT, PAGE_SIZE).unwrap();
+    unsafe { std::alloc::alloc_zeroed(layout) as usize }
+  };
+}
+
+impl Address for usize {
+  fn pa2kva(&self) -> usize {
+    *PHYSICAL_MEMORY_BASE + *self
+  }
+  fn kva2pa(&self) -> usize {
+    *self - *PHYSICAL_MEMORY_BASE
+  }
+}
+
+pub type Arch = MockArch;
+
+pub type ContextFrame = super::context_frame::MockContextFrame;
+
+pub type PageTable = super::page_table::MockPageTable;
+
+pub type ArchPageTableEntry = super::page_table::MockPageTableEntry;
+
+pub type AddressSpaceId = u16;
+
+pub type FaultSyndrome = MockFaultSyndrome;
+
+pub const FRAME_RECORD_FP_OFFSET: isize = 0;
+pub const FRAME_RECORD_RA_OFFSET: isize = 8;
+
+pub const BREAKPOINT_INSTRUCTIONS: &[&[u8]] = &[&[0xcc]];
+
+#[derive(Copy, Clone, Debug)]
+pub struct MockFaultSyndrome;
+
+impl core::fmt::Display for MockFaultSyndrome {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+    write!(f, "mock fault")
+  }
+}
+
+pub struct MockArch;
+
+impl crate::arch::ArchTrait for MockArch {
+  fn exception_init() {}
+
+  fn invalidate_tlb() {}
+
+  fn wait_for_event() {
+    std::thread::yield_now();
+  }
+
+  fn nop() {}
+
+  fn fault_address() -> usize {
+    0
+  }
+
+  fn fault_syndrome() -> FaultSyndrome {
+    MockFaultSyndrome
+  }
+
+  fn core_id() -> usize {
+    0
+  }
+
+  fn frame_pointer() -> usize {
+    0
+  }
+
+  fn sync_instruction_cache(_kva: usize) {}
+
+  fn interrupt_save_disable() -> usize {
+    0
+  }
+
+  fn interrupt_restore(_state: usize) {}
+
+  fn interrupt_pending() -> bool {
+    false
+  }
+
+  // Note: host tests never run threads, reaching this is a test bug
+  fn switch_context(_from_sp: *mut usize, _to_sp: usize) {
+    panic!("mock: switch_context, host tests must not schedule");
+  }
+
+  fn kernel_stack_init(top: usize) -> usize {
+    top - core::mem::size_of::<ContextFrame>()
+  }
+
+  fn user_access_begin() {}
+
+  fn user_access_end() {}
+
+  fn device_tree() -> Option<usize> {
+    None
+  }
+}
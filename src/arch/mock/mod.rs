@@ -0,0 +1,7 @@
+// Note: host arch for `cargo test`, see README
+//       physical memory is a host allocation, page tables are plain maps
+pub use self::interface::*;
+
+mod interface;
+mod page_table;
+mod context_frame;
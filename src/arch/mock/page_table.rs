@@ -0,0 +1,246 @@
+use alloc::collections::BTreeMap;
+
+use spin::Mutex;
+
+use crate::arch::*;
+use crate::lib::page_table::{BLOCK_SIZE_1G, BLOCK_SIZE_2M, Entry, EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::round_down;
+use crate::mm::PageFrame;
+
+const PTE_VALID: usize = 1 << 0;
+const PTE_WRITABLE: usize = 1 << 1;
+const PTE_USER: usize = 1 << 2;
+const PTE_DEVICE: usize = 1 << 3;
+const PTE_K_EXECUTABLE: usize = 1 << 4;
+const PTE_U_EXECUTABLE: usize = 1 << 5;
+const PTE_COPY_ON_WRITE: usize = 1 << 6;
+const PTE_SHARED: usize = 1 << 7;
+const PTE_BLOCK_2M: usize = 1 << 8;
+const PTE_BLOCK_1G: usize = 1 << 9;
+const PTE_BLOCK_MASK: usize = PTE_BLOCK_2M | PTE_BLOCK_1G;
+const PTE_ADDRESS_MASK: usize = !(PAGE_SIZE - 1);
+
+// Note: kernel page table is not backed by pool memory
+const KERNEL_DIRECTORY: usize = crate::board::BOARD_NORMAL_MEMORY_RANGE.end;
+
+lazy_static! {
+  // Note: page directory pa -> (leaf va -> leaf entry), no intermediate levels
+  //       blocks are keyed by their first page and marked by `PTE_BLOCK_*`
+  static ref TABLES: Mutex<BTreeMap<usize, BTreeMap<usize, MockPageTableEntry>>> = Mutex::new(BTreeMap::new());
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MockPageTable {
+  directory: PageFrame
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct MockPageTableEntry(usize);
+
+impl ArchPageTableEntryTrait for MockPageTableEntry {
+  fn from_pte(value: usize) -> Self {
+    MockPageTableEntry(value)
+  }
+
+  fn from_pa(pa: usize) -> Self {
+    MockPageTableEntry(pa & PTE_ADDRESS_MASK)
+  }
+
+  fn to_pte(&self) -> usize {
+    self.0
+  }
+
+  fn to_pa(&self) -> usize {
+    self.0 & PTE_ADDRESS_MASK
+  }
+
+  fn to_kva(&self) -> usize {
+    self.to_pa().pa2kva()
+  }
+
+  fn valid(&self) -> bool {
+    self.0 & PTE_VALID != 0
+  }
+
+  fn entry(&self, index: usize) -> Self {
+    let addr = self.to_kva() + index * MACHINE_SIZE;
+    unsafe { MockPageTableEntry(core::intrinsics::volatile_load(addr as *const usize)) }
+  }
+
+  fn set_entry(&self, index: usize, value: Self) {
+    let addr = self.to_kva() + index * MACHINE_SIZE;
+    unsafe { core::intrinsics::volatile_store(addr as *mut usize, value.0) }
+  }
+
+  fn alloc_table() -> Self {
+    use crate::mm::page_pool::{FRAME_PAGE_TABLE, Owner};
+    let frame = crate::mm::page_pool::try_alloc_tagged(FRAME_PAGE_TABLE, Owner::Unowned)
+      .and_then(|frame| crate::mm::page_pool::increase_rc(frame).map(|_| frame));
+    let frame = match frame {
+      Ok(frame) => { frame }
+      Err(e) => { panic!("page_table: alloc_table: {:?}", e) }
+    };
+    MockPageTableEntry(frame.pa() | PTE_VALID)
+  }
+}
+
+impl core::convert::From<MockPageTableEntry> for Entry {
+  fn from(u: MockPageTableEntry) -> Self {
+    Entry::new(EntryAttribute::new(
+      u.0 & PTE_WRITABLE != 0,
+      u.0 & PTE_USER != 0,
+      u.0 & PTE_DEVICE != 0,
+      u.0 & PTE_K_EXECUTABLE != 0,
+      u.0 & PTE_U_EXECUTABLE != 0,
+      u.0 & PTE_COPY_ON_WRITE != 0,
+      u.0 & PTE_SHARED != 0,
+    ), u.to_pa())
+  }
+}
+
+impl core::convert::From<Entry> for MockPageTableEntry {
+  fn from(pte: Entry) -> Self {
+    let attr = pte.attribute();
+    MockPageTableEntry(
+      (pte.pa() & PTE_ADDRESS_MASK)
+        | PTE_VALID
+        | if attr.writable() { PTE_WRITABLE } else { 0 }
+        | if attr.u_readable() { PTE_USER } else { 0 }
+        | if attr.device() { PTE_DEVICE } else { 0 }
+        | if attr.k_executable() { PTE_K_EXECUTABLE } else { 0 }
+        | if attr.u_executable() { PTE_U_EXECUTABLE } else { 0 }
+        | if attr.u_copy_on_write() { PTE_COPY_ON_WRITE } else { 0 }
+        | if attr.u_shared() { PTE_SHARED } else { 0 }
+    )
+  }
+}
+
+// Note: reference counting follows `Riscv64PageTable`
+//       `insert_page` takes a reference, `remove_page` and `destroy` drop it
+impl PageTableTrait for MockPageTable {
+  fn new(directory: PageFrame) -> Self {
+    MockPageTable {
+      directory
+    }
+  }
+
+  fn directory(&self) -> PageFrame {
+    self.directory
+  }
+
+  fn map(&self, va: usize, pa: usize, attr: EntryAttribute) {
+    let mut tables = TABLES.lock();
+    let table = tables.entry(self.directory.pa()).or_insert_with(BTreeMap::new);
+    table.insert(round_down(va, PAGE_SIZE), MockPageTableEntry::from(Entry::new(attr, pa)));
+    drop(tables);
+  }
+
+  // Note: a block conflicts with any other leaf in its range, as if it were under a table
+  fn map_block(&self, va: usize, pa: usize, size: usize, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
+    assert_eq!(va % size, 0);
+    assert_eq!(pa % size, 0);
+    let bit = match size {
+      BLOCK_SIZE_2M => { PTE_BLOCK_2M }
+      BLOCK_SIZE_1G => { PTE_BLOCK_1G }
+      _ => { panic!("page_table: map_block: size 0x{:x}", size) }
+    };
+    let mut tables = TABLES.lock();
+    let table = tables.entry(self.directory.pa()).or_insert_with(BTreeMap::new);
+    let conflict = table.range(va..(va + size)).any(|(k, pte)| *k != va || pte.0 & PTE_BLOCK_MASK != bit)
+      || (size != BLOCK_SIZE_1G && table.get(&round_down(va, BLOCK_SIZE_1G)).map_or(false, |pte| pte.0 & PTE_BLOCK_1G != 0));
+    if !conflict {
+      table.insert(va, MockPageTableEntry(MockPageTableEntry::from(Entry::new(attr, pa)).0 | bit));
+    }
+    drop(tables);
+    if conflict {
+      Err(crate::lib::page_table::Error::BlockConflictError)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn unmap(&self, va: usize) {
+    let size = self.lookup(va).map(|(_, size)| size);
+    assert!(size.is_some());
+    let mut tables = TABLES.lock();
+    let r = tables.get_mut(&self.directory.pa()).and_then(|table| table.remove(&round_down(va, size.unwrap())));
+    drop(tables);
+    assert!(r.is_some());
+  }
+
+  fn insert_page(&self, va: usize, frame: PageFrame, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
+    let pa = frame.pa();
+    if let Some((p, size)) = self.lookup(va) {
+      if size != PAGE_SIZE {
+        return Err(crate::lib::page_table::Error::BlockConflictError);
+      }
+      if p.pa() != pa {
+        // replace mapped frame
+        self.remove_page(va)?;
+      } else {
+        // update attribute
+        self.map(va, pa, attr);
+        return Ok(());
+      }
+    }
+    crate::mm::page_pool::increase_rc(frame)?;
+    self.map(va, pa, attr);
+    Ok(())
+  }
+
+  fn lookup(&self, va: usize) -> Option<(Entry, usize)> {
+    let tables = TABLES.lock();
+    let r = tables.get(&self.directory.pa()).and_then(|table| {
+      [(PAGE_SIZE, 0), (BLOCK_SIZE_2M, PTE_BLOCK_2M), (BLOCK_SIZE_1G, PTE_BLOCK_1G)].iter()
+        .filter_map(|(size, bit)| {
+          table.get(&round_down(va, *size))
+            .filter(|pte| pte.0 & PTE_BLOCK_MASK == *bit)
+            .map(|pte| (Entry::from(*pte), *size))
+        })
+        .next()
+    });
+    drop(tables);
+    r
+  }
+
+  fn remove_page(&self, va: usize) -> Result<(), crate::lib::page_table::Error> {
+    if let Some((pte, _)) = self.lookup(va) {
+      let frame = PageFrame::new(pte.pa());
+      self.unmap(va);
+      // Note: released once unmapped, the frame may be freed here
+      crate::mm::page_pool::decrease_rc(frame)?;
+      Ok(())
+    } else {
+      Err(crate::lib::page_table::Error::AddressNotMappedError)
+    }
+  }
+
+  fn recursive_map(&self, va: usize) {
+    self.map(va, self.directory.pa(), EntryAttribute::user_readonly());
+  }
+
+  fn destroy(&self) {
+    let mut tables = TABLES.lock();
+    let table = tables.remove(&self.directory.pa());
+    drop(tables);
+    for (_, pte) in table.unwrap_or_default() {
+      let pa = pte.to_pa();
+      // Note: the recursive mapping holds no reference to the directory
+      if pa != self.directory.pa() {
+        crate::lib::page_table::release(pa);
+      }
+    }
+  }
+
+  fn kernel_page_table() -> PageTable {
+    PageTable::new(PageFrame::new(KERNEL_DIRECTORY))
+  }
+
+  fn user_page_table() -> PageTable {
+    // Note: no hardware to install into, user tables are only walked by the kernel
+    Self::kernel_page_table()
+  }
+
+  fn set_user_page_table(_pt: PageTable, _asid: AddressSpaceId) {}
+}
@@ -1,11 +1,15 @@
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(test)))]
 pub use self::aarch64::*;
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub use self::riscv64::*;
+#[cfg(test)]
+pub use self::mock::*;
 pub use self::traits::*;
 
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(test)))]
 mod aarch64;
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 mod riscv64;
+#[cfg(test)]
+mod mock;
 mod traits;
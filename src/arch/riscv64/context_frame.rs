@@ -44,6 +44,12 @@ impl crate::arch::traits::ContextFrameTrait for Riscv64ContextFrame {
     self.gpr[i + 10] as usize
   }
 
+  fn set_syscall_argument(&mut self, i: usize, v: usize) {
+    assert!(i <= 5);
+    // a0 ~ a5 -> x10 ~ x15
+    self.gpr[i + 10] = v as u64;
+  }
+
   fn syscall_number(&self) -> usize {
     // a7 -> x17
     self.gpr[17] as usize
@@ -71,8 +77,49 @@ impl crate::arch::traits::ContextFrameTrait for Riscv64ContextFrame {
     self.gpr[2] = sp as u64;
   }
 
+  fn frame_pointer(&self) -> usize {
+    // s0 -> x8
+    self.gpr[8] as usize
+  }
+
+  fn link_register(&self) -> usize {
+    // ra -> x1
+    self.gpr[1] as usize
+  }
+
   fn set_argument(&mut self, arg: usize) {
     self.gpr[10] = arg as u64;
   }
-}
 
+  fn restore_from_user(&mut self, saved: &Self) {
+    // Note: sstatus holds no user state, keep the kernel one
+    let sstatus = self.sstatus;
+    *self = *saved;
+    self.sstatus = sstatus;
+  }
+
+  fn gdb_register(&self, i: usize) -> Option<(u64, usize)> {
+    // x0 ~ x31, pc
+    match i {
+      0 => Some((0, 8)),
+      1..=31 => Some((self.gpr[i], 8)),
+      32 => Some((self.sepc, 8)),
+      _ => None,
+    }
+  }
+
+  fn set_gdb_register(&mut self, i: usize, v: u64) -> bool {
+    match i {
+      0 => {}
+      1..=31 => { self.gpr[i] = v; }
+      32 => { self.sepc = v; }
+      _ => { return false; }
+    }
+    true
+  }
+
+  fn set_single_step(&mut self, _step: bool) -> bool {
+    // Note: no hardware single step in S mode, gdb steps riscv with breakpoints itself
+    false
+  }
+}
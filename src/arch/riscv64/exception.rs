@@ -77,17 +77,55 @@ impl core::convert::From<usize> for Exception {
   }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct Riscv64FaultSyndrome {
+  scause: u64,
+}
+
+impl Riscv64FaultSyndrome {
+  pub fn read() -> Self {
+    Riscv64FaultSyndrome {
+      scause: SCAUSE.get() as u64,
+    }
+  }
+}
+
+impl core::fmt::Display for Riscv64FaultSyndrome {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+    let irq = (self.scause >> 63) != 0;
+    let code = (self.scause & 0xf) as usize;
+    if irq {
+      write!(f, "scause {:016x} {:?}", self.scause, Interrupt::from(code))
+    } else {
+      write!(f, "scause {:016x} {:?}", self.scause, Exception::from(code))
+    }
+  }
+}
+
+// Note: from `push_context` on the emergency stack, no trap frame was pushed
+#[no_mangle]
+unsafe extern "C" fn kernel_stack_overflow(sp: usize) -> ! {
+  println!("exception_entry: kernel {}", Riscv64FaultSyndrome::read());
+  panic!("kernel stack overflow: sp {:016x} stval {:016x}", sp, STVAL.get());
+}
+
 #[no_mangle]
 unsafe extern "C" fn exception_entry(ctx: usize) {
   let from_kernel = SSTATUS.is_set(SSTATUS::SPP);
-  if from_kernel {
-    //panic!("kernel exception");
-  }
   let core = crate::lib::core::current();
   core.set_context(ctx as *mut ContextFrame);
   let cause = SCAUSE.get();
   let irq = (cause >> 63) != 0;
   let code = (cause & 0xf) as usize;
+  if from_kernel && !irq {
+    // Note: any synchronous exception from kernel is a bug, dump before panic
+    println!("exception_entry: kernel {}", Riscv64FaultSyndrome::read());
+    println!("stval {:016x}", STVAL.get());
+    crate::lib::backtrace::print_context(core.context());
+    if crate::mm::kernel_stack::is_guard(STVAL.get() as usize) {
+      panic!("kernel stack overflow {:016x}", STVAL.get());
+    }
+  }
   if irq {
     match Interrupt::from(code) {
       Interrupt::UserSoftware => { panic!("Interrupt::UserSoft") }
@@ -105,7 +143,12 @@ unsafe extern "C" fn exception_entry(ctx: usize) {
       Exception::InstructionAddressMisaligned => { panic!("Exception::InstructionMisaligned") }
       Exception::InstructionAccessFault => { panic!("Exception::InstructionFault") }
       Exception::IllegalInstruction => { Isr::default() }
-      Exception::Breakpoint => { panic!("Exception::Breakpoint") }
+      Exception::Breakpoint => {
+        if from_kernel {
+          panic!("Exception::Breakpoint");
+        }
+        Isr::breakpoint()
+      }
       Exception::LoadAccessFault => { panic!("Exception::LoadFault") }
       Exception::StoreAddressMisaligned => { panic!("Exception::StoreMisaligned") }
       Exception::StoreAccessFault => {
@@ -125,7 +168,9 @@ unsafe extern "C" fn exception_entry(ctx: usize) {
       _ => { panic!("Exception::Unknown") }
     }
   }
-  core.clear_context();
+  crate::lib::signal::handle_pending();
+  crate::lib::scheduler::leave_trap();
+  crate::lib::core::current().clear_context();
 }
 
 pub fn init() {
@@ -28,6 +28,15 @@ pub type ArchPageTableEntry = super::page_table::Riscv64PageTableEntry;
 
 pub type AddressSpaceId = u16;
 
+pub type FaultSyndrome = super::exception::Riscv64FaultSyndrome;
+
+// Note: frame record below fp (s0): [fp - 16] previous fp, [fp - 8] ra
+pub const FRAME_RECORD_FP_OFFSET: isize = -16;
+pub const FRAME_RECORD_RA_OFFSET: isize = -8;
+
+// Note: `ebreak` and `c.ebreak`, little endian
+pub const BREAKPOINT_INSTRUCTIONS: &[&[u8]] = &[&[0x73, 0x00, 0x10, 0x00], &[0x02, 0x90]];
+
 pub struct Riscv64Arch;
 
 impl crate::arch::ArchTrait for Riscv64Arch {
@@ -51,8 +60,89 @@ impl crate::arch::ArchTrait for Riscv64Arch {
     STVAL.get() as usize
   }
 
+  fn fault_syndrome() -> FaultSyndrome {
+    FaultSyndrome::read()
+  }
+
   fn core_id() -> usize {
     // TODO: (riscv64) core id
     0
   }
-}
\ No newline at end of file
+
+  #[inline(always)]
+  fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+      llvm_asm!("mv $0, s0" : "=r"(fp) ::: "volatile");
+    }
+    fp
+  }
+
+  fn sync_instruction_cache(_kva: usize) {
+    unsafe {
+      llvm_asm!("fence.i" :::: "volatile");
+    }
+  }
+
+  fn interrupt_save_disable() -> usize {
+    // Note: clear sstatus.SIE (bit 1) and return its old value
+    let sstatus: usize;
+    unsafe {
+      llvm_asm!("csrrci $0, sstatus, 2" : "=r"(sstatus) ::: "volatile");
+    }
+    sstatus & 0b10
+  }
+
+  fn interrupt_restore(state: usize) {
+    unsafe {
+      llvm_asm!("csrs sstatus, $0" :: "r"(state & 0b10) :: "volatile");
+    }
+  }
+
+  fn interrupt_pending() -> bool {
+    // Note: sip.STIP (bit 5), the only interrupt enabled
+    let sip: usize;
+    unsafe {
+      llvm_asm!("csrr $0, sip" : "=r"(sip) ::: "volatile");
+    }
+    sip & (1 << 5) != 0
+  }
+
+  fn switch_context(from_sp: *mut usize, to_sp: usize) {
+    extern "C" {
+      fn switch_context(from_sp: *mut usize, to_sp: usize);
+    }
+    unsafe { switch_context(from_sp, to_sp) }
+  }
+
+  fn kernel_stack_init(top: usize) -> usize {
+    extern "C" {
+      fn thread_entry();
+    }
+    // Note: ra, s0 ~ s11 as pushed by `switch_context` in `exception.S`, fp (s0) 0 ends backtraces
+    const SWITCH_FRAME_SIZE: usize = 14 * MACHINE_SIZE;
+    let sp = top - core::mem::size_of::<ContextFrame>() - SWITCH_FRAME_SIZE;
+    unsafe {
+      core::intrinsics::volatile_set_memory(sp as *mut u8, 0, SWITCH_FRAME_SIZE);
+      core::intrinsics::volatile_store(sp as *mut usize, thread_entry as usize);
+    }
+    sp
+  }
+
+  fn user_access_begin() {
+    // Note: set sstatus.SUM (bit 18)
+    unsafe {
+      llvm_asm!("csrs sstatus, $0" :: "r"(1usize << 18) :: "volatile");
+    }
+  }
+
+  fn user_access_end() {
+    unsafe {
+      llvm_asm!("csrc sstatus, $0" :: "r"(1usize << 18) :: "volatile");
+    }
+  }
+
+  fn device_tree() -> Option<usize> {
+    super::start::device_tree()
+  }
+}
@@ -1,8 +1,10 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use riscv::regs::*;
 
 use crate::arch::*;
 use crate::config::*;
-use crate::lib::page_table::{Entry, EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::page_table::{BLOCK_SIZE_1G, BLOCK_SIZE_2M, Entry, EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
 use crate::mm::PageFrame;
 
 use super::vm_descriptor::*;
@@ -11,6 +13,9 @@ pub const PAGE_TABLE_L1_SHIFT: usize = 30;
 pub const PAGE_TABLE_L2_SHIFT: usize = 21;
 pub const PAGE_TABLE_L3_SHIFT: usize = 12;
 
+// Note: level 2 table of the kernel stack window, shared by all page directories
+static KERNEL_STACK_TABLE: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Copy, Clone, Debug)]
 pub struct Riscv64PageTable {
   directory: PageFrame
@@ -20,6 +25,13 @@ pub struct Riscv64PageTable {
 #[derive(Copy, Clone, Debug)]
 pub struct Riscv64PageTableEntry(usize);
 
+impl Riscv64PageTableEntry {
+  // Note: any of R/W/X set, a page at level 3, a block at level 1/2
+  fn leaf(&self) -> bool {
+    self.0 & 0b1110 != 0
+  }
+}
+
 impl ArchPageTableEntryTrait for Riscv64PageTableEntry {
   fn from_pte(value: usize) -> Self {
     Riscv64PageTableEntry(value)
@@ -57,8 +69,15 @@ impl ArchPageTableEntryTrait for Riscv64PageTableEntry {
   }
 
   fn alloc_table() -> Self {
-    let frame = crate::mm::page_pool::alloc();
-    crate::mm::page_pool::increase_rc(frame);
+    use crate::mm::page_pool::{FRAME_PAGE_TABLE, Owner};
+    let frame = crate::mm::page_pool::try_alloc_tagged(FRAME_PAGE_TABLE, Owner::Unowned)
+      .and_then(|frame| crate::mm::page_pool::increase_rc(frame).map(|_| frame));
+    let frame = match frame {
+      Ok(frame) => { frame }
+      Err(e) => { panic!("page_table: alloc_table: {:?}", e) }
+    };
+    // Note: frames come back from the pool dirty
+    frame.zero();
     Riscv64PageTableEntry(
       (TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_PPN.val((frame.pa() >> PAGE_SHIFT) as u64)
         + TABLE_DESCRIPTOR::DIRTY::True
@@ -123,6 +142,24 @@ impl core::convert::From<Entry> for Riscv64PageTableEntry {
 }
 
 impl Riscv64PageTable {
+  // Note: the level 2 table of `va`, allocated on demand
+  //       user ones are exposed in the read only level 2 window
+  fn level_2_table(&self, va: usize) -> Riscv64PageTableEntry {
+    let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
+    let mut l1e = directory.entry(va.l1x());
+    if !l1e.valid() && va.l1x() == CONFIG_KERNEL_STACK_BTM.l1x() {
+      l1e = kernel_stack_table();
+      directory.set_entry(va.l1x(), l1e);
+    } else if !l1e.valid() {
+      l1e = Riscv64PageTableEntry::alloc_table();
+      if va <= CONFIG_READ_ONLY_LEVEL_1_PAGE_TABLE_BTM {
+        self.map(CONFIG_READ_ONLY_LEVEL_2_PAGE_TABLE_BTM + va.l1x() * PAGE_SIZE, l1e.to_pa(), EntryAttribute::user_readonly());
+      }
+      directory.set_entry(va.l1x(), l1e);
+    }
+    l1e
+  }
+
   fn map_kernel_gigabyte_page(&self, va: usize, pa: usize) {
     let l1x = va.l1x();
     let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
@@ -138,6 +175,31 @@ impl Riscv64PageTable {
         + PAGE_DESCRIPTOR::VALID::True).value as usize
     ));
   }
+
+  // Note: kernel stacks are mapped in whichever directory is installed, see `mm/kernel_stack.rs`
+  //       the window is copied into a directory before it is installed
+  fn map_kernel_stack_window(&self) {
+    let l1e = KERNEL_STACK_TABLE.load(Ordering::Acquire);
+    if l1e != 0 {
+      let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
+      directory.set_entry(CONFIG_KERNEL_STACK_BTM.l1x(), Riscv64PageTableEntry(l1e));
+    }
+  }
+}
+
+fn kernel_stack_table() -> Riscv64PageTableEntry {
+  let l1e = KERNEL_STACK_TABLE.load(Ordering::Acquire);
+  if l1e != 0 {
+    return Riscv64PageTableEntry(l1e);
+  }
+  let table = Riscv64PageTableEntry::alloc_table();
+  match KERNEL_STACK_TABLE.compare_exchange(0, table.to_pte(), Ordering::AcqRel, Ordering::Acquire) {
+    Ok(_) => { table }
+    Err(other) => {
+      crate::lib::page_table::release(table.to_pa());
+      Riscv64PageTableEntry(other)
+    }
+  }
 }
 
 impl PageTableTrait for Riscv64PageTable {
@@ -157,15 +219,7 @@ impl PageTableTrait for Riscv64PageTable {
   }
 
   fn map(&self, va: usize, pa: usize, attr: EntryAttribute) {
-    let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
-    let mut l1e = directory.entry(va.l1x());
-    if !l1e.valid() {
-      l1e = Riscv64PageTableEntry::alloc_table();
-      if va <= CONFIG_READ_ONLY_LEVEL_1_PAGE_TABLE_BTM {
-        self.map(CONFIG_READ_ONLY_LEVEL_2_PAGE_TABLE_BTM + va.l1x() * PAGE_SIZE, l1e.to_pa(), EntryAttribute::user_readonly());
-      }
-      directory.set_entry(va.l1x(), l1e);
-    }
+    let l1e = self.level_2_table(va);
     let mut l2e = l1e.entry(va.l2x());
     if !l2e.valid() {
       l2e = Riscv64PageTableEntry::alloc_table();
@@ -177,18 +231,52 @@ impl PageTableTrait for Riscv64PageTable {
     l2e.set_entry(va.l3x(), Riscv64PageTableEntry::from(Entry::new(attr, pa)));
   }
 
+  fn map_block(&self, va: usize, pa: usize, size: usize, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
+    assert_eq!(va % size, 0);
+    assert_eq!(pa % size, 0);
+    let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
+    let (table, index) = match size {
+      BLOCK_SIZE_1G => { (directory, va.l1x()) }
+      BLOCK_SIZE_2M => {
+        let l1e = directory.entry(va.l1x());
+        if l1e.valid() && l1e.leaf() {
+          return Err(crate::lib::page_table::Error::BlockConflictError);
+        }
+        (self.level_2_table(va), va.l2x())
+      }
+      _ => { panic!("page_table: map_block: size 0x{:x}", size) }
+    };
+    let old = table.entry(index);
+    if old.valid() && !old.leaf() {
+      return Err(crate::lib::page_table::Error::BlockConflictError);
+    }
+    table.set_entry(index, Riscv64PageTableEntry::from(Entry::new(attr, pa)));
+    Ok(())
+  }
+
   fn unmap(&self, va: usize) {
     let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
     let l1e = directory.entry(va.l1x());
     assert!(l1e.valid());
+    if l1e.leaf() {
+      directory.set_entry(va.l1x(), Riscv64PageTableEntry(0));
+      return;
+    }
     let l2e = l1e.entry(va.l2x());
     assert!(l2e.valid());
+    if l2e.leaf() {
+      l1e.set_entry(va.l2x(), Riscv64PageTableEntry(0));
+      return;
+    }
     l2e.set_entry(va.l3x(), Riscv64PageTableEntry(0));
   }
 
   fn insert_page(&self, va: usize, frame: PageFrame, attr: EntryAttribute) -> Result<(), crate::lib::page_table::Error> {
     let pa = frame.pa();
-    if let Some(p) = self.lookup_page(va) {
+    if let Some((p, size)) = self.lookup(va) {
+      if size != PAGE_SIZE {
+        return Err(crate::lib::page_table::Error::BlockConflictError);
+      }
       if p.pa() != pa {
         // replace mapped frame
         self.remove_page(va)?;
@@ -200,35 +288,42 @@ impl PageTableTrait for Riscv64PageTable {
       }
     }
     crate::arch::Arch::invalidate_tlb();
+    crate::mm::page_pool::increase_rc(frame)?;
     self.map(va, pa, attr);
-    crate::mm::page_pool::increase_rc(frame);
     Ok(())
   }
 
-  fn lookup_page(&self, va: usize) -> Option<Entry> {
+  fn lookup(&self, va: usize) -> Option<(Entry, usize)> {
     let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
     let l1e = directory.entry(va.l1x());
     if !l1e.valid() {
       return None;
     }
+    if l1e.leaf() {
+      return Some((Entry::from(l1e), BLOCK_SIZE_1G));
+    }
     let l2e = l1e.entry(va.l2x());
     if !l2e.valid() {
       return None;
     }
+    if l2e.leaf() {
+      return Some((Entry::from(l2e), BLOCK_SIZE_2M));
+    }
     let l3e = l2e.entry(va.l3x());
     if !(l3e.valid()) {
       None
     } else {
-      Some(Entry::from(l3e))
+      Some((Entry::from(l3e), PAGE_SIZE))
     }
   }
 
   fn remove_page(&self, va: usize) -> Result<(), crate::lib::page_table::Error> {
-    if let Some(pte) = self.lookup_page(va) {
+    if let Some((pte, _)) = self.lookup(va) {
       let frame = PageFrame::new(pte.pa());
-      crate::mm::page_pool::decrease_rc(frame);
       self.unmap(va);
       crate::arch::Arch::invalidate_tlb();
+      // Note: released once unmapped, the frame may be freed here
+      crate::mm::page_pool::decrease_rc(frame)?;
       Ok(())
     } else {
       Err(crate::lib::page_table::Error::AddressNotMappedError)
@@ -239,12 +334,25 @@ impl PageTableTrait for Riscv64PageTable {
     self.map(CONFIG_READ_ONLY_LEVEL_1_PAGE_TABLE_BTM, self.directory.pa(), EntryAttribute::user_readonly());
   }
 
+  // Note: user entries are cleared, the directory may still be installed (see `process::ControlBlock::drop`)
   fn destroy(&self) {
+    use crate::lib::page_table::release;
     let directory = Riscv64PageTableEntry::from_pa(self.directory.pa());
     for l1x in 0..(PAGE_SIZE / MACHINE_SIZE) {
       let l1e = directory.entry(l1x);
-      if !l1e.valid() || l1e.to_pte() & 0b1110 != 0 {
-        // Note: level 1 has gigabyte pages
+      if !l1e.valid() {
+        continue;
+      }
+      // Note: the kernel stack window is shared by all page tables
+      if l1x == CONFIG_KERNEL_STACK_BTM.l1x() {
+        continue;
+      }
+      if l1e.leaf() {
+        // Note: kernel gigabyte pages are shared by all page tables
+        if Entry::from(l1e).attribute().u_readable() {
+          release(l1e.to_pa());
+          directory.set_entry(l1x, Riscv64PageTableEntry(0));
+        }
         continue;
       }
       for l2x in 0..(PAGE_SIZE / MACHINE_SIZE) {
@@ -252,28 +360,26 @@ impl PageTableTrait for Riscv64PageTable {
         if !l2e.valid() {
           continue;
         }
+        if l2e.leaf() {
+          release(l2e.to_pa());
+          continue;
+        }
         for l3x in 0..(PAGE_SIZE / MACHINE_SIZE) {
           let l3e = l2e.entry(l3x);
           if !l3e.valid() {
             continue;
           }
-          let pa = l3e.to_pa();
-          if crate::mm::config::paged_range().contains(&pa) {
-            let frame = PageFrame::new(pa);
-            crate::mm::page_pool::decrease_rc(frame);
+          // Note: read only windows onto the tables hold no reference, see `map`
+          let va = (l1x << PAGE_TABLE_L1_SHIFT) | (l2x << PAGE_TABLE_L2_SHIFT) | (l3x << PAGE_TABLE_L3_SHIFT);
+          if va >= CONFIG_READ_ONLY_LEVEL_1_PAGE_TABLE_BTM {
+            continue;
           }
+          release(l3e.to_pa());
         }
-        let pa = l2e.to_pa();
-        if crate::mm::config::paged_range().contains(&pa) {
-          let frame = PageFrame::new(pa);
-          crate::mm::page_pool::decrease_rc(frame);
-        }
-      }
-      let pa = l1e.to_pa();
-      if crate::mm::config::paged_range().contains(&pa) {
-        let frame = PageFrame::new(pa);
-        crate::mm::page_pool::decrease_rc(frame);
+        release(l2e.to_pa());
       }
+      release(l1e.to_pa());
+      directory.set_entry(l1x, Riscv64PageTableEntry(0));
     }
   }
 
@@ -288,6 +394,7 @@ impl PageTableTrait for Riscv64PageTable {
   }
 
   fn set_user_page_table(pt: PageTable, asid: AddressSpaceId) {
+    pt.map_kernel_stack_window();
     SATP.write(SATP::MODE::Sv39 + SATP::ASID.val(asid as u64) + SATP::PPN.val((pt.directory().pa() >> PAGE_SHIFT) as u64));
     riscv::barrier::sfence_vma_all();
   }
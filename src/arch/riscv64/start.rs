@@ -1,5 +1,16 @@
 global_asm!(include_str!("start.S"));
 
+pub fn device_tree() -> Option<usize> {
+  extern "C" {
+    // Note: saved from a1 in `_start`, see start.S
+    static BOOT_DEVICE_TREE: usize;
+  }
+  match unsafe { BOOT_DEVICE_TREE } {
+    0 => { None }
+    pa => { Some(pa) }
+  }
+}
+
 // Workaround for abort symbol not found
 #[no_mangle]
 pub extern "C" fn abort() {
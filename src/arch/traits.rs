@@ -1,5 +1,6 @@
 use crate::{
   arch::ContextFrame,
+  arch::FaultSyndrome,
 };
 use crate::lib::thread::Thread;
 
@@ -20,7 +21,27 @@ pub trait ArchTrait {
   fn wait_for_event();
   fn nop();
   fn fault_address() -> usize;
+  fn fault_syndrome() -> FaultSyndrome;
   fn core_id() -> usize;
+  fn frame_pointer() -> usize;
+  // Note: make instruction fetch observe code written through `kva` (e.g. breakpoints)
+  fn sync_instruction_cache(kva: usize);
+  // Note: mask irq on this core, return previous state for `interrupt_restore`
+  fn interrupt_save_disable() -> usize;
+  fn interrupt_restore(state: usize);
+  // Note: an interrupt is waiting while masked, see `scheduler::preempt_point`
+  fn interrupt_pending() -> bool;
+  // Note: save callee-saved registers on the current kernel stack, its pointer to `from_sp`
+  //       then resume the kernel stack at `to_sp`, returns once switched back to
+  fn switch_context(from_sp: *mut usize, to_sp: usize);
+  // Note: a trap frame is stored right below `top`, the first switch to the returned
+  //       stack pointer goes through `thread_start` and pops that frame
+  fn kernel_stack_init(top: usize) -> usize;
+  // Note: allow kernel loads and stores through user mappings in between
+  fn user_access_begin();
+  fn user_access_end();
+  // Note: physical address of the flattened device tree passed by firmware at boot
+  fn device_tree() -> Option<usize>;
 }
 
 pub trait CoreTrait {
@@ -38,13 +59,26 @@ pub trait ContextFrameTrait {
   fn new(pc: usize, sp: usize, arg: usize, privileged: bool) -> Self;
 
   fn syscall_argument(&self, i: usize) -> usize;
+  fn set_syscall_argument(&mut self, i: usize, v: usize);
   fn syscall_number(&self) -> usize;
   fn set_syscall_return_value(&mut self, v: usize);
   fn exception_pc(&self) -> usize;
   fn set_exception_pc(&mut self, pc: usize);
   fn stack_pointer(&self) -> usize;
   fn set_stack_pointer(&mut self, sp: usize);
+  fn frame_pointer(&self) -> usize;
+  fn link_register(&self) -> usize;
   fn set_argument(&mut self, arg: usize);
+  // Note: restore a frame provided by user space (e.g. `signal_return`)
+  //       privileged state must not be taken from `saved`
+  fn restore_from_user(&mut self, saved: &Self);
+  // Note: register file in gdb remote protocol order, (value, size in bytes)
+  //       `None` beyond the last register
+  fn gdb_register(&self, i: usize) -> Option<(u64, usize)>;
+  fn set_gdb_register(&mut self, i: usize, v: u64) -> bool;
+  // Note: trap after one instruction when returning to this context
+  //       `false` if the arch has no hardware single step
+  fn set_single_step(&mut self, step: bool) -> bool;
 }
 
 pub trait ArchPageTableEntryTrait {
@@ -0,0 +1,407 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Once;
+
+use crate::arch::{Address, Arch, ArchTrait};
+use crate::board::{BOARD_CORE_NUMBER, BOARD_PHYSICAL_ADDRESS_LIMIT};
+use crate::config::CONFIG_DEVICE_TREE_SIZE;
+use crate::lib::fdt::{self, DeviceTree, Node};
+
+// Note: runtime board layout, from the device tree passed by firmware
+//       or from the `BOARD_*` constants and `board::memory` (firmware on raspi3) if there is none
+//       drivers look up their registers here and keep built-in addresses otherwise
+
+#[derive(Debug)]
+pub struct Device {
+  pub name: String,
+  pub compatible: Vec<String>,
+  // Note: cpu physical addresses, translated through `ranges` of parent buses
+  pub reg: Vec<Range<usize>>,
+  // Note: raw cells of `interrupts`, meaning depends on the interrupt controller
+  pub interrupts: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct Description {
+  pub device_tree: bool,
+  pub memory: Vec<Range<usize>>,
+  pub reserved: Vec<Range<usize>>,
+  pub cores: usize,
+  pub timebase_frequency: Option<u64>,
+  pub devices: Vec<Device>,
+  // Note: node name of the console from `/chosen/stdout-path`, aliases resolved
+  pub stdout: Option<String>,
+}
+
+impl Description {
+  fn built_in() -> Self {
+    let mut memory = Vec::new();
+    memory.push(crate::board::memory());
+    Description {
+      device_tree: false,
+      memory,
+      reserved: Vec::new(),
+      cores: BOARD_CORE_NUMBER,
+      timebase_frequency: None,
+      devices: Vec::new(),
+      stdout: None,
+    }
+  }
+
+  // Note: first enabled device matching any of `compatible`
+  pub fn device(&self, compatible: &[&str]) -> Option<&Device> {
+    self.devices.iter().find(|d| d.compatible.iter().any(|c| compatible.contains(&c.as_str())))
+  }
+
+  // Note: in device tree order, e.g. every `virtio,mmio` slot
+  pub fn devices<'a>(&'a self, compatible: &'a [&str]) -> impl Iterator<Item=&'a Device> {
+    self.devices.iter().filter(move |d| d.compatible.iter().any(|c| compatible.contains(&c.as_str())))
+  }
+
+  pub fn stdout(&self) -> Option<&Device> {
+    let name = self.stdout.as_ref()?;
+    self.devices.iter().find(|d| &d.name == name)
+  }
+}
+
+impl Device {
+  pub fn base(&self) -> Option<usize> {
+    self.reg.first().map(|r| r.start)
+  }
+}
+
+// Note: a bus level while walking the tree
+struct Bus {
+  address_cells: usize,
+  size_cells: usize,
+  // Note: (child address, parent address, length)
+  ranges: Vec<(u64, u64, u64)>,
+}
+
+impl Bus {
+  fn new(node: &Node, parent_address_cells: usize) -> Self {
+    let address_cells = node.address_cells();
+    let size_cells = node.size_cells();
+    let mut ranges = Vec::new();
+    let entry = (address_cells + parent_address_cells + size_cells) * 4;
+    if entry != 0 {
+      for value in node.property("ranges").unwrap_or(&[]).chunks_exact(entry) {
+        let (child, rest) = fdt::read_cells(value, address_cells).unwrap_or((0, &[]));
+        let (parent, rest) = fdt::read_cells(rest, parent_address_cells).unwrap_or((0, &[]));
+        let (length, _) = fdt::read_cells(rest, size_cells).unwrap_or((0, &[]));
+        ranges.push((child, parent, length));
+      }
+    }
+    Bus { address_cells, size_cells, ranges }
+  }
+}
+
+// Note: `buses` from the root down to the parent of the device
+//       empty or missing `ranges` is taken as identity
+fn translate(buses: &[Bus], address: u64) -> u64 {
+  let mut address = address;
+  for bus in buses.iter().rev() {
+    if let Some((child, parent, _)) = bus.ranges.iter().find(|(c, _, l)| *c <= address && address - *c < *l) {
+      address = address - child + parent;
+    }
+  }
+  address
+}
+
+fn reg(node: &Node, buses: &[Bus]) -> Vec<Range<usize>> {
+  let bus = buses.last().unwrap();
+  node.reg(bus.address_cells, bus.size_cells).iter()
+    .map(|(address, size)| {
+      let start = translate(buses, *address) as usize;
+      start..start + *size as usize
+    })
+    .collect()
+}
+
+fn walk(node: &Node, buses: &mut Vec<Bus>, devices: &mut Vec<Device>) {
+  for child in &node.children {
+    if !child.enabled() {
+      continue;
+    }
+    let compatible = child.strings("compatible");
+    if !compatible.is_empty() {
+      devices.push(Device {
+        name: child.name.to_string(),
+        compatible: compatible.iter().map(|s| s.to_string()).collect(),
+        reg: reg(child, buses),
+        interrupts: child.cells("interrupts"),
+      });
+    }
+    if !child.children.is_empty() {
+      let parent_address_cells = buses.last().unwrap().address_cells;
+      buses.push(Bus::new(child, parent_address_cells));
+      walk(child, buses, devices);
+      buses.pop();
+    }
+  }
+}
+
+fn build(tree: &DeviceTree) -> Description {
+  let root = &tree.root;
+  let mut d = Description {
+    device_tree: true,
+    memory: Vec::new(),
+    reserved: Vec::new(),
+    cores: 0,
+    timebase_frequency: None,
+    devices: Vec::new(),
+    stdout: None,
+  };
+  let mut buses = Vec::new();
+  buses.push(Bus::new(root, root.address_cells()));
+  for node in &root.children {
+    if node.string("device_type") == Some("memory") && node.enabled() {
+      d.memory.extend(reg(node, &buses));
+    }
+  }
+  if d.memory.is_empty() {
+    d.memory.push(crate::board::memory());
+  }
+  for (address, size) in &tree.reservations {
+    d.reserved.push(*address as usize..(*address + *size) as usize);
+  }
+  if let Some(reserved) = root.child("reserved-memory") {
+    buses.push(Bus::new(reserved, root.address_cells()));
+    for node in &reserved.children {
+      d.reserved.extend(reg(node, &buses));
+    }
+    buses.pop();
+  }
+  if let Some(cpus) = root.child("cpus") {
+    d.timebase_frequency = cpus.u64("timebase-frequency");
+    d.cores = cpus.children.iter()
+      .filter(|c| c.string("device_type") == Some("cpu") && c.enabled())
+      .count();
+  }
+  // Note: the generic timer node overrides `CNTFRQ_EL0`, for firmware leaving it unset
+  for node in &root.children {
+    if node.strings("compatible").iter().any(|c| ARM_TIMER_COMPATIBLE.contains(c)) && node.enabled() {
+      if let Some(frequency) = node.u32("clock-frequency") {
+        d.timebase_frequency = Some(frequency as u64);
+      }
+    }
+  }
+  walk(root, &mut buses, &mut d.devices);
+  d.stdout = stdout(root).map(|s| s.to_string());
+  d
+}
+
+// Note: "serial0:115200n8" or "/soc/serial@7e215040", options after ':' are ignored
+fn stdout<'a>(root: &Node<'a>) -> Option<&'a str> {
+  let chosen = root.child("chosen")?;
+  let path = chosen.string("stdout-path").or(chosen.string("linux,stdout-path"))?;
+  let path = path.split(':').next().unwrap_or(path);
+  let path = if path.starts_with('/') {
+    path
+  } else {
+    root.child("aliases")?.string(path)?
+  };
+  path.rsplit('/').next()
+}
+
+const ARM_TIMER_COMPATIBLE: &[&str] = &["arm,armv8-timer", "arm,armv7-timer"];
+
+static mut DEVICE_TREE: [u8; CONFIG_DEVICE_TREE_SIZE] = [0; CONFIG_DEVICE_TREE_SIZE];
+// Note: size of the saved blob, 0 if there is none
+static DEVICE_TREE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+static DESCRIPTION: Once<Description> = Once::new();
+
+// Note: firmware may place the blob anywhere in RAM, including where the heap goes
+//       must run before `mm::heap::init`, nothing is printed this early
+pub fn save() {
+  let pa = match Arch::device_tree() {
+    Some(pa) if pa < BOARD_PHYSICAL_ADDRESS_LIMIT => { pa }
+    _ => { return; }
+  };
+  let header = unsafe { core::slice::from_raw_parts(pa.pa2kva() as *const u8, fdt::FDT_HEADER_SIZE) };
+  if let Ok(size) = fdt::total_size(header) {
+    if size <= CONFIG_DEVICE_TREE_SIZE {
+      unsafe {
+        core::ptr::copy_nonoverlapping(pa.pa2kva() as *const u8, DEVICE_TREE.as_mut_ptr(), size);
+      }
+      DEVICE_TREE_SIZE.store(size, Ordering::SeqCst);
+    }
+  }
+}
+
+// Note: parsed on first use, the heap must be ready
+pub fn get() -> &'static Description {
+  DESCRIPTION.call_once(|| {
+    let size = DEVICE_TREE_SIZE.load(Ordering::SeqCst);
+    let blob = unsafe { &DEVICE_TREE[..size] };
+    match fdt::parse(blob) {
+      Ok(tree) => { build(&tree) }
+      Err(_) => { Description::built_in() }
+    }
+  })
+}
+
+pub fn report() {
+  let d = get();
+  if d.device_tree {
+    info!("device tree: {} cores, {} devices", d.cores, d.devices.len());
+  } else {
+    info!("no device tree, built-in board layout");
+  }
+  for r in &d.memory {
+    info!("memory   {:#x}..{:#x}", r.start, r.end);
+  }
+  for r in &d.reserved {
+    info!("reserved {:#x}..{:#x}", r.start, r.end);
+  }
+  if d.cores > BOARD_CORE_NUMBER {
+    warn!("{} cores, only {} supported", d.cores, BOARD_CORE_NUMBER);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::lib::fdt::builder::Builder;
+
+  use super::*;
+
+  // Note: trimmed down from the raspi3 tree, a bus with `ranges`
+  fn rpi3() -> Vec<u8> {
+    Builder::new()
+      .reserve(0x0, 0x1000)
+      .begin("")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[1])
+      .begin("memory")
+      .strings("device_type", &["memory"])
+      .cells("reg", &[0x0, 0x3b40_0000])
+      .end()
+      .begin("cpus")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[0])
+      .begin("cpu@0").strings("device_type", &["cpu"]).cells("reg", &[0]).end()
+      .begin("cpu@1").strings("device_type", &["cpu"]).cells("reg", &[1]).end()
+      .begin("cpu@2").strings("device_type", &["cpu"]).cells("reg", &[2]).strings("status", &["disabled"]).end()
+      .begin("cpu-map").end()
+      .end()
+      .begin("chosen").strings("stdout-path", &["serial0:115200n8"]).end()
+      .begin("aliases").strings("serial0", &["/soc/serial@7e201000"]).end()
+      .begin("timer")
+      .strings("compatible", &["arm,armv7-timer"])
+      .cells("clock-frequency", &[19_200_000])
+      .end()
+      .begin("soc")
+      .strings("compatible", &["simple-bus"])
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[1])
+      .cells("ranges", &[0x7e00_0000, 0x3f00_0000, 0x100_0000, 0x4000_0000, 0x4000_0000, 0x1000])
+      .begin("serial@7e201000")
+      .strings("compatible", &["brcm,bcm2835-pl011", "arm,pl011", "arm,primecell"])
+      .cells("reg", &[0x7e20_1000, 0x200])
+      .cells("interrupts", &[2, 25])
+      .end()
+      .begin("serial@7e215040")
+      .strings("compatible", &["brcm,bcm2835-aux-uart"])
+      .cells("reg", &[0x7e21_5040, 0x40])
+      .strings("status", &["disabled"])
+      .end()
+      .begin("local_intc@40000000")
+      .strings("compatible", &["brcm,bcm2836-l1-intc"])
+      .cells("reg", &[0x4000_0000, 0x100])
+      .end()
+      .end()
+      .end()
+      .build()
+  }
+
+  #[test]
+  fn rpi3_layout() {
+    let blob = rpi3();
+    let d = build(&fdt::parse(&blob).unwrap());
+    assert!(d.device_tree);
+    assert_eq!(d.memory, vec![0x0..0x3b40_0000]);
+    assert_eq!(d.reserved, vec![0x0..0x1000]);
+    assert_eq!(d.cores, 2);
+    assert_eq!(d.timebase_frequency, Some(19_200_000));
+    let pl011 = d.device(&["arm,pl011"]).unwrap();
+    assert_eq!(pl011.name, "serial@7e201000");
+    assert_eq!(pl011.reg, vec![0x3f20_1000..0x3f20_1200]);
+    assert_eq!(pl011.interrupts, vec![2, 25]);
+    assert_eq!(d.stdout().unwrap().name, "serial@7e201000");
+    assert!(d.device(&["brcm,bcm2835-aux-uart"]).is_none());
+    assert_eq!(d.device(&["brcm,bcm2836-l1-intc"]).unwrap().base(), Some(0x4000_0000));
+    assert_eq!(d.device(&["simple-bus"]).unwrap().reg, vec![]);
+  }
+
+  #[test]
+  fn riscv_virt_layout() {
+    let blob = Builder::new()
+      .begin("")
+      .cells("#address-cells", &[2])
+      .cells("#size-cells", &[2])
+      .begin("reserved-memory")
+      .cells("#address-cells", &[2])
+      .cells("#size-cells", &[2])
+      .property("ranges", &[])
+      .begin("mmode_resources@80000000").cells("reg", &[0, 0x8000_0000, 0, 0x4_0000]).end()
+      .end()
+      .begin("memory@80000000")
+      .strings("device_type", &["memory"])
+      .cells("reg", &[0, 0x8000_0000, 0, 0x8000_0000])
+      .end()
+      .begin("cpus")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[0])
+      .cells("timebase-frequency", &[10_000_000])
+      .begin("cpu@0").strings("device_type", &["cpu"]).cells("reg", &[0]).end()
+      .end()
+      .begin("chosen").strings("stdout-path", &["/soc/uart@10000000"]).end()
+      .begin("soc")
+      .strings("compatible", &["simple-bus"])
+      .cells("#address-cells", &[2])
+      .cells("#size-cells", &[2])
+      .property("ranges", &[])
+      .begin("uart@10000000")
+      .strings("compatible", &["ns16550a"])
+      .cells("reg", &[0, 0x1000_0000, 0, 0x100])
+      .cells("interrupts", &[10])
+      .end()
+      .begin("virtio_mmio@10002000")
+      .strings("compatible", &["virtio,mmio"])
+      .cells("reg", &[0, 0x1000_2000, 0, 0x1000])
+      .cells("interrupts", &[2])
+      .end()
+      .begin("virtio_mmio@10001000")
+      .strings("compatible", &["virtio,mmio"])
+      .cells("reg", &[0, 0x1000_1000, 0, 0x1000])
+      .cells("interrupts", &[1])
+      .end()
+      .end()
+      .end()
+      .build();
+    let d = build(&fdt::parse(&blob).unwrap());
+    assert_eq!(d.memory, vec![0x8000_0000..0x1_0000_0000]);
+    assert_eq!(d.reserved, vec![0x8000_0000..0x8004_0000]);
+    assert_eq!(d.cores, 1);
+    assert_eq!(d.timebase_frequency, Some(10_000_000));
+    let uart = d.device(&["ns16550a"]).unwrap();
+    assert_eq!(uart.base(), Some(0x1000_0000));
+    assert_eq!(uart.interrupts, vec![10]);
+    assert_eq!(d.stdout().unwrap().name, "uart@10000000");
+    let virtio: Vec<_> = d.devices(&["virtio,mmio"]).filter_map(|d| d.base()).collect();
+    assert_eq!(virtio, vec![0x1000_2000, 0x1000_1000]);
+  }
+
+  #[test]
+  fn built_in_without_device_tree() {
+    let d = get();
+    assert!(!d.device_tree);
+    assert_eq!(d.memory, vec![crate::board::BOARD_NORMAL_MEMORY_RANGE]);
+    assert_eq!(d.cores, BOARD_CORE_NUMBER);
+    assert!(d.device(&["ns16550a"]).is_none());
+  }
+}
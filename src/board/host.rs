@@ -0,0 +1,18 @@
+use core::ops::Range;
+
+// Note: `cargo test` on the host, physical memory is emulated by `arch::mock`
+pub const BOARD_CORE_NUMBER: usize = 1;
+pub const BOARD_PHYSICAL_ADDRESS_LIMIT: usize = 0x1000_0000;
+pub const BOARD_NORMAL_MEMORY_RANGE: Range<usize> = 0x0000_0000..0x1000_0000;
+#[allow(dead_code)]
+pub const BOARD_DEVICE_MEMORY_RANGE: Range<usize> = 0x1000_0000..0x1000_0000;
+
+pub fn memory() -> Range<usize> {
+  BOARD_NORMAL_MEMORY_RANGE
+}
+
+#[allow(dead_code)]
+pub fn init() {}
+
+#[allow(dead_code)]
+pub fn init_per_core() {}
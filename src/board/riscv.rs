@@ -4,16 +4,23 @@ use crate::arch::ArchTrait;
 
 #[allow(dead_code)]
 pub const BOARD_CORE_NUMBER: usize = 1;
+// Note: end of the kernel linear map, see start.S
 #[allow(dead_code)]
-pub const BOARD_PHYSICAL_ADDRESS_LIMIT: usize = 0xc000_0000;
+pub const BOARD_PHYSICAL_ADDRESS_LIMIT: usize = 0x1_0000_0000;
+// Note: `-m 1024`, used without a device tree
 #[allow(dead_code)]
 pub const BOARD_NORMAL_MEMORY_RANGE: Range<usize> = 0x8000_0000..0xc000_0000;
 #[allow(dead_code)]
 pub const BOARD_DEVICE_MEMORY_RANGE: Range<usize> = 0x0000_0000..0x8000_0000;
 
+pub fn memory() -> Range<usize> {
+  BOARD_NORMAL_MEMORY_RANGE
+}
+
 pub fn init() {
   crate::driver::uart::init();
   crate::driver::plic::init();
+  crate::driver::rtc::init();
 }
 
 pub fn init_per_core() {
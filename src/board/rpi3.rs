@@ -7,8 +7,18 @@ pub const BOARD_PHYSICAL_ADDRESS_LIMIT: usize = 0x4000_0000;
 pub const BOARD_NORMAL_MEMORY_RANGE: Range<usize> = 0x0000_0000..0x3f00_0000;
 pub const BOARD_DEVICE_MEMORY_RANGE: Range<usize> = 0x3f00_0000..0x4000_0000;
 
+// Note: ARM part of the memory split with the GPU, from the firmware, used without a device tree
+pub fn memory() -> Range<usize> {
+  match crate::driver::mailbox::arm_memory() {
+    Ok(r) if r.start < r.end && r.end <= BOARD_DEVICE_MEMORY_RANGE.start => { r }
+    _ => { BOARD_NORMAL_MEMORY_RANGE }
+  }
+}
+
 pub fn init() {
+  crate::driver::mailbox::init();
   crate::driver::uart::init();
+  crate::driver::mailbox::report();
 }
 
 pub fn init_per_core() {
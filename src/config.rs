@@ -1,18 +1,80 @@
 // user space read only page table map
-#[cfg(target_arch = "aarch64")]
+#[cfg(any(target_arch = "aarch64", test))]
 pub const CONFIG_RECURSIVE_PAGE_TABLE_BTM: usize = 0x3f_c000_0000;
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub const CONFIG_RECURSIVE_PAGE_TABLE_BTM: usize = 0; // Note: not used
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub const CONFIG_READ_ONLY_LEVEL_3_PAGE_TABLE_BTM: usize = 0x3f_c000_0000;
 // 1 GB
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub const CONFIG_READ_ONLY_LEVEL_2_PAGE_TABLE_BTM: usize = 0x3f_c000_0000 - 0x20_0000;
 // 2 MB
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub const CONFIG_READ_ONLY_LEVEL_1_PAGE_TABLE_BTM: usize = 0x3f_c000_0000 - 0x20_0000 - 0x1000; // 4 KB
 
 // user space map
 pub const CONFIG_USER_LIMIT: usize = 0x3f_a000_0000;
 pub const CONFIG_USER_STACK_TOP: usize = 0x3f_8000_0000;
+// Note: the stack grows on fault down to `CONFIG_USER_STACK_TOP - CONFIG_USER_STACK_LIMIT`
+//       with an unmapped guard of `CONFIG_USER_STACK_GUARD` below it
+// 8 MB
+pub const CONFIG_USER_STACK_LIMIT: usize = 0x80_0000;
+// 64 KB
+pub const CONFIG_USER_STACK_GUARD: usize = 0x1_0000;
+
+// kernel stack
+// Note: one slot per thread in a kernel window, the lower half of a slot is an unmapped guard
+#[cfg(all(target_arch = "aarch64", not(test)))]
+pub const CONFIG_KERNEL_STACK_BTM: usize = 0xffff_ffc0_0000_0000;
+#[cfg(all(target_arch = "riscv64", not(test)))]
+pub const CONFIG_KERNEL_STACK_BTM: usize = 0xffff_fffe_c000_0000;
+// 1 GB
+pub const CONFIG_KERNEL_STACK_WINDOW_SIZE: usize = 0x4000_0000;
+// Note: power of two, allocated as one contiguous block
+//       trap entry in `exception.S` tests bit 14 of sp (16 KB stack), keep both in sync
+pub const CONFIG_KERNEL_STACK_PAGES: usize = 4;
+
+// scheduler
+// Note: nanoseconds, the timer is one shot and armed on every pick
+pub const CONFIG_TIME_SLICE: u64 = 10_000_000;
+// Note: an idle core still wakes up this often to poll the console (mini UART / NS16550 have no rx interrupt)
+pub const CONFIG_IDLE_POLL_INTERVAL: u64 = 100_000_000;
+
+// console
+// Note: raspi3 only, `None` follows `/chosen/stdout-path` of the device tree, mini UART without one
+//       the other UART carries the gdb stub
+#[cfg(all(target_arch = "aarch64", not(test)))]
+pub const CONFIG_CONSOLE: Option<crate::driver::uart::Port> = None;
+
+// framebuffer
+// Note: mode asked of the firmware (raspi3) or set up for ramfb (riscv64), 32 bits per pixel
+pub const CONFIG_FRAMEBUFFER_WIDTH: u32 = 640;
+pub const CONFIG_FRAMEBUFFER_HEIGHT: u32 = 480;
+// Note: kernel output is also drawn on the framebuffer, until a process maps it
+pub const CONFIG_FRAMEBUFFER_CONSOLE: bool = true;
+
+// network
+// Note: static setup of QEMU user mode networking (`-netdev user`), no DHCP
+pub const CONFIG_NET_ADDRESS: [u8; 4] = [10, 0, 2, 15];
+pub const CONFIG_NET_NETMASK: [u8; 4] = [255, 255, 255, 0];
+pub const CONFIG_NET_GATEWAY: [u8; 4] = [10, 0, 2, 2];
+
+// ipc
+pub const CONFIG_IPC_QUEUE_LENGTH: usize = 16;
+
+// log
+pub const CONFIG_LOG_BUFFER_SIZE: usize = 16 * 1024;
+pub const CONFIG_LOG_LEVEL: crate::lib::log::Level = crate::lib::log::Level::Info;
+// Note: per module level, matched by prefix of the module path without crate name
+//       e.g. ("lib::scheduler", Level::Trace) to see every context switch
+pub const CONFIG_LOG_FILTER: &[(&str, crate::lib::log::Level)] = &[];
+
+// trace
+pub const CONFIG_TRACE_BUFFER_LENGTH: usize = 1024;
+// Note: enabled events at boot, see `TRACE_*` in `lib/trace.rs`
+pub const CONFIG_TRACE_MASK: usize = 0;
+
+// device tree
+// Note: the blob from firmware is copied to the kernel image before the heap is set up
+pub const CONFIG_DEVICE_TREE_SIZE: usize = 64 * 1024;
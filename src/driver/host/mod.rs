@@ -0,0 +1,7 @@
+pub mod uart;
+pub mod timer;
+pub mod rtc;
+pub mod framebuffer;
+pub mod net;
+pub mod port;
+pub mod rng;
@@ -0,0 +1,4 @@
+// Note: nanoseconds since the Unix epoch
+pub fn read() -> Option<u64> {
+  std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_nanos() as u64)
+}
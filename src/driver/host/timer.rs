@@ -0,0 +1,14 @@
+lazy_static! {
+  static ref BOOT: std::time::Instant = std::time::Instant::now();
+}
+
+pub fn set_deadline(_count: Option<u64>) {}
+
+// Note: nanoseconds since the first read
+pub fn counter() -> u64 {
+  BOOT.elapsed().as_nanos() as u64
+}
+
+pub fn frequency() -> u64 {
+  1_000_000_000
+}
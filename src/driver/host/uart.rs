@@ -0,0 +1,14 @@
+// Note: console of `cargo test`, captured by the test harness
+pub fn putc(c: u8) {
+  std::print!("{}", c as char);
+}
+
+pub fn putc_raw(c: u8) {
+  putc(c);
+}
+
+pub fn getc() -> Option<u8> {
+  None
+}
+
+pub fn interrupt() {}
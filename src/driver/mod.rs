@@ -1,12 +1,17 @@
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 pub use self::riscv::*;
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(test)))]
 pub use self::rpi3::*;
+#[cfg(test)]
+pub use self::host::*;
 
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(test)))]
 mod rpi3;
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(test)))]
 mod riscv;
 
+#[cfg(test)]
+mod host;
+
 pub mod mmio;
@@ -0,0 +1,53 @@
+use alloc::vec::Vec;
+
+use crate::arch::{Address, PAGE_SIZE};
+use crate::config::{CONFIG_FRAMEBUFFER_HEIGHT, CONFIG_FRAMEBUFFER_WIDTH};
+use crate::driver::fw_cfg;
+use crate::lib::framebuffer::Info;
+use crate::lib::round_up;
+
+// Note: QEMU `-device ramfb`, guest memory scanned out by QEMU, set up through fw_cfg
+//       taken from the kernel heap (outside of the page pool) and never freed
+const RAMFB_FILE: &str = "etc/ramfb";
+// Note: "XR24", DRM fourcc of 32 bits 0x00RRGGBB
+const DRM_FORMAT_XRGB8888: u32 = 0x3432_5258;
+
+pub fn init() -> Option<Info> {
+  if fw_cfg::init().is_err() {
+    return None;
+  }
+  // Note: no `-device ramfb`
+  let key = fw_cfg::find(RAMFB_FILE).ok()?;
+  let width = CONFIG_FRAMEBUFFER_WIDTH;
+  let height = CONFIG_FRAMEBUFFER_HEIGHT;
+  let pitch = width * 4;
+  let size = round_up((pitch * height) as usize, PAGE_SIZE);
+  let layout = core::alloc::Layout::from_size_align(size, PAGE_SIZE).ok()?;
+  let kva = unsafe { alloc::alloc::alloc_zeroed(layout) } as usize;
+  if kva == 0 {
+    warn!("framebuffer: out of heap");
+    return None;
+  }
+  let pa = kva.kva2pa();
+  // Note: address, fourcc, flags, width, height, stride, packed big endian
+  let mut config = Vec::new();
+  config.extend_from_slice(&(pa as u64).to_be_bytes());
+  for v in [DRM_FORMAT_XRGB8888, 0, width, height, pitch].iter() {
+    config.extend_from_slice(&v.to_be_bytes());
+  }
+  if let Err(e) = fw_cfg::write(key, &config) {
+    warn!("framebuffer: ramfb {:?}", e);
+    unsafe { alloc::alloc::dealloc(kva as *mut u8, layout); }
+    return None;
+  }
+  Some(Info {
+    pa,
+    size,
+    width,
+    height,
+    pitch,
+    depth: 32,
+  })
+}
+
+pub fn flush(_kva: usize, _len: usize) {}
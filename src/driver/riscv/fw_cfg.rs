@@ -0,0 +1,127 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::Address;
+use crate::driver::mmio::*;
+
+// Note: QEMU firmware configuration device, MMIO flavour of QEMU virt
+//       items are read byte by byte from the data register, files are written by DMA
+//       selector, DMA address and DMA descriptors are big endian
+const FW_CFG_DEFAULT_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x1010_0000;
+
+const FW_CFG_COMPATIBLE: &[&str] = &["qemu,fw-cfg-mmio"];
+
+static FW_CFG_BASE_ADDR: AtomicUsize = AtomicUsize::new(FW_CFG_DEFAULT_BASE_ADDR);
+
+const FW_CFG_DATA: usize = 0x00;
+const FW_CFG_SELECTOR: usize = 0x08;
+// Note: the transfer starts once the low half is written
+const FW_CFG_DMA_HIGH: usize = 0x10;
+const FW_CFG_DMA_LOW: usize = 0x14;
+
+const FW_CFG_SIGNATURE: u16 = 0x0000;
+const FW_CFG_ID: u16 = 0x0001;
+const FW_CFG_FILE_DIR: u16 = 0x0019;
+
+const FW_CFG_ID_DMA: u32 = 1 << 1;
+
+const FW_CFG_DMA_CTL_ERROR: u32 = 1 << 0;
+const FW_CFG_DMA_CTL_SELECT: u32 = 1 << 3;
+const FW_CFG_DMA_CTL_WRITE: u32 = 1 << 4;
+
+// Note: size, select, reserved, name
+const FILE_ENTRY_SIZE: usize = 64;
+const FILE_NAME_OFFSET: usize = 8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  NotPresentError,
+  NoDmaError,
+  FileNotFoundError,
+  DmaError,
+}
+
+#[repr(C, align(16))]
+struct DmaAccess {
+  control: u32,
+  length: u32,
+  address: u64,
+}
+
+fn register(offset: usize) -> usize {
+  FW_CFG_BASE_ADDR.load(Ordering::Relaxed) + offset
+}
+
+fn select(key: u16) {
+  unsafe { write_half(register(FW_CFG_SELECTOR), key.to_be()); }
+}
+
+fn read(buf: &mut [u8]) {
+  for b in buf.iter_mut() {
+    *b = unsafe { read_byte(register(FW_CFG_DATA)) };
+  }
+}
+
+pub fn init() -> Result<(), Error> {
+  if let Some(pa) = crate::board::description::get().device(FW_CFG_COMPATIBLE).and_then(|d| d.base()) {
+    FW_CFG_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+  let mut signature = [0u8; 4];
+  select(FW_CFG_SIGNATURE);
+  read(&mut signature);
+  if signature != *b"QEMU" {
+    return Err(Error::NotPresentError);
+  }
+  Ok(())
+}
+
+// Note: selector of a file, e.g. "etc/ramfb"
+pub fn find(name: &str) -> Result<u16, Error> {
+  let mut count = [0u8; 4];
+  select(FW_CFG_FILE_DIR);
+  read(&mut count);
+  for _ in 0..u32::from_be_bytes(count) {
+    let mut entry = [0u8; FILE_ENTRY_SIZE];
+    read(&mut entry);
+    let file = &entry[FILE_NAME_OFFSET..];
+    let len = file.iter().position(|b| *b == 0).unwrap_or(file.len());
+    if &file[..len] == name.as_bytes() {
+      return Ok(u16::from_be_bytes([entry[4], entry[5]]));
+    }
+  }
+  Err(Error::FileNotFoundError)
+}
+
+// Note: descriptor and data are copied to the heap, the device takes physical addresses
+//       and kernel stacks are outside of the linear map
+pub fn write(key: u16, data: &[u8]) -> Result<(), Error> {
+  let mut id = [0u8; 4];
+  select(FW_CFG_ID);
+  read(&mut id);
+  if u32::from_le_bytes(id) & FW_CFG_ID_DMA == 0 {
+    return Err(Error::NoDmaError);
+  }
+  let data: Vec<u8> = data.to_vec();
+  let access = Box::new(DmaAccess {
+    control: (((key as u32) << 16) | FW_CFG_DMA_CTL_SELECT | FW_CFG_DMA_CTL_WRITE).to_be(),
+    length: (data.len() as u32).to_be(),
+    address: ((data.as_ptr() as usize).kva2pa() as u64).to_be(),
+  });
+  let pa = (&*access as *const DmaAccess as usize).kva2pa() as u64;
+  unsafe {
+    llvm_asm!("fence iorw, iorw" :::: "volatile");
+    write_word(register(FW_CFG_DMA_HIGH), ((pa >> 32) as u32).to_be());
+    write_word(register(FW_CFG_DMA_LOW), (pa as u32).to_be());
+  }
+  // Note: the device clears everything but the error bit when done
+  loop {
+    let control = u32::from_be(unsafe { core::intrinsics::volatile_load(&access.control) });
+    if control & FW_CFG_DMA_CTL_ERROR != 0 {
+      return Err(Error::DmaError);
+    }
+    if control == 0 {
+      return Ok(());
+    }
+  }
+}
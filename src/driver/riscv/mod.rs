@@ -1,4 +1,12 @@
 pub mod timer;
+pub mod rtc;
 pub mod uart;
+pub mod qemu;
+pub mod fw_cfg;
+pub mod framebuffer;
+pub mod virtio;
+pub mod net;
+pub mod port;
+pub mod rng;
 #[allow(dead_code)]
 pub mod plic;
\ No newline at end of file
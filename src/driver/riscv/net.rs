@@ -0,0 +1,83 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::driver::virtio::{self, Channel, Device, DeviceType};
+use crate::lib::net::MacAddress;
+
+// Note: virtio-net on the shared virtio transport, QEMU `-device virtio-net-device`
+//       no offloads, one frame per receive buffer (no VIRTIO_NET_F_MRG_RXBUF)
+//       every frame is preceded by a `virtio_net_hdr`, left zeroed on transmit
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+// Note: with VIRTIO_F_VERSION_1 the header always has `num_buffers`
+const HEADER_SIZE: usize = 12;
+const FRAME_SIZE: usize = 1514;
+const BUFFER_SIZE: usize = HEADER_SIZE + FRAME_SIZE;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+const QUEUE_SIZE: u16 = 32;
+
+// Note: locally administered, if the device has no address of its own
+const DEFAULT_MAC: MacAddress = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+struct Net {
+  device: Device,
+  channel: Channel,
+}
+
+static NET: Mutex<Option<Net>> = Mutex::new(None);
+
+pub fn init() -> Option<MacAddress> {
+  let device = virtio::probe(DeviceType::Net).ok()?;
+  let r = device.negotiate(VIRTIO_NET_F_MAC).and_then(|features| {
+    let channel = Channel::new(&device, RECEIVE_QUEUE, TRANSMIT_QUEUE, QUEUE_SIZE, BUFFER_SIZE)?;
+    Ok((features, channel))
+  });
+  let (features, channel) = match r {
+    Ok(r) => { r }
+    Err(e) => {
+      warn!("net: virtio {:?}", e);
+      return None;
+    }
+  };
+  let mut mac = DEFAULT_MAC;
+  if features & VIRTIO_NET_F_MAC != 0 {
+    device.config(0, &mut mac);
+  }
+  device.ready();
+  channel.start(&device);
+  *NET.lock() = Some(Net { device, channel });
+  Some(mac)
+}
+
+// Note: `false` if the frame is dropped, transmit queue full or no device
+pub fn send(frame: &[u8]) -> bool {
+  if frame.len() > FRAME_SIZE {
+    return false;
+  }
+  let mut lock = NET.lock();
+  let r = match lock.as_mut() {
+    None => { false }
+    Some(net) => {
+      let mut buffer = vec![0u8; HEADER_SIZE];
+      buffer.extend_from_slice(frame);
+      net.channel.send(&net.device, buffer)
+    }
+  };
+  drop(lock);
+  r
+}
+
+// Note: next received frame without the header, its buffer goes back to the device
+pub fn receive() -> Option<Vec<u8>> {
+  let mut lock = NET.lock();
+  let r = lock.as_mut().and_then(|net| {
+    let mut data = net.channel.receive(&net.device)?;
+    Some(data.split_off(core::cmp::min(HEADER_SIZE, data.len())))
+  });
+  drop(lock);
+  r
+}
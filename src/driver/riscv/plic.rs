@@ -1,36 +1,67 @@
-use crate::arch::ArchTrait;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::{Address, ArchTrait};
 use crate::driver::mmio::*;
 
 // platform level interrupt controller
-const PLIC_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x0c00_0000;
-const PLIC_PENDING_ADDR: usize = PLIC_BASE_ADDR + 0x1000;
-const PLIC_MACHINE_ENABLE_ADDR: usize = PLIC_BASE_ADDR + 0x2000;
-const PLIC_MACHINE_PRIORITY_ADDR: usize = PLIC_BASE_ADDR + 0x200000;
-const PLIC_MACHINE_CLAIM_ADDR: usize = PLIC_BASE_ADDR + 0x200004;
+// Note: QEMU virt, replaced by the device tree one in `init`
+const PLIC_DEFAULT_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x0c00_0000;
+
+const PLIC_PENDING: usize = 0x1000;
+const PLIC_MACHINE_ENABLE: usize = 0x2000;
+const PLIC_MACHINE_PRIORITY: usize = 0x200000;
+const PLIC_MACHINE_CLAIM: usize = 0x200004;
 
-const PLIC_SUPERVISOR_ENABLE_ADDR: usize = PLIC_BASE_ADDR + 0x2080;
+const PLIC_SUPERVISOR_ENABLE: usize = 0x2080;
 // by 0x100
-const PLIC_SUPERVISOR_PRIORITY_ADDR: usize = PLIC_BASE_ADDR + 0x201000;
+const PLIC_SUPERVISOR_PRIORITY: usize = 0x201000;
 // by 0x2000
-const PLIC_SUPERVISOR_CLAIM_ADDR: usize = PLIC_BASE_ADDR + 0x201004;
+const PLIC_SUPERVISOR_CLAIM: usize = 0x201004;
 // by 0x2000
 
+const PLIC_COMPATIBLE: &[&str] = &["riscv,plic0", "sifive,plic-1.0.0"];
+
 const PLIC_IRQ_VIRTIO: usize = 1;
 const PLIC_IRQ_UART: usize = 10;
 
+static PLIC_BASE_ADDR: AtomicUsize = AtomicUsize::new(PLIC_DEFAULT_BASE_ADDR);
+
+fn base() -> usize {
+  PLIC_BASE_ADDR.load(Ordering::Relaxed)
+}
+
 pub fn init() {
+  let description = crate::board::description::get();
+  if let Some(pa) = description.device(PLIC_COMPATIBLE).and_then(|d| d.base()) {
+    PLIC_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+  let uart = description.device(super::uart::UART_COMPATIBLE)
+    .and_then(|d| d.interrupts.first())
+    .map_or(PLIC_IRQ_UART, |irq| *irq as usize);
+  let core_id = crate::arch::Arch::core_id();
+  let irqs = [PLIC_IRQ_VIRTIO, uart];
   unsafe {
-    write_word(PLIC_BASE_ADDR + PLIC_IRQ_UART * 4, 1);
-    write_word(PLIC_BASE_ADDR + PLIC_IRQ_VIRTIO * 4, 1);
-    let core_id = crate::arch::Arch::core_id();
-    write_word(PLIC_SUPERVISOR_ENABLE_ADDR + core_id * 0x100, ((1 << PLIC_IRQ_VIRTIO) | (1 << PLIC_IRQ_UART)) as u32);
-    write_word(PLIC_SUPERVISOR_PRIORITY_ADDR + core_id * 0x2000, 0);
+    // Note: nothing else is enabled in the words touched
+    for irq in irqs.iter() {
+      write_word(enable_word(core_id, *irq), 0);
+    }
+    for irq in irqs.iter() {
+      write_word(base() + irq * 4, 1);
+      let word = enable_word(core_id, *irq);
+      write_word(word, read_word(word) | (1 << (irq % 32)));
+    }
+    write_word(base() + PLIC_SUPERVISOR_PRIORITY + core_id * 0x2000, 0);
   }
 }
 
+// Note: one enable bit per source, 32 sources a word
+fn enable_word(core_id: usize, irq: usize) -> usize {
+  base() + PLIC_SUPERVISOR_ENABLE + core_id * 0x100 + (irq / 32) * 4
+}
+
 pub fn pending() -> usize {
   unsafe {
-    let mask = read_dword(PLIC_PENDING_ADDR);
+    let mask = read_dword(base() + PLIC_PENDING);
     mask as usize
   }
 }
@@ -38,13 +69,13 @@ pub fn pending() -> usize {
 pub fn claim() -> usize {
   unsafe {
     let core_id = crate::arch::Arch::core_id();
-    read_word(PLIC_MACHINE_CLAIM_ADDR + core_id * 0x2000) as usize
+    read_word(base() + PLIC_MACHINE_CLAIM + core_id * 0x2000) as usize
   }
 }
 
 pub fn clear(irq: usize) {
   unsafe {
     let core_id = crate::arch::Arch::core_id();
-    write_word(PLIC_MACHINE_CLAIM_ADDR + core_id * 0x2000, irq as u32);
+    write_word(base() + PLIC_MACHINE_CLAIM + core_id * 0x2000, irq as u32);
   }
 }
@@ -0,0 +1,252 @@
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::driver::virtio::{self, Channel, Device, DeviceType};
+use crate::lib::chardev::{self, CharDevice, Error};
+
+// Note: virtio-console on the shared virtio transport, QEMU `-device virtio-serial-device`
+//       every port is a character device, `vport0p<id>` and the name given by the host
+//       (`-device virtserialport,name=...`), or `virtio-console` without VIRTIO_CONSOLE_F_MULTIPORT
+//       the device is only looked at when a port is used
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+
+// Note: `max_nr_ports` in the device configuration
+const MAX_NR_PORTS_OFFSET: usize = 4;
+const PORT_LIMIT: usize = 4;
+
+const CONTROL_RECEIVE_QUEUE: u16 = 2;
+const QUEUE_SIZE: u16 = 16;
+const BUFFER_SIZE: usize = 512;
+
+// Note: `virtio_console_control`, id (u32), event (u16), value (u16), a port name may follow
+const CONTROL_SIZE: usize = 8;
+const DEVICE_READY: u16 = 0;
+const DEVICE_ADD: u16 = 1;
+const DEVICE_REMOVE: u16 = 2;
+const PORT_READY: u16 = 3;
+const CONSOLE_PORT: u16 = 4;
+const RESIZE: u16 = 5;
+const PORT_OPEN: u16 = 6;
+const PORT_NAME: u16 = 7;
+
+// Note: port 0 and the control channel come first, then a pair of queues per port
+fn receive_queue(id: usize) -> u16 {
+  if id == 0 { 0 } else { (2 + 2 * id) as u16 }
+}
+
+// Note: the transmit queue follows the receive queue
+fn channel(device: &Device, receive: u16) -> Result<Channel, virtio::Error> {
+  Channel::new(device, receive, receive + 1, QUEUE_SIZE, BUFFER_SIZE)
+}
+
+struct Port {
+  channel: Channel,
+  // Note: announced by the device (DEVICE_ADD)
+  present: bool,
+  // Note: a program on the host side (PORT_OPEN)
+  connected: bool,
+  // Note: `chardev_open` minus `chardev_close`, the host is told of the first and the last
+  users: usize,
+  // Note: received, not read yet
+  pending: Vec<u8>,
+}
+
+struct Console {
+  device: Device,
+  // Note: `None` without VIRTIO_CONSOLE_F_MULTIPORT
+  control: Option<Channel>,
+  // Note: by port id
+  ports: Vec<Port>,
+}
+
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+
+impl Console {
+  fn send_control(&mut self, id: u32, event: u16, value: u16) {
+    let mut message = Vec::with_capacity(CONTROL_SIZE);
+    message.extend_from_slice(&id.to_le_bytes());
+    message.extend_from_slice(&event.to_le_bytes());
+    message.extend_from_slice(&value.to_le_bytes());
+    if let Some(control) = &mut self.control {
+      control.send(&self.device, message);
+    }
+  }
+
+  // Note: every control message received so far, the device may answer with more meanwhile
+  fn control(&mut self) {
+    loop {
+      let message = match &mut self.control {
+        Some(control) => { control.receive(&self.device) }
+        None => { None }
+      };
+      match message {
+        Some(message) => { self.event(&message) }
+        None => { break; }
+      }
+    }
+  }
+
+  fn event(&mut self, message: &[u8]) {
+    if message.len() < CONTROL_SIZE {
+      return;
+    }
+    let id = u32::from_le_bytes([message[0], message[1], message[2], message[3]]);
+    let event = u16::from_le_bytes([message[4], message[5]]);
+    let value = u16::from_le_bytes([message[6], message[7]]);
+    let port = id as usize;
+    if port >= self.ports.len() {
+      warn!("virtio console: port {} ignored", id);
+      return;
+    }
+    match event {
+      DEVICE_ADD => {
+        self.ports[port].present = true;
+        self.send_control(id, PORT_READY, 1);
+        chardev::register(&format!("vport0p{}", id), Arc::new(PortDevice(port)));
+      }
+      DEVICE_REMOVE => {
+        self.ports[port].present = false;
+        self.ports[port].connected = false;
+      }
+      PORT_OPEN => {
+        self.ports[port].connected = value != 0;
+      }
+      PORT_NAME => {
+        match core::str::from_utf8(&message[CONTROL_SIZE..]) {
+          Ok(name) => { chardev::register(name.trim_end_matches('\0'), Arc::new(PortDevice(port))) }
+          Err(_) => { warn!("virtio console: port {} name not utf-8", id) }
+        }
+      }
+      // Note: no difference to other ports, no terminal to resize
+      CONSOLE_PORT | RESIZE => {}
+      _ => { warn!("virtio console: unknown event {}", event) }
+    }
+  }
+}
+
+fn with<T, F: FnOnce(&mut Console) -> Result<T, Error>>(f: F) -> Result<T, Error> {
+  let mut lock = CONSOLE.lock();
+  let r = match lock.as_mut() {
+    None => { Err(Error::NotFoundError) }
+    Some(console) => {
+      console.control();
+      f(console)
+    }
+  };
+  drop(lock);
+  r
+}
+
+// Note: by port id
+struct PortDevice(usize);
+
+impl CharDevice for PortDevice {
+  fn open(&self) {
+    let _ = with(|console| {
+      let port = &mut console.ports[self.0];
+      port.users += 1;
+      if port.users == 1 {
+        console.send_control(self.0 as u32, PORT_OPEN, 1);
+      }
+      Ok(())
+    });
+  }
+
+  fn close(&self) {
+    let _ = with(|console| {
+      let port = &mut console.ports[self.0];
+      if port.users > 0 {
+        port.users -= 1;
+        if port.users == 0 {
+          console.send_control(self.0 as u32, PORT_OPEN, 0);
+        }
+      }
+      Ok(())
+    });
+  }
+
+  fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+    with(|console| {
+      let port = &mut console.ports[self.0];
+      while let Some(data) = port.channel.receive(&console.device) {
+        port.pending.extend_from_slice(&data);
+      }
+      if port.pending.is_empty() {
+        return if port.present && port.connected { Err(Error::WouldBlockError) } else { Ok(0) };
+      }
+      let n = core::cmp::min(buf.len(), port.pending.len());
+      buf[..n].copy_from_slice(&port.pending[..n]);
+      port.pending.drain(..n);
+      Ok(n)
+    })
+  }
+
+  // Note: sent even if nobody is on the host side, the device drops it then
+  fn write(&self, data: &[u8]) -> Result<usize, Error> {
+    with(|console| {
+      let port = &mut console.ports[self.0];
+      if !port.present {
+        return Err(Error::NotFoundError);
+      }
+      if data.is_empty() || port.channel.send(&console.device, data.to_vec()) {
+        Ok(data.len())
+      } else {
+        Err(Error::WouldBlockError)
+      }
+    })
+  }
+}
+
+pub fn init() {
+  let device = match virtio::probe(DeviceType::Console) {
+    Ok(device) => { device }
+    Err(_) => { return; }
+  };
+  let r = device.negotiate(VIRTIO_CONSOLE_F_MULTIPORT).and_then(|features| {
+    let multiport = features & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+    let count = if multiport {
+      let mut max = [0u8; 4];
+      device.config(MAX_NR_PORTS_OFFSET, &mut max);
+      core::cmp::min(u32::from_le_bytes(max) as usize, PORT_LIMIT)
+    } else {
+      1
+    };
+    let mut ports = Vec::new();
+    for id in 0..count {
+      ports.push(Port {
+        channel: channel(&device, receive_queue(id))?,
+        present: !multiport,
+        connected: !multiport,
+        users: 0,
+        pending: Vec::new(),
+      });
+    }
+    let control = if multiport { Some(channel(&device, CONTROL_RECEIVE_QUEUE)?) } else { None };
+    Ok((control, ports))
+  });
+  let (control, ports) = match r {
+    Ok(r) => { r }
+    Err(e) => {
+      warn!("virtio console: {:?}", e);
+      return;
+    }
+  };
+  let mut console = Console { device, control, ports };
+  console.device.ready();
+  for port in console.ports.iter() {
+    port.channel.start(&console.device);
+  }
+  match &console.control {
+    Some(control) => {
+      control.start(&console.device);
+      info!("virtio console: {} ports", console.ports.len());
+      console.send_control(0, DEVICE_READY, 1);
+      console.control();
+    }
+    None => { chardev::register("virtio-console", Arc::new(PortDevice(0))); }
+  }
+  *CONSOLE.lock() = Some(console);
+}
@@ -0,0 +1,38 @@
+use crate::arch::ArchTrait;
+
+// Note: `sifive_test` device of QEMU virt
+const SIFIVE_TEST_ADDR: usize = 0xffff_ffff_0000_0000 + 0x0010_0000;
+const SIFIVE_TEST_PASS: u32 = 0x5555;
+const SIFIVE_TEST_FAIL: u32 = 0x3333;
+
+// Note: SBI system reset extension ("SRST"), then the legacy shutdown
+const SBI_EXT_SRST: usize = 0x5352_5354;
+const SBI_SRST_SHUTDOWN: usize = 0;
+const SBI_SRST_REASON_NONE: usize = 0;
+const SBI_SRST_REASON_FAILURE: usize = 1;
+const SBI_SHUTDOWN: usize = 0x08;
+
+// Note: returns only if the call is not supported
+fn ecall(extension: usize, function: usize, arg0: usize, arg1: usize) {
+  let _error: usize;
+  let _value: usize;
+  unsafe {
+    llvm_asm!("ecall"
+        : "={x10}" (_error), "={x11}" (_value)
+        : "{x10}" (arg0), "{x11}" (arg1), "{x16}" (function), "{x17}" (extension)
+        : "memory"
+        : "volatile");
+  }
+}
+
+// Note: exit QEMU, its exit status is 0 on `success` or 1 otherwise
+pub fn exit(success: bool) -> ! {
+  // Note: only the test device carries an exit code, OpenSBI reports every shutdown as a pass
+  let value = if success { SIFIVE_TEST_PASS } else { (1 << 16) | SIFIVE_TEST_FAIL };
+  unsafe { crate::driver::mmio::write_word(SIFIVE_TEST_ADDR, value); }
+  ecall(SBI_EXT_SRST, 0, SBI_SRST_SHUTDOWN, if success { SBI_SRST_REASON_NONE } else { SBI_SRST_REASON_FAILURE });
+  ecall(SBI_SHUTDOWN, 0, 0, 0);
+  loop {
+    crate::arch::Arch::wait_for_event();
+  }
+}
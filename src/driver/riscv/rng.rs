@@ -0,0 +1,76 @@
+use alloc::vec;
+
+use spin::Mutex;
+
+use crate::arch::Address;
+use crate::driver::virtio::{self, Device, DeviceType, Queue};
+
+// Note: virtio-rng on the shared virtio transport, QEMU `-device virtio-rng-device`
+//       one request queue, the device fills the writable buffers handed to it
+const REQUEST_QUEUE: u16 = 0;
+const QUEUE_SIZE: u16 = 4;
+
+// Note: nanoseconds, the device is as fast as its host backend, a request is given up after it
+const READ_TIMEOUT: u64 = 10_000_000;
+
+struct Rng {
+  device: Device,
+  queue: Queue,
+}
+
+static RNG: Mutex<Option<Rng>> = Mutex::new(None);
+
+pub fn init() {
+  let device = match virtio::probe(DeviceType::Entropy) {
+    Ok(device) => { device }
+    Err(_) => { return; }
+  };
+  match device.negotiate(0).and_then(|_| device.queue(REQUEST_QUEUE, QUEUE_SIZE)) {
+    Ok(queue) => {
+      device.ready();
+      *RNG.lock() = Some(Rng { device, queue });
+    }
+    Err(e) => { warn!("rng: virtio {:?}", e); }
+  }
+}
+
+// Note: bytes written to `buf`, 0 without a device or if it does not answer in time
+pub fn read(buf: &mut [u8]) -> usize {
+  if buf.is_empty() {
+    return 0;
+  }
+  let mut lock = RNG.lock();
+  let r = match lock.as_mut() {
+    None => { 0 }
+    Some(rng) => {
+      let buffer = vec![0u8; buf.len()];
+      let pa = (buffer.as_ptr() as usize).kva2pa();
+      match rng.queue.push(&[(pa, buffer.len(), true)]) {
+        Err(_) => { 0 }
+        Ok(head) => {
+          rng.device.notify(&rng.queue);
+          let deadline = crate::lib::time::now() + READ_TIMEOUT;
+          loop {
+            match rng.queue.pop() {
+              Some((used, len)) if used == head => {
+                let n = core::cmp::min(len, buf.len());
+                buf[..n].copy_from_slice(&buffer[..n]);
+                break n;
+              }
+              // Note: a request given up on earlier
+              Some(_) => {}
+              None if crate::lib::time::now() > deadline => {
+                // Note: still the device's, never freed
+                core::mem::forget(buffer);
+                break 0;
+              }
+              None => {}
+            }
+          }
+        }
+      }
+    }
+  };
+  drop(lock);
+  r
+}
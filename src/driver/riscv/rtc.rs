@@ -0,0 +1,28 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::Address;
+use crate::driver::mmio::*;
+
+// Note: Google Goldfish RTC of QEMU virt, nanoseconds since the Unix epoch
+//       alarm (PLIC IRQ 11) is not used, the kernel only reads the time once at boot
+const RTC_DEFAULT_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x0010_1000;
+
+const RTC_COMPATIBLE: &[&str] = &["google,goldfish-rtc"];
+
+// Note: reading TIME_LOW latches TIME_HIGH
+const RTC_TIME_LOW: usize = 0x00;
+const RTC_TIME_HIGH: usize = 0x04;
+
+static RTC_BASE_ADDR: AtomicUsize = AtomicUsize::new(RTC_DEFAULT_BASE_ADDR);
+
+pub fn init() {
+  if let Some(pa) = crate::board::description::get().device(RTC_COMPATIBLE).and_then(|d| d.base()) {
+    RTC_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+}
+
+pub fn read() -> Option<u64> {
+  let base = RTC_BASE_ADDR.load(Ordering::Relaxed);
+  let (low, high) = unsafe { (read_word(base + RTC_TIME_LOW), read_word(base + RTC_TIME_HIGH)) };
+  Some(((high as u64) << 32) | low as u64)
+}
@@ -2,7 +2,8 @@ use riscv::regs::*;
 
 const SBI_SET_TIMER: usize = 0x00;
 
-const TIMER_DEFAULT_COUNT: usize = 250000;
+// Note: timebase of QEMU virt, used without `timebase-frequency` in the device tree
+const TIMER_DEFAULT_FREQUENCY: u64 = 10_000_000;
 
 #[inline(always)]
 fn ecall(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
@@ -17,13 +18,22 @@ fn ecall(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
   ret
 }
 
-#[no_mangle]
-pub fn next() {
-  let time = TIME.get() as usize;
-  ecall(SBI_SET_TIMER, time + TIMER_DEFAULT_COUNT, 0, 0);
+// Note: one shot, interrupt once `counter()` reaches `count`, `None` stops the timer
+//       SBI clears the pending interrupt when a new deadline is set
+pub fn set_deadline(count: Option<u64>) {
+  ecall(SBI_SET_TIMER, count.unwrap_or(u64::MAX) as usize, 0, 0);
+}
+
+pub fn counter() -> u64 {
+  TIME.get() as u64
+}
+
+pub fn frequency() -> u64 {
+  crate::board::description::get().timebase_frequency.unwrap_or(TIMER_DEFAULT_FREQUENCY)
 }
 
 pub fn init(_core_id: usize) {
-  next();
+  // Note: armed by the scheduler, see `lib/timer.rs`
+  set_deadline(None);
   SIE.write(SIE::STIE.val(1));
 }
@@ -1,9 +1,17 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::Address;
 use crate::driver::mmio::*;
 
 // Note: NS16550A uart driver from
 // https://github.com/michaeljclark/riscv-probe/blob/master/libfemto/drivers/ns16550a.c
 
-const UART_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x1000_0000;
+// Note: QEMU virt, replaced by the device tree one in `init`
+const UART_DEFAULT_BASE_ADDR: usize = 0xffff_ffff_0000_0000 + 0x1000_0000;
+
+static UART_BASE_ADDR: AtomicUsize = AtomicUsize::new(UART_DEFAULT_BASE_ADDR);
+
+pub const UART_COMPATIBLE: &[&str] = &["ns16550a", "ns16550"];
 
 const UART_FCR: usize = 0x02;  /* FIFO Control Register */
 const UART_LCR: usize = 0x03;  /* Line Control Register */
@@ -13,9 +21,15 @@ const UART_MCR: usize = 0x04;  /* Modem Control Register */
 const UART_LCR_DLAB: u8 = 0x80;  /* Divisor Latch Bit */
 const UART_LCR_8BIT: u8 = 0x03;  /* 8-bit */
 const UART_THR: usize = 0x00;  /* Transmit Hold Register */
+const UART_RBR: usize = 0x00;  /* Receive Buffer Register */
+const UART_LSR: usize = 0x05;  /* Line Status Register */
+const UART_LSR_DA: u8 = 0x01;  /* Data Available */
 
 pub fn init() {
-  let base = UART_BASE_ADDR;
+  if let Some(pa) = crate::board::description::get().device(UART_COMPATIBLE).and_then(|d| d.base()) {
+    UART_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+  let base = UART_BASE_ADDR.load(Ordering::Relaxed);
   unsafe {
     write_byte(base + UART_FCR, 0);
     write_byte(base + UART_LCR, UART_LCR_DLAB as u8);
@@ -27,7 +41,7 @@ pub fn init() {
 }
 
 fn send(c: u8) {
-  let base = UART_BASE_ADDR;
+  let base = UART_BASE_ADDR.load(Ordering::Relaxed);
   unsafe {
     write_byte(base + UART_THR, c);
   }
@@ -38,4 +52,22 @@ pub fn putc(c: u8) {
     send(b'\r');
   }
   send(c);
-}
\ No newline at end of file
+}
+
+pub fn putc_raw(c: u8) {
+  send(c);
+}
+
+// Note: rx interrupt is not enabled, the console is polled
+pub fn interrupt() {}
+
+pub fn getc() -> Option<u8> {
+  let base = UART_BASE_ADDR.load(Ordering::Relaxed);
+  unsafe {
+    if read_byte(base + UART_LSR) & UART_LSR_DA != 0 {
+      Some(read_byte(base + UART_RBR))
+    } else {
+      None
+    }
+  }
+}
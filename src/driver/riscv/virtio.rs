@@ -0,0 +1,367 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+
+use spin::Mutex;
+
+use crate::arch::{Address, PAGE_SIZE};
+use crate::driver::mmio::*;
+use crate::lib::round_up;
+
+// Note: virtio over MMIO (virtio 1.1, 4.2), shared by the virtio device drivers
+//       only the non legacy interface (version 2), QEMU needs `-global virtio-mmio.force-legacy=false`
+//       split virtqueues, drivers check the used rings by polling, no interrupt is taken
+// Note: physical, the 8 slots of QEMU virt, used without a device tree
+const VIRTIO_DEFAULT_BASE_ADDR: usize = 0x1000_1000;
+const VIRTIO_DEFAULT_SLOTS: usize = 8;
+const VIRTIO_SLOT_SIZE: usize = 0x1000;
+
+const VIRTIO_COMPATIBLE: &[&str] = &["virtio,mmio"];
+
+const VIRTIO_MAGIC: usize = 0x000;
+const VIRTIO_VERSION: usize = 0x004;
+const VIRTIO_DEVICE_ID: usize = 0x008;
+const VIRTIO_DEVICE_FEATURES: usize = 0x010;
+const VIRTIO_DEVICE_FEATURES_SEL: usize = 0x014;
+const VIRTIO_DRIVER_FEATURES: usize = 0x020;
+const VIRTIO_DRIVER_FEATURES_SEL: usize = 0x024;
+const VIRTIO_QUEUE_SEL: usize = 0x030;
+const VIRTIO_QUEUE_NUM_MAX: usize = 0x034;
+const VIRTIO_QUEUE_NUM: usize = 0x038;
+const VIRTIO_QUEUE_READY: usize = 0x044;
+const VIRTIO_QUEUE_NOTIFY: usize = 0x050;
+const VIRTIO_STATUS: usize = 0x070;
+// Note: low word, the high word follows
+const VIRTIO_QUEUE_DESC: usize = 0x080;
+const VIRTIO_QUEUE_DRIVER: usize = 0x090;
+const VIRTIO_QUEUE_DEVICE: usize = 0x0a0;
+const VIRTIO_CONFIG_GENERATION: usize = 0x0fc;
+const VIRTIO_CONFIG: usize = 0x100;
+
+// "virt"
+const VIRTIO_MAGIC_VALUE: u32 = 0x7472_6976;
+const VIRTIO_VERSION_MODERN: u32 = 2;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+const STATUS_FAILED: u32 = 128;
+
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+const VIRTQ_DESC_SIZE: usize = 16;
+const VIRTQ_USED_ELEM_SIZE: usize = 8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceType {
+  Net = 1,
+  Console = 3,
+  Entropy = 4,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  DeviceNotFoundError,
+  FeaturesError,
+  QueueUnavailableError,
+  QueueFullError,
+  OutOfMemoryError,
+}
+
+// Note: kva of the slots handed out by `probe`
+static CLAIMED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+fn slots() -> Vec<usize> {
+  let description = crate::board::description::get();
+  let mut slots: Vec<usize> = description.devices(VIRTIO_COMPATIBLE).filter_map(|d| d.base()).collect();
+  if !description.device_tree {
+    slots = (0..VIRTIO_DEFAULT_SLOTS).map(|i| VIRTIO_DEFAULT_BASE_ADDR + i * VIRTIO_SLOT_SIZE).collect();
+  }
+  slots.iter().map(|pa| pa.pa2kva()).collect()
+}
+
+// Note: sequence of memory accesses and device register writes
+fn barrier() {
+  unsafe { llvm_asm!("fence iorw, iorw" :::: "volatile"); }
+}
+
+pub struct Device {
+  base: usize,
+}
+
+// Note: first unclaimed device of type `t`, empty slots read device id 0
+pub fn probe(t: DeviceType) -> Result<Device, Error> {
+  let mut claimed = CLAIMED.lock();
+  let r = slots().into_iter()
+    .filter(|base| !claimed.contains(base))
+    .map(|base| Device { base })
+    .find(|d| d.read(VIRTIO_MAGIC) == VIRTIO_MAGIC_VALUE
+      && d.read(VIRTIO_VERSION) == VIRTIO_VERSION_MODERN
+      && d.read(VIRTIO_DEVICE_ID) == t as u32);
+  if let Some(d) = &r {
+    claimed.push(d.base);
+  }
+  drop(claimed);
+  r.ok_or(Error::DeviceNotFoundError)
+}
+
+impl Device {
+  fn read(&self, offset: usize) -> u32 {
+    unsafe { read_word(self.base + offset) }
+  }
+
+  fn write(&self, offset: usize, value: u32) {
+    unsafe { write_word(self.base + offset, value) }
+  }
+
+  fn write_address(&self, offset: usize, pa: usize) {
+    self.write(offset, pa as u32);
+    self.write(offset + 4, (pa >> 32) as u32);
+  }
+
+  // Note: reset, then accept `wanted` of the offered features (and VIRTIO_F_VERSION_1)
+  //       return the accepted ones, queues are set up next
+  pub fn negotiate(&self, wanted: u64) -> Result<u64, Error> {
+    self.write(VIRTIO_STATUS, 0);
+    self.write(VIRTIO_STATUS, STATUS_ACKNOWLEDGE);
+    self.write(VIRTIO_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    let mut offered = 0u64;
+    for sel in 0..2 {
+      self.write(VIRTIO_DEVICE_FEATURES_SEL, sel);
+      offered |= (self.read(VIRTIO_DEVICE_FEATURES) as u64) << (sel * 32);
+    }
+    if offered & VIRTIO_F_VERSION_1 == 0 {
+      self.write(VIRTIO_STATUS, STATUS_FAILED);
+      return Err(Error::FeaturesError);
+    }
+    let features = offered & (wanted | VIRTIO_F_VERSION_1);
+    for sel in 0..2 {
+      self.write(VIRTIO_DRIVER_FEATURES_SEL, sel);
+      self.write(VIRTIO_DRIVER_FEATURES, (features >> (sel * 32)) as u32);
+    }
+    self.write(VIRTIO_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+    if self.read(VIRTIO_STATUS) & STATUS_FEATURES_OK == 0 {
+      self.write(VIRTIO_STATUS, STATUS_FAILED);
+      return Err(Error::FeaturesError);
+    }
+    Ok(features)
+  }
+
+  // Note: up to `size` entries, fewer if the device has a smaller maximum
+  pub fn queue(&self, index: u16, size: u16) -> Result<Queue, Error> {
+    self.write(VIRTIO_QUEUE_SEL, index as u32);
+    if self.read(VIRTIO_QUEUE_READY) != 0 {
+      return Err(Error::QueueUnavailableError);
+    }
+    let size = core::cmp::min(size as u32, self.read(VIRTIO_QUEUE_NUM_MAX)) as u16;
+    if size == 0 {
+      return Err(Error::QueueUnavailableError);
+    }
+    let queue = Queue::new(index, size)?;
+    self.write(VIRTIO_QUEUE_NUM, size as u32);
+    self.write_address(VIRTIO_QUEUE_DESC, queue.desc.kva2pa());
+    self.write_address(VIRTIO_QUEUE_DRIVER, queue.avail.kva2pa());
+    self.write_address(VIRTIO_QUEUE_DEVICE, queue.used.kva2pa());
+    self.write(VIRTIO_QUEUE_READY, 1);
+    Ok(queue)
+  }
+
+  // Note: after the queues are set up, buffers may be handed over before it
+  pub fn ready(&self) {
+    let status = self.read(VIRTIO_STATUS);
+    self.write(VIRTIO_STATUS, status | STATUS_DRIVER_OK);
+  }
+
+  pub fn notify(&self, queue: &Queue) {
+    barrier();
+    self.write(VIRTIO_QUEUE_NOTIFY, queue.index as u32);
+  }
+
+  // Note: device specific configuration, read again if the device changed it meanwhile
+  pub fn config(&self, offset: usize, buf: &mut [u8]) {
+    loop {
+      let generation = self.read(VIRTIO_CONFIG_GENERATION);
+      for (i, b) in buf.iter_mut().enumerate() {
+        *b = unsafe { read_byte(self.base + VIRTIO_CONFIG + offset + i) };
+      }
+      if self.read(VIRTIO_CONFIG_GENERATION) == generation {
+        break;
+      }
+    }
+  }
+}
+
+// Note: descriptor table, driver (available) ring and device (used) ring in one zeroed
+//       heap block (physically contiguous, outside of the page pool), never freed
+//       buffers are given by physical address, they stay with the caller until used
+pub struct Queue {
+  index: u16,
+  size: u16,
+  desc: usize,
+  avail: usize,
+  used: usize,
+  free: Vec<u16>,
+  last_used: u16,
+}
+
+impl Queue {
+  fn new(index: u16, size: u16) -> Result<Self, Error> {
+    let n = size as usize;
+    let avail = n * VIRTQ_DESC_SIZE;
+    // Note: flags, idx, ring, used_event
+    let used = round_up(avail + 4 + 2 * n + 2, 4);
+    // Note: flags, idx, ring, avail_event
+    let end = used + 4 + VIRTQ_USED_ELEM_SIZE * n + 2;
+    let layout = Layout::from_size_align(round_up(end, PAGE_SIZE), PAGE_SIZE).map_err(|_| Error::OutOfMemoryError)?;
+    let kva = unsafe { alloc::alloc::alloc_zeroed(layout) } as usize;
+    if kva == 0 {
+      return Err(Error::OutOfMemoryError);
+    }
+    Ok(Queue {
+      index,
+      size,
+      desc: kva,
+      avail: kva + avail,
+      used: kva + used,
+      free: (0..size).rev().collect(),
+      last_used: 0,
+    })
+  }
+
+  pub fn size(&self) -> u16 {
+    self.size
+  }
+
+  // Note: one chain of (pa, length, device writable) buffers, return its head
+  //       the device is told by `Device::notify`
+  pub fn push(&mut self, buffers: &[(usize, usize, bool)]) -> Result<u16, Error> {
+    if buffers.is_empty() || buffers.len() > self.free.len() {
+      return Err(Error::QueueFullError);
+    }
+    let ids: Vec<u16> = (0..buffers.len()).map(|_| self.free.pop().unwrap()).collect();
+    for (i, (pa, len, writable)) in buffers.iter().enumerate() {
+      let next = ids.get(i + 1);
+      let mut flags = if *writable { VIRTQ_DESC_F_WRITE } else { 0 };
+      if next.is_some() {
+        flags |= VIRTQ_DESC_F_NEXT;
+      }
+      let desc = self.desc + ids[i] as usize * VIRTQ_DESC_SIZE;
+      unsafe {
+        core::intrinsics::volatile_store(desc as *mut u64, *pa as u64);
+        core::intrinsics::volatile_store((desc + 8) as *mut u32, *len as u32);
+        core::intrinsics::volatile_store((desc + 12) as *mut u16, flags);
+        core::intrinsics::volatile_store((desc + 14) as *mut u16, *next.unwrap_or(&0));
+      }
+    }
+    unsafe {
+      let idx = core::intrinsics::volatile_load((self.avail + 2) as *const u16);
+      let slot = self.avail + 4 + 2 * (idx % self.size) as usize;
+      core::intrinsics::volatile_store(slot as *mut u16, ids[0]);
+      // Note: the entry is visible before the index moves past it
+      barrier();
+      core::intrinsics::volatile_store((self.avail + 2) as *mut u16, idx.wrapping_add(1));
+    }
+    Ok(ids[0])
+  }
+
+  // Note: next chain the device is done with, its head and the bytes it wrote
+  pub fn pop(&mut self) -> Option<(u16, usize)> {
+    let idx = unsafe { core::intrinsics::volatile_load((self.used + 2) as *const u16) };
+    if idx == self.last_used {
+      return None;
+    }
+    barrier();
+    let elem = self.used + 4 + VIRTQ_USED_ELEM_SIZE * (self.last_used % self.size) as usize;
+    let (head, len) = unsafe {
+      (core::intrinsics::volatile_load(elem as *const u32) as u16,
+       core::intrinsics::volatile_load((elem + 4) as *const u32) as usize)
+    };
+    self.last_used = self.last_used.wrapping_add(1);
+    let mut id = head;
+    loop {
+      self.free.push(id);
+      let desc = self.desc + id as usize * VIRTQ_DESC_SIZE;
+      let flags = unsafe { core::intrinsics::volatile_load((desc + 12) as *const u16) };
+      if flags & VIRTQ_DESC_F_NEXT == 0 {
+        break;
+      }
+      id = unsafe { core::intrinsics::volatile_load((desc + 14) as *const u16) };
+    }
+    Some((head, len))
+  }
+}
+
+// Note: a receive and a transmit queue, the receive queue kept full of `buffer_size` byte buffers
+//       heap buffers, in the linear map, by head descriptor, owned by the device until used
+pub struct Channel {
+  rx: Queue,
+  tx: Queue,
+  buffer_size: usize,
+  rx_buffers: Vec<Option<Box<[u8]>>>,
+  tx_buffers: Vec<Option<Vec<u8>>>,
+}
+
+impl Channel {
+  pub fn new(device: &Device, receive: u16, transmit: u16, size: u16, buffer_size: usize) -> Result<Self, Error> {
+    let rx = device.queue(receive, size)?;
+    let tx = device.queue(transmit, size)?;
+    let mut channel = Channel {
+      rx_buffers: (0..rx.size()).map(|_| None).collect(),
+      tx_buffers: (0..tx.size()).map(|_| None).collect(),
+      rx,
+      tx,
+      buffer_size,
+    };
+    for _ in 0..channel.rx.size() {
+      channel.post(vec![0u8; buffer_size].into_boxed_slice());
+    }
+    Ok(channel)
+  }
+
+  // Note: the device is told of the receive buffers, once it is ready
+  pub fn start(&self, device: &Device) {
+    device.notify(&self.rx);
+  }
+
+  fn post(&mut self, buffer: Box<[u8]>) {
+    let pa = (buffer.as_ptr() as usize).kva2pa();
+    if let Ok(head) = self.rx.push(&[(pa, self.buffer_size, true)]) {
+      self.rx_buffers[head as usize] = Some(buffer);
+    }
+  }
+
+  fn reclaim(&mut self) {
+    while let Some((head, _)) = self.tx.pop() {
+      self.tx_buffers[head as usize] = None;
+    }
+  }
+
+  // Note: `false` if the transmit queue is full
+  pub fn send(&mut self, device: &Device, buffer: Vec<u8>) -> bool {
+    self.reclaim();
+    let pa = (buffer.as_ptr() as usize).kva2pa();
+    match self.tx.push(&[(pa, buffer.len(), false)]) {
+      Ok(head) => {
+        self.tx_buffers[head as usize] = Some(buffer);
+        device.notify(&self.tx);
+        true
+      }
+      Err(_) => { false }
+    }
+  }
+
+  // Note: what the device wrote into the next received buffer, the buffer goes back to it
+  pub fn receive(&mut self, device: &Device) -> Option<Vec<u8>> {
+    let (head, len) = self.rx.pop()?;
+    let buffer = self.rx_buffers[head as usize].take()?;
+    let data = buffer[..core::cmp::min(len, self.buffer_size)].to_vec();
+    self.post(buffer);
+    device.notify(&self.rx);
+    Some(data)
+  }
+}
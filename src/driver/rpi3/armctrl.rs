@@ -0,0 +1,30 @@
+use crate::arch::Address;
+use crate::driver::mmio::write_word;
+
+// Note: BCM2835 ARM interrupt controller, peripheral interrupts
+//       they reach core 0 only (GPU interrupt routing of the local intc, reset value)
+//       no decoding of pending bits, handlers check their own device status
+const ARMCTRL_DEFAULT_BASE_ADDR: usize = 0xFFFFFF8000000000 + 0x3F00B200;
+
+const ARMCTRL_COMPATIBLE: &[&str] = &["brcm,bcm2836-armctrl-ic", "brcm,bcm2835-armctrl-ic"];
+
+// Note: by bank, the first cell of `interrupts`: basic, GPU 0..31, GPU 32..63
+const ARMCTRL_ENABLE: [usize; 3] = [0x18, 0x10, 0x14];
+const ARMCTRL_DISABLE: [usize; 3] = [0x24, 0x1C, 0x20];
+
+fn armctrl() -> usize {
+  crate::board::description::get().device(ARMCTRL_COMPATIBLE)
+    .and_then(|d| d.base())
+    .map(|pa| pa.pa2kva())
+    .unwrap_or(ARMCTRL_DEFAULT_BASE_ADDR)
+}
+
+// Note: `interrupts` cells of the device tree, e.g. <2 25> for the PL011
+pub fn enable(bank: usize, irq: usize) {
+  unsafe { write_word(armctrl() + ARMCTRL_ENABLE[bank], 1 << irq); }
+}
+
+#[allow(dead_code)]
+pub fn disable(bank: usize, irq: usize) {
+  unsafe { write_word(armctrl() + ARMCTRL_DISABLE[bank], 1 << irq); }
+}
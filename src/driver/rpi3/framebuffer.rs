@@ -0,0 +1,40 @@
+use cortex_a::barrier;
+
+use crate::config::{CONFIG_FRAMEBUFFER_HEIGHT, CONFIG_FRAMEBUFFER_WIDTH};
+use crate::driver::mailbox;
+use crate::lib::framebuffer::Info;
+
+// Note: allocated by the firmware in GPU memory, outside of the page pool
+//       the GPU scans it out behind the ARM caches, drawing is flushed
+const CACHE_LINE_SIZE: usize = 64;
+
+pub fn init() -> Option<Info> {
+  match mailbox::allocate_framebuffer(CONFIG_FRAMEBUFFER_WIDTH, CONFIG_FRAMEBUFFER_HEIGHT, 32) {
+    Ok(fb) if fb.depth == 32 => {
+      Some(Info {
+        pa: fb.base,
+        size: fb.size,
+        width: fb.width,
+        height: fb.height,
+        pitch: fb.pitch,
+        depth: fb.depth,
+      })
+    }
+    Ok(fb) => {
+      warn!("framebuffer: {} bits per pixel not supported", fb.depth);
+      None
+    }
+    Err(e) => {
+      warn!("framebuffer: {:?}", e);
+      None
+    }
+  }
+}
+
+pub fn flush(kva: usize, len: usize) {
+  let start = kva & !(CACHE_LINE_SIZE - 1);
+  for line in (start..kva + len).step_by(CACHE_LINE_SIZE) {
+    unsafe { llvm_asm!("dc cvac, $0" :: "r"(line) :: "volatile"); }
+  }
+  barrier::dsb(barrier::SY);
+}
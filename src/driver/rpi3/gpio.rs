@@ -0,0 +1,69 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::{Address, Arch, ArchTrait};
+use crate::driver::mmio::{read_word, write_word};
+
+// Note: BCM2837 GPIO, pin function select and pull up/down only
+//       built-in address is replaced by the device tree one in `init`
+const GPIO_DEFAULT_BASE_ADDR: usize = 0xFFFFFF8000000000 + 0x3F200000;
+
+const GPIO_COMPATIBLE: &[&str] = &["brcm,bcm2835-gpio"];
+
+const GPFSEL0: usize = 0x00;
+const GPPUD: usize = 0x94;
+const GPPUDCLK0: usize = 0x98;
+
+// Note: function select values, not in alphabetical order
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Function {
+  Input = 0b000,
+  Output = 0b001,
+  Alt0 = 0b100,
+  Alt1 = 0b101,
+  Alt2 = 0b110,
+  Alt3 = 0b111,
+  Alt4 = 0b011,
+  Alt5 = 0b010,
+}
+
+static GPIO_BASE_ADDR: AtomicUsize = AtomicUsize::new(GPIO_DEFAULT_BASE_ADDR);
+
+fn gpio(offset: usize) -> usize {
+  GPIO_BASE_ADDR.load(Ordering::Relaxed) + offset
+}
+
+fn clock_delay(n: u32) -> () {
+  for _ in 0..n {
+    Arch::nop();
+  }
+}
+
+pub fn init() {
+  if let Some(pa) = crate::board::description::get().device(GPIO_COMPATIBLE).and_then(|d| d.base()) {
+    GPIO_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+}
+
+pub fn select(pin: usize, function: Function) {
+  let register = gpio(GPFSEL0 + pin / 10 * 4);
+  let shift = pin % 10 * 3;
+  unsafe {
+    let mut ra = read_word(register);
+    ra &= !(7u32 << shift);
+    ra |= (function as u32) << shift;
+    write_word(register, ra);
+  }
+}
+
+// Note: pins 0..32, pull up/down off, as the UARTs want
+pub fn disable_pull(pins: &[usize]) {
+  let mask = pins.iter().fold(0u32, |mask, pin| mask | (1u32 << pin));
+  unsafe {
+    write_word(gpio(GPPUD), 0);
+    clock_delay(150);
+    write_word(gpio(GPPUDCLK0), mask);
+    clock_delay(150);
+    write_word(gpio(GPPUDCLK0), 0);
+  }
+}
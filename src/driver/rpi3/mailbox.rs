@@ -0,0 +1,327 @@
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use cortex_a::barrier;
+use spin::Mutex;
+
+use crate::arch::{Address, PAGE_SIZE};
+use crate::driver::mmio::{read_word, write_word};
+
+// Note: VideoCore mailbox 0, property channel only (ARM to VC)
+//       the message buffer is a static of the kernel image, handed over by bus address
+//       usable before the device tree is parsed (built-in address until `init`)
+const MAILBOX_DEFAULT_BASE_ADDR: usize = 0xFFFFFF8000000000 + 0x3F00B880;
+
+const MAILBOX_COMPATIBLE: &[&str] = &["brcm,bcm2835-mbox"];
+
+static MAILBOX_BASE_ADDR: AtomicUsize = AtomicUsize::new(MAILBOX_DEFAULT_BASE_ADDR);
+
+const MAILBOX_READ: usize = 0x00;
+const MAILBOX_STATUS: usize = 0x18;
+const MAILBOX_WRITE: usize = 0x20;
+
+const MAILBOX_STATUS_FULL: u32 = 1 << 31;
+const MAILBOX_STATUS_EMPTY: u32 = 1 << 30;
+
+const CHANNEL_PROPERTY: u32 = 8;
+
+// Note: L2 uncached alias of the ARM physical address space, as seen by the VC
+const BUS_ADDRESS_OFFSET: usize = 0xC000_0000;
+const BUS_ADDRESS_MASK: usize = 0x3FFF_FFFF;
+
+// Note: polls of the status register before giving up
+const MAILBOX_TIMEOUT: usize = 0x100_0000;
+
+const CACHE_LINE_SIZE: usize = 64;
+
+const CODE_REQUEST: u32 = 0;
+const CODE_SUCCESS: u32 = 0x8000_0000;
+const TAG_RESPONSE: u32 = 0x8000_0000;
+const TAG_END: u32 = 0;
+
+// Note: tags, see https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
+const TAG_GET_BOARD_REVISION: u32 = 0x0001_0002;
+const TAG_GET_BOARD_SERIAL: u32 = 0x0001_0004;
+const TAG_GET_ARM_MEMORY: u32 = 0x0001_0005;
+const TAG_GET_VC_MEMORY: u32 = 0x0001_0006;
+const TAG_GET_POWER_STATE: u32 = 0x0002_0001;
+const TAG_SET_POWER_STATE: u32 = 0x0002_8001;
+const TAG_GET_CLOCK_RATE: u32 = 0x0003_0002;
+const TAG_ALLOCATE_BUFFER: u32 = 0x0004_0001;
+const TAG_GET_PITCH: u32 = 0x0004_0008;
+const TAG_SET_PHYSICAL_SIZE: u32 = 0x0004_8003;
+const TAG_SET_VIRTUAL_SIZE: u32 = 0x0004_8004;
+const TAG_SET_DEPTH: u32 = 0x0004_8005;
+const TAG_SET_PIXEL_ORDER: u32 = 0x0004_8006;
+
+const POWER_ON: u32 = 1 << 0;
+const POWER_WAIT: u32 = 1 << 1;
+const POWER_NO_DEVICE: u32 = 1 << 1;
+
+const BUFFER_WORDS: usize = 64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  TimeoutError,
+  // Note: request does not fit in the message buffer
+  BufferSizeError,
+  // Note: the firmware failed the whole message or did not answer a tag
+  FirmwareError,
+  NoDeviceError,
+}
+
+// Note: clock ids of the property interface
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Clock {
+  Emmc = 1,
+  Uart = 2,
+  Arm = 3,
+  Core = 4,
+  V3d = 5,
+  H264 = 6,
+  Isp = 7,
+  Sdram = 8,
+  Pixel = 9,
+  Pwm = 10,
+}
+
+// Note: power domain ids of the property interface
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PowerDomain {
+  SdCard = 0,
+  Uart0 = 1,
+  Uart1 = 2,
+  UsbHcd = 3,
+  I2c0 = 4,
+  I2c1 = 5,
+  I2c2 = 6,
+  Spi = 7,
+  Ccp2tx = 8,
+}
+
+// Note: one tag of a message, `values` is sent and overwritten by the response
+//       its length is the value buffer size, enough for both request and response
+pub struct Tag<'a> {
+  pub id: u32,
+  pub values: &'a mut [u32],
+}
+
+#[repr(C, align(16))]
+struct Buffer([u32; BUFFER_WORDS]);
+
+static BUFFER: Mutex<Buffer> = Mutex::new(Buffer([0; BUFFER_WORDS]));
+
+fn register(offset: usize) -> usize {
+  MAILBOX_BASE_ADDR.load(Ordering::Relaxed) + offset
+}
+
+// Note: the VC reads and writes memory behind the ARM caches
+fn clean_invalidate(kva: usize, len: usize) {
+  for line in (kva..kva + len).step_by(CACHE_LINE_SIZE) {
+    unsafe { llvm_asm!("dc civac, $0" :: "r"(line) :: "volatile"); }
+  }
+  barrier::dsb(barrier::SY);
+}
+
+fn wait(flag: u32) -> Result<(), Error> {
+  for _ in 0..MAILBOX_TIMEOUT {
+    if unsafe { read_word(register(MAILBOX_STATUS)) } & flag == 0 {
+      return Ok(());
+    }
+  }
+  Err(Error::TimeoutError)
+}
+
+fn exchange(buffer: &Buffer) -> Result<(), Error> {
+  let kva = buffer.0.as_ptr() as usize;
+  let len = core::mem::size_of::<Buffer>();
+  clean_invalidate(kva, len);
+  let message = (kva.kva2pa() | BUS_ADDRESS_OFFSET) as u32 | CHANNEL_PROPERTY;
+  wait(MAILBOX_STATUS_FULL)?;
+  unsafe { write_word(register(MAILBOX_WRITE), message); }
+  loop {
+    wait(MAILBOX_STATUS_EMPTY)?;
+    // Note: answers to other channels are not ours
+    if unsafe { read_word(register(MAILBOX_READ)) } == message {
+      break;
+    }
+  }
+  clean_invalidate(kva, len);
+  Ok(())
+}
+
+pub fn init() {
+  if let Some(pa) = crate::board::description::get().device(MAILBOX_COMPATIBLE).and_then(|d| d.base()) {
+    MAILBOX_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+}
+
+// Note: send `tags` as one message, the firmware handles them in order
+pub fn call(tags: &mut [Tag]) -> Result<(), Error> {
+  // Note: size, code, per tag id, size, code and values, end tag
+  let words = 3 + tags.iter().map(|t| 3 + t.values.len()).sum::<usize>();
+  if words > BUFFER_WORDS {
+    return Err(Error::BufferSizeError);
+  }
+  let mut buffer = BUFFER.lock();
+  let b = &mut buffer.0;
+  b[0] = (words * 4) as u32;
+  b[1] = CODE_REQUEST;
+  let mut i = 2;
+  for tag in tags.iter() {
+    b[i] = tag.id;
+    b[i + 1] = (tag.values.len() * 4) as u32;
+    b[i + 2] = 0;
+    b[i + 3..i + 3 + tag.values.len()].copy_from_slice(&tag.values[..]);
+    i += 3 + tag.values.len();
+  }
+  b[i] = TAG_END;
+  let r = exchange(&buffer).and_then(|_| {
+    let b = &buffer.0;
+    if b[1] != CODE_SUCCESS {
+      return Err(Error::FirmwareError);
+    }
+    let mut i = 2;
+    for tag in tags.iter_mut() {
+      if b[i + 2] & TAG_RESPONSE == 0 {
+        return Err(Error::FirmwareError);
+      }
+      tag.values.copy_from_slice(&b[i + 3..i + 3 + tag.values.len()]);
+      i += 3 + tag.values.len();
+    }
+    Ok(())
+  });
+  drop(buffer);
+  r
+}
+
+fn property(id: u32, values: &mut [u32]) -> Result<(), Error> {
+  call(&mut [Tag { id, values }])
+}
+
+pub fn board_revision() -> Result<u32, Error> {
+  let mut v = [0u32; 1];
+  property(TAG_GET_BOARD_REVISION, &mut v)?;
+  Ok(v[0])
+}
+
+pub fn board_serial() -> Result<u64, Error> {
+  let mut v = [0u32; 2];
+  property(TAG_GET_BOARD_SERIAL, &mut v)?;
+  Ok(((v[1] as u64) << 32) | v[0] as u64)
+}
+
+// Note: ARM part of the memory split, physical addresses
+pub fn arm_memory() -> Result<Range<usize>, Error> {
+  let mut v = [0u32; 2];
+  property(TAG_GET_ARM_MEMORY, &mut v)?;
+  Ok(v[0] as usize..(v[0] as usize + v[1] as usize))
+}
+
+pub fn vc_memory() -> Result<Range<usize>, Error> {
+  let mut v = [0u32; 2];
+  property(TAG_GET_VC_MEMORY, &mut v)?;
+  Ok(v[0] as usize..(v[0] as usize + v[1] as usize))
+}
+
+// Note: in Hz
+pub fn clock_rate(clock: Clock) -> Result<u32, Error> {
+  let mut v = [clock as u32, 0];
+  property(TAG_GET_CLOCK_RATE, &mut v)?;
+  Ok(v[1])
+}
+
+#[allow(dead_code)]
+pub fn power_state(domain: PowerDomain) -> Result<bool, Error> {
+  let mut v = [domain as u32, 0];
+  property(TAG_GET_POWER_STATE, &mut v)?;
+  if v[1] & POWER_NO_DEVICE != 0 {
+    return Err(Error::NoDeviceError);
+  }
+  Ok(v[1] & POWER_ON != 0)
+}
+
+// Note: waits for the domain to settle, returns the new state
+#[allow(dead_code)]
+pub fn set_power_state(domain: PowerDomain, on: bool) -> Result<bool, Error> {
+  let mut v = [domain as u32, if on { POWER_ON | POWER_WAIT } else { POWER_WAIT }];
+  property(TAG_SET_POWER_STATE, &mut v)?;
+  if v[1] & POWER_NO_DEVICE != 0 {
+    return Err(Error::NoDeviceError);
+  }
+  Ok(v[1] & POWER_ON != 0)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Framebuffer {
+  // Note: ARM physical address
+  pub base: usize,
+  pub size: usize,
+  pub width: u32,
+  pub height: u32,
+  // Note: bytes per line
+  pub pitch: u32,
+  // Note: bits per pixel
+  pub depth: u32,
+}
+
+// Note: RGB pixel order, page aligned, the firmware may pick another size or depth, check the result
+pub fn allocate_framebuffer(width: u32, height: u32, depth: u32) -> Result<Framebuffer, Error> {
+  let mut physical = [width, height];
+  let mut virt = [width, height];
+  let mut bpp = [depth];
+  let mut order = [1u32];
+  // Note: alignment in, base and size out
+  let mut buffer = [PAGE_SIZE as u32, 0];
+  let mut pitch = [0u32];
+  call(&mut [
+    Tag { id: TAG_SET_PHYSICAL_SIZE, values: &mut physical },
+    Tag { id: TAG_SET_VIRTUAL_SIZE, values: &mut virt },
+    Tag { id: TAG_SET_DEPTH, values: &mut bpp },
+    Tag { id: TAG_SET_PIXEL_ORDER, values: &mut order },
+    Tag { id: TAG_ALLOCATE_BUFFER, values: &mut buffer },
+    Tag { id: TAG_GET_PITCH, values: &mut pitch },
+  ])?;
+  if buffer[0] == 0 || buffer[1] == 0 {
+    return Err(Error::FirmwareError);
+  }
+  Ok(Framebuffer {
+    base: buffer[0] as usize & BUS_ADDRESS_MASK,
+    size: buffer[1] as usize,
+    width: physical[0],
+    height: physical[1],
+    pitch: pitch[0],
+    depth: bpp[0],
+  })
+}
+
+pub fn report() {
+  match (board_revision(), board_serial()) {
+    (Ok(revision), Ok(serial)) => { info!("board revision {:#x} serial {:016x}", revision, serial); }
+    (Err(e), _) | (_, Err(e)) => {
+      warn!("mailbox: no answer from the firmware {:?}", e);
+      return;
+    }
+  }
+  if let (Ok(arm), Ok(vc)) = (arm_memory(), vc_memory()) {
+    info!("memory split: ARM {:#x}..{:#x} VC {:#x}..{:#x}", arm.start, arm.end, vc.start, vc.end);
+  }
+  if let (Ok(arm), Ok(core)) = (clock_rate(Clock::Arm), clock_rate(Clock::Core)) {
+    info!("clock: ARM {} Hz core {} Hz", arm, core);
+  }
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use super::*;
+
+  ktest!(mailbox_arm_memory {
+    let arm = arm_memory().ok().unwrap();
+    assert_eq!(arm.start, 0);
+    assert!(arm.end > 0 && arm.end <= crate::board::BOARD_DEVICE_MEMORY_RANGE.start);
+    assert!(clock_rate(Clock::Arm).ok().unwrap() > 0);
+    assert!(matches!(call(&mut [Tag { id: TAG_GET_BOARD_REVISION, values: &mut [0; BUFFER_WORDS] }]), Err(Error::BufferSizeError)));
+  });
+}
@@ -0,0 +1,67 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::{Address, Arch, ArchTrait};
+use crate::driver::mmio::{read_word, write_word};
+
+// Note: BCM2837 mini UART (AUX), polled, second `-serial` of QEMU raspi3
+//       GPIO 14/15 are muxed by `uart::init` if this is the console
+//       built-in address is replaced by the device tree one in `init`
+const AUX_DEFAULT_BASE_ADDR: usize = 0xFFFFFF8000000000 + 0x3F215000;
+
+const AUX_COMPATIBLE: &[&str] = &["brcm,bcm2835-aux"];
+
+const AUX_ENABLES: usize = 0x04;
+const AUX_MU_IO_REG: usize = 0x40;
+const AUX_MU_IER_REG: usize = 0x44;
+const AUX_MU_IIR_REG: usize = 0x48;
+const AUX_MU_LCR_REG: usize = 0x4C;
+const AUX_MU_MCR_REG: usize = 0x50;
+const AUX_MU_LSR_REG: usize = 0x54;
+const AUX_MU_CNTL_REG: usize = 0x60;
+const AUX_MU_BAUD_REG: usize = 0x68;
+
+static AUX_BASE_ADDR: AtomicUsize = AtomicUsize::new(AUX_DEFAULT_BASE_ADDR);
+
+fn aux(offset: usize) -> usize {
+  AUX_BASE_ADDR.load(Ordering::Relaxed) + offset
+}
+
+pub fn init() -> () {
+  if let Some(pa) = crate::board::description::get().device(AUX_COMPATIBLE).and_then(|d| d.base()) {
+    AUX_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+  unsafe {
+    write_word(aux(AUX_ENABLES), 1);
+    write_word(aux(AUX_MU_IER_REG), 0);
+    write_word(aux(AUX_MU_CNTL_REG), 0);
+    write_word(aux(AUX_MU_LCR_REG), 3);
+    write_word(aux(AUX_MU_MCR_REG), 0);
+    write_word(aux(AUX_MU_IER_REG), 0);
+    write_word(aux(AUX_MU_IIR_REG), 0xC6);
+    write_word(aux(AUX_MU_BAUD_REG), 270);
+    write_word(aux(AUX_MU_CNTL_REG), 3);
+  }
+}
+
+// Note: raw byte, no newline translation (see `uart::putc`)
+pub fn putc(c: u8) {
+  unsafe {
+    loop {
+      if (read_word(aux(AUX_MU_LSR_REG)) & 0x20) != 0 {
+        break;
+      }
+      Arch::nop();
+    }
+    write_word(aux(AUX_MU_IO_REG), c as u32);
+  }
+}
+
+pub fn getc() -> Option<u8> {
+  unsafe {
+    if (read_word(aux(AUX_MU_LSR_REG)) & 0x01) != 0 {
+      Some((read_word(aux(AUX_MU_IO_REG)) & 0xff) as u8)
+    } else {
+      None
+    }
+  }
+}
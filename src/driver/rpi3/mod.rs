@@ -1,2 +1,13 @@
 pub mod uart;
-pub mod timer;
\ No newline at end of file
+pub mod mini_uart;
+pub mod pl011;
+pub mod gpio;
+pub mod armctrl;
+pub mod mailbox;
+pub mod framebuffer;
+pub mod net;
+pub mod port;
+pub mod rng;
+pub mod timer;
+pub mod rtc;
+pub mod qemu;
@@ -0,0 +1,17 @@
+use alloc::vec::Vec;
+
+use crate::lib::net::MacAddress;
+
+// Note: the Ethernet of the Raspberry Pi 3 hangs off USB (LAN9514), not supported
+//       QEMU raspi3 has no network device either
+pub fn init() -> Option<MacAddress> {
+  None
+}
+
+pub fn send(_frame: &[u8]) -> bool {
+  false
+}
+
+pub fn receive() -> Option<Vec<u8>> {
+  None
+}
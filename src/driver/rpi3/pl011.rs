@@ -0,0 +1,209 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::{Address, Arch, ArchTrait};
+use crate::driver::mmio::{read_word, write_word};
+
+// Note: PL011 (UART0), first `-serial` of QEMU raspi3
+//       rx and tx go through rings filled / drained by the FIFO interrupts
+//       with irq masked on this core (trap handlers, gdb stub) it falls back to polling
+//       built-in address and interrupt are replaced by the device tree ones in `init`
+const PL011_DEFAULT_BASE_ADDR: usize = 0xFFFFFF8000000000 + 0x3F201000;
+// Note: GPU irq 57
+const PL011_DEFAULT_INTERRUPT: (usize, usize) = (2, 25);
+
+const PL011_COMPATIBLE: &[&str] = &["arm,pl011"];
+
+static PL011_BASE_ADDR: AtomicUsize = AtomicUsize::new(PL011_DEFAULT_BASE_ADDR);
+
+const UART_DR: usize = 0x00;
+const UART_FR: usize = 0x18;
+const UART_IBRD: usize = 0x24;
+const UART_FBRD: usize = 0x28;
+const UART_LCRH: usize = 0x2C;
+const UART_CR: usize = 0x30;
+const UART_IFLS: usize = 0x34;
+const UART_IMSC: usize = 0x38;
+const UART_MIS: usize = 0x40;
+const UART_ICR: usize = 0x44;
+
+const UART_FR_RXFE: u32 = 1 << 4;
+const UART_FR_TXFF: u32 = 1 << 5;
+
+// Note: same bits in IMSC, MIS and ICR
+const UART_INT_RX: u32 = 1 << 4;
+const UART_INT_TX: u32 = 1 << 5;
+const UART_INT_RT: u32 = 1 << 6;
+
+// Note: DAIF.I as saved by `interrupt_save_disable`
+const DAIF_I: usize = 1 << 7;
+
+const RING_SIZE: usize = 1024;
+
+// Note: `head` and `tail` are byte counts, as the log ring
+struct Ring {
+  buf: [u8; RING_SIZE],
+  head: usize,
+  tail: usize,
+}
+
+impl Ring {
+  const fn new() -> Self {
+    Ring {
+      buf: [0; RING_SIZE],
+      head: 0,
+      tail: 0,
+    }
+  }
+
+  fn is_empty(&self) -> bool {
+    self.head == self.tail
+  }
+
+  fn is_full(&self) -> bool {
+    self.tail - self.head == RING_SIZE
+  }
+
+  fn push(&mut self, c: u8) -> bool {
+    if self.is_full() {
+      return false;
+    }
+    self.buf[self.tail % RING_SIZE] = c;
+    self.tail += 1;
+    true
+  }
+
+  fn pop(&mut self) -> Option<u8> {
+    if self.is_empty() {
+      return None;
+    }
+    let c = self.buf[self.head % RING_SIZE];
+    self.head += 1;
+    Some(c)
+  }
+}
+
+static RX: Mutex<Ring> = Mutex::new(Ring::new());
+static TX: Mutex<Ring> = Mutex::new(Ring::new());
+
+fn register(offset: usize) -> usize {
+  PL011_BASE_ADDR.load(Ordering::Relaxed) + offset
+}
+
+fn tx_full() -> bool {
+  unsafe { read_word(register(UART_FR)) & UART_FR_TXFF != 0 }
+}
+
+fn rx_empty() -> bool {
+  unsafe { read_word(register(UART_FR)) & UART_FR_RXFE != 0 }
+}
+
+fn send(c: u8) {
+  while tx_full() {}
+  unsafe { write_word(register(UART_DR), c as u32); }
+}
+
+fn receive() -> Option<u8> {
+  if rx_empty() {
+    None
+  } else {
+    Some(unsafe { (read_word(register(UART_DR)) & 0xff) as u8 })
+  }
+}
+
+// Note: caller holds the tx lock, IMSC is only changed under it
+fn set_tx_interrupt(enable: bool) {
+  unsafe {
+    let imsc = read_word(register(UART_IMSC));
+    write_word(register(UART_IMSC), if enable { imsc | UART_INT_TX } else { imsc & !UART_INT_TX });
+  }
+}
+
+pub fn init() {
+  let device = crate::board::description::get().device(PL011_COMPATIBLE);
+  if let Some(pa) = device.and_then(|d| d.base()) {
+    PL011_BASE_ADDR.store(pa.pa2kva(), Ordering::Relaxed);
+  }
+  let (bank, irq) = match device.map(|d| d.interrupts.as_slice()) {
+    Some([bank, irq, ..]) => { (*bank as usize, *irq as usize) }
+    _ => { PL011_DEFAULT_INTERRUPT }
+  };
+  unsafe {
+    write_word(register(UART_CR), 0);
+    write_word(register(UART_ICR), 0x7ff);
+    // Note: 115200 baud with 48 MHz UART clock
+    write_word(register(UART_IBRD), 26);
+    write_word(register(UART_FBRD), 3);
+    // 8n1, FIFO enabled
+    write_word(register(UART_LCRH), (0b11 << 5) | (1 << 4));
+    // Note: rx at 1/2 full (the timeout catches single keys), tx at 1/8 full
+    write_word(register(UART_IFLS), 0b010 << 3);
+    write_word(register(UART_IMSC), UART_INT_RX | UART_INT_RT);
+    // UARTEN | TXE | RXE
+    write_word(register(UART_CR), (1 << 0) | (1 << 8) | (1 << 9));
+  }
+  crate::driver::armctrl::enable(bank, irq);
+}
+
+// Note: raw byte, no newline translation (see `uart::putc`)
+//       queued only behind a full FIFO, the tx interrupt fires once it drains
+pub fn putc(c: u8) {
+  let state = Arch::interrupt_save_disable();
+  let mut tx = TX.lock();
+  if state & DAIF_I != 0 {
+    // Note: no interrupt to come, flush in order
+    while let Some(b) = tx.pop() {
+      send(b);
+    }
+    send(c);
+  } else if tx.is_empty() && !tx_full() {
+    unsafe { write_word(register(UART_DR), c as u32); }
+  } else {
+    if tx.is_full() {
+      send(tx.pop().unwrap());
+    }
+    tx.push(c);
+    set_tx_interrupt(true);
+  }
+  drop(tx);
+  Arch::interrupt_restore(state);
+}
+
+// Note: bytes in the ring came first, then those still in the FIFO
+pub fn getc() -> Option<u8> {
+  let state = Arch::interrupt_save_disable();
+  let mut rx = RX.lock();
+  let r = rx.pop().or_else(receive);
+  drop(rx);
+  Arch::interrupt_restore(state);
+  r
+}
+
+// Note: irq context, on core 0
+pub fn interrupt() {
+  let status = unsafe { read_word(register(UART_MIS)) };
+  if status & (UART_INT_RX | UART_INT_RT) != 0 {
+    let mut rx = RX.lock();
+    while let Some(c) = receive() {
+      // Note: dropped if nobody reads
+      rx.push(c);
+    }
+    drop(rx);
+    unsafe { write_word(register(UART_ICR), UART_INT_RX | UART_INT_RT); }
+  }
+  if status & UART_INT_TX != 0 {
+    let mut tx = TX.lock();
+    while !tx_full() {
+      match tx.pop() {
+        Some(c) => { unsafe { write_word(register(UART_DR), c as u32); } }
+        None => { break; }
+      }
+    }
+    if tx.is_empty() {
+      set_tx_interrupt(false);
+    }
+    drop(tx);
+    unsafe { write_word(register(UART_ICR), UART_INT_TX); }
+  }
+}
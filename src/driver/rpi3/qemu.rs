@@ -0,0 +1,18 @@
+use crate::arch::ArchTrait;
+
+// Note: Arm semihosting, QEMU needs `-semihosting`
+const SYS_EXIT: u64 = 0x18;
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+// Note: exit QEMU, its exit status is 0 on `success` or 1 otherwise
+pub fn exit(success: bool) -> ! {
+  // Note: aarch64 `SYS_EXIT` takes a pointer to (reason, exit code)
+  let block: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, if success { 0 } else { 1 }];
+  unsafe {
+    llvm_asm!("hlt #0xf000" :: "{x0}"(SYS_EXIT), "{x1}"(&block as *const _ as u64) : "memory" : "volatile");
+  }
+  // Note: not running in QEMU (or without semihosting)
+  loop {
+    crate::arch::Arch::wait_for_event();
+  }
+}
@@ -3,19 +3,51 @@ use crate::{
   lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait},
 };
 
-const TIMER_DEFAULT_COUNT: u32 = 10000000;
+// Note: one shot, interrupt once `counter()` reaches `count`, `None` stops the timer
+pub fn set_deadline(count: Option<u64>) {
+  use cortex_a::regs::*;
+  match count {
+    Some(count) => {
+      unsafe { llvm_asm!("msr cntp_cval_el0, $0" :: "r"(count) :: "volatile"); }
+      CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE.val(1) + CNTP_CTL_EL0::IMASK.val(0));
+    }
+    None => {
+      CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE.val(0) + CNTP_CTL_EL0::IMASK.val(1));
+    }
+  }
+}
 
-pub fn next() {
+// Note: free running counter of the generic timer
+pub fn counter() -> u64 {
   use cortex_a::regs::*;
-  CNTP_TVAL_EL0.set(TIMER_DEFAULT_COUNT);
-  CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE.val(1) + CNTP_CTL_EL0::IMASK.val(0));
+  CNTPCT_EL0.get()
+}
+
+// Note: `clock-frequency` of the device tree timer node if any, as Linux does
+pub fn frequency() -> u64 {
+  use cortex_a::regs::*;
+  crate::board::description::get().timebase_frequency.unwrap_or(CNTFRQ_EL0.get() as u64)
+}
+
+// Note: BCM2836 per core local peripherals, outside of the kernel linear map
+const LOCAL_INTC_DEFAULT_BASE_ADDR: usize = 0x4000_0000;
+const LOCAL_INTC_COMPATIBLE: &[&str] = &["brcm,bcm2836-l1-intc"];
+// Note: core timers interrupt control, by 4
+const LOCAL_INTC_TIMER_CONTROL: usize = 0x40;
+
+fn local_intc() -> usize {
+  crate::board::description::get().device(LOCAL_INTC_COMPATIBLE)
+    .and_then(|d| d.base())
+    .unwrap_or(LOCAL_INTC_DEFAULT_BASE_ADDR)
 }
 
 pub fn init(core_id: usize) {
+  let base = local_intc();
   if core_id == 0 {
     let page_table = crate::arch::PageTable::kernel_page_table();
-    page_table.map(0x4000_0000, 0x4000_0000, EntryAttribute::kernel_device());
+    page_table.map(base, base, EntryAttribute::kernel_device());
   }
-  unsafe { write_byte(0x4000_0040 + core_id * 4, 0b1111); }
-  next();
+  unsafe { write_byte(base + LOCAL_INTC_TIMER_CONTROL + core_id * 4, 0b1111); }
+  // Note: armed by the scheduler, see `lib/timer.rs`
+  set_deadline(None);
 }
@@ -1,67 +1,100 @@
-use crate::arch::{Arch, ArchTrait};
-use crate::driver::mmio::{read_word, write_word};
-
-const GPFSEL1: usize = 0xFFFFFF8000000000 + 0x3F200004;
-const GPPUD: usize = 0xFFFFFF8000000000 + 0x3F200094;
-const GPPUDCLK0: usize = 0xFFFFFF8000000000 + 0x3F200098;
-
-const AUX_ENABLES: usize = 0xFFFFFF8000000000 + 0x3F215004;
-const AUX_MU_IO_REG: usize = 0xFFFFFF8000000000 + 0x3F215040;
-const AUX_MU_IER_REG: usize = 0xFFFFFF8000000000 + 0x3F215044;
-const AUX_MU_IIR_REG: usize = 0xFFFFFF8000000000 + 0x3F215048;
-const AUX_MU_LCR_REG: usize = 0xFFFFFF8000000000 + 0x3F21504C;
-const AUX_MU_MCR_REG: usize = 0xFFFFFF8000000000 + 0x3F215050;
-const AUX_MU_LSR_REG: usize = 0xFFFFFF8000000000 + 0x3F215054;
-const AUX_MU_CNTL_REG: usize = 0xFFFFFF8000000000 + 0x3F215060;
-const AUX_MU_BAUD_REG: usize = 0xFFFFFF8000000000 + 0x3F215068;
-
-fn clock_delay(n: u32) -> () {
-  for _ in 0..n {
-    Arch::nop();
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::CONFIG_CONSOLE;
+use crate::driver::{gpio, mini_uart, pl011};
+
+// Note: console is one of the two UARTs, the other one is left to the gdb stub
+//       chosen by `CONFIG_CONSOLE`, else by `/chosen/stdout-path` of the device tree,
+//       else the mini UART (QEMU raspi3: second `-serial`)
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Port {
+  MiniUart,
+  Pl011,
+}
+
+const PL011_COMPATIBLE: &str = "arm,pl011";
+
+// Note: TXD / RXD of the header
+const UART_PINS: [usize; 2] = [14, 15];
+
+static CONSOLE_PL011: AtomicBool = AtomicBool::new(false);
+
+impl Port {
+  pub fn putc(&self, c: u8) {
+    match self {
+      Port::MiniUart => { mini_uart::putc(c) }
+      Port::Pl011 => { pl011::putc(c) }
+    }
+  }
+
+  pub fn getc(&self) -> Option<u8> {
+    match self {
+      Port::MiniUart => { mini_uart::getc() }
+      Port::Pl011 => { pl011::getc() }
+    }
+  }
+
+  fn pin_function(&self) -> gpio::Function {
+    match self {
+      Port::MiniUart => { gpio::Function::Alt5 }
+      Port::Pl011 => { gpio::Function::Alt0 }
+    }
   }
 }
 
-pub fn init() -> () {
-  unsafe {
-    write_word(AUX_ENABLES, 1);
-    write_word(AUX_MU_IER_REG, 0);
-    write_word(AUX_MU_CNTL_REG, 0);
-    write_word(AUX_MU_LCR_REG, 3);
-    write_word(AUX_MU_MCR_REG, 0);
-    write_word(AUX_MU_IER_REG, 0);
-    write_word(AUX_MU_IIR_REG, 0xC6);
-    write_word(AUX_MU_BAUD_REG, 270);
-
-    let mut ra = read_word(GPFSEL1);
-    ra &= !(7u32 << 12);
-    ra |= 2u32 << 12;
-    ra &= !(7u32 << 15);
-    ra |= 2u32 << 15;
-    write_word(GPFSEL1, ra);
-    write_word(GPPUD, 0);
-    clock_delay(150);
-    write_word(GPPUDCLK0, (1u32 << 14) | (1u32 << 15));
-    clock_delay(150);
-    write_word(GPPUDCLK0, 0);
-    write_word(AUX_MU_CNTL_REG, 3);
+fn select() -> Port {
+  if let Some(port) = CONFIG_CONSOLE {
+    return port;
+  }
+  match crate::board::description::get().stdout() {
+    Some(d) if d.compatible.iter().any(|c| c == PL011_COMPATIBLE) => { Port::Pl011 }
+    _ => { Port::MiniUart }
   }
 }
 
-fn send(c: u8) {
-  unsafe {
-    loop {
-      if (read_word(AUX_MU_LSR_REG) & 0x20) != 0 {
-        break;
-      }
-      Arch::nop();
-    }
-    write_word(AUX_MU_IO_REG, c as u32);
+pub fn console() -> Port {
+  if CONSOLE_PL011.load(Ordering::Relaxed) { Port::Pl011 } else { Port::MiniUart }
+}
+
+pub fn debug() -> Port {
+  match console() {
+    Port::MiniUart => { Port::Pl011 }
+    Port::Pl011 => { Port::MiniUart }
+  }
+}
+
+// Note: only the console gets GPIO 14/15, on real hardware the PL011 is
+//       wired to Bluetooth otherwise and the mini UART to nothing
+pub fn init() {
+  gpio::init();
+  mini_uart::init();
+  pl011::init();
+  let port = select();
+  CONSOLE_PL011.store(port == Port::Pl011, Ordering::Relaxed);
+  for pin in UART_PINS.iter() {
+    gpio::select(*pin, port.pin_function());
   }
+  gpio::disable_pull(&UART_PINS);
 }
 
 pub fn putc(c: u8) {
+  let port = console();
   if c == b'\n' {
-    send(b'\r');
+    port.putc(b'\r');
   }
-  send(c);
-}
\ No newline at end of file
+  port.putc(c);
+}
+
+pub fn putc_raw(c: u8) {
+  console().putc(c);
+}
+
+pub fn getc() -> Option<u8> {
+  console().getc()
+}
+
+// Note: irq context, only the PL011 has its interrupts on
+pub fn interrupt() {
+  pl011::interrupt();
+}
@@ -0,0 +1,159 @@
+use crate::arch::{Address, ContextFrame, ContextFrameTrait, FRAME_RECORD_FP_OFFSET, FRAME_RECORD_RA_OFFSET, MACHINE_SIZE};
+
+const BACKTRACE_DEPTH: usize = 32;
+
+// Note: symbol table embedded in `.ksymtab` after link (see Makefile)
+//       format is the output of `nm -n -C`: "<hex address> <type> <name>\n"
+//       terminated (padded) by NUL bytes
+#[cfg(not(test))]
+fn symbol_table() -> &'static [u8] {
+  extern "C" {
+    // Note: link-time label, see kernel.*.lds
+    fn KSYMTAB_START();
+    fn KSYMTAB_END();
+  }
+  let start = KSYMTAB_START as usize;
+  let end = KSYMTAB_END as usize;
+  unsafe { core::slice::from_raw_parts(start as *const u8, end - start) }
+}
+
+#[cfg(test)]
+fn symbol_table() -> &'static [u8] {
+  &[]
+}
+
+fn parse_hex(s: &[u8]) -> Option<usize> {
+  if s.is_empty() {
+    return None;
+  }
+  let mut r: usize = 0;
+  for c in s {
+    let d = match c {
+      b'0'..=b'9' => c - b'0',
+      b'a'..=b'f' => c - b'a' + 10,
+      b'A'..=b'F' => c - b'A' + 10,
+      _ => { return None; }
+    };
+    r = r.checked_mul(16)?.checked_add(d as usize)?;
+  }
+  Some(r)
+}
+
+// Note: find the text symbol covering `pc`, return its name and offset
+pub fn symbol(pc: usize) -> Option<(&'static str, usize)> {
+  let table = symbol_table();
+  let mut best: Option<(usize, &'static [u8])> = None;
+  for line in table.split(|c| *c == b'\n') {
+    if line.first().map_or(true, |c| *c == 0) {
+      break;
+    }
+    let mut fields = line.splitn(3, |c| *c == b' ');
+    let addr = match fields.next().and_then(parse_hex) {
+      Some(addr) => { addr }
+      None => { continue; }
+    };
+    match fields.next() {
+      Some(b"t") | Some(b"T") => {}
+      _ => { continue; }
+    }
+    let name = match fields.next() {
+      Some(name) => { name }
+      None => { continue; }
+    };
+    // Note: table is sorted by address
+    if addr > pc {
+      break;
+    }
+    best = Some((addr, name));
+  }
+  let (addr, name) = best?;
+  let name = core::str::from_utf8(name).ok()?;
+  Some((name, pc - addr))
+}
+
+pub fn print_frame(depth: usize, pc: usize) {
+  match symbol(pc) {
+    Some((name, offset)) => { println!("  #{:02} {:016x} {}+0x{:x}", depth, pc, name, offset); }
+    None => { println!("  #{:02} {:016x} ?", depth, pc); }
+  }
+}
+
+// Note: RAM in the linear map, or a kernel stack (thread stacks are in their own window)
+fn valid_kernel_address(addr: usize) -> bool {
+  if crate::mm::kernel_stack::in_stack(addr) {
+    return true;
+  }
+  let pa = addr.kva2pa();
+  pa.pa2kva() == addr && crate::board::description::get().memory.iter().any(|r| r.contains(&pa))
+}
+
+// Note: only follow frame pointers to frame records in mapped kernel memory
+fn valid_kernel_fp(fp: usize) -> bool {
+  fp != 0
+    && fp % MACHINE_SIZE == 0
+    && valid_kernel_address((fp as isize + FRAME_RECORD_FP_OFFSET) as usize)
+    && valid_kernel_address((fp as isize + FRAME_RECORD_RA_OFFSET) as usize)
+}
+
+// Note: walk kernel frame records starting at `fp`, return the number of frames printed
+//       kernel is built with frame pointers (see target.*.json)
+pub fn print_kernel_backtrace(fp: usize, first_depth: usize) -> usize {
+  let mut fp = fp;
+  let mut printed = 0;
+  for depth in first_depth..BACKTRACE_DEPTH {
+    if !valid_kernel_fp(fp) {
+      break;
+    }
+    let record_fp = (fp as isize + FRAME_RECORD_FP_OFFSET) as usize;
+    let record_ra = (fp as isize + FRAME_RECORD_RA_OFFSET) as usize;
+    let (next, ra) = unsafe {
+      (core::intrinsics::volatile_load(record_fp as *const usize),
+       core::intrinsics::volatile_load(record_ra as *const usize))
+    };
+    if ra == 0 {
+      break;
+    }
+    print_frame(depth, ra);
+    printed += 1;
+    if next <= fp {
+      break;
+    }
+    fp = next;
+  }
+  printed
+}
+
+// Note: print backtrace of a trapped kernel context
+//       the context itself is dumped by the panic handler
+pub fn print_context(ctx: &ContextFrame) {
+  println!("trap backtrace:");
+  print_frame(0, ctx.exception_pc());
+  print_frame(1, ctx.link_register());
+  print_kernel_backtrace(ctx.frame_pointer(), 2);
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use crate::mm::kernel_stack::KernelStack;
+
+  use super::*;
+
+  // Note: frame records as a kernel thread leaves them on its stack, in the kernel stack window
+  ktest!(kernel_stack_backtrace {
+    let stack = KernelStack::new().ok().unwrap();
+    let frames = 4;
+    let fp = |i: usize| stack.top() - (frames - i) * 64;
+    for i in 0..frames {
+      let next = if i + 1 < frames { fp(i + 1) } else { 0 };
+      let record_fp = (fp(i) as isize + FRAME_RECORD_FP_OFFSET) as *mut usize;
+      let record_ra = (fp(i) as isize + FRAME_RECORD_RA_OFFSET) as *mut usize;
+      unsafe {
+        core::intrinsics::volatile_store(record_fp, next);
+        core::intrinsics::volatile_store(record_ra, print_frame as usize + i);
+      }
+    }
+    assert_eq!(print_kernel_backtrace(fp(0), 2), frames);
+    // Note: nothing is followed into a guard
+    assert!(!valid_kernel_fp(stack.bottom() - 64));
+  });
+}
@@ -45,4 +45,42 @@ impl BitMap {
     }
     panic!("bitmap: out of zero bit");
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn alloc_lowest_clear_bit() {
+    let mut bitmap = BitMap::new();
+    assert_eq!(bitmap.alloc(), 0);
+    assert_eq!(bitmap.alloc(), 1);
+    assert_eq!(bitmap.alloc(), 2);
+    bitmap.clear(1);
+    assert!(!bitmap.is_set(1));
+    assert_eq!(bitmap.alloc(), 1);
+    assert!(bitmap.is_set(1));
+  }
+
+  #[test]
+  fn alloc_across_atoms() {
+    let mut bitmap = BitMap::new();
+    for i in 0..BITMAP_ATOMIC_SIZE {
+      bitmap.set(i);
+    }
+    assert_eq!(bitmap.alloc(), BITMAP_ATOMIC_SIZE);
+    bitmap.clear(BITMAP_ATOMIC_SIZE - 1);
+    assert_eq!(bitmap.alloc(), BITMAP_ATOMIC_SIZE - 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "out of zero bit")]
+  fn alloc_full() {
+    let mut bitmap = BitMap::new();
+    for i in 0..BITMAP_SIZE {
+      bitmap.set(i);
+    }
+    bitmap.alloc();
+  }
+}
@@ -0,0 +1,162 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::lib::process::{Pid, Process};
+
+// Note: byte stream devices by name (virtio console ports, `random`), opened by `chardev_open`
+//       the number returned is the slot, the same in every process, devices are never removed
+//       a process only uses the devices it opened, they are closed when it exits
+//       reads and writes never block, `WouldBlockError` asks the caller to try again later
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  NotFoundError,
+  NotOpenError,
+  WouldBlockError,
+}
+
+pub trait CharDevice: Send + Sync {
+  fn open(&self) {}
+  fn close(&self) {}
+  // Note: 0 at end of stream (e.g. nobody on the host side)
+  fn read(&self, buf: &mut [u8]) -> Result<usize, Error>;
+  fn write(&self, data: &[u8]) -> Result<usize, Error>;
+}
+
+static DEVICES: Mutex<Vec<(String, Arc<dyn CharDevice>)>> = Mutex::new(Vec::new());
+// Note: one entry per `open`, by owner and slot
+static OPENED: Mutex<Vec<(Pid, usize)>> = Mutex::new(Vec::new());
+
+// Note: names are unique, a second device of the same name is not added
+pub fn register(name: &str, device: Arc<dyn CharDevice>) {
+  let mut devices = DEVICES.lock();
+  if devices.iter().any(|(n, _)| n == name) {
+    warn!("chardev: {} registered twice", name);
+  } else {
+    info!("chardev: {}", name);
+    devices.push((name.to_string(), device));
+  }
+  drop(devices);
+}
+
+fn find(name: &str) -> Option<usize> {
+  let devices = DEVICES.lock();
+  let r = devices.iter().position(|(n, _)| n == name);
+  drop(devices);
+  r
+}
+
+// Note: the lock is not held while the device works
+fn get(id: usize) -> Result<Arc<dyn CharDevice>, Error> {
+  let devices = DEVICES.lock();
+  let r = devices.get(id).map(|(_, d)| d.clone());
+  drop(devices);
+  r.ok_or(Error::NotFoundError)
+}
+
+// Note: the device of `id` if `owner` has it open
+fn opened(owner: Pid, id: usize) -> Result<Arc<dyn CharDevice>, Error> {
+  let device = get(id)?;
+  let opened = OPENED.lock();
+  let r = opened.contains(&(owner, id));
+  drop(opened);
+  if r { Ok(device) } else { Err(Error::NotOpenError) }
+}
+
+pub fn open(owner: Pid, name: &str) -> Result<usize, Error> {
+  let id = find(name).ok_or(Error::NotFoundError)?;
+  let device = get(id)?;
+  let mut opened = OPENED.lock();
+  opened.push((owner, id));
+  drop(opened);
+  device.open();
+  Ok(id)
+}
+
+pub fn read(owner: Pid, id: usize, buf: &mut [u8]) -> Result<usize, Error> {
+  opened(owner, id)?.read(buf)
+}
+
+pub fn write(owner: Pid, id: usize, data: &[u8]) -> Result<usize, Error> {
+  opened(owner, id)?.write(data)
+}
+
+pub fn close(owner: Pid, id: usize) -> Result<(), Error> {
+  let device = get(id)?;
+  let mut opened = OPENED.lock();
+  let r = opened.iter().position(|o| *o == (owner, id)).map(|i| opened.remove(i));
+  drop(opened);
+  r.ok_or(Error::NotOpenError)?;
+  device.close();
+  Ok(())
+}
+
+// Note: called on process exit, devices opened by `p` are closed
+pub fn release_process(p: &Process) {
+  let mut opened = OPENED.lock();
+  let (closed, kept): (Vec<_>, Vec<_>) = opened.drain(..).partition(|(owner, _)| *owner == p.pid());
+  *opened = kept;
+  drop(opened);
+  for (_, id) in closed {
+    if let Ok(device) = get(id) {
+      device.close();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Echo(Mutex<Vec<u8>>);
+
+  impl CharDevice for Echo {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+      let mut data = self.0.lock();
+      if data.is_empty() {
+        return Err(Error::WouldBlockError);
+      }
+      let n = core::cmp::min(buf.len(), data.len());
+      buf[..n].copy_from_slice(&data[..n]);
+      data.drain(..n);
+      Ok(n)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, Error> {
+      self.0.lock().extend_from_slice(data);
+      Ok(data.len())
+    }
+  }
+
+  #[test]
+  fn registry() {
+    register("test-echo", Arc::new(Echo(Mutex::new(Vec::new()))));
+    register("test-echo", Arc::new(Echo(Mutex::new(alloc::vec![0xff]))));
+    assert!(find("test-echo").is_some());
+    assert!(find("test-none").is_none());
+    assert!(get(usize::MAX).is_err());
+    assert_eq!(open(1, "test-none"), Err(Error::NotFoundError));
+  }
+
+  #[test]
+  fn open_per_owner() {
+    register("test-owned", Arc::new(Echo(Mutex::new(Vec::new()))));
+    let (a, b) = (Pid::MAX - 1, Pid::MAX - 2);
+    let id = open(a, "test-owned").unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(read(a, id, &mut buf), Err(Error::WouldBlockError));
+    assert_eq!(write(a, id, b"ab"), Ok(2));
+    // Note: not opened by `b`
+    assert_eq!(read(b, id, &mut buf), Err(Error::NotOpenError));
+    assert_eq!(write(b, id, b"cd"), Err(Error::NotOpenError));
+    assert_eq!(close(b, id), Err(Error::NotOpenError));
+    assert_eq!(read(a, id, &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"ab");
+    assert_eq!(close(a, id), Ok(()));
+    assert_eq!(close(a, id), Err(Error::NotOpenError));
+    assert_eq!(read(a, id, &mut buf), Err(Error::NotOpenError));
+  }
+}
@@ -1,13 +1,34 @@
-use crate::arch::{ArchTrait, ContextFrame, CoreTrait};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::arch::{Arch, ArchTrait, ContextFrame, CoreTrait};
 use crate::board::BOARD_CORE_NUMBER;
 use crate::lib::scheduler::{RoundRobinScheduler, SchedulerTrait};
+use crate::lib::process::Process;
 use crate::lib::thread::Thread;
 use spin::Mutex;
 
 pub struct Core {
   context: Mutex<*mut ContextFrame>,
   running_thread: Mutex<Option<Thread>>,
+  // Note: destroyed while running, its kernel stack is in use until the core switches away
+  retired: Mutex<Option<Thread>>,
+  // Note: owner of the installed user page table, holds its directory while installed
+  //       kernel threads run on whatever was installed before them
+  address_space: Mutex<Option<Process>>,
   scheduler: Mutex<RoundRobinScheduler>,
+  // Note: runs when nothing else is runnable, never in the scheduler's candidates
+  idle: Mutex<Option<Thread>>,
+  // Note: nanoseconds, charged on every pick
+  idle_time: AtomicU64,
+  busy_time: AtomicU64,
+  last_pick: AtomicU64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Usage {
+  pub idle: u64,
+  pub busy: u64,
 }
 
 // Note: only the core itself can be allowed to access its `Core`
@@ -17,7 +38,13 @@ unsafe impl core::marker::Sync for Core {}
 static CORES: [Core; BOARD_CORE_NUMBER] = [Core {
   context: Mutex::new(0usize as *mut ContextFrame),
   running_thread: Mutex::new(None),
+  retired: Mutex::new(None),
+  address_space: Mutex::new(None),
   scheduler: Mutex::new(RoundRobinScheduler::new()),
+  idle: Mutex::new(None),
+  idle_time: AtomicU64::new(0),
+  busy_time: AtomicU64::new(0),
+  last_pick: AtomicU64::new(0),
 }; BOARD_CORE_NUMBER];
 
 impl CoreTrait for Core {
@@ -67,10 +94,96 @@ impl CoreTrait for Core {
     drop(lock);
   }
 
+  // Note: the scheduler is not held across the switch
   fn schedule(&self) {
     let mut lock = self.scheduler.lock();
-    lock.schedule();
+    let picked = lock.pick();
+    drop(lock);
+    self.account();
+    let t = match picked {
+      Some(t) => { t }
+      None => { self.idle_thread() }
+    };
+    crate::lib::timer::arm(!self.is_idle(&t));
+    trace!("switch to [{}]", t.tid());
+    t.run();
+  }
+}
+
+impl Core {
+  pub fn retire(&self, t: Thread) {
+    let mut lock = self.retired.lock();
+    let previous = lock.replace(t);
+    drop(lock);
+    drop(previous);
+  }
+
+  // Note: after its page table is installed, the previous owner may be freed here
+  pub fn set_address_space(&self, p: Process) {
+    let mut lock = self.address_space.lock();
+    let previous = lock.replace(p);
+    drop(lock);
+    drop(previous);
+  }
+
+  // Note: called once switched to another kernel stack
+  pub fn reap(&self) {
+    let mut lock = self.retired.lock();
+    let t = lock.take();
+    drop(lock);
+    drop(t);
+  }
+
+  fn idle_thread(&self) -> Thread {
+    let mut lock = self.idle.lock();
+    let r = lock.get_or_insert_with(|| crate::lib::thread::alloc_kernel(idle as usize, 0)).clone();
     drop(lock);
+    r
+  }
+
+  pub fn is_idle(&self, t: &Thread) -> bool {
+    let lock = self.idle.lock();
+    let r = lock.as_ref() == Some(t);
+    drop(lock);
+    r
+  }
+
+  // Note: before any thread runs (boot) counts as busy
+  fn idling(&self) -> bool {
+    self.running_thread().map_or(false, |t| self.is_idle(&t))
+  }
+
+  // Note: charge the time since the last pick to whatever was running
+  fn account(&self) {
+    let now = crate::lib::time::now();
+    let elapsed = now.saturating_sub(self.last_pick.swap(now, Ordering::Relaxed));
+    if self.idling() {
+      self.idle_time.fetch_add(elapsed, Ordering::Relaxed);
+    } else {
+      self.busy_time.fetch_add(elapsed, Ordering::Relaxed);
+    }
+  }
+
+  // Note: includes the time since the last pick
+  pub fn usage(&self) -> Usage {
+    let current = crate::lib::time::now().saturating_sub(self.last_pick.load(Ordering::Relaxed));
+    let mut usage = Usage {
+      idle: self.idle_time.load(Ordering::Relaxed),
+      busy: self.busy_time.load(Ordering::Relaxed),
+    };
+    if self.idling() {
+      usage.idle += current;
+    } else {
+      usage.busy += current;
+    }
+    usage
+  }
+}
+
+// Note: interrupts are on, woken up by the timer or a device
+fn idle(_arg: usize) {
+  loop {
+    Arch::wait_for_event();
   }
 }
 
@@ -78,3 +191,7 @@ pub fn current() -> &'static Core {
   let core_id = crate::arch::Arch::core_id();
   &CORES[core_id]
 }
+
+pub fn get(core_id: usize) -> Option<&'static Core> {
+  CORES.get(core_id)
+}
@@ -0,0 +1,101 @@
+use crate::arch::{Arch, ArchTrait, ContextFrameTrait, CoreTrait, FRAME_RECORD_FP_OFFSET, FRAME_RECORD_RA_OFFSET, MACHINE_SIZE, PAGE_SIZE};
+use crate::config::CONFIG_USER_LIMIT;
+use crate::lib::{current_core, current_thread, round_down};
+use crate::lib::page_table::PageTableTrait;
+use crate::lib::process::Process;
+use crate::lib::signal::{self, Signal};
+
+const CRASH_BACKTRACE_DEPTH: usize = 16;
+// Note: pages printed before and after the faulting page
+const CRASH_MAPPING_WINDOW: usize = 2;
+
+fn signal_name(sig: Signal) -> &'static str {
+  match sig {
+    signal::SIGILL => "SIGILL",
+    signal::SIGTRAP => "SIGTRAP",
+    signal::SIGSEGV => "SIGSEGV",
+    _ => "?",
+  }
+}
+
+// Note: deliver a fault signal to `p`
+//       print a crash report if the process is going to be killed by it
+pub fn fault(p: &Process, sig: Signal, reason: &str) {
+  signal::force(p, sig);
+  if signal::is_fatal(p, sig) {
+    report(p, sig, reason);
+  }
+}
+
+fn report(p: &Process, sig: Signal, reason: &str) {
+  let ctx = current_core().context();
+  let addr = Arch::fault_address();
+  println!();
+  println!("=== user crash report ===");
+  match current_thread() {
+    None => { println!("pid {} tid -", p.pid()); }
+    Some(t) => { println!("pid {} tid {}", p.pid(), t.tid()); }
+  }
+  println!("reason:  {}", reason);
+  println!("signal:  {} ({})", sig, signal_name(sig));
+  println!("fault:   {}", Arch::fault_syndrome());
+  println!("address: {:016x}", addr);
+  println!("pc:      {:016x}", ctx.exception_pc());
+  println!("registers:");
+  print!("{}", ctx);
+  match p.exception_handler() {
+    None => { println!("exception handler: none"); }
+    Some((entry, stack_top)) => { println!("exception handler: entry {:016x} stack top {:016x}", entry, stack_top); }
+  }
+  if addr < CONFIG_USER_LIMIT {
+    println!("mappings around {:016x}:", addr);
+    let page_table = p.page_table();
+    let fault_page = round_down(addr, PAGE_SIZE);
+    let first = fault_page.saturating_sub(CRASH_MAPPING_WINDOW * PAGE_SIZE);
+    for va in (first..=fault_page + CRASH_MAPPING_WINDOW * PAGE_SIZE).step_by(PAGE_SIZE) {
+      let mark = if va == fault_page { "=>" } else { "  " };
+      match page_table.lookup_page(va) {
+        None => { println!("{} {:016x}: not mapped", mark, va); }
+        Some(pte) => { println!("{} {:016x}: {}", mark, va, pte); }
+      }
+    }
+  }
+  println!("backtrace:");
+  println!("  #00 pc {:016x}", ctx.exception_pc());
+  println!("  #01 lr {:016x}", ctx.link_register());
+  backtrace(p, ctx.frame_pointer());
+  println!("=== end of crash report ===");
+}
+
+// Note: walk user frame records, user code needs to be built with frame pointers
+fn backtrace(p: &Process, fp: usize) {
+  let page_table = p.page_table();
+  let mut fp = fp;
+  for depth in 2..CRASH_BACKTRACE_DEPTH {
+    if fp == 0 || fp % MACHINE_SIZE != 0 || fp >= CONFIG_USER_LIMIT {
+      break;
+    }
+    let record_fp = (fp as isize + FRAME_RECORD_FP_OFFSET) as usize;
+    let record_ra = (fp as isize + FRAME_RECORD_RA_OFFSET) as usize;
+    let next: usize = match crate::lib::uaccess::read_user(page_table, record_fp) {
+      Ok(v) => { v }
+      Err(_) => {
+        println!("  frame pointer {:016x} not readable", fp);
+        break;
+      }
+    };
+    let ra: usize = match crate::lib::uaccess::read_user(page_table, record_ra) {
+      Ok(v) => { v }
+      Err(_) => { break; }
+    };
+    if ra == 0 {
+      break;
+    }
+    println!("  #{:02} ra {:016x} fp {:016x}", depth, ra, fp);
+    // Note: stack grows down, frames must move up
+    if next <= fp {
+      break;
+    }
+    fp = next;
+  }
+}
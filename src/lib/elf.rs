@@ -1,5 +1,6 @@
 use crate::arch::{PAGE_SIZE, PageTable};
 use crate::lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::mm::page_pool::{FRAME_USER, Owner};
 use crate::mm::PageFrame;
 
 unsafe fn memcpy(src: &'static [u8], offset: usize, dest: PageFrame, length: usize) {
@@ -28,7 +29,10 @@ pub unsafe fn load_elf(src: &'static [u8], page_table: PageTable) -> usize {
       for i in (va..va + mem_size).step_by(PAGE_SIZE) {
         /* Note: we require `LOAD` type program data page aligned */
         assert_eq!(i % PAGE_SIZE, 0);
-        let frame = crate::mm::page_pool::alloc();
+        let frame = match crate::mm::page_pool::try_alloc_tagged(FRAME_USER, Owner::Unowned) {
+          Ok(frame) => { frame }
+          Err(_) => { panic!("elf: load_elf: out of frame") }
+        };
         if i + PAGE_SIZE > va + mem_size {
           // last page
           if i > va + file_size {
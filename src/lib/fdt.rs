@@ -0,0 +1,422 @@
+use alloc::vec::Vec;
+
+// Note: flattened device tree blob (devicetree specification v0.3, chapter 5)
+//       parsed into a tree of nodes borrowing names and values from the blob
+
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+pub const FDT_HEADER_SIZE: usize = 40;
+
+// Note: oldest format with the layout below, dtc emits 17
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  BadMagicError,
+  UnsupportedVersionError,
+  TruncatedError,
+  BadStructureError,
+}
+
+#[derive(Debug)]
+pub struct Property<'a> {
+  pub name: &'a str,
+  pub value: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct Node<'a> {
+  // Note: with unit address, e.g. "uart@10000000", empty for the root
+  pub name: &'a str,
+  pub properties: Vec<Property<'a>>,
+  pub children: Vec<Node<'a>>,
+}
+
+#[derive(Debug)]
+pub struct DeviceTree<'a> {
+  pub root: Node<'a>,
+  // Note: memory reservation block, (address, size)
+  pub reservations: Vec<(u64, u64)>,
+}
+
+fn be32(blob: &[u8], offset: usize) -> Result<u32, Error> {
+  match blob.get(offset..offset + 4) {
+    Some(b) => { Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])) }
+    None => { Err(Error::TruncatedError) }
+  }
+}
+
+fn be64(blob: &[u8], offset: usize) -> Result<u64, Error> {
+  Ok(((be32(blob, offset)? as u64) << 32) | be32(blob, offset + 4)? as u64)
+}
+
+fn c_str(blob: &[u8], offset: usize) -> Result<&str, Error> {
+  let bytes = blob.get(offset..).ok_or(Error::TruncatedError)?;
+  let len = bytes.iter().position(|b| *b == 0).ok_or(Error::TruncatedError)?;
+  core::str::from_utf8(&bytes[..len]).map_err(|_| Error::BadStructureError)
+}
+
+fn block(blob: &[u8], offset: u32, size: u32) -> Result<&[u8], Error> {
+  let start = offset as usize;
+  blob.get(start..start + size as usize).ok_or(Error::TruncatedError)
+}
+
+// Note: `totalsize` in the header, e.g. to copy the blob before parsing
+pub fn total_size(header: &[u8]) -> Result<usize, Error> {
+  if be32(header, 0)? != FDT_MAGIC {
+    return Err(Error::BadMagicError);
+  }
+  Ok(be32(header, 4)? as usize)
+}
+
+struct Cursor<'a> {
+  structure: &'a [u8],
+  strings: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+  fn token(&mut self) -> Result<u32, Error> {
+    let t = be32(self.structure, self.offset)?;
+    self.offset += 4;
+    Ok(t)
+  }
+
+  fn skip_nop(&mut self) -> Result<u32, Error> {
+    loop {
+      match self.token()? {
+        FDT_NOP => {}
+        t => { return Ok(t); }
+      }
+    }
+  }
+
+  // Note: called after FDT_BEGIN_NODE, consumes the matching FDT_END_NODE
+  fn node(&mut self) -> Result<Node<'a>, Error> {
+    let name = c_str(self.structure, self.offset)?;
+    self.offset = crate::lib::round_up(self.offset + name.len() + 1, 4);
+    let mut node = Node {
+      name,
+      properties: Vec::new(),
+      children: Vec::new(),
+    };
+    loop {
+      match self.token()? {
+        FDT_PROP => {
+          let len = be32(self.structure, self.offset)? as usize;
+          let name_offset = be32(self.structure, self.offset + 4)? as usize;
+          let start = self.offset + 8;
+          let value = self.structure.get(start..start + len).ok_or(Error::TruncatedError)?;
+          node.properties.push(Property {
+            name: c_str(self.strings, name_offset)?,
+            value,
+          });
+          self.offset = crate::lib::round_up(start + len, 4);
+        }
+        FDT_BEGIN_NODE => { node.children.push(self.node()?); }
+        FDT_END_NODE => { return Ok(node); }
+        FDT_NOP => {}
+        _ => { return Err(Error::BadStructureError); }
+      }
+    }
+  }
+}
+
+pub fn parse(blob: &[u8]) -> Result<DeviceTree<'_>, Error> {
+  let size = total_size(blob)?;
+  let blob = blob.get(..size).ok_or(Error::TruncatedError)?;
+  if be32(blob, 24)? > FDT_LAST_COMPATIBLE_VERSION {
+    return Err(Error::UnsupportedVersionError);
+  }
+  let structure = block(blob, be32(blob, 8)?, be32(blob, 36)?)?;
+  let strings = block(blob, be32(blob, 12)?, be32(blob, 32)?)?;
+
+  let mut reservations = Vec::new();
+  let mut offset = be32(blob, 16)? as usize;
+  loop {
+    let address = be64(blob, offset)?;
+    let size = be64(blob, offset + 8)?;
+    if address == 0 && size == 0 {
+      break;
+    }
+    reservations.push((address, size));
+    offset += 16;
+  }
+
+  let mut cursor = Cursor { structure, strings, offset: 0 };
+  if cursor.skip_nop()? != FDT_BEGIN_NODE {
+    return Err(Error::BadStructureError);
+  }
+  let root = cursor.node()?;
+  if cursor.skip_nop()? != FDT_END {
+    return Err(Error::BadStructureError);
+  }
+  Ok(DeviceTree { root, reservations })
+}
+
+// Note: a number of `n` cells (big endian u32) at the start of `value`, and the rest
+pub fn read_cells(value: &[u8], n: usize) -> Option<(u64, &[u8])> {
+  if n > 2 || value.len() < n * 4 {
+    return None;
+  }
+  let mut r = 0u64;
+  for i in 0..n {
+    r = (r << 32) | be32(value, i * 4).ok()? as u64;
+  }
+  Some((r, &value[n * 4..]))
+}
+
+impl<'a> Node<'a> {
+  // Note: node name without unit address
+  pub fn base_name(&self) -> &'a str {
+    match self.name.find('@') {
+      Some(i) => { &self.name[..i] }
+      None => { self.name }
+    }
+  }
+
+  pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+    self.properties.iter().find(|p| p.name == name).map(|p| p.value)
+  }
+
+  pub fn u32(&self, name: &str) -> Option<u32> {
+    self.property(name).and_then(|v| be32(v, 0).ok())
+  }
+
+  // Note: `<u32>` or `<u64>` valued property, e.g. "clock-frequency"
+  pub fn u64(&self, name: &str) -> Option<u64> {
+    let value = self.property(name)?;
+    read_cells(value, value.len() / 4).map(|(v, _)| v)
+  }
+
+  pub fn cells(&self, name: &str) -> Vec<u32> {
+    let value = self.property(name).unwrap_or(&[]);
+    (0..value.len() / 4).filter_map(|i| be32(value, i * 4).ok()).collect()
+  }
+
+  // Note: `<stringlist>` property, e.g. "compatible"
+  pub fn strings(&self, name: &str) -> Vec<&'a str> {
+    let value = self.property(name).unwrap_or(&[]);
+    value.split(|b| *b == 0)
+      .filter(|s| !s.is_empty())
+      .filter_map(|s| core::str::from_utf8(s).ok())
+      .collect()
+  }
+
+  pub fn string(&self, name: &str) -> Option<&'a str> {
+    self.strings(name).first().copied()
+  }
+
+  // Note: by full name or by name without unit address
+  pub fn child(&self, name: &str) -> Option<&Node<'a>> {
+    self.children.iter().find(|c| c.name == name || c.base_name() == name)
+  }
+
+  // Note: cells of `reg` in children of this node
+  pub fn address_cells(&self) -> usize {
+    self.u32("#address-cells").unwrap_or(2) as usize
+  }
+
+  pub fn size_cells(&self) -> usize {
+    self.u32("#size-cells").unwrap_or(1) as usize
+  }
+
+  pub fn enabled(&self) -> bool {
+    match self.string("status") {
+      None | Some("okay") | Some("ok") => { true }
+      _ => { false }
+    }
+  }
+
+  // Note: (address, size) pairs of `reg`, cells given by the parent
+  pub fn reg(&self, address_cells: usize, size_cells: usize) -> Vec<(u64, u64)> {
+    let mut r = Vec::new();
+    if address_cells + size_cells == 0 {
+      return r;
+    }
+    let mut value = self.property("reg").unwrap_or(&[]);
+    while let Some((address, rest)) = read_cells(value, address_cells) {
+      match read_cells(rest, size_cells) {
+        Some((size, rest)) => {
+          r.push((address, size));
+          value = rest;
+        }
+        None => { break; }
+      }
+    }
+    r
+  }
+}
+
+// Note: blob builder for host tests, a subset of what dtc emits
+#[cfg(test)]
+pub mod builder {
+  use alloc::vec::Vec;
+
+  use super::*;
+
+  pub struct Builder {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    reservations: Vec<(u64, u64)>,
+  }
+
+  impl Builder {
+    pub fn new() -> Self {
+      Builder {
+        structure: Vec::new(),
+        strings: Vec::new(),
+        reservations: Vec::new(),
+      }
+    }
+
+    fn pad(&mut self) {
+      while self.structure.len() % 4 != 0 {
+        self.structure.push(0);
+      }
+    }
+
+    pub fn reserve(&mut self, address: u64, size: u64) -> &mut Self {
+      self.reservations.push((address, size));
+      self
+    }
+
+    pub fn begin(&mut self, name: &str) -> &mut Self {
+      self.structure.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+      self.structure.extend_from_slice(name.as_bytes());
+      self.structure.push(0);
+      self.pad();
+      self
+    }
+
+    pub fn end(&mut self) -> &mut Self {
+      self.structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+      self
+    }
+
+    pub fn nop(&mut self) -> &mut Self {
+      self.structure.extend_from_slice(&FDT_NOP.to_be_bytes());
+      self
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) -> &mut Self {
+      let name_offset = self.strings.len() as u32;
+      self.strings.extend_from_slice(name.as_bytes());
+      self.strings.push(0);
+      self.structure.extend_from_slice(&FDT_PROP.to_be_bytes());
+      self.structure.extend_from_slice(&(value.len() as u32).to_be_bytes());
+      self.structure.extend_from_slice(&name_offset.to_be_bytes());
+      self.structure.extend_from_slice(value);
+      self.pad();
+      self
+    }
+
+    pub fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+      let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes().to_vec()).collect();
+      self.property(name, &value)
+    }
+
+    pub fn strings(&mut self, name: &str, strings: &[&str]) -> &mut Self {
+      let mut value = Vec::new();
+      for s in strings {
+        value.extend_from_slice(s.as_bytes());
+        value.push(0);
+      }
+      self.property(name, &value)
+    }
+
+    pub fn build(&mut self) -> Vec<u8> {
+      let mut structure = self.structure.clone();
+      structure.extend_from_slice(&FDT_END.to_be_bytes());
+      let reservations_offset = FDT_HEADER_SIZE;
+      let structure_offset = reservations_offset + (self.reservations.len() + 1) * 16;
+      let strings_offset = structure_offset + structure.len();
+      let total = strings_offset + self.strings.len();
+      let mut blob = Vec::new();
+      for v in &[FDT_MAGIC, total as u32, structure_offset as u32, strings_offset as u32,
+        reservations_offset as u32, 17, 16, 0, self.strings.len() as u32, structure.len() as u32] {
+        blob.extend_from_slice(&v.to_be_bytes());
+      }
+      for (address, size) in self.reservations.iter().chain(core::iter::once(&(0, 0))) {
+        blob.extend_from_slice(&address.to_be_bytes());
+        blob.extend_from_slice(&size.to_be_bytes());
+      }
+      blob.extend_from_slice(&structure);
+      blob.extend_from_slice(&self.strings);
+      blob
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::builder::Builder;
+  use super::*;
+
+  fn sample() -> Vec<u8> {
+    Builder::new()
+      .reserve(0x8000_0000, 0x20_0000)
+      .begin("")
+      .cells("#address-cells", &[2])
+      .cells("#size-cells", &[2])
+      .strings("compatible", &["riscv-virtio"])
+      .begin("memory@80000000")
+      .strings("device_type", &["memory"])
+      .cells("reg", &[0, 0x8000_0000, 0, 0x4000_0000, 0x1, 0, 0, 0x1000])
+      .end()
+      .nop()
+      .begin("uart@10000000")
+      .cells("interrupts", &[10])
+      .strings("compatible", &["ns16550a"])
+      .cells("reg", &[0, 0x1000_0000, 0, 0x100])
+      .property("empty", &[])
+      .end()
+      .end()
+      .build()
+  }
+
+  #[test]
+  fn parse_tree() {
+    let blob = sample();
+    assert_eq!(total_size(&blob), Ok(blob.len()));
+    let tree = parse(&blob).unwrap();
+    assert_eq!(tree.reservations, vec![(0x8000_0000, 0x20_0000)]);
+    let root = &tree.root;
+    assert_eq!(root.name, "");
+    assert_eq!(root.string("compatible"), Some("riscv-virtio"));
+    assert_eq!(root.children.len(), 2);
+    let memory = root.child("memory").unwrap();
+    assert_eq!(memory.name, "memory@80000000");
+    assert_eq!(memory.reg(root.address_cells(), root.size_cells()),
+               vec![(0x8000_0000, 0x4000_0000), (0x1_0000_0000, 0x1000)]);
+    let uart = root.child("uart@10000000").unwrap();
+    assert_eq!(uart.strings("compatible"), vec!["ns16550a"]);
+    assert_eq!(uart.cells("interrupts"), vec![10]);
+    assert_eq!(uart.property("empty"), Some(&[][..]));
+    assert!(uart.enabled());
+    assert!(uart.property("missing").is_none());
+  }
+
+  #[test]
+  fn malformed() {
+    let blob = sample();
+    let mut bad = blob.clone();
+    bad[0] = 0;
+    assert_eq!(parse(&bad).err(), Some(Error::BadMagicError));
+    assert_eq!(parse(&blob[..blob.len() - 8]).err(), Some(Error::TruncatedError));
+    let unbalanced = Builder::new().begin("").begin("a").end().build();
+    assert!(parse(&unbalanced).is_err());
+  }
+
+  #[test]
+  fn cells() {
+    assert_eq!(read_cells(&[0, 0, 0, 1, 0, 0, 0, 2], 2), Some((0x1_0000_0002, &[][..])));
+    assert_eq!(read_cells(&[0, 0, 0, 1, 0, 0, 0, 2], 1), Some((1, &[0, 0, 0, 2][..])));
+    assert_eq!(read_cells(&[0, 0, 1], 1), None);
+  }
+}
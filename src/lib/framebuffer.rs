@@ -0,0 +1,323 @@
+use core::ops::Range;
+
+use spin::Mutex;
+
+use crate::arch::{Address, Arch, ArchTrait};
+use crate::config::CONFIG_FRAMEBUFFER_CONSOLE;
+use crate::lib::font::{FONT_HEIGHT, FONT_WIDTH, glyph};
+use crate::lib::process::{Pid, Process};
+
+// Note: linear framebuffer of the board driver (mailbox on raspi3, ramfb on riscv64)
+//       32 bits per pixel, 0x00RRGGBB
+//       kernel output is drawn on it as text while no process maps it (`framebuffer_map`)
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  BusyError,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Info {
+  // Note: physical address, page aligned
+  pub pa: usize,
+  pub size: usize,
+  pub width: u32,
+  pub height: u32,
+  // Note: bytes per line
+  pub pitch: u32,
+  // Note: bits per pixel
+  pub depth: u32,
+}
+
+// Note: a glyph and one pixel of spacing right and below
+const CELL_WIDTH: usize = FONT_WIDTH + 1;
+const CELL_HEIGHT: usize = FONT_HEIGHT + 1;
+const TAB: usize = 8;
+
+const FOREGROUND: u32 = 0x00aa_aaaa;
+const BACKGROUND: u32 = 0x0000_0000;
+
+struct TextConsole {
+  // Note: kva of pixel (0, 0)
+  base: usize,
+  pitch: usize,
+  columns: usize,
+  rows: usize,
+  column: usize,
+  row: usize,
+}
+
+impl TextConsole {
+  fn new(base: usize, info: &Info) -> Self {
+    TextConsole {
+      base,
+      pitch: info.pitch as usize,
+      columns: info.width as usize / CELL_WIDTH,
+      rows: info.height as usize / CELL_HEIGHT,
+      column: 0,
+      row: 0,
+    }
+  }
+
+  // Note: bytes of the text area, whole lines of pixels
+  fn lines(&self, row: usize, n: usize) -> Range<usize> {
+    let start = self.base + row * CELL_HEIGHT * self.pitch;
+    start..start + n * CELL_HEIGHT * self.pitch
+  }
+
+  fn fill(&self, row: usize, n: usize, color: u32) {
+    let lines = self.lines(row, n);
+    for p in lines.step_by(4) {
+      unsafe { core::intrinsics::volatile_store(p as *mut u32, color); }
+    }
+  }
+
+  fn draw(&self, c: u8) {
+    let g = glyph(c);
+    let top = self.base + self.row * CELL_HEIGHT * self.pitch + self.column * CELL_WIDTH * 4;
+    for y in 0..CELL_HEIGHT {
+      for x in 0..CELL_WIDTH {
+        let on = x < FONT_WIDTH && (g[x] >> y) & 1 == 1;
+        let p = top + y * self.pitch + x * 4;
+        unsafe { core::intrinsics::volatile_store(p as *mut u32, if on { FOREGROUND } else { BACKGROUND }); }
+      }
+    }
+  }
+
+  // Note: one text line up, the last one cleared
+  fn scroll(&mut self) {
+    let to = self.lines(0, self.rows - 1);
+    let from = self.lines(1, self.rows - 1);
+    unsafe { core::ptr::copy(from.start as *const u8, to.start as *mut u8, to.end - to.start); }
+    self.fill(self.rows - 1, 1, BACKGROUND);
+  }
+
+  fn clear(&mut self) -> Range<usize> {
+    self.fill(0, self.rows, BACKGROUND);
+    self.column = 0;
+    self.row = 0;
+    self.lines(0, self.rows)
+  }
+
+  // Note: bytes to flush, a line or everything after a scroll
+  fn newline(&mut self) -> Range<usize> {
+    self.column = 0;
+    if self.row + 1 < self.rows {
+      self.row += 1;
+      self.lines(self.row, 1)
+    } else {
+      self.scroll();
+      self.lines(0, self.rows)
+    }
+  }
+
+  fn putc(&mut self, c: u8) -> Range<usize> {
+    match c {
+      b'\n' => { self.newline() }
+      b'\r' => {
+        self.column = 0;
+        0..0
+      }
+      b'\t' => {
+        self.column = core::cmp::min((self.column / TAB + 1) * TAB, self.columns - 1);
+        0..0
+      }
+      0x08 => {
+        self.column = self.column.saturating_sub(1);
+        0..0
+      }
+      _ => {
+        if self.column == self.columns {
+          let scrolled = self.newline();
+          self.draw(c);
+          self.column += 1;
+          return scrolled.start..core::cmp::max(scrolled.end, self.lines(self.row, 1).end);
+        }
+        self.draw(c);
+        self.column += 1;
+        self.lines(self.row, 1)
+      }
+    }
+  }
+}
+
+static INFO: Mutex<Option<Info>> = Mutex::new(None);
+static CONSOLE: Mutex<Option<TextConsole>> = Mutex::new(None);
+// Note: the process the screen is handed over to
+static OWNER: Mutex<Option<Pid>> = Mutex::new(None);
+
+pub fn init() {
+  let info = match crate::driver::framebuffer::init() {
+    Some(info) => { info }
+    None => {
+      info!("no framebuffer");
+      return;
+    }
+  };
+  info!("framebuffer {}x{} pitch {} at {:#x}", info.width, info.height, info.pitch, info.pa);
+  *INFO.lock() = Some(info);
+  attach_console(&info);
+}
+
+// Note: a blank screen with the log so far, as much as fits
+fn attach_console(info: &Info) {
+  if !CONFIG_FRAMEBUFFER_CONSOLE {
+    return;
+  }
+  let mut console = TextConsole::new(info.pa.pa2kva(), info);
+  let all = console.clear();
+  crate::driver::framebuffer::flush(all.start, all.end - all.start);
+  let backlog = crate::lib::log::read(console.columns * console.rows);
+  let irq = Arch::interrupt_save_disable();
+  *CONSOLE.lock() = Some(console);
+  Arch::interrupt_restore(irq);
+  for b in backlog {
+    putc(b);
+  }
+}
+
+pub fn info() -> Option<Info> {
+  let lock = INFO.lock();
+  let r = *lock;
+  drop(lock);
+  r
+}
+
+// Note: called by the log drain (log lock held) or on panic, a busy console drops the byte
+pub fn putc(c: u8) {
+  if let Some(mut lock) = CONSOLE.try_lock() {
+    if let Some(console) = lock.as_mut() {
+      let dirty = console.putc(c);
+      if dirty.start < dirty.end {
+        crate::driver::framebuffer::flush(dirty.start, dirty.end - dirty.start);
+      }
+    }
+  }
+}
+
+// Note: hand the screen over to `p`, the kernel stops drawing on it
+//       one process at a time, mapping it again from the owner is fine
+pub fn acquire(p: &Process) -> Result<(), Error> {
+  let mut owner = OWNER.lock();
+  if owner.map_or(false, |pid| pid != p.pid()) {
+    drop(owner);
+    return Err(Error::BusyError);
+  }
+  *owner = Some(p.pid());
+  drop(owner);
+  let irq = Arch::interrupt_save_disable();
+  let mut lock = CONSOLE.lock();
+  *lock = None;
+  drop(lock);
+  Arch::interrupt_restore(irq);
+  Ok(())
+}
+
+pub fn owner() -> Option<Pid> {
+  let lock = OWNER.lock();
+  let r = *lock;
+  drop(lock);
+  r
+}
+
+// Note: the owner exited, its mappings are gone, the text console comes back
+pub fn release_process(p: &Process) {
+  let mut owner = OWNER.lock();
+  if *owner != Some(p.pid()) {
+    drop(owner);
+    return;
+  }
+  *owner = None;
+  drop(owner);
+  if let Some(info) = info() {
+    attach_console(&info);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::lib::page_table::PageTableTrait;
+
+  use super::*;
+
+  const WIDTH: usize = 4 * CELL_WIDTH;
+  const HEIGHT: usize = 3 * CELL_HEIGHT;
+
+  fn console(pixels: &mut [u32]) -> TextConsole {
+    let info = Info {
+      pa: 0,
+      size: WIDTH * HEIGHT * 4,
+      width: WIDTH as u32,
+      height: HEIGHT as u32,
+      pitch: (WIDTH * 4) as u32,
+      depth: 32,
+    };
+    TextConsole::new(pixels.as_mut_ptr() as usize, &info)
+  }
+
+  // Note: column `x` of the glyph at cell (`column`, `row`) as a font byte
+  fn column_bits(pixels: &[u32], column: usize, row: usize, x: usize) -> u8 {
+    (0..FONT_HEIGHT).fold(0, |bits, y| {
+      let on = pixels[(row * CELL_HEIGHT + y) * WIDTH + column * CELL_WIDTH + x] == FOREGROUND;
+      bits | ((on as u8) << y)
+    })
+  }
+
+  #[test]
+  fn owner() {
+    crate::mm::test_init();
+    let a = crate::lib::process::alloc(None);
+    let b = crate::lib::process::alloc(None);
+    assert_eq!(acquire(&a), Ok(()));
+    assert_eq!(acquire(&a), Ok(()));
+    assert_eq!(acquire(&b), Err(Error::BusyError));
+    assert_eq!(super::owner(), Some(a.pid()));
+    release_process(&b);
+    assert_eq!(super::owner(), Some(a.pid()));
+    release_process(&a);
+    assert_eq!(super::owner(), None);
+    assert_eq!(acquire(&b), Ok(()));
+    release_process(&b);
+    for p in [a, b].iter() {
+      p.page_table().destroy();
+      crate::lib::process::free(p);
+    }
+  }
+
+  #[test]
+  fn text() {
+    let mut pixels = vec![0xdead_beefu32; WIDTH * HEIGHT];
+    let mut c = console(&mut pixels);
+    assert_eq!((c.columns, c.rows), (4, 3));
+    c.clear();
+    c.putc(b'A');
+    c.putc(b'\t');
+    assert_eq!((c.column, c.row), (3, 0));
+    c.putc(b'\n');
+    c.putc(b'|');
+    assert_eq!((0..FONT_WIDTH).map(|x| column_bits(&pixels, 0, 0, x)).collect::<alloc::vec::Vec<_>>(), glyph(b'A').to_vec());
+    assert_eq!(column_bits(&pixels, 0, 1, 2), 0x7f);
+    // Note: spacing column and line stay blank
+    assert!((0..CELL_HEIGHT).all(|y| pixels[y * WIDTH + FONT_WIDTH] == BACKGROUND));
+    assert!((0..WIDTH).all(|x| pixels[FONT_HEIGHT * WIDTH + x] == BACKGROUND));
+  }
+
+  #[test]
+  fn wrap_and_scroll() {
+    let mut pixels = vec![0u32; WIDTH * HEIGHT];
+    let mut c = console(&mut pixels);
+    c.clear();
+    for b in b"abcdXY\n\nZ" {
+      c.putc(*b);
+    }
+    // Note: "abcd" wrapped to "XY", then scrolled off the top
+    assert_eq!((c.column, c.row), (1, 2));
+    assert_eq!(column_bits(&pixels, 0, 0, 0), glyph(b'X')[0]);
+    assert_eq!(column_bits(&pixels, 1, 0, 0), glyph(b'Y')[0]);
+    assert_eq!(column_bits(&pixels, 0, 1, 0), 0);
+    assert_eq!(column_bits(&pixels, 0, 2, 0), glyph(b'Z')[0]);
+  }
+}
@@ -0,0 +1,572 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait, BREAKPOINT_INSTRUCTIONS, ContextFrame, ContextFrameTrait, CoreTrait, PAGE_SIZE, PageTable};
+use crate::lib::{current_core, current_thread, round_down};
+use crate::lib::page_table::PageTableTrait;
+use crate::lib::process::{Pid, Process};
+use crate::lib::thread::{Thread, Tid};
+
+// Note: gdb remote serial protocol stub, all-stop mode
+//       the kernel stays in the trap handler while gdb holds the target
+//       gdb thread id is `tid + 1`, since 0 and -1 are special in the protocol
+
+#[cfg(all(target_arch = "aarch64", not(test)))]
+mod transport {
+  // Note: the UART that is not the console, see `driver/rpi3/uart.rs`
+  pub fn getc() -> Option<u8> {
+    crate::driver::uart::debug().getc()
+  }
+
+  pub fn putc(c: u8) {
+    crate::driver::uart::debug().putc(c)
+  }
+}
+
+#[cfg(any(target_arch = "riscv64", test))]
+mod transport {
+  // Note: QEMU riscv `virt` has a single NS16550 which is the console
+  //       no transport yet, the stub is never entered
+  pub fn getc() -> Option<u8> {
+    None
+  }
+
+  pub fn putc(_c: u8) {}
+}
+
+const PACKET_SIZE: usize = 0x1000;
+const CTRL_C: u8 = 0x03;
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+// Note: signal numbers of stop replies are gdb's, not ours
+const GDB_SIGINT: u8 = 2;
+const GDB_SIGTRAP: u8 = 5;
+
+// Note: cache line size lower bound of both arch
+const CACHE_LINE_SIZE: usize = 32;
+
+struct Breakpoint {
+  pid: Pid,
+  va: usize,
+  original: Vec<u8>,
+}
+
+enum Action {
+  Reply(Vec<u8>),
+  Continue,
+  Step,
+  Detach,
+  Kill,
+}
+
+struct Stub {
+  attached: bool,
+  // Note: thread selected by `Hg`, `None` is the trapped one
+  general: Option<Tid>,
+  breakpoints: Vec<Breakpoint>,
+}
+
+static STUB: Mutex<Stub> = Mutex::new(Stub {
+  attached: false,
+  general: None,
+  breakpoints: Vec::new(),
+});
+
+fn hex_digit(c: u8) -> Option<u8> {
+  match c {
+    b'0'..=b'9' => Some(c - b'0'),
+    b'a'..=b'f' => Some(c - b'a' + 10),
+    b'A'..=b'F' => Some(c - b'A' + 10),
+    _ => None,
+  }
+}
+
+fn parse_hex(s: &[u8]) -> Option<usize> {
+  if s.is_empty() {
+    return None;
+  }
+  let mut r: usize = 0;
+  for c in s {
+    r = r.checked_mul(16)?.checked_add(hex_digit(*c)? as usize)?;
+  }
+  Some(r)
+}
+
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+  if s.len() % 2 != 0 {
+    return None;
+  }
+  let mut r = Vec::new();
+  for pair in s.chunks(2) {
+    r.push((hex_digit(pair[0])? << 4) | hex_digit(pair[1])?);
+  }
+  Some(r)
+}
+
+fn encode_hex(out: &mut Vec<u8>, bytes: &[u8]) {
+  for b in bytes {
+    out.push(HEX[(b >> 4) as usize]);
+    out.push(HEX[(b & 0xf) as usize]);
+  }
+}
+
+// Note: registers are sent in target byte order (little endian)
+fn encode_register(out: &mut Vec<u8>, v: u64, size: usize) {
+  encode_hex(out, &v.to_le_bytes()[..size]);
+}
+
+fn decode_register(bytes: &[u8]) -> u64 {
+  let mut v = [0u8; 8];
+  let n = core::cmp::min(bytes.len(), 8);
+  v[..n].copy_from_slice(&bytes[..n]);
+  u64::from_le_bytes(v)
+}
+
+// Note: split "a,b" / "a,b:c" style arguments
+fn split<'a>(s: &'a [u8], sep: u8) -> Option<(&'a [u8], &'a [u8])> {
+  let i = s.iter().position(|c| *c == sep)?;
+  Some((&s[..i], &s[i + 1..]))
+}
+
+fn error() -> Vec<u8> {
+  b"E01".to_vec()
+}
+
+fn ok() -> Vec<u8> {
+  b"OK".to_vec()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+  data.iter().fold(0u8, |sum, c| sum.wrapping_add(*c))
+}
+
+fn getc_blocking() -> u8 {
+  loop {
+    if let Some(c) = transport::getc() {
+      return c;
+    }
+  }
+}
+
+// Note: read a packet body, the leading '$' has been consumed
+fn read_body() -> Option<Vec<u8>> {
+  let mut body = Vec::new();
+  loop {
+    let c = getc_blocking();
+    match c {
+      b'#' => { break; }
+      b'$' => { body.clear(); }
+      _ => {
+        if body.len() < PACKET_SIZE {
+          body.push(c);
+        }
+      }
+    }
+  }
+  let hi = hex_digit(getc_blocking());
+  let lo = hex_digit(getc_blocking());
+  match (hi, lo) {
+    (Some(hi), Some(lo)) if (hi << 4) | lo == checksum(&body) => {
+      transport::putc(b'+');
+      Some(body)
+    }
+    _ => {
+      transport::putc(b'-');
+      None
+    }
+  }
+}
+
+fn read_packet() -> Vec<u8> {
+  loop {
+    if getc_blocking() == b'$' {
+      if let Some(body) = read_body() {
+        return body;
+      }
+    }
+  }
+}
+
+fn send_packet(data: &[u8]) {
+  let sum = checksum(data);
+  loop {
+    transport::putc(b'$');
+    for c in data {
+      transport::putc(*c);
+    }
+    transport::putc(b'#');
+    transport::putc(HEX[(sum >> 4) as usize]);
+    transport::putc(HEX[(sum & 0xf) as usize]);
+    loop {
+      match getc_blocking() {
+        b'+' => { return; }
+        b'-' => { break; }
+        _ => {}
+      }
+    }
+  }
+}
+
+fn gdb_thread_id(t: &Thread) -> usize {
+  t.tid() as usize + 1
+}
+
+fn lookup_gdb_thread(id: usize) -> Option<Thread> {
+  if id == 0 || id > Tid::max_value() as usize + 1 {
+    return None;
+  }
+  crate::lib::thread::lookup((id - 1) as Tid)
+}
+
+// Note: the trapped thread lives in the core context, others in their saved context
+fn with_context<R, F: FnOnce(&mut ContextFrame) -> R>(t: &Thread, f: F) -> R {
+  if current_thread().as_ref() == Some(t) {
+    f(current_core().context_mut())
+  } else {
+    let mut ctx = t.context();
+    let r = f(&mut *ctx);
+    drop(ctx);
+    r
+  }
+}
+
+// Note: clean written code to the point of unification
+fn sync_code(page_table: PageTable, va: usize, len: usize) {
+  let mut addr = round_down(va, CACHE_LINE_SIZE);
+  while addr < va + len {
+    if let Some(pte) = page_table.lookup_page(round_down(addr, PAGE_SIZE)) {
+      Arch::sync_instruction_cache(pte.kva() + addr % PAGE_SIZE);
+    }
+    addr += CACHE_LINE_SIZE;
+  }
+}
+
+fn stop_reply(sig: u8) -> Vec<u8> {
+  let mut r = Vec::new();
+  match current_thread() {
+    None => {
+      r.push(b'S');
+      encode_hex(&mut r, &[sig]);
+    }
+    Some(t) => {
+      r.push(b'T');
+      encode_hex(&mut r, &[sig]);
+      r.extend_from_slice(format!("thread:{:x};", gdb_thread_id(&t)).as_bytes());
+    }
+  }
+  r
+}
+
+impl Stub {
+  fn thread(&self) -> Option<Thread> {
+    match self.general {
+      Some(tid) => { crate::lib::thread::lookup(tid) }
+      None => { current_thread().or_else(|| crate::lib::thread::list().into_iter().next()) }
+    }
+  }
+
+  fn process(&self) -> Option<Process> {
+    self.thread()?.process()
+  }
+
+  fn handle(&mut self, packet: &[u8], sig: u8) -> Action {
+    let (cmd, args) = match packet.split_first() {
+      Some((cmd, args)) => { (*cmd, args) }
+      None => { return Action::Reply(Vec::new()); }
+    };
+    let reply = match cmd {
+      b'?' => { Some(stop_reply(sig)) }
+      b'g' => { self.read_registers() }
+      b'G' => { self.write_registers(args) }
+      b'p' => { self.read_register(args) }
+      b'P' => { self.write_register(args) }
+      b'm' => { self.read_memory(args) }
+      b'M' => { self.write_memory(args) }
+      b'H' => { self.select_thread(args) }
+      b'T' => { parse_hex(args).and_then(lookup_gdb_thread).map(|_| ok()) }
+      b'q' => { Some(self.query(args)) }
+      b'Z' => { self.insert_breakpoint(args) }
+      b'z' => { self.remove_breakpoint(args) }
+      b'c' | b's' => {
+        if let Some(pc) = parse_hex(args) {
+          current_core().context_mut().set_exception_pc(pc);
+        }
+        return if cmd == b'c' { Action::Continue } else { Action::Step };
+      }
+      b'D' => { return Action::Detach; }
+      b'k' => { return Action::Kill; }
+      // Note: empty reply means unsupported (e.g. `vCont`)
+      _ => { Some(Vec::new()) }
+    };
+    Action::Reply(reply.unwrap_or_else(error))
+  }
+
+  fn read_registers(&self) -> Option<Vec<u8>> {
+    let t = self.thread()?;
+    let mut r = Vec::new();
+    with_context(&t, |ctx| {
+      let mut i = 0;
+      while let Some((v, size)) = ctx.gdb_register(i) {
+        encode_register(&mut r, v, size);
+        i += 1;
+      }
+    });
+    Some(r)
+  }
+
+  fn write_registers(&self, args: &[u8]) -> Option<Vec<u8>> {
+    let t = self.thread()?;
+    let bytes = decode_hex(args)?;
+    with_context(&t, |ctx| {
+      let mut i = 0;
+      let mut offset = 0;
+      while let Some((_, size)) = ctx.gdb_register(i) {
+        if offset + size > bytes.len() {
+          break;
+        }
+        ctx.set_gdb_register(i, decode_register(&bytes[offset..offset + size]));
+        offset += size;
+        i += 1;
+      }
+    });
+    Some(ok())
+  }
+
+  fn read_register(&self, args: &[u8]) -> Option<Vec<u8>> {
+    let t = self.thread()?;
+    let i = parse_hex(args)?;
+    let (v, size) = with_context(&t, |ctx| ctx.gdb_register(i))?;
+    let mut r = Vec::new();
+    encode_register(&mut r, v, size);
+    Some(r)
+  }
+
+  fn write_register(&self, args: &[u8]) -> Option<Vec<u8>> {
+    let t = self.thread()?;
+    let (i, value) = split(args, b'=')?;
+    let i = parse_hex(i)?;
+    let v = decode_register(&decode_hex(value)?);
+    if with_context(&t, |ctx| ctx.set_gdb_register(i, v)) {
+      Some(ok())
+    } else {
+      None
+    }
+  }
+
+  // Note: only user memory of the selected thread's process is reachable
+  fn read_memory(&self, args: &[u8]) -> Option<Vec<u8>> {
+    let p = self.process()?;
+    let (addr, len) = split(args, b',')?;
+    let addr = parse_hex(addr)?;
+    let len = core::cmp::min(parse_hex(len)?, PACKET_SIZE / 2);
+    let mut buf = vec![0u8; len];
+    crate::lib::uaccess::copy_from_user(p.page_table(), addr, &mut buf).ok()?;
+    let mut r = Vec::new();
+    encode_hex(&mut r, &buf);
+    Some(r)
+  }
+
+  fn write_memory(&self, args: &[u8]) -> Option<Vec<u8>> {
+    let p = self.process()?;
+    let (range, data) = split(args, b':')?;
+    let (addr, len) = split(range, b',')?;
+    let addr = parse_hex(addr)?;
+    let data = decode_hex(data)?;
+    if parse_hex(len)? != data.len() {
+      return None;
+    }
+    crate::lib::uaccess::poke_user(p.page_table(), addr, &data).ok()?;
+    sync_code(p.page_table(), addr, data.len());
+    Some(ok())
+  }
+
+  fn select_thread(&mut self, args: &[u8]) -> Option<Vec<u8>> {
+    let (op, id) = args.split_first()?;
+    // Note: all threads resume together, `Hc` is accepted and ignored
+    if *op != b'g' {
+      return Some(ok());
+    }
+    if id == b"-1" || id == b"0" {
+      self.general = None;
+    } else {
+      let t = lookup_gdb_thread(parse_hex(id)?)?;
+      self.general = Some(t.tid());
+    }
+    Some(ok())
+  }
+
+  fn query(&self, args: &[u8]) -> Vec<u8> {
+    if args.starts_with(b"Supported") {
+      format!("PacketSize={:x}", PACKET_SIZE).into_bytes()
+    } else if args == b"Attached" {
+      b"1".to_vec()
+    } else if args == b"C" {
+      match self.thread() {
+        None => { Vec::new() }
+        Some(t) => { format!("QC{:x}", gdb_thread_id(&t)).into_bytes() }
+      }
+    } else if args == b"fThreadInfo" {
+      let mut r = b"m".to_vec();
+      let ids: Vec<String> = crate::lib::thread::list().iter().map(|t| format!("{:x}", gdb_thread_id(t))).collect();
+      r.extend_from_slice(ids.join(",").as_bytes());
+      r
+    } else if args == b"sThreadInfo" {
+      b"l".to_vec()
+    } else if args.starts_with(b"ThreadExtraInfo,") {
+      let t = match parse_hex(&args[b"ThreadExtraInfo,".len()..]).and_then(lookup_gdb_thread) {
+        Some(t) => { t }
+        None => { return error(); }
+      };
+      let state = if t.runnable() { "runnable" } else { "blocked" };
+      let info = match t.process() {
+        Some(p) => { format!("pid {} {}", p.pid(), state) }
+        None => { format!("kernel {}", state) }
+      };
+      let mut r = Vec::new();
+      encode_hex(&mut r, info.as_bytes());
+      r
+    } else {
+      Vec::new()
+    }
+  }
+
+  fn insert_breakpoint(&mut self, args: &[u8]) -> Option<Vec<u8>> {
+    let (kind, args) = split(args, b',')?;
+    // Note: software breakpoints only, hardware ones are unsupported
+    if kind != b"0" {
+      return Some(Vec::new());
+    }
+    let (va, size) = split(args, b',')?;
+    let va = parse_hex(va)?;
+    let size = parse_hex(size)?;
+    let instruction = BREAKPOINT_INSTRUCTIONS.iter().find(|i| i.len() == size)?;
+    let p = self.process()?;
+    if self.breakpoints.iter().any(|b| b.pid == p.pid() && b.va == va) {
+      return Some(ok());
+    }
+    let page_table = p.page_table();
+    let mut original = vec![0u8; size];
+    crate::lib::uaccess::copy_from_user(page_table, va, &mut original).ok()?;
+    crate::lib::uaccess::poke_user(page_table, va, instruction).ok()?;
+    sync_code(page_table, va, size);
+    self.breakpoints.push(Breakpoint {
+      pid: p.pid(),
+      va,
+      original,
+    });
+    Some(ok())
+  }
+
+  fn remove_breakpoint(&mut self, args: &[u8]) -> Option<Vec<u8>> {
+    let (kind, args) = split(args, b',')?;
+    if kind != b"0" {
+      return Some(Vec::new());
+    }
+    let (va, _) = split(args, b',')?;
+    let va = parse_hex(va)?;
+    let pid = self.process()?.pid();
+    if let Some(i) = self.breakpoints.iter().position(|b| b.pid == pid && b.va == va) {
+      let b = self.breakpoints.remove(i);
+      restore(&b);
+    }
+    Some(ok())
+  }
+
+  fn detach(&mut self) {
+    for b in self.breakpoints.drain(..) {
+      restore(&b);
+    }
+    self.general = None;
+    self.attached = false;
+  }
+}
+
+// Note: process may have exited meanwhile, nothing to restore then
+fn restore(b: &Breakpoint) {
+  if let Some(p) = crate::lib::process::lookup(b.pid) {
+    let page_table = p.page_table();
+    if crate::lib::uaccess::poke_user(page_table, b.va, &b.original).is_ok() {
+      sync_code(page_table, b.va, b.original.len());
+    }
+  }
+}
+
+// Note: hold the target until gdb resumes it
+//       `first` is a packet already received while the target was running
+fn enter(sig: u8, first: Option<Vec<u8>>) {
+  let mut stub = STUB.lock();
+  stub.attached = true;
+  let ctx = current_core().context_mut();
+  ctx.set_single_step(false);
+  let mut pending = first;
+  if pending.is_none() {
+    send_packet(&stop_reply(sig));
+  }
+  loop {
+    let packet = match pending.take() {
+      Some(packet) => { packet }
+      None => { read_packet() }
+    };
+    match stub.handle(&packet, sig) {
+      Action::Reply(reply) => { send_packet(&reply); }
+      Action::Continue => { break; }
+      Action::Step => {
+        // Note: only user threads can be stepped, kernel runs with debug exceptions masked
+        if current_thread().and_then(|t| t.process()).is_some() && ctx.set_single_step(true) {
+          break;
+        }
+        send_packet(&error());
+      }
+      Action::Detach => {
+        stub.detach();
+        send_packet(&ok());
+        break;
+      }
+      // Note: the inferior is destroyed by SIGKILL, gdb expects no reply
+      Action::Kill => {
+        let inferior = stub.process();
+        stub.detach();
+        if let Some(p) = inferior {
+          let _ = crate::lib::signal::send(&p, crate::lib::signal::SIGKILL);
+        }
+        break;
+      }
+    }
+  }
+  drop(stub);
+}
+
+// Note: called on every tick, gdb connecting or pressing Ctrl-C stops the target
+pub fn poll() {
+  while let Some(c) = transport::getc() {
+    match c {
+      CTRL_C => {
+        enter(GDB_SIGINT, None);
+        return;
+      }
+      b'$' => {
+        if let Some(packet) = read_body() {
+          enter(GDB_SIGTRAP, Some(packet));
+          return;
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+// Note: breakpoint or single step trap from user space
+//       `false` if no debugger is attached
+pub fn trap() -> bool {
+  let stub = STUB.lock();
+  let attached = stub.attached;
+  drop(stub);
+  if attached {
+    enter(GDB_SIGTRAP, None);
+  }
+  attached
+}
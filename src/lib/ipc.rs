@@ -0,0 +1,572 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::arch::{ContextFrame, ContextFrameTrait, PAGE_SIZE};
+use crate::config::{CONFIG_IPC_QUEUE_LENGTH, CONFIG_USER_LIMIT};
+use crate::lib::bitmap::BitMap;
+use crate::lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::process::{Pid, Process};
+use crate::lib::thread::{Status, Thread, Tid};
+use crate::mm::PageFrame;
+
+use self::Error::*;
+
+pub type EndpointId = u16;
+
+// Note: message registers are passed in syscall argument 2 ~ 5
+//       (riscv64 only has a0 ~ a5 for syscall arguments)
+pub const IPC_MESSAGE_REGISTERS: usize = 4;
+
+// Note: message info word layout
+//    [ 63 : 12 ][ 11 : 6 ][    5     ][    4     ][ 3 : 0 ]
+//      page va   reserved   writable   transfer    length
+pub const IPC_INFO_LENGTH_MASK: usize = 0xf;
+pub const IPC_INFO_TRANSFER: usize = 1 << 4;
+pub const IPC_INFO_WRITABLE: usize = 1 << 5;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+  EndpointNotFoundError,
+  QueueFullError,
+  InvalidMessageError,
+  NotOwnerError,
+}
+
+// Note: not `Copy`, the transferred page reference is released exactly once, by `deliver` or on drop
+#[derive(Debug)]
+pub struct Message {
+  sender: Tid,
+  length: usize,
+  registers: [usize; IPC_MESSAGE_REGISTERS],
+  page: Option<(PageFrame, EntryAttribute)>,
+}
+
+impl Message {
+  // Note: build a message from syscall arguments of the sending context
+  //       a transferred page is held (rc + 1) until it is delivered or dropped
+  fn from_context(sender: &Thread, ctx: &ContextFrame, allow_transfer: bool) -> Result<Self, Error> {
+    let info = ctx.syscall_argument(1);
+    let length = info & IPC_INFO_LENGTH_MASK;
+    if length > IPC_MESSAGE_REGISTERS {
+      return Err(InvalidMessageError);
+    }
+    let mut registers = [0; IPC_MESSAGE_REGISTERS];
+    for i in 0..length {
+      registers[i] = ctx.syscall_argument(2 + i);
+    }
+    let page = if info & IPC_INFO_TRANSFER != 0 {
+      if !allow_transfer {
+        return Err(InvalidMessageError);
+      }
+      let va = info & !(PAGE_SIZE - 1);
+      if va >= CONFIG_USER_LIMIT {
+        return Err(InvalidMessageError);
+      }
+      let p = sender.process().ok_or(InvalidMessageError)?;
+      // Note: blocks are not transferable, the reference is kept on their first frame
+      let (pte, size) = p.page_table().lookup(va).ok_or(InvalidMessageError)?;
+      if size != PAGE_SIZE {
+        return Err(InvalidMessageError);
+      }
+      let attr = pte.attribute().filter();
+      let writable = attr.writable() && info & IPC_INFO_WRITABLE != 0;
+      let attr = EntryAttribute::new(writable, true, false, false, attr.u_executable(), attr.u_copy_on_write(), attr.u_shared());
+      let frame = PageFrame::new(pte.pa());
+      crate::mm::page_pool::increase_rc(frame).map_err(|_| InvalidMessageError)?;
+      Some((frame, attr))
+    } else {
+      None
+    };
+    Ok(Message {
+      sender: sender.tid(),
+      length,
+      registers,
+      page,
+    })
+  }
+
+  // Note: write message into receiver's context
+  //    return value (x0/a0) <- sender tid
+  //    argument 1 <- message info
+  //    argument 2 ~ 5 <- message registers
+  fn deliver(mut self, receiver: &Thread, ctx: &mut ContextFrame) {
+    let mut info = self.length;
+    if let Some((frame, attr)) = self.page.take() {
+      let dst_va = receiver.ipc_receive_va();
+      if let (Some(va), Some(p)) = (dst_va, receiver.process()) {
+        if p.page_table().insert_page(va, frame, attr).is_ok() {
+          info |= va | IPC_INFO_TRANSFER;
+          if attr.writable() {
+            info |= IPC_INFO_WRITABLE;
+          }
+        }
+      }
+      if let Err(e) = crate::mm::page_pool::decrease_rc(frame) {
+        error!("ipc: deliver: {:?}", e);
+      }
+    }
+    ctx.set_syscall_return_value(self.sender as usize);
+    ctx.set_syscall_argument(1, info);
+    for i in 0..IPC_MESSAGE_REGISTERS {
+      ctx.set_syscall_argument(2 + i, self.registers[i]);
+    }
+  }
+
+}
+
+impl Drop for Message {
+  // Note: never delivered, give the page reference back
+  fn drop(&mut self) {
+    if let Some((frame, _)) = self.page.take() {
+      if let Err(e) = crate::mm::page_pool::decrease_rc(frame) {
+        error!("ipc: drop: {:?}", e);
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Pending {
+  message: Message,
+  // Note: `Some` if sent by `call`, the caller is waiting for reply
+  caller: Option<Thread>,
+}
+
+#[derive(Debug)]
+struct ControlBlock {
+  id: EndpointId,
+  owner: Pid,
+  queue: Mutex<VecDeque<Pending>>,
+  receivers: Mutex<VecDeque<Thread>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Endpoint(Arc<ControlBlock>);
+
+impl Endpoint {
+  pub fn id(&self) -> EndpointId {
+    self.0.id
+  }
+
+  // Note: only threads of the owner may receive from or free an endpoint
+  fn check_owner(&self, t: &Thread) -> Result<(), Error> {
+    match t.process() {
+      Some(p) if p.pid() == self.0.owner => { Ok(()) }
+      _ => { Err(NotOwnerError) }
+    }
+  }
+
+  fn enqueue(&self, pending: Pending) -> Result<(), Error> {
+    let mut queue = self.0.queue.lock();
+    let r = if queue.len() >= CONFIG_IPC_QUEUE_LENGTH {
+      Err(QueueFullError)
+    } else {
+      queue.push_back(pending);
+      Ok(())
+    };
+    drop(queue);
+    r
+  }
+
+  fn dequeue(&self) -> Option<Pending> {
+    let mut queue = self.0.queue.lock();
+    let r = queue.pop_front();
+    drop(queue);
+    r
+  }
+
+  fn pop_receiver(&self) -> Option<Thread> {
+    let mut receivers = self.0.receivers.lock();
+    let r = receivers.pop_front();
+    drop(receivers);
+    r
+  }
+
+  fn push_receiver(&self, t: Thread) {
+    let mut receivers = self.0.receivers.lock();
+    receivers.push_back(t);
+    drop(receivers);
+  }
+
+  fn remove_thread(&self, t: &Thread) {
+    let mut receivers = self.0.receivers.lock();
+    receivers.retain(|r| r != t);
+    drop(receivers);
+    let mut queue = self.0.queue.lock();
+    queue.retain(|pending| pending.caller.as_ref() != Some(t));
+    drop(queue);
+  }
+
+  fn destroy(&self) {
+    while let Some(pending) = self.dequeue() {
+      if let Some(caller) = pending.caller {
+        caller.context().set_syscall_return_value(-(crate::lib::syscall::Error::IpcEndpointNotFoundError as isize) as usize);
+        caller.set_status(Status::TsRunnable);
+      }
+    }
+    while let Some(receiver) = self.pop_receiver() {
+      receiver.context().set_syscall_return_value(-(crate::lib::syscall::Error::IpcEndpointNotFoundError as isize) as usize);
+      receiver.set_status(Status::TsRunnable);
+    }
+  }
+}
+
+// Note: per-thread ipc state, see `Thread::ipc_*`
+#[derive(Debug)]
+pub struct ThreadState {
+  receive_va: Option<usize>,
+  reply_to: Option<Thread>,
+}
+
+impl ThreadState {
+  pub const fn new() -> Self {
+    ThreadState {
+      receive_va: None,
+      reply_to: None,
+    }
+  }
+
+  pub fn receive_va(&self) -> Option<usize> {
+    self.receive_va
+  }
+
+  pub fn set_receive_va(&mut self, va: Option<usize>) {
+    self.receive_va = va;
+  }
+
+  pub fn take_reply_to(&mut self) -> Option<Thread> {
+    self.reply_to.take()
+  }
+
+  pub fn set_reply_to(&mut self, t: Option<Thread>) {
+    self.reply_to = t;
+  }
+
+  // Note: `client` is gone, it must not be replied to
+  pub fn forget_reply_to(&mut self, client: &Thread) {
+    if self.reply_to.as_ref() == Some(client) {
+      self.reply_to = None;
+    }
+  }
+}
+
+struct EndpointPool {
+  bitmap: BitMap,
+  map: BTreeMap<EndpointId, Endpoint>,
+}
+
+impl EndpointPool {
+  fn alloc(&mut self, owner: Pid) -> Endpoint {
+    let id = self.bitmap.alloc() as EndpointId;
+    let ep = Endpoint(Arc::new(ControlBlock {
+      id,
+      owner,
+      queue: Mutex::new(VecDeque::new()),
+      receivers: Mutex::new(VecDeque::new()),
+    }));
+    self.map.insert(id, ep.clone());
+    ep
+  }
+
+  fn free(&mut self, owner: Pid, id: EndpointId) -> Result<Endpoint, Error> {
+    match self.map.get(&id) {
+      None => { return Err(EndpointNotFoundError); }
+      Some(ep) if ep.0.owner != owner => { return Err(NotOwnerError); }
+      Some(_) => {}
+    }
+    self.bitmap.clear(id as usize);
+    self.map.remove(&id).ok_or(EndpointNotFoundError)
+  }
+
+  fn lookup(&self, id: EndpointId) -> Option<Endpoint> {
+    self.map.get(&id).cloned()
+  }
+
+  fn list(&self) -> Vec<Endpoint> {
+    self.map.values().cloned().collect()
+  }
+}
+
+lazy_static! {
+  static ref ENDPOINT_POOL: Mutex<EndpointPool> = Mutex::new(EndpointPool {
+    bitmap: BitMap::new(),
+    map: BTreeMap::new(),
+  });
+}
+
+pub fn alloc(owner: &Process) -> Endpoint {
+  let mut pool = ENDPOINT_POOL.lock();
+  let r = pool.alloc(owner.pid());
+  drop(pool);
+  r
+}
+
+// Note: `owner` is the pid of the caller, see `alloc`
+pub fn free(owner: Pid, id: EndpointId) -> Result<(), Error> {
+  let mut pool = ENDPOINT_POOL.lock();
+  let r = pool.free(owner, id);
+  drop(pool);
+  let ep = r?;
+  ep.destroy();
+  Ok(())
+}
+
+pub fn lookup(id: EndpointId) -> Result<Endpoint, Error> {
+  let pool = ENDPOINT_POOL.lock();
+  let r = pool.lookup(id);
+  drop(pool);
+  r.ok_or(EndpointNotFoundError)
+}
+
+// Note: called on process destroy, endpoints owned by `p` are freed
+pub fn release_process(p: &Process) {
+  let pool = ENDPOINT_POOL.lock();
+  let list = pool.list();
+  drop(pool);
+  for ep in list {
+    if ep.0.owner == p.pid() {
+      let _ = free(p.pid(), ep.id());
+    }
+  }
+}
+
+// Note: called on thread destroy, remove `t` from all wait queues
+//       and from servers that were to reply to it
+pub fn release_thread(t: &Thread) {
+  let pool = ENDPOINT_POOL.lock();
+  let list = pool.list();
+  drop(pool);
+  for ep in list {
+    ep.remove_thread(t);
+  }
+  for server in crate::lib::thread::list() {
+    server.forget_ipc_reply_to(t);
+  }
+}
+
+// Note: the following functions operate on the running thread
+//       `ctx` is the context of `current` saved on trap
+//       `send` never blocks, the message is delivered to a waiting receiver or queued
+//       the others return `Ok(Some(tid))` if a message is delivered into `ctx`
+//       or `Ok(None)` if `current` is blocked and the core switched away
+
+pub fn send(current: &Thread, ctx: &ContextFrame, ep: &Endpoint) -> Result<(), Error> {
+  let message = Message::from_context(current, ctx, true)?;
+  if let Some(receiver) = ep.pop_receiver() {
+    message.deliver(&receiver, &mut *receiver.context());
+    receiver.set_ipc_receive_va(None);
+    receiver.set_status(Status::TsRunnable);
+    Ok(())
+  } else {
+    // Note: a message refused by a full queue is dropped here, with its page
+    ep.enqueue(Pending { message, caller: None })
+  }
+}
+
+pub fn receive(current: &Thread, ctx: &mut ContextFrame, ep: &Endpoint) -> Result<Option<Tid>, Error> {
+  ep.check_owner(current)?;
+  let info = ctx.syscall_argument(1);
+  let va = info & !(PAGE_SIZE - 1);
+  if info & IPC_INFO_TRANSFER != 0 {
+    if va >= CONFIG_USER_LIMIT {
+      return Err(InvalidMessageError);
+    }
+    current.set_ipc_receive_va(Some(va));
+  } else {
+    current.set_ipc_receive_va(None);
+  }
+  if let Some(pending) = ep.dequeue() {
+    let sender = pending.message.sender;
+    pending.message.deliver(current, ctx);
+    current.set_ipc_receive_va(None);
+    current.set_ipc_reply_to(pending.caller);
+    Ok(Some(sender))
+  } else {
+    current.set_status(Status::TsWaitForReceive);
+    ep.push_receiver(current.clone());
+    crate::lib::scheduler::schedule();
+    Ok(None)
+  }
+}
+
+pub fn call(current: &Thread, ctx: &ContextFrame, ep: &Endpoint) -> Result<Option<Tid>, Error> {
+  let message = Message::from_context(current, ctx, true)?;
+  current.set_ipc_receive_va(None);
+  if let Some(receiver) = ep.pop_receiver() {
+    // Note: fast path, switch directly to the server thread
+    message.deliver(&receiver, &mut *receiver.context());
+    receiver.set_ipc_receive_va(None);
+    receiver.set_ipc_reply_to(Some(current.clone()));
+    receiver.set_status(Status::TsRunnable);
+    current.set_status(Status::TsWaitForReply);
+    receiver.run();
+    Ok(None)
+  } else {
+    ep.enqueue(Pending { message, caller: Some(current.clone()) })?;
+    current.set_status(Status::TsWaitForReply);
+    crate::lib::scheduler::schedule();
+    Ok(None)
+  }
+}
+
+pub fn reply_receive(current: &Thread, ctx: &mut ContextFrame, ep: &Endpoint) -> Result<Option<Tid>, Error> {
+  ep.check_owner(current)?;
+  // Note: replies carry message registers only (no page transfer)
+  let reply = Message::from_context(current, ctx, false)?;
+  // Note: a client destroyed meanwhile is left to unwind, see `Thread::destroy`
+  let client = current.take_ipc_reply_to().filter(|c| !c.exiting());
+  if let Some(client) = &client {
+    reply.deliver(client, &mut *client.context());
+    client.set_status(Status::TsRunnable);
+  }
+  current.set_ipc_receive_va(None);
+  if let Some(pending) = ep.dequeue() {
+    let sender = pending.message.sender;
+    pending.message.deliver(current, ctx);
+    current.set_ipc_reply_to(pending.caller);
+    return Ok(Some(sender));
+  }
+  current.set_status(Status::TsWaitForReceive);
+  ep.push_receiver(current.clone());
+  match client {
+    Some(client) => {
+      // Note: fast path, switch directly back to the client thread
+      client.run();
+    }
+    None => {
+      crate::lib::scheduler::schedule();
+    }
+  }
+  Ok(None)
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use crate::lib::ktest::release_process;
+
+  use super::*;
+
+  fn message(info: usize, registers: &[usize]) -> ContextFrame {
+    let mut ctx = ContextFrame::new(0, 0, 0, false);
+    ctx.set_syscall_argument(1, info | registers.len());
+    for (i, r) in registers.iter().enumerate() {
+      ctx.set_syscall_argument(2 + i, *r);
+    }
+    ctx
+  }
+
+  ktest!(endpoint_queue {
+    let p = crate::lib::process::alloc(None);
+    let sender = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    let receiver = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    let ep = alloc(&p);
+    assert!(lookup(ep.id()).is_ok());
+
+    send(&sender, &message(0, &[0x11, 0x22]), &ep).ok().unwrap();
+    let mut ctx = message(0, &[]);
+    assert_eq!(receive(&receiver, &mut ctx, &ep).ok(), Some(Some(sender.tid())));
+    assert_eq!(ctx.syscall_argument(1), 2);
+    assert_eq!(ctx.syscall_argument(2), 0x11);
+    assert_eq!(ctx.syscall_argument(3), 0x22);
+
+    assert!(matches!(send(&sender, &message(0, &[0; IPC_MESSAGE_REGISTERS + 1]), &ep), Err(InvalidMessageError)));
+    for i in 0..CONFIG_IPC_QUEUE_LENGTH {
+      send(&sender, &message(0, &[i]), &ep).ok().unwrap();
+    }
+    assert!(matches!(send(&sender, &message(0, &[]), &ep), Err(QueueFullError)));
+
+    // Note: only the owner receives from and frees it
+    let q = crate::lib::process::alloc(None);
+    let other = crate::lib::thread::alloc_user(0, 0, 0, q.clone());
+    assert!(matches!(receive(&other, &mut message(0, &[]), &ep), Err(NotOwnerError)));
+    assert!(matches!(free(q.pid(), ep.id()), Err(NotOwnerError)));
+
+    free(p.pid(), ep.id()).ok().unwrap();
+    assert!(lookup(ep.id()).is_err());
+    sender.destroy();
+    receiver.destroy();
+    other.destroy();
+    release_process(&p);
+    release_process(&q);
+  });
+
+  // Note: a server is never left to reply to a destroyed client
+  ktest!(reply_to_released {
+    let p = crate::lib::process::alloc(None);
+    let client = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    let server = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    server.set_ipc_reply_to(Some(client.clone()));
+    client.destroy();
+    assert!(server.take_ipc_reply_to().is_none());
+    server.destroy();
+    release_process(&p);
+  });
+
+  // Note: a signal takes a blocked receiver off the endpoint, SIGKILL destroys its process
+  //       blocked as `receive` leaves it, short of switching away
+  ktest!(receive_interrupted {
+    use crate::lib::signal::{send, SIGINT, SIGKILL};
+    let p = crate::lib::process::alloc(None);
+    let receiver = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    p.set_main_thread(receiver.clone());
+    let ep = alloc(&p);
+    receiver.set_status(Status::TsWaitForReceive);
+    ep.push_receiver(receiver.clone());
+    send(&p, SIGINT).ok().unwrap();
+    assert!(receiver.runnable());
+    assert!(ep.pop_receiver().is_none());
+    let interrupted = -(crate::lib::syscall::Error::InterruptedError as isize) as usize;
+    assert_eq!(receiver.context().syscall_argument(0), interrupted);
+
+    receiver.set_status(Status::TsWaitForReceive);
+    ep.push_receiver(receiver.clone());
+    send(&p, SIGKILL).ok().unwrap();
+    assert!(matches!(p.exit_status(), Some(crate::lib::process::ExitStatus::Killed(SIGKILL))));
+    assert!(crate::lib::thread::lookup(receiver.tid()).is_none());
+    assert!(lookup(ep.id()).is_err());
+    assert!(crate::lib::process::lookup(p.pid()).is_none());
+  });
+
+  ktest!(page_transfer {
+    let p = crate::lib::process::alloc(None);
+    let q = crate::lib::process::alloc(None);
+    let sender = crate::lib::thread::alloc_user(0, 0, 0, p.clone());
+    let receiver = crate::lib::thread::alloc_user(0, 0, 0, q.clone());
+    let ep = alloc(&q);
+    let (src_va, dst_va) = (0x1000_0000, 0x2000_0000);
+    let frame = crate::mm::page_pool::alloc();
+    p.page_table().insert_page(src_va, frame, EntryAttribute::user_default()).ok().unwrap();
+
+    send(&sender, &message(src_va | IPC_INFO_TRANSFER, &[]), &ep).ok().unwrap();
+    // Note: the queued message holds the frame
+    assert_eq!(crate::mm::page_pool::rc(frame).ok(), Some(2));
+    let mut ctx = message(dst_va | IPC_INFO_TRANSFER, &[]);
+    receive(&receiver, &mut ctx, &ep).ok().unwrap();
+    let info = ctx.syscall_argument(1);
+    assert_eq!(info & !(PAGE_SIZE - 1), dst_va);
+    assert!(info & IPC_INFO_TRANSFER != 0);
+    assert!(info & IPC_INFO_WRITABLE == 0);
+    let entry = q.page_table().lookup_page(dst_va).unwrap();
+    assert_eq!(entry.pa(), frame.pa());
+    assert!(!entry.attribute().writable());
+    assert_eq!(crate::mm::page_pool::rc(frame).ok(), Some(2));
+
+    // Note: refused or left in the queue, the message gives its reference back
+    send(&sender, &message(src_va | IPC_INFO_TRANSFER, &[]), &ep).ok().unwrap();
+    for i in 1..CONFIG_IPC_QUEUE_LENGTH {
+      send(&sender, &message(0, &[i]), &ep).ok().unwrap();
+    }
+    assert_eq!(crate::mm::page_pool::rc(frame).ok(), Some(3));
+    assert!(matches!(send(&sender, &message(src_va | IPC_INFO_TRANSFER, &[]), &ep), Err(QueueFullError)));
+    assert_eq!(crate::mm::page_pool::rc(frame).ok(), Some(3));
+    free(q.pid(), ep.id()).ok().unwrap();
+    assert_eq!(crate::mm::page_pool::rc(frame).ok(), Some(2));
+    sender.destroy();
+    receiver.destroy();
+    release_process(&p);
+    release_process(&q);
+  });
+}
@@ -5,6 +5,8 @@ use crate::config::CONFIG_USER_LIMIT;
 use crate::lib::{current_core, current_process, current_thread, round_down};
 use crate::lib::page_table::PageTableTrait;
 use crate::lib::process::Pid;
+use crate::lib::crash;
+use crate::lib::signal;
 use crate::lib::syscall::{SystemCall, SystemCallTrait};
 use crate::mm::PageFrame;
 
@@ -12,11 +14,14 @@ pub trait InterruptServiceRoutine {
   fn system_call();
   fn interrupt_request();
   fn page_fault();
+  fn breakpoint();
   fn default();
 }
 
 pub struct Isr;
 
+const CTRL_C: u8 = 0x03;
+
 #[derive(Debug)]
 pub enum SystemCallResult {
   Void,
@@ -26,6 +31,10 @@ pub enum SystemCallResult {
 
 pub trait SystemCallResultOk {
   fn to_isize(&self) -> isize;
+  // Note: the caller slept, its return value is written by whoever woke it up
+  fn blocked(&self) -> bool {
+    false
+  }
 }
 
 impl SystemCallResultOk for () {
@@ -40,6 +49,26 @@ impl SystemCallResultOk for u16 {
   }
 }
 
+impl SystemCallResultOk for usize {
+  fn to_isize(&self) -> isize {
+    *self as isize
+  }
+}
+
+// Note: `None` means the caller has been blocked
+impl SystemCallResultOk for Option<u16> {
+  fn to_isize(&self) -> isize {
+    match self {
+      None => { 0 }
+      Some(u) => { *u as isize }
+    }
+  }
+
+  fn blocked(&self) -> bool {
+    self.is_none()
+  }
+}
+
 impl core::convert::From<Pid> for SystemCallResult {
   fn from(pid: Pid) -> Self {
     SystemCallResult::Pid(pid)
@@ -56,7 +85,7 @@ impl<T> core::convert::From<Result<T, crate::lib::syscall::Error>> for SystemCal
   fn from(sce: Result<T, crate::lib::syscall::Error>) -> Self {
     SystemCallResult::R(
       match sce {
-        Ok(t) => { Some(t.to_isize()) }
+        Ok(t) => { if t.blocked() { None } else { Some(t.to_isize()) } }
         Err(e) => { Some(-(e as isize)) }
       }
     )
@@ -66,8 +95,11 @@ impl<T> core::convert::From<Result<T, crate::lib::syscall::Error>> for SystemCal
 impl InterruptServiceRoutine for Isr {
   fn system_call() {
     let ctx = current_core().context_mut();
+    let caller = current_thread();
+    let number = ctx.syscall_number();
+    crate::lib::trace::syscall_enter(number);
     let arg = |i: usize| { ctx.syscall_argument(i) };
-    let scr = match ctx.syscall_number() {
+    let scr = match number {
       1 => {
         //print!("core_{}: putc({})", crate::arch::Arch::core_id(), arg(0) as u8 as char);
         //println!();
@@ -112,8 +144,121 @@ impl InterruptServiceRoutine for Isr {
       12 => {
         SystemCall::ipc_can_send(arg(0) as u16, arg(1), arg(2), arg(3)).into()
       }
-      _ => { println!("system call: unrecognized system call number").into() }
+      13 => {
+        SystemCall::endpoint_alloc().into()
+      }
+      14 => {
+        SystemCall::endpoint_free(arg(0) as u16).into()
+      }
+      15 => {
+        SystemCall::endpoint_send(arg(0) as u16).into()
+      }
+      16 => {
+        SystemCall::endpoint_receive(arg(0) as u16).into()
+      }
+      17 => {
+        SystemCall::endpoint_call(arg(0) as u16).into()
+      }
+      18 => {
+        SystemCall::endpoint_reply_receive(arg(0) as u16).into()
+      }
+      19 => {
+        SystemCall::signal_action(arg(0), arg(1)).into()
+      }
+      20 => {
+        SystemCall::signal_mask(arg(0), arg(1)).into()
+      }
+      21 => {
+        SystemCall::signal_send(arg(0) as u16, arg(1)).into()
+      }
+      22 => {
+        SystemCall::signal_return(arg(0)).into()
+      }
+      23 => {
+        SystemCall::process_exit(arg(0) as i32).into()
+      }
+      24 => {
+        SystemCall::process_wait(arg(0) as u16, arg(1)).into()
+      }
+      25 => {
+        SystemCall::log_read(arg(0), arg(1)).into()
+      }
+      26 => {
+        SystemCall::trace_control(arg(0)).into()
+      }
+      27 => {
+        SystemCall::trace_dump(arg(0)).into()
+      }
+      28 => {
+        SystemCall::mem_alloc_large(arg(0) as u16, arg(1), arg(2)).into()
+      }
+      29 => {
+        SystemCall::clock_gettime(arg(0), arg(1)).into()
+      }
+      30 => {
+        SystemCall::nanosleep(arg(0)).into()
+      }
+      31 => {
+        SystemCall::core_usage(arg(0), arg(1)).into()
+      }
+      32 => {
+        SystemCall::gettimeofday(arg(0)).into()
+      }
+      33 => {
+        SystemCall::framebuffer_map(arg(0), arg(1)).into()
+      }
+      34 => {
+        SystemCall::net_socket(arg(0)).into()
+      }
+      35 => {
+        SystemCall::net_bind(arg(0), arg(1)).into()
+      }
+      36 => {
+        SystemCall::net_connect(arg(0), arg(1)).into()
+      }
+      37 => {
+        SystemCall::net_listen(arg(0)).into()
+      }
+      38 => {
+        SystemCall::net_accept(arg(0), arg(1)).into()
+      }
+      39 => {
+        SystemCall::net_send(arg(0), arg(1), arg(2), arg(3)).into()
+      }
+      40 => {
+        SystemCall::net_receive(arg(0), arg(1), arg(2), arg(3)).into()
+      }
+      41 => {
+        SystemCall::net_close(arg(0)).into()
+      }
+      42 => {
+        SystemCall::getrandom(arg(0), arg(1), arg(2)).into()
+      }
+      43 => {
+        SystemCall::chardev_open(arg(0), arg(1)).into()
+      }
+      44 => {
+        SystemCall::chardev_read(arg(0), arg(1), arg(2)).into()
+      }
+      45 => {
+        SystemCall::chardev_write(arg(0), arg(1), arg(2)).into()
+      }
+      46 => {
+        SystemCall::chardev_close(arg(0)).into()
+      }
+      47 => {
+        SystemCall::process_set_group(arg(0) as u16, arg(1) as u16).into()
+      }
+      48 => {
+        SystemCall::console_set_foreground(arg(0) as u16).into()
+      }
+      _ => { warn!("unrecognized system call number {}", ctx.syscall_number()).into() }
     };
+    crate::lib::trace::syscall_exit(caller.as_ref().map(|t| t.tid()), number, match &scr {
+      SystemCallResult::Void => { 0 }
+      SystemCallResult::Pid(pid) => { *pid as isize }
+      SystemCallResult::R(o) => { o.unwrap_or(0) }
+    });
     match scr {
       SystemCallResult::Void => {}
       SystemCallResult::Pid(pid) => {
@@ -123,7 +268,7 @@ impl InterruptServiceRoutine for Isr {
       SystemCallResult::R(o) => {
         //println!("{}:{:?}", (*ctx).syscall_number(), scr);
         match o {
-          None => { ctx.set_syscall_return_value(0); }
+          None => {}
           Some(i) => { ctx.set_syscall_return_value(i as usize); }
         }
       }
@@ -131,7 +276,20 @@ impl InterruptServiceRoutine for Isr {
   }
 
   fn interrupt_request() {
-    crate::driver::timer::next();
+    crate::lib::trace::irq();
+    // Note: the PL011 fills its rx ring, whichever of console and gdb it serves
+    crate::driver::uart::interrupt();
+    // Note: gdb packets and Ctrl-C are picked up on every interrupt
+    //       the mini UART (raspi3) and the NS16550 (riscv) have no rx interrupt
+    crate::lib::gdb::poll();
+    while let Some(c) = crate::driver::uart::getc() {
+      if c == CTRL_C {
+        crate::lib::signal::console_interrupt();
+      }
+    }
+    // Note: the network device is polled as well, no rx interrupt is taken
+    crate::lib::net::poll();
+    crate::lib::timer::interrupt();
     crate::lib::scheduler::schedule();
   }
 
@@ -147,15 +305,28 @@ impl InterruptServiceRoutine for Isr {
     let p = p.unwrap();
 
     let addr = Arch::fault_address();
+    crate::lib::trace::page_fault(addr);
     let va = round_down(addr, PAGE_SIZE);
     if va >= CONFIG_USER_LIMIT {
-      println!("isr: page_fault: va >= CONFIG_USER_LIMIT, process killed");
-      p.destroy();
+      crash::fault(&p, signal::SIGSEGV, "fault address beyond CONFIG_USER_LIMIT");
       return;
     }
+    // Note: sp in the guard as well, a large frame may skip over it
+    let sp = current_core().context().stack_pointer();
+    if crate::lib::process::in_stack_guard(va, PAGE_SIZE) || crate::lib::process::in_stack_guard(sp, 1) {
+      crash::fault(&p, signal::SIGSEGV, "stack overflow");
+      return;
+    }
+    match p.grow_stack(va) {
+      Ok(true) => { return; }
+      Ok(false) => {}
+      Err(_) => {
+        crash::fault(&p, signal::SIGSEGV, "stack growth, out of memory");
+        return;
+      }
+    }
     if p.exception_handler().is_none() {
-      println!("isr: page_fault: process has no handler, process killed");
-      p.destroy();
+      crash::fault(&p, signal::SIGSEGV, "page fault, process has no handler");
       return;
     }
     let (entry, stack_top) = p.exception_handler().unwrap();
@@ -164,8 +335,7 @@ impl InterruptServiceRoutine for Isr {
     match page_table.lookup_page(stack_btm) {
       Some(stack_pte) => {
         if va == stack_btm {
-          println!("isr: page_fault: fault on exception stack, process killed");
-          p.destroy();
+          crash::fault(&p, signal::SIGSEGV, "page fault on exception stack");
           return;
         }
         let ctx = current_core().context_mut();
@@ -184,13 +354,24 @@ impl InterruptServiceRoutine for Isr {
         return;
       }
       None => {
-        println!("isr: page_fault: exception stack not valid, process killed");
-        p.destroy();
+        crash::fault(&p, signal::SIGSEGV, "page fault, exception stack not valid");
         return;
       }
     }
   }
 
+  fn breakpoint() {
+    if crate::lib::gdb::trap() {
+      return;
+    }
+    match current_process() {
+      None => { panic!("isr: breakpoint: no running process") }
+      Some(p) => {
+        crash::fault(&p, signal::SIGTRAP, "breakpoint, no debugger attached");
+      }
+    }
+  }
+
   fn default() {
     match current_thread() {
       None => { panic!("isr: default: no running thread") }
@@ -198,8 +379,7 @@ impl InterruptServiceRoutine for Isr {
         match t.process() {
           None => { panic!("isr: default: no running process") }
           Some(p) => {
-            println!("isr: default: process killed");
-            p.destroy();
+            crash::fault(&p, signal::SIGILL, "unhandled exception");
           }
         }
       }
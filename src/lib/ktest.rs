@@ -0,0 +1,60 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::lib::page_table::PageTableTrait;
+use crate::lib::process::Process;
+
+// Note: a kernel test, registered with `ktest!` and run by `run` on the boot core
+//       a test passes by returning, any panic (or kernel fault) fails it
+pub struct KernelTest {
+  pub name: &'static str,
+  pub module: &'static str,
+  pub run: fn(),
+}
+
+// Note: `ktest!` statics are collected in `.ktest`, see kernel.*.lds
+fn registry() -> &'static [KernelTest] {
+  extern "C" {
+    fn KTEST_START();
+    fn KTEST_END();
+  }
+  let start = KTEST_START as usize;
+  let end = KTEST_END as usize;
+  unsafe { core::slice::from_raw_parts(start as *const KernelTest, (end - start) / core::mem::size_of::<KernelTest>()) }
+}
+
+// Note: number of the running test (TAP numbering starts at 1), 0 if none
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+// Note: TAP output on the console, then exit QEMU with the result
+pub fn run() -> ! {
+  let tests = registry();
+  println!("TAP version 13");
+  println!("1..{}", tests.len());
+  for (i, test) in tests.iter().enumerate() {
+    RUNNING.store(i + 1, Ordering::SeqCst);
+    (test.run)();
+    println!("ok {} - {}::{}", i + 1, crate::lib::log::module_name(test.module), test.name);
+  }
+  RUNNING.store(0, Ordering::SeqCst);
+  println!("# pass {}", tests.len());
+  crate::driver::qemu::exit(true)
+}
+
+// Note: called by the panic handler after the panic is reported
+pub fn panicked() -> ! {
+  let running = RUNNING.load(Ordering::SeqCst);
+  if running != 0 {
+    let test = &registry()[running - 1];
+    println!("not ok {} - {}::{}", running, crate::lib::log::module_name(test.module), test.name);
+  }
+  println!("Bail out! kernel panic");
+  crate::driver::qemu::exit(false)
+}
+
+// Note: tear down a process made by a test
+//       `Process::exit` would schedule away with no thread left to return to
+//       the directory goes with the last reference to `p`
+pub fn release_process(p: &Process) {
+  p.page_table().destroy();
+  crate::lib::process::free(p);
+}
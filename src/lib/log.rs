@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait};
+use crate::config::{CONFIG_LOG_BUFFER_SIZE, CONFIG_LOG_FILTER, CONFIG_LOG_LEVEL};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+  Error = 1,
+  Warn,
+  Info,
+  Debug,
+  Trace,
+}
+
+impl Level {
+  fn tag(&self) -> char {
+    match self {
+      Level::Error => 'E',
+      Level::Warn => 'W',
+      Level::Info => 'I',
+      Level::Debug => 'D',
+      Level::Trace => 'T',
+    }
+  }
+}
+
+// Note: dmesg-style ring buffer, every byte printed by the kernel goes through it
+//       `written` and `drained` are byte counts since boot, the buffer keeps the
+//       last `CONFIG_LOG_BUFFER_SIZE` bytes
+struct RingBuffer {
+  buf: [u8; CONFIG_LOG_BUFFER_SIZE],
+  written: usize,
+  drained: usize,
+}
+
+impl RingBuffer {
+  fn oldest(&self) -> usize {
+    self.written.saturating_sub(CONFIG_LOG_BUFFER_SIZE)
+  }
+
+  // Note: push pending bytes to console, and to the framebuffer console if any
+  fn drain(&mut self) {
+    let start = core::cmp::max(self.drained, self.oldest());
+    for i in start..self.written {
+      crate::driver::uart::putc(self.buf[i % CONFIG_LOG_BUFFER_SIZE]);
+      crate::lib::framebuffer::putc(self.buf[i % CONFIG_LOG_BUFFER_SIZE]);
+    }
+    self.drained = self.written;
+  }
+
+  // Note: most recent `len` bytes, oldest first
+  fn recent(&self, len: usize) -> Vec<u8> {
+    let start = core::cmp::max(self.oldest(), self.written.saturating_sub(len));
+    (start..self.written).map(|i| self.buf[i % CONFIG_LOG_BUFFER_SIZE]).collect()
+  }
+}
+
+impl fmt::Write for RingBuffer {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for b in s.bytes() {
+      self.buf[self.written % CONFIG_LOG_BUFFER_SIZE] = b;
+      self.written += 1;
+    }
+    Ok(())
+  }
+}
+
+static LOG: Mutex<RingBuffer> = Mutex::new(RingBuffer {
+  buf: [0; CONFIG_LOG_BUFFER_SIZE],
+  written: 0,
+  drained: 0,
+});
+
+// Note: set on panic, output then bypasses the (possibly held) buffer lock
+static EMERGENCY: AtomicBool = AtomicBool::new(false);
+
+pub struct Console;
+
+impl Console {
+  // Note: as is, no newline translation, for binary dumps
+  pub fn write_bytes(&mut self, bytes: &[u8]) {
+    for b in bytes {
+      crate::driver::uart::putc_raw(*b);
+    }
+  }
+}
+
+impl fmt::Write for Console {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for b in s.bytes() {
+      crate::driver::uart::putc(b);
+    }
+    Ok(())
+  }
+}
+
+// Note: panic output, unbuffered on both consoles
+struct Emergency;
+
+impl fmt::Write for Emergency {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for b in s.bytes() {
+      crate::driver::uart::putc(b);
+      crate::lib::framebuffer::putc(b);
+    }
+    Ok(())
+  }
+}
+
+pub fn emergency() {
+  EMERGENCY.store(true, Ordering::SeqCst);
+}
+
+// Note: `module_path!()` without the crate name, e.g. "lib::scheduler"
+pub fn module_name(module: &str) -> &str {
+  match module.find("::") {
+    Some(i) => { &module[i + 2..] }
+    None => { module }
+  }
+}
+
+// Note: longest matching prefix in `CONFIG_LOG_FILTER` wins
+pub fn enabled(level: Level, module: &str) -> bool {
+  let module = module_name(module);
+  let mut max = CONFIG_LOG_LEVEL;
+  let mut matched = 0;
+  for (prefix, l) in CONFIG_LOG_FILTER {
+    if module.starts_with(prefix) && prefix.len() >= matched {
+      matched = prefix.len();
+      max = *l;
+    }
+  }
+  level <= max
+}
+
+// Note: a record is formatted, buffered and drained under one lock
+//       with irq masked, so lines from different cores (or from an irq) never interleave
+fn write(f: impl FnOnce(&mut dyn fmt::Write) -> fmt::Result) {
+  if EMERGENCY.load(Ordering::SeqCst) {
+    let _ = f(&mut Emergency);
+    return;
+  }
+  let irq = Arch::interrupt_save_disable();
+  let mut lock = LOG.lock();
+  let _ = f(&mut *lock);
+  lock.drain();
+  drop(lock);
+  Arch::interrupt_restore(irq);
+}
+
+// Note: raw console access (e.g. binary dumps) bypassing the buffer
+//       pending log is drained first, other output waits until `f` returns
+pub fn console_exclusive<F: FnOnce(&mut Console)>(f: F) {
+  if EMERGENCY.load(Ordering::SeqCst) {
+    f(&mut Console);
+    return;
+  }
+  let irq = Arch::interrupt_save_disable();
+  let mut lock = LOG.lock();
+  lock.drain();
+  f(&mut Console);
+  drop(lock);
+  Arch::interrupt_restore(irq);
+}
+
+pub fn print(args: fmt::Arguments) {
+  write(|w| w.write_fmt(args));
+}
+
+pub fn log(level: Level, module: &'static str, args: fmt::Arguments) {
+  if !enabled(level, module) {
+    return;
+  }
+  write(|w| {
+    write!(w, "[{} {}] {}: ", level.tag(), Arch::core_id(), module_name(module))?;
+    w.write_fmt(args)?;
+    w.write_str("\n")
+  });
+}
+
+// Note: copy of the most recent `len` bytes of the log
+pub fn read(len: usize) -> Vec<u8> {
+  let irq = Arch::interrupt_save_disable();
+  let lock = LOG.lock();
+  let r = lock.recent(len);
+  drop(lock);
+  Arch::interrupt_restore(irq);
+  r
+}
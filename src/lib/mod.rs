@@ -2,6 +2,8 @@ use crate::arch::CoreTrait;
 use crate::lib::core::Core;
 
 pub mod print;
+pub mod log;
+pub mod trace;
 pub mod isr;
 pub mod process;
 pub mod elf;
@@ -12,6 +14,22 @@ pub mod page_table;
 pub mod thread;
 pub mod bitmap;
 pub mod core;
+pub mod ipc;
+pub mod crash;
+pub mod backtrace;
+pub mod signal;
+pub mod uaccess;
+pub mod gdb;
+pub mod fdt;
+pub mod time;
+pub mod timer;
+pub mod font;
+pub mod framebuffer;
+pub mod net;
+pub mod chardev;
+pub mod random;
+#[cfg(feature = "ktest")]
+pub mod ktest;
 
 #[inline(always)]
 pub fn round_up(addr: usize, n: usize) -> usize {
@@ -40,3 +58,19 @@ pub fn current_process() -> Option<self::process::Process> {
     Some(t) => { t.process() }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round() {
+    assert_eq!(round_up(0, 0x1000), 0);
+    assert_eq!(round_up(1, 0x1000), 0x1000);
+    assert_eq!(round_up(0x1000, 0x1000), 0x1000);
+    assert_eq!(round_up(0x1001, 0x1000), 0x2000);
+    assert_eq!(round_down(0x1fff, 0x1000), 0x1000);
+    assert_eq!(round_down(0x2000, 0x1000), 0x2000);
+    assert_eq!(round_down(0xfff, 0x1000), 0);
+  }
+}
@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+
+use super::{Ipv4Address, MacAddress};
+
+// Note: Ethernet / IPv4 only
+const PACKET_SIZE: usize = 28;
+const HARDWARE_ETHERNET: u16 = 1;
+const PROTOCOL_IPV4: u16 = 0x0800;
+
+pub const OPERATION_REQUEST: u16 = 1;
+pub const OPERATION_REPLY: u16 = 2;
+
+// Note: entries never expire, the oldest one makes room
+const CACHE_SIZE: usize = 16;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+  pub operation: u16,
+  pub sender_mac: MacAddress,
+  pub sender_address: Ipv4Address,
+  pub target_mac: MacAddress,
+  pub target_address: Ipv4Address,
+}
+
+pub fn parse(data: &[u8]) -> Option<Packet> {
+  if data.len() < PACKET_SIZE
+    || u16::from_be_bytes([data[0], data[1]]) != HARDWARE_ETHERNET
+    || u16::from_be_bytes([data[2], data[3]]) != PROTOCOL_IPV4
+    || data[4] != 6 || data[5] != 4 {
+    return None;
+  }
+  let mut packet = Packet {
+    operation: u16::from_be_bytes([data[6], data[7]]),
+    sender_mac: [0; 6],
+    sender_address: [0; 4],
+    target_mac: [0; 6],
+    target_address: [0; 4],
+  };
+  packet.sender_mac.copy_from_slice(&data[8..14]);
+  packet.sender_address.copy_from_slice(&data[14..18]);
+  packet.target_mac.copy_from_slice(&data[18..24]);
+  packet.target_address.copy_from_slice(&data[24..28]);
+  Some(packet)
+}
+
+impl Packet {
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = Vec::with_capacity(PACKET_SIZE);
+    data.extend_from_slice(&HARDWARE_ETHERNET.to_be_bytes());
+    data.extend_from_slice(&PROTOCOL_IPV4.to_be_bytes());
+    data.extend_from_slice(&[6, 4]);
+    data.extend_from_slice(&self.operation.to_be_bytes());
+    data.extend_from_slice(&self.sender_mac);
+    data.extend_from_slice(&self.sender_address);
+    data.extend_from_slice(&self.target_mac);
+    data.extend_from_slice(&self.target_address);
+    data
+  }
+}
+
+pub struct Cache {
+  entries: Vec<(Ipv4Address, MacAddress)>,
+}
+
+impl Cache {
+  pub fn new() -> Self {
+    Cache { entries: Vec::new() }
+  }
+
+  pub fn lookup(&self, address: Ipv4Address) -> Option<MacAddress> {
+    self.entries.iter().find(|(a, _)| *a == address).map(|(_, mac)| *mac)
+  }
+
+  pub fn insert(&mut self, address: Ipv4Address, mac: MacAddress) {
+    self.entries.retain(|(a, _)| *a != address);
+    if self.entries.len() == CACHE_SIZE {
+      self.entries.remove(0);
+    }
+    self.entries.push((address, mac));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn packet() {
+    let request = Packet {
+      operation: OPERATION_REQUEST,
+      sender_mac: [0x52, 0x55, 10, 0, 2, 2],
+      sender_address: [10, 0, 2, 2],
+      target_mac: [0; 6],
+      target_address: [10, 0, 2, 15],
+    };
+    let bytes = request.to_bytes();
+    assert_eq!(bytes.len(), PACKET_SIZE);
+    assert_eq!(&bytes[..8], &[0, 1, 8, 0, 6, 4, 0, 1]);
+    assert_eq!(parse(&bytes), Some(request));
+    assert_eq!(parse(&bytes[..PACKET_SIZE - 1]), None);
+  }
+
+  #[test]
+  fn cache() {
+    let mut cache = Cache::new();
+    for i in 0..=CACHE_SIZE as u8 {
+      cache.insert([10, 0, 2, i], [i; 6]);
+    }
+    assert_eq!(cache.lookup([10, 0, 2, 0]), None);
+    assert_eq!(cache.lookup([10, 0, 2, 1]), Some([1; 6]));
+    cache.insert([10, 0, 2, 1], [0xaa; 6]);
+    assert_eq!(cache.lookup([10, 0, 2, 1]), Some([0xaa; 6]));
+  }
+}
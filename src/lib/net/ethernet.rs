@@ -0,0 +1,46 @@
+use alloc::vec::Vec;
+
+use super::MacAddress;
+
+pub const HEADER_SIZE: usize = 14;
+// Note: without the frame check sequence, shorter frames are padded
+const MIN_FRAME_SIZE: usize = 60;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+pub const BROADCAST: MacAddress = [0xff; 6];
+
+pub struct Frame<'a> {
+  pub destination: MacAddress,
+  pub source: MacAddress,
+  pub ethertype: u16,
+  // Note: may carry padding, upper layers use their own length
+  pub payload: &'a [u8],
+}
+
+pub fn parse(frame: &[u8]) -> Option<Frame> {
+  if frame.len() < HEADER_SIZE {
+    return None;
+  }
+  let mut destination = [0u8; 6];
+  let mut source = [0u8; 6];
+  destination.copy_from_slice(&frame[0..6]);
+  source.copy_from_slice(&frame[6..12]);
+  Some(Frame {
+    destination,
+    source,
+    ethertype: u16::from_be_bytes([frame[12], frame[13]]),
+    payload: &frame[HEADER_SIZE..],
+  })
+}
+
+pub fn build(destination: MacAddress, source: MacAddress, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+  let mut frame = Vec::with_capacity(core::cmp::max(HEADER_SIZE + payload.len(), MIN_FRAME_SIZE));
+  frame.extend_from_slice(&destination);
+  frame.extend_from_slice(&source);
+  frame.extend_from_slice(&ethertype.to_be_bytes());
+  frame.extend_from_slice(payload);
+  frame.resize(core::cmp::max(frame.len(), MIN_FRAME_SIZE), 0);
+  frame
+}
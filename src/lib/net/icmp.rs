@@ -0,0 +1,45 @@
+use alloc::vec::Vec;
+
+use super::ipv4::checksum;
+
+const HEADER_SIZE: usize = 8;
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_ECHO_REQUEST: u8 = 8;
+
+// Note: only echo is answered, other messages are dropped
+//       the reply carries identifier, sequence number and data of the request
+pub fn echo_reply(request: &[u8]) -> Option<Vec<u8>> {
+  if request.len() < HEADER_SIZE || request[0] != TYPE_ECHO_REQUEST || request[1] != 0 {
+    return None;
+  }
+  if checksum(request, 0) != 0 {
+    return None;
+  }
+  let mut reply = request.to_vec();
+  reply[0] = TYPE_ECHO_REPLY;
+  reply[2] = 0;
+  reply[3] = 0;
+  let sum = checksum(&reply, 0);
+  reply[2..4].copy_from_slice(&sum.to_be_bytes());
+  Some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn echo() {
+    let mut request = alloc::vec![TYPE_ECHO_REQUEST, 0, 0, 0, 0x12, 0x34, 0, 1, b'p', b'i', b'n', b'g'];
+    let sum = checksum(&request, 0);
+    request[2..4].copy_from_slice(&sum.to_be_bytes());
+    let reply = echo_reply(&request).unwrap();
+    assert_eq!(reply[0], TYPE_ECHO_REPLY);
+    assert_eq!(&reply[4..], &request[4..]);
+    assert_eq!(checksum(&reply, 0), 0);
+    assert!(echo_reply(&reply).is_none());
+    request[11] ^= 1;
+    assert!(echo_reply(&request).is_none());
+  }
+}
@@ -0,0 +1,613 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::lib::process::Pid;
+
+use super::{Endpoint, Error, Ipv4Address, MacAddress, Protocol};
+use super::{arp, ethernet, icmp, ipv4, tcp, udp};
+use super::tcp::Connection;
+
+// Note: IPv4 packets wait this long (nanoseconds) for their next hop to resolve
+const ARP_TIMEOUT: u64 = 1_000_000_000;
+const ARP_PENDING_LIMIT: usize = 16;
+
+const SOCKET_LIMIT: usize = 64;
+const UDP_QUEUE_LENGTH: usize = 16;
+// Note: an unfragmented datagram on a 1500 bytes MTU
+const UDP_PAYLOAD_LIMIT: usize = 1500 - ipv4::HEADER_SIZE - udp::HEADER_SIZE;
+const TCP_BACKLOG: usize = 4;
+
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+struct Udp {
+  port: u16,
+  // Note: set by `connect`, other senders are filtered out
+  peer: Option<Endpoint>,
+  rx: VecDeque<(Endpoint, Vec<u8>)>,
+}
+
+enum Tcp {
+  // Note: port 0 until bound
+  Open(u16),
+  // Note: sockets of the connections not accepted yet
+  Listener(u16, VecDeque<usize>),
+  Connection(Connection),
+}
+
+enum Kind {
+  Udp(Udp),
+  Tcp(Tcp),
+}
+
+struct Socket {
+  // Note: `None` once closed, a TCP connection lingers until its FIN is acknowledged
+  owner: Option<Pid>,
+  kind: Kind,
+}
+
+// Note: one Ethernet interface with a static IPv4 address, sockets are numbered by their slot
+pub struct Interface {
+  mac: MacAddress,
+  address: Ipv4Address,
+  netmask: Ipv4Address,
+  gateway: Ipv4Address,
+  arp: arp::Cache,
+  // Note: (next hop, time of the request, IPv4 packet)
+  pending: Vec<(Ipv4Address, u64, Vec<u8>)>,
+  sockets: Vec<Option<Socket>>,
+  ip_id: u16,
+  next_port: u16,
+  // Note: frames to be sent, taken by `take_frames`
+  frames: Vec<Vec<u8>>,
+}
+
+impl Interface {
+  pub fn new(mac: MacAddress, address: Ipv4Address, netmask: Ipv4Address, gateway: Ipv4Address) -> Self {
+    Interface {
+      mac,
+      address,
+      netmask,
+      gateway,
+      arp: arp::Cache::new(),
+      pending: Vec::new(),
+      sockets: Vec::new(),
+      ip_id: 0,
+      next_port: *EPHEMERAL_PORTS.start(),
+      frames: Vec::new(),
+    }
+  }
+
+  pub fn take_frames(&mut self) -> Vec<Vec<u8>> {
+    core::mem::take(&mut self.frames)
+  }
+
+  fn on_link(&self, address: Ipv4Address) -> bool {
+    (0..4).all(|i| address[i] & self.netmask[i] == self.address[i] & self.netmask[i])
+  }
+
+  fn send_arp(&mut self, operation: u16, target_mac: MacAddress, target_address: Ipv4Address) {
+    let packet = arp::Packet {
+      operation,
+      sender_mac: self.mac,
+      sender_address: self.address,
+      target_mac,
+      target_address,
+    };
+    let destination = if operation == arp::OPERATION_REQUEST { ethernet::BROADCAST } else { target_mac };
+    self.frames.push(ethernet::build(destination, self.mac, ethernet::ETHERTYPE_ARP, &packet.to_bytes()));
+  }
+
+  // Note: off link destinations go through the gateway, unresolved next hops are asked for once
+  fn send_ipv4(&mut self, destination: Ipv4Address, protocol: u8, payload: &[u8], now: u64) {
+    let packet = ipv4::build(self.ip_id, self.address, destination, protocol, payload);
+    self.ip_id = self.ip_id.wrapping_add(1);
+    if destination == ipv4::BROADCAST {
+      self.frames.push(ethernet::build(ethernet::BROADCAST, self.mac, ethernet::ETHERTYPE_IPV4, &packet));
+      return;
+    }
+    let hop = if self.on_link(destination) { destination } else { self.gateway };
+    match self.arp.lookup(hop) {
+      Some(mac) => {
+        self.frames.push(ethernet::build(mac, self.mac, ethernet::ETHERTYPE_IPV4, &packet));
+      }
+      None => {
+        if !self.pending.iter().any(|(a, _, _)| *a == hop) {
+          self.send_arp(arp::OPERATION_REQUEST, [0; 6], hop);
+        }
+        if self.pending.len() < ARP_PENDING_LIMIT {
+          self.pending.push((hop, now, packet));
+        }
+      }
+    }
+  }
+
+  pub fn receive(&mut self, frame: &[u8], now: u64) {
+    let frame = match ethernet::parse(frame) {
+      Some(f) if f.destination == self.mac || f.destination == ethernet::BROADCAST => { f }
+      _ => { return; }
+    };
+    match frame.ethertype {
+      ethernet::ETHERTYPE_ARP => { self.receive_arp(frame.payload) }
+      ethernet::ETHERTYPE_IPV4 => { self.receive_ipv4(frame.payload, now) }
+      _ => {}
+    }
+  }
+
+  fn receive_arp(&mut self, data: &[u8]) {
+    let packet = match arp::parse(data) {
+      Some(p) if p.target_address == self.address => { p }
+      _ => { return; }
+    };
+    self.arp.insert(packet.sender_address, packet.sender_mac);
+    let mac = packet.sender_mac;
+    let (ready, pending): (Vec<_>, Vec<_>) = core::mem::take(&mut self.pending).into_iter().partition(|(a, _, _)| *a == packet.sender_address);
+    self.pending = pending;
+    for (_, _, p) in ready.into_iter() {
+      self.frames.push(ethernet::build(mac, self.mac, ethernet::ETHERTYPE_IPV4, &p));
+    }
+    if packet.operation == arp::OPERATION_REQUEST {
+      self.send_arp(arp::OPERATION_REPLY, packet.sender_mac, packet.sender_address);
+    }
+  }
+
+  fn receive_ipv4(&mut self, data: &[u8], now: u64) {
+    let packet = match ipv4::parse(data) {
+      Some(p) if p.destination == self.address || p.destination == ipv4::BROADCAST => { p }
+      _ => { return; }
+    };
+    match packet.protocol {
+      ipv4::PROTOCOL_ICMP => {
+        if let Some(reply) = icmp::echo_reply(packet.payload) {
+          self.send_ipv4(packet.source, ipv4::PROTOCOL_ICMP, &reply, now);
+        }
+      }
+      ipv4::PROTOCOL_UDP => { self.receive_udp(packet.source, packet.destination, packet.payload) }
+      ipv4::PROTOCOL_TCP => {
+        if packet.destination == self.address {
+          self.receive_tcp(packet.source, packet.payload, now);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  // Note: datagrams for no socket or a full queue are dropped
+  fn receive_udp(&mut self, source: Ipv4Address, destination: Ipv4Address, data: &[u8]) {
+    let datagram = match udp::parse(source, destination, data) {
+      Some(d) => { d }
+      None => { return; }
+    };
+    let from = Endpoint { address: source, port: datagram.source_port };
+    let socket = self.sockets.iter_mut().flatten().find_map(|s| match &mut s.kind {
+      Kind::Udp(u) if u.port == datagram.destination_port && u.peer.map_or(true, |p| p == from) => { Some(u) }
+      _ => { None }
+    });
+    if let Some(u) = socket {
+      if u.rx.len() < UDP_QUEUE_LENGTH {
+        u.rx.push_back((from, datagram.payload.to_vec()));
+      }
+    }
+  }
+
+  // Note: a connection first, then a listener, else a reset
+  fn receive_tcp(&mut self, source: Ipv4Address, data: &[u8], now: u64) {
+    let segment = match tcp::parse(source, self.address, data) {
+      Some(s) => { s }
+      None => { return; }
+    };
+    let local = Endpoint { address: self.address, port: segment.destination_port };
+    let remote = Endpoint { address: source, port: segment.source_port };
+    let connection = self.sockets.iter_mut().flatten().find_map(|s| match &mut s.kind {
+      Kind::Tcp(Tcp::Connection(c)) if c.local == local && c.remote == remote && !c.is_closed() => { Some(c) }
+      _ => { None }
+    });
+    if let Some(c) = connection {
+      c.receive(&segment, now);
+      c.poll(now);
+      for s in c.take_output() {
+        self.send_ipv4(source, ipv4::PROTOCOL_TCP, &s, now);
+      }
+      return;
+    }
+    if segment.flags & (tcp::FLAG_SYN | tcp::FLAG_ACK | tcp::FLAG_RST) == tcp::FLAG_SYN {
+      let listener = self.sockets.iter().position(|s| match s {
+        Some(Socket { owner: Some(_), kind: Kind::Tcp(Tcp::Listener(port, backlog)) }) => {
+          *port == local.port && backlog.len() < TCP_BACKLOG
+        }
+        _ => { false }
+      });
+      if let Some(i) = listener {
+        let mut c = Connection::accept(local, remote, &segment, iss(now), now);
+        let out = c.take_output();
+        let owner = self.sockets[i].as_ref().unwrap().owner;
+        if let Ok(sid) = self.insert(Socket { owner, kind: Kind::Tcp(Tcp::Connection(c)) }) {
+          if let Some(Socket { kind: Kind::Tcp(Tcp::Listener(_, backlog)), .. }) = self.sockets[i].as_mut() {
+            backlog.push_back(sid);
+          }
+          for s in out {
+            self.send_ipv4(source, ipv4::PROTOCOL_TCP, &s, now);
+          }
+        }
+        return;
+      }
+    }
+    if let Some(rst) = tcp::reset(local, remote, &segment) {
+      self.send_ipv4(source, ipv4::PROTOCOL_TCP, &rst, now);
+    }
+  }
+
+  // Note: connection timers and output, lingering connections, unresolved packets
+  //       a closed socket's connection stuck in FIN_WAIT_2 is dropped after `tcp::FIN_WAIT_2_TIMEOUT`
+  pub fn poll(&mut self, now: u64) {
+    for i in 0..self.sockets.len() {
+      let (remote, out) = match self.sockets[i].as_mut() {
+        Some(Socket { kind: Kind::Tcp(Tcp::Connection(c)), .. }) => {
+          c.poll(now);
+          (c.remote.address, c.take_output())
+        }
+        _ => { continue; }
+      };
+      for s in out {
+        self.send_ipv4(remote, ipv4::PROTOCOL_TCP, &s, now);
+      }
+    }
+    for slot in self.sockets.iter_mut() {
+      if let Some(Socket { owner: None, kind: Kind::Tcp(Tcp::Connection(c)) }) = slot {
+        if c.is_closed() || c.fin_wait_expired(now) {
+          *slot = None;
+        }
+      }
+    }
+    self.pending.retain(|(_, t, _)| now.saturating_sub(*t) < ARP_TIMEOUT);
+  }
+
+  fn insert(&mut self, socket: Socket) -> Result<usize, Error> {
+    if let Some(sid) = self.sockets.iter().position(|s| s.is_none()) {
+      self.sockets[sid] = Some(socket);
+      return Ok(sid);
+    }
+    if self.sockets.len() == SOCKET_LIMIT {
+      return Err(Error::OutOfSocketError);
+    }
+    self.sockets.push(Some(socket));
+    Ok(self.sockets.len() - 1)
+  }
+
+  fn lookup(&mut self, owner: Pid, sid: usize) -> Result<&mut Socket, Error> {
+    match self.sockets.get_mut(sid) {
+      Some(Some(s)) if s.owner == Some(owner) => { Ok(s) }
+      _ => { Err(Error::SocketNotFoundError) }
+    }
+  }
+
+  fn port_in_use(&self, protocol: Protocol, port: u16) -> bool {
+    self.sockets.iter().flatten().any(|s| match (&s.kind, protocol) {
+      (Kind::Udp(u), Protocol::Udp) => { u.port == port }
+      (Kind::Tcp(Tcp::Open(p)), Protocol::Tcp) | (Kind::Tcp(Tcp::Listener(p, _)), Protocol::Tcp) => { *p == port }
+      (Kind::Tcp(Tcp::Connection(c)), Protocol::Tcp) => { c.local.port == port }
+      _ => { false }
+    })
+  }
+
+  fn ephemeral_port(&mut self, protocol: Protocol) -> Result<u16, Error> {
+    for _ in EPHEMERAL_PORTS {
+      let port = self.next_port;
+      self.next_port = if port == *EPHEMERAL_PORTS.end() { *EPHEMERAL_PORTS.start() } else { port + 1 };
+      if !self.port_in_use(protocol, port) {
+        return Ok(port);
+      }
+    }
+    Err(Error::AddressInUseError)
+  }
+
+  pub fn socket(&mut self, owner: Pid, protocol: Protocol) -> Result<usize, Error> {
+    let kind = match protocol {
+      Protocol::Udp => { Kind::Udp(Udp { port: 0, peer: None, rx: VecDeque::new() }) }
+      Protocol::Tcp => { Kind::Tcp(Tcp::Open(0)) }
+    };
+    self.insert(Socket { owner: Some(owner), kind })
+  }
+
+  // Note: port 0 takes an ephemeral one
+  pub fn bind(&mut self, owner: Pid, sid: usize, port: u16) -> Result<(), Error> {
+    let protocol = match &self.lookup(owner, sid)?.kind {
+      Kind::Udp(Udp { port: 0, .. }) => { Protocol::Udp }
+      Kind::Tcp(Tcp::Open(0)) => { Protocol::Tcp }
+      _ => { return Err(Error::InvalidStateError); }
+    };
+    let port = if port == 0 {
+      self.ephemeral_port(protocol)?
+    } else if self.port_in_use(protocol, port) {
+      return Err(Error::AddressInUseError);
+    } else {
+      port
+    };
+    match &mut self.lookup(owner, sid)?.kind {
+      Kind::Udp(u) => { u.port = port; }
+      Kind::Tcp(t) => { *t = Tcp::Open(port); }
+    }
+    Ok(())
+  }
+
+  fn bound(&mut self, owner: Pid, sid: usize) -> Result<u16, Error> {
+    let port = match &self.lookup(owner, sid)?.kind {
+      Kind::Udp(u) => { u.port }
+      Kind::Tcp(Tcp::Open(port)) => { *port }
+      _ => { return Err(Error::InvalidStateError); }
+    };
+    if port == 0 {
+      self.bind(owner, sid, 0)?;
+      return self.bound(owner, sid);
+    }
+    Ok(port)
+  }
+
+  // Note: UDP only records the peer, TCP sends its SYN and returns, see `Connection::send`
+  pub fn connect(&mut self, owner: Pid, sid: usize, remote: Endpoint, now: u64) -> Result<(), Error> {
+    if remote.port == 0 {
+      return Err(Error::InvalidArgumentError);
+    }
+    let port = self.bound(owner, sid)?;
+    let local = Endpoint { address: self.address, port };
+    match &mut self.lookup(owner, sid)?.kind {
+      Kind::Udp(u) => { u.peer = Some(remote); }
+      Kind::Tcp(t) => { *t = Tcp::Connection(Connection::connect(local, remote, iss(now), now)); }
+    }
+    Ok(())
+  }
+
+  pub fn listen(&mut self, owner: Pid, sid: usize) -> Result<(), Error> {
+    match &mut self.lookup(owner, sid)?.kind {
+      Kind::Tcp(t) => {
+        match t {
+          Tcp::Open(port) if *port != 0 => {
+            *t = Tcp::Listener(*port, VecDeque::new());
+            Ok(())
+          }
+          _ => { Err(Error::InvalidStateError) }
+        }
+      }
+      _ => { Err(Error::InvalidStateError) }
+    }
+  }
+
+  // Note: the first connection past its handshake, as a new socket
+  pub fn accept(&mut self, owner: Pid, sid: usize) -> Result<(usize, Endpoint), Error> {
+    let backlog = match &self.lookup(owner, sid)?.kind {
+      Kind::Tcp(Tcp::Listener(_, backlog)) => { backlog.clone() }
+      _ => { return Err(Error::InvalidStateError); }
+    };
+    let accepted = backlog.iter().copied().find_map(|s| match self.sockets[s].as_ref() {
+      Some(Socket { kind: Kind::Tcp(Tcp::Connection(c)), .. }) if c.state() != tcp::State::SynReceived => {
+        Some((s, c.remote))
+      }
+      _ => { None }
+    });
+    match accepted {
+      None => { Err(Error::WouldBlockError) }
+      Some((s, remote)) => {
+        if let Kind::Tcp(Tcp::Listener(_, backlog)) = &mut self.lookup(owner, sid)?.kind {
+          backlog.retain(|b| *b != s);
+        }
+        Ok((s, remote))
+      }
+    }
+  }
+
+  // Note: `to` overrides the peer of a UDP socket, TCP takes what fits its buffer
+  pub fn send(&mut self, owner: Pid, sid: usize, data: &[u8], to: Option<Endpoint>, now: u64) -> Result<usize, Error> {
+    let is_udp = matches!(self.lookup(owner, sid)?.kind, Kind::Udp(_));
+    if !is_udp {
+      return match &mut self.lookup(owner, sid)?.kind {
+        Kind::Tcp(Tcp::Connection(c)) => { c.send(data) }
+        _ => { Err(Error::NotConnectedError) }
+      };
+    }
+    if data.len() > UDP_PAYLOAD_LIMIT {
+      return Err(Error::InvalidArgumentError);
+    }
+    let port = self.bound(owner, sid)?;
+    let peer = match &self.lookup(owner, sid)?.kind {
+      Kind::Udp(u) => { u.peer }
+      _ => { None }
+    };
+    let to = to.or(peer).ok_or(Error::NotConnectedError)?;
+    let datagram = udp::build(Endpoint { address: self.address, port }, to, data);
+    self.send_ipv4(to.address, ipv4::PROTOCOL_UDP, &datagram, now);
+    Ok(data.len())
+  }
+
+  // Note: a datagram longer than `buf` is truncated
+  pub fn receive_from(&mut self, owner: Pid, sid: usize, buf: &mut [u8]) -> Result<(usize, Endpoint), Error> {
+    match &mut self.lookup(owner, sid)?.kind {
+      Kind::Udp(u) => {
+        let (from, data) = u.rx.pop_front().ok_or(Error::WouldBlockError)?;
+        let n = core::cmp::min(buf.len(), data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok((n, from))
+      }
+      Kind::Tcp(Tcp::Connection(c)) => {
+        let n = c.read(buf)?;
+        Ok((n, c.remote))
+      }
+      _ => { Err(Error::NotConnectedError) }
+    }
+  }
+
+  pub fn close(&mut self, owner: Pid, sid: usize) -> Result<(), Error> {
+    let socket = self.lookup(owner, sid)?;
+    socket.owner = None;
+    let backlog = match &mut socket.kind {
+      Kind::Tcp(Tcp::Connection(c)) => {
+        c.close();
+        return Ok(());
+      }
+      Kind::Tcp(Tcp::Listener(_, backlog)) => { core::mem::take(backlog) }
+      _ => { VecDeque::new() }
+    };
+    self.sockets[sid] = None;
+    // Note: connections never accepted are closed as well
+    for s in backlog {
+      if let Some(Socket { owner, kind: Kind::Tcp(Tcp::Connection(c)) }) = self.sockets[s].as_mut() {
+        *owner = None;
+        c.close();
+      }
+    }
+    Ok(())
+  }
+
+  pub fn release(&mut self, owner: Pid) {
+    for sid in 0..self.sockets.len() {
+      let _ = self.close(owner, sid);
+    }
+  }
+}
+
+// Note: initial sequence number, the 4 microseconds clock of RFC 793 plus a random offset
+//       per connection, not to be guessed by an off-path attacker (RFC 6528)
+//       the entropy pool is used even if not seeded yet
+fn iss(now: u64) -> u32 {
+  let mut offset = [0u8; 4];
+  let _ = crate::lib::random::fill(&mut offset, true);
+  ((now / 4_000) as u32).wrapping_add(u32::from_le_bytes(offset))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MAC: MacAddress = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+  const GATEWAY_MAC: MacAddress = [0x52, 0x55, 10, 0, 2, 2];
+  const ADDRESS: Ipv4Address = [10, 0, 2, 15];
+  const GATEWAY: Ipv4Address = [10, 0, 2, 2];
+  const HOST: Ipv4Address = [192, 168, 1, 1];
+
+  fn interface() -> Interface {
+    Interface::new(MAC, ADDRESS, [255, 255, 255, 0], GATEWAY)
+  }
+
+  // Note: a frame of the gateway to us
+  fn from_gateway(source: Ipv4Address, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let packet = ipv4::build(0, source, ADDRESS, protocol, payload);
+    ethernet::build(MAC, GATEWAY_MAC, ethernet::ETHERTYPE_IPV4, &packet)
+  }
+
+  fn resolve_gateway(i: &mut Interface) {
+    let reply = arp::Packet {
+      operation: arp::OPERATION_REPLY,
+      sender_mac: GATEWAY_MAC,
+      sender_address: GATEWAY,
+      target_mac: MAC,
+      target_address: ADDRESS,
+    };
+    i.receive(&ethernet::build(MAC, GATEWAY_MAC, ethernet::ETHERTYPE_ARP, &reply.to_bytes()), 0);
+  }
+
+  // Note: IPv4 payloads of the frames sent, all to the gateway
+  fn sent(i: &mut Interface, protocol: u8) -> Vec<Vec<u8>> {
+    i.take_frames().iter().map(|f| {
+      let frame = ethernet::parse(f).unwrap();
+      assert_eq!(frame.destination, GATEWAY_MAC);
+      let packet = ipv4::parse(frame.payload).unwrap();
+      assert_eq!(packet.protocol, protocol);
+      packet.payload.to_vec()
+    }).collect()
+  }
+
+  #[test]
+  fn arp_and_ping() {
+    let mut i = interface();
+    let request = arp::Packet {
+      operation: arp::OPERATION_REQUEST,
+      sender_mac: GATEWAY_MAC,
+      sender_address: GATEWAY,
+      target_mac: [0; 6],
+      target_address: ADDRESS,
+    };
+    i.receive(&ethernet::build(ethernet::BROADCAST, GATEWAY_MAC, ethernet::ETHERTYPE_ARP, &request.to_bytes()), 0);
+    let frames = i.take_frames();
+    assert_eq!(frames.len(), 1);
+    let reply = arp::parse(ethernet::parse(&frames[0]).unwrap().payload).unwrap();
+    assert_eq!((reply.operation, reply.sender_mac, reply.target_address), (arp::OPERATION_REPLY, MAC, GATEWAY));
+    let mut echo = alloc::vec![8, 0, 0, 0, 0, 1, 0, 1];
+    let sum = ipv4::checksum(&echo, 0);
+    echo[2..4].copy_from_slice(&sum.to_be_bytes());
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_ICMP, &echo), 0);
+    let replies = sent(&mut i, ipv4::PROTOCOL_ICMP);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0][0], 0);
+  }
+
+  #[test]
+  fn udp() {
+    let mut i = interface();
+    let sid = i.socket(1, Protocol::Udp).unwrap();
+    i.bind(1, sid, 5555).unwrap();
+    let other = i.socket(1, Protocol::Udp).unwrap();
+    assert_eq!(i.bind(1, other, 5555), Err(Error::AddressInUseError));
+    assert_eq!(i.bind(2, sid, 5556), Err(Error::SocketNotFoundError));
+    let peer = Endpoint { address: HOST, port: 7 };
+    assert_eq!(i.send(1, sid, b"ping", Some(peer), 0), Ok(4));
+    // Note: held until the gateway answers the ARP request
+    let frames = i.take_frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(ethernet::parse(&frames[0]).unwrap().ethertype, ethernet::ETHERTYPE_ARP);
+    resolve_gateway(&mut i);
+    let datagrams = sent(&mut i, ipv4::PROTOCOL_UDP);
+    let d = udp::parse(ADDRESS, HOST, &datagrams[0]).unwrap();
+    assert_eq!((d.source_port, d.destination_port, d.payload), (5555, 7, &b"ping"[..]));
+    let mut buf = [0u8; 2];
+    assert_eq!(i.receive_from(1, sid, &mut buf), Err(Error::WouldBlockError));
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_UDP, &udp::build(peer, Endpoint { address: ADDRESS, port: 5555 }, b"pong")), 0);
+    assert_eq!(i.receive_from(1, sid, &mut buf), Ok((2, peer)));
+    assert_eq!(&buf, b"po");
+    i.close(1, sid).unwrap();
+    assert_eq!(i.receive_from(1, sid, &mut buf), Err(Error::SocketNotFoundError));
+  }
+
+  #[test]
+  fn tcp_listen_and_reset() {
+    let mut i = interface();
+    resolve_gateway(&mut i);
+    let local = Endpoint { address: ADDRESS, port: 80 };
+    let remote = Endpoint { address: HOST, port: 40000 };
+    // Note: nobody listens
+    let syn = tcp::build(remote, local, 100, 0, tcp::FLAG_SYN, 1024, &[]);
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_TCP, &syn), 0);
+    let out = sent(&mut i, ipv4::PROTOCOL_TCP);
+    assert_eq!(tcp::parse(ADDRESS, HOST, &out[0]).unwrap().flags, tcp::FLAG_RST | tcp::FLAG_ACK);
+    let sid = i.socket(1, Protocol::Tcp).unwrap();
+    assert_eq!(i.listen(1, sid), Err(Error::InvalidStateError));
+    i.bind(1, sid, 80).unwrap();
+    i.listen(1, sid).unwrap();
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_TCP, &syn), 0);
+    let out = sent(&mut i, ipv4::PROTOCOL_TCP);
+    let syn_ack = tcp::parse(ADDRESS, HOST, &out[0]).unwrap();
+    assert_eq!((syn_ack.flags, syn_ack.acknowledgment), (tcp::FLAG_SYN | tcp::FLAG_ACK, 101));
+    assert_eq!(i.accept(1, sid), Err(Error::WouldBlockError));
+    let ack = tcp::build(remote, local, 101, syn_ack.sequence.wrapping_add(1), tcp::FLAG_ACK | tcp::FLAG_PSH, 1024, b"GET");
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_TCP, &ack), 0);
+    let (connection, peer) = i.accept(1, sid).unwrap();
+    assert_eq!(peer, remote);
+    let mut buf = [0u8; 8];
+    assert_eq!(i.receive_from(1, connection, &mut buf), Ok((3, remote)));
+    assert_eq!(i.send(1, connection, b"OK", None, 0), Ok(2));
+    i.take_frames();
+    i.poll(0);
+    let out = sent(&mut i, ipv4::PROTOCOL_TCP);
+    assert_eq!(tcp::parse(ADDRESS, HOST, &out[0]).unwrap().payload, b"OK");
+    // Note: the connection lingers after close until the FIN is acknowledged
+    i.release(1);
+    i.poll(0);
+    assert!(i.sockets[sid].is_none());
+    assert!(i.sockets[connection].is_some());
+    assert_eq!(i.receive_from(1, connection, &mut buf), Err(Error::SocketNotFoundError));
+    // Note: the peer acknowledges our FIN but never sends its own
+    let fin_ack = tcp::build(remote, local, 104, syn_ack.sequence.wrapping_add(4), tcp::FLAG_ACK, 1024, &[]);
+    i.receive(&from_gateway(HOST, ipv4::PROTOCOL_TCP, &fin_ack), 0);
+    i.poll(tcp::FIN_WAIT_2_TIMEOUT - 1);
+    assert!(i.sockets[connection].is_some());
+    i.poll(tcp::FIN_WAIT_2_TIMEOUT);
+    assert!(i.sockets[connection].is_none());
+  }
+}
@@ -0,0 +1,125 @@
+use alloc::vec::Vec;
+
+use super::Ipv4Address;
+
+pub const HEADER_SIZE: usize = 20;
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+const DEFAULT_TTL: u8 = 64;
+const FLAG_DONT_FRAGMENT: u16 = 0x4000;
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+const FRAGMENT_OFFSET_MASK: u16 = 0x1fff;
+
+pub const BROADCAST: Ipv4Address = [0xff; 4];
+
+// Note: 16 bits ones' complement sum (RFC 1071) of `data` on top of `sum`
+//       a region with a valid checksum in it sums to 0
+pub fn checksum(data: &[u8], sum: u32) -> u16 {
+  let mut sum = sum;
+  for pair in data.chunks(2) {
+    let word = if pair.len() == 2 { u16::from_be_bytes([pair[0], pair[1]]) } else { (pair[0] as u16) << 8 };
+    sum += word as u32;
+  }
+  while sum >> 16 != 0 {
+    sum = (sum & 0xffff) + (sum >> 16);
+  }
+  !(sum as u16)
+}
+
+// Note: unfolded sum of the pseudo header of TCP and UDP, `checksum` takes it as start
+pub fn pseudo_header_sum(source: Ipv4Address, destination: Ipv4Address, protocol: u8, length: usize) -> u32 {
+  let word = |a: Ipv4Address, i: usize| u16::from_be_bytes([a[i], a[i + 1]]) as u32;
+  word(source, 0) + word(source, 2) + word(destination, 0) + word(destination, 2) + protocol as u32 + length as u32
+}
+
+pub struct Packet<'a> {
+  pub source: Ipv4Address,
+  pub destination: Ipv4Address,
+  pub protocol: u8,
+  pub payload: &'a [u8],
+}
+
+// Note: options are skipped, fragments are dropped (no reassembly)
+pub fn parse(data: &[u8]) -> Option<Packet> {
+  if data.len() < HEADER_SIZE || data[0] >> 4 != 4 {
+    return None;
+  }
+  let header_size = ((data[0] & 0xf) as usize) * 4;
+  let total_size = u16::from_be_bytes([data[2], data[3]]) as usize;
+  if header_size < HEADER_SIZE || total_size < header_size || total_size > data.len() {
+    return None;
+  }
+  if checksum(&data[..header_size], 0) != 0 {
+    return None;
+  }
+  let fragment = u16::from_be_bytes([data[6], data[7]]);
+  if fragment & FLAG_MORE_FRAGMENTS != 0 || fragment & FRAGMENT_OFFSET_MASK != 0 {
+    return None;
+  }
+  let mut source = [0u8; 4];
+  let mut destination = [0u8; 4];
+  source.copy_from_slice(&data[12..16]);
+  destination.copy_from_slice(&data[16..20]);
+  Some(Packet {
+    source,
+    destination,
+    protocol: data[9],
+    payload: &data[header_size..total_size],
+  })
+}
+
+// Note: never fragmented, the caller keeps the payload within the MTU
+pub fn build(id: u16, source: Ipv4Address, destination: Ipv4Address, protocol: u8, payload: &[u8]) -> Vec<u8> {
+  let mut data = Vec::with_capacity(HEADER_SIZE + payload.len());
+  data.extend_from_slice(&[0x45, 0]);
+  data.extend_from_slice(&((HEADER_SIZE + payload.len()) as u16).to_be_bytes());
+  data.extend_from_slice(&id.to_be_bytes());
+  data.extend_from_slice(&FLAG_DONT_FRAGMENT.to_be_bytes());
+  data.extend_from_slice(&[DEFAULT_TTL, protocol, 0, 0]);
+  data.extend_from_slice(&source);
+  data.extend_from_slice(&destination);
+  let sum = checksum(&data, 0);
+  data[10..12].copy_from_slice(&sum.to_be_bytes());
+  data.extend_from_slice(payload);
+  data
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn checksum_rfc1071() {
+    // Note: example of RFC 1071 section 3
+    let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+    assert_eq!(checksum(&data, 0), !0xddf2);
+    assert_eq!(checksum(&[0xff], 0), !0xff00);
+  }
+
+  #[test]
+  fn packet() {
+    let data = build(1, [10, 0, 2, 15], [10, 0, 2, 2], PROTOCOL_UDP, b"payload");
+    assert_eq!(data.len(), HEADER_SIZE + 7);
+    // Note: padded as a short Ethernet frame would be
+    let mut padded = data.clone();
+    padded.resize(46, 0);
+    let p = parse(&padded).unwrap();
+    assert_eq!(p.source, [10, 0, 2, 15]);
+    assert_eq!(p.destination, [10, 0, 2, 2]);
+    assert_eq!(p.protocol, PROTOCOL_UDP);
+    assert_eq!(p.payload, b"payload");
+    let mut corrupted = data.clone();
+    corrupted[8] ^= 1;
+    assert!(parse(&corrupted).is_none());
+    let mut fragment = data.clone();
+    fragment[6] = (FLAG_MORE_FRAGMENTS >> 8) as u8;
+    fragment[10] = 0;
+    fragment[11] = 0;
+    let sum = checksum(&fragment[..HEADER_SIZE], 0);
+    fragment[10..12].copy_from_slice(&sum.to_be_bytes());
+    assert!(parse(&fragment).is_none());
+  }
+}
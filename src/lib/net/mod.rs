@@ -0,0 +1,140 @@
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait};
+use crate::config::{CONFIG_NET_ADDRESS, CONFIG_NET_GATEWAY, CONFIG_NET_NETMASK};
+use crate::lib::process::{Pid, Process};
+
+use self::interface::Interface;
+
+mod ethernet;
+mod arp;
+mod ipv4;
+mod icmp;
+mod udp;
+mod tcp;
+mod interface;
+
+// Note: a minimal TCP/IP stack in the kernel (ARP, IPv4, ICMP echo, UDP, TCP) on the board
+//       network driver, static configuration (`CONFIG_NET_*`), no IP fragments, no DHCP
+//       the device is polled on every interrupt (see `Isr::interrupt_request`)
+//       sockets are non blocking, `WouldBlockError` asks the caller to try again later
+
+pub type MacAddress = [u8; 6];
+pub type Ipv4Address = [u8; 4];
+
+// Note: address and port as taken and filled by the `net_*` system calls, port in native byte order
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Endpoint {
+  pub address: Ipv4Address,
+  pub port: u16,
+}
+
+// Note: `protocol` of `net_socket`
+pub const SOCKET_UDP: usize = 0;
+pub const SOCKET_TCP: usize = 1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Protocol {
+  Udp,
+  Tcp,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  NoDeviceError,
+  InvalidArgumentError,
+  OutOfSocketError,
+  SocketNotFoundError,
+  InvalidStateError,
+  AddressInUseError,
+  WouldBlockError,
+  NotConnectedError,
+  ConnectionResetError,
+}
+
+static INTERFACE: Mutex<Option<Interface>> = Mutex::new(None);
+
+pub fn init() {
+  let mac = match crate::driver::net::init() {
+    Some(mac) => { mac }
+    None => {
+      info!("no network device");
+      return;
+    }
+  };
+  let a = CONFIG_NET_ADDRESS;
+  info!("net: mac {:02x?} address {}.{}.{}.{}", mac, a[0], a[1], a[2], a[3]);
+  *INTERFACE.lock() = Some(Interface::new(mac, CONFIG_NET_ADDRESS, CONFIG_NET_NETMASK, CONFIG_NET_GATEWAY));
+}
+
+// Note: the interface with irq masked, frames it queued are handed to the driver afterwards
+fn with<T, F: FnOnce(&mut Interface, u64) -> Result<T, Error>>(f: F) -> Result<T, Error> {
+  let irq = Arch::interrupt_save_disable();
+  let mut lock = INTERFACE.lock();
+  let r = match lock.as_mut() {
+    None => { Err(Error::NoDeviceError) }
+    Some(interface) => {
+      let now = crate::lib::time::now();
+      let r = f(interface, now);
+      interface.poll(now);
+      for frame in interface.take_frames() {
+        crate::driver::net::send(&frame);
+      }
+      r
+    }
+  };
+  drop(lock);
+  Arch::interrupt_restore(irq);
+  r
+}
+
+// Note: irq context, received frames first, then timers
+pub fn poll() {
+  let _ = with(|interface, now| {
+    while let Some(frame) = crate::driver::net::receive() {
+      interface.receive(&frame, now);
+    }
+    Ok(())
+  });
+}
+
+pub fn socket(owner: Pid, protocol: Protocol) -> Result<usize, Error> {
+  with(|i, _| i.socket(owner, protocol))
+}
+
+pub fn bind(owner: Pid, sid: usize, port: u16) -> Result<(), Error> {
+  with(|i, _| i.bind(owner, sid, port))
+}
+
+pub fn connect(owner: Pid, sid: usize, remote: Endpoint) -> Result<(), Error> {
+  with(|i, now| i.connect(owner, sid, remote, now))
+}
+
+pub fn listen(owner: Pid, sid: usize) -> Result<(), Error> {
+  with(|i, _| i.listen(owner, sid))
+}
+
+pub fn accept(owner: Pid, sid: usize) -> Result<(usize, Endpoint), Error> {
+  with(|i, _| i.accept(owner, sid))
+}
+
+pub fn send(owner: Pid, sid: usize, data: &[u8], to: Option<Endpoint>) -> Result<usize, Error> {
+  with(|i, now| i.send(owner, sid, data, to, now))
+}
+
+pub fn receive(owner: Pid, sid: usize, buf: &mut [u8]) -> Result<(usize, Endpoint), Error> {
+  with(|i, _| i.receive_from(owner, sid, buf))
+}
+
+pub fn close(owner: Pid, sid: usize) -> Result<(), Error> {
+  with(|i, _| i.close(owner, sid))
+}
+
+// Note: called on process exit, sockets owned by `p` are closed
+pub fn release_process(p: &Process) {
+  let _ = with(|i, _| {
+    i.release(p.pid());
+    Ok(())
+  });
+}
@@ -0,0 +1,641 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::{Endpoint, Error, Ipv4Address};
+use super::ipv4::{checksum, PROTOCOL_TCP, pseudo_header_sum};
+
+// Note: a basic TCP (RFC 793): in order receive only (out of order segments are dropped
+//       and acknowledged with what we expect), no congestion control, no window scaling,
+//       timeout retransmission of the oldest segment only, one MSS option on SYN
+pub const HEADER_SIZE: usize = 20;
+
+pub const FLAG_FIN: u8 = 0x01;
+pub const FLAG_SYN: u8 = 0x02;
+pub const FLAG_RST: u8 = 0x04;
+pub const FLAG_PSH: u8 = 0x08;
+pub const FLAG_ACK: u8 = 0x10;
+
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_MSS: u8 = 2;
+
+// Note: without an MSS option (RFC 879)
+const DEFAULT_MSS: usize = 536;
+// Note: what fits a 1500 bytes Ethernet MTU, announced on SYN
+const MSS: usize = 1460;
+
+const RX_BUFFER_SIZE: usize = 16 * 1024;
+const TX_BUFFER_SIZE: usize = 16 * 1024;
+
+// Note: nanoseconds, doubled on each retry, the connection is reset after the last one
+const RETRANSMIT_TIMEOUT: u64 = 1_000_000_000;
+const RETRANSMIT_LIMIT: u32 = 6;
+// Note: 2 MSL, kept short
+const TIME_WAIT: u64 = 2_000_000_000;
+// Note: how long a connection without a socket waits in FIN_WAIT_2 for the FIN of the peer
+pub const FIN_WAIT_2_TIMEOUT: u64 = 60_000_000_000;
+
+pub struct Segment<'a> {
+  pub source_port: u16,
+  pub destination_port: u16,
+  pub sequence: u32,
+  pub acknowledgment: u32,
+  pub flags: u8,
+  pub window: u16,
+  pub mss: Option<usize>,
+  pub payload: &'a [u8],
+}
+
+impl Segment<'_> {
+  // Note: sequence space taken, SYN and FIN count as one
+  fn length(&self) -> u32 {
+    self.payload.len() as u32 + (self.flags & FLAG_SYN != 0) as u32 + (self.flags & FLAG_FIN != 0) as u32
+  }
+}
+
+// Note: `data` is the IPv4 payload
+pub fn parse(source: Ipv4Address, destination: Ipv4Address, data: &[u8]) -> Option<Segment> {
+  if data.len() < HEADER_SIZE {
+    return None;
+  }
+  let header_size = ((data[12] >> 4) as usize) * 4;
+  if header_size < HEADER_SIZE || header_size > data.len() {
+    return None;
+  }
+  if checksum(data, pseudo_header_sum(source, destination, PROTOCOL_TCP, data.len())) != 0 {
+    return None;
+  }
+  let mut mss = None;
+  let mut i = HEADER_SIZE;
+  while i < header_size {
+    match data[i] {
+      OPTION_END => { break; }
+      OPTION_NOP => { i += 1; }
+      kind => {
+        let len = *data.get(i + 1).unwrap_or(&0) as usize;
+        if len < 2 || i + len > header_size {
+          break;
+        }
+        if kind == OPTION_MSS && len == 4 {
+          mss = Some(u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize);
+        }
+        i += len;
+      }
+    }
+  }
+  let word = |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+  Some(Segment {
+    source_port: u16::from_be_bytes([data[0], data[1]]),
+    destination_port: u16::from_be_bytes([data[2], data[3]]),
+    sequence: word(4),
+    acknowledgment: word(8),
+    flags: data[13] & 0x3f,
+    window: u16::from_be_bytes([data[14], data[15]]),
+    mss,
+    payload: &data[header_size..],
+  })
+}
+
+pub fn build(source: Endpoint, destination: Endpoint, sequence: u32, acknowledgment: u32, flags: u8, window: u16, payload: &[u8]) -> Vec<u8> {
+  let options: &[u8] = if flags & FLAG_SYN != 0 { &[OPTION_MSS, 4, (MSS >> 8) as u8, MSS as u8] } else { &[] };
+  let header_size = HEADER_SIZE + options.len();
+  let mut data = Vec::with_capacity(header_size + payload.len());
+  data.extend_from_slice(&source.port.to_be_bytes());
+  data.extend_from_slice(&destination.port.to_be_bytes());
+  data.extend_from_slice(&sequence.to_be_bytes());
+  data.extend_from_slice(&acknowledgment.to_be_bytes());
+  data.extend_from_slice(&[((header_size / 4) as u8) << 4, flags]);
+  data.extend_from_slice(&window.to_be_bytes());
+  // Note: checksum, urgent pointer
+  data.extend_from_slice(&[0, 0, 0, 0]);
+  data.extend_from_slice(options);
+  data.extend_from_slice(payload);
+  let sum = checksum(&data, pseudo_header_sum(source.address, destination.address, PROTOCOL_TCP, data.len()));
+  data[16..18].copy_from_slice(&sum.to_be_bytes());
+  data
+}
+
+// Note: answer to a segment no connection takes, `None` for a reset
+pub fn reset(local: Endpoint, remote: Endpoint, segment: &Segment) -> Option<Vec<u8>> {
+  if segment.flags & FLAG_RST != 0 {
+    return None;
+  }
+  if segment.flags & FLAG_ACK != 0 {
+    Some(build(local, remote, segment.acknowledgment, 0, FLAG_RST, 0, &[]))
+  } else {
+    let acknowledgment = segment.sequence.wrapping_add(segment.length());
+    Some(build(local, remote, 0, acknowledgment, FLAG_RST | FLAG_ACK, 0, &[]))
+  }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum State {
+  SynSent,
+  SynReceived,
+  Established,
+  FinWait1,
+  FinWait2,
+  CloseWait,
+  Closing,
+  LastAck,
+  TimeWait,
+  Closed,
+}
+
+// Note: listening is done by the socket layer, a connection starts from a SYN it sends or takes
+pub struct Connection {
+  state: State,
+  pub local: Endpoint,
+  pub remote: Endpoint,
+  iss: u32,
+  snd_una: u32,
+  snd_nxt: u32,
+  snd_wnd: usize,
+  rcv_nxt: u32,
+  mss: usize,
+  // Note: from `snd_una` on, the first `sent` bytes are in flight
+  tx: VecDeque<u8>,
+  sent: usize,
+  rx: VecDeque<u8>,
+  // Note: `close` was called, the FIN follows the data
+  closing: bool,
+  fin_sent: bool,
+  fin_received: bool,
+  // Note: closed by a RST or after the last retransmission
+  reset: bool,
+  ack_pending: bool,
+  retransmit_at: Option<u64>,
+  retries: u32,
+  time_wait_until: u64,
+  fin_wait_since: u64,
+  // Note: segments to be sent, taken by `take_output`
+  out: Vec<Vec<u8>>,
+}
+
+impl Connection {
+  fn new(state: State, local: Endpoint, remote: Endpoint, iss: u32) -> Self {
+    Connection {
+      state,
+      local,
+      remote,
+      iss,
+      snd_una: iss,
+      snd_nxt: iss.wrapping_add(1),
+      snd_wnd: 0,
+      rcv_nxt: 0,
+      mss: DEFAULT_MSS,
+      tx: VecDeque::new(),
+      sent: 0,
+      rx: VecDeque::new(),
+      closing: false,
+      fin_sent: false,
+      fin_received: false,
+      reset: false,
+      ack_pending: false,
+      retransmit_at: None,
+      retries: 0,
+      time_wait_until: 0,
+      fin_wait_since: 0,
+      out: Vec::new(),
+    }
+  }
+
+  // Note: active open, the SYN is queued
+  pub fn connect(local: Endpoint, remote: Endpoint, iss: u32, now: u64) -> Self {
+    let mut c = Connection::new(State::SynSent, local, remote, iss);
+    c.emit(iss, FLAG_SYN, &[]);
+    c.retransmit_at = Some(now + RETRANSMIT_TIMEOUT);
+    c
+  }
+
+  // Note: passive open on the SYN of `remote`, the SYN ACK is queued
+  pub fn accept(local: Endpoint, remote: Endpoint, syn: &Segment, iss: u32, now: u64) -> Self {
+    let mut c = Connection::new(State::SynReceived, local, remote, iss);
+    c.rcv_nxt = syn.sequence.wrapping_add(1);
+    c.snd_wnd = syn.window as usize;
+    c.mss = core::cmp::min(syn.mss.unwrap_or(DEFAULT_MSS), MSS);
+    c.emit(iss, FLAG_SYN | FLAG_ACK, &[]);
+    c.retransmit_at = Some(now + RETRANSMIT_TIMEOUT);
+    c
+  }
+
+  pub fn state(&self) -> State {
+    self.state
+  }
+
+  pub fn take_output(&mut self) -> Vec<Vec<u8>> {
+    core::mem::take(&mut self.out)
+  }
+
+  fn window(&self) -> u16 {
+    core::cmp::min(RX_BUFFER_SIZE - self.rx.len(), u16::MAX as usize) as u16
+  }
+
+  fn emit(&mut self, sequence: u32, flags: u8, payload: &[u8]) {
+    let acknowledgment = if flags & FLAG_ACK != 0 {
+      self.ack_pending = false;
+      self.rcv_nxt
+    } else {
+      0
+    };
+    let segment = build(self.local, self.remote, sequence, acknowledgment, flags, self.window(), payload);
+    self.out.push(segment);
+  }
+
+  fn tx_bytes(&self, offset: usize, n: usize) -> Vec<u8> {
+    self.tx.iter().skip(offset).take(n).copied().collect()
+  }
+
+  fn abort(&mut self) {
+    self.state = State::Closed;
+    self.reset = true;
+    self.retransmit_at = None;
+    self.tx.clear();
+    self.sent = 0;
+  }
+
+  fn time_wait(&mut self, now: u64) {
+    self.state = State::TimeWait;
+    self.time_wait_until = now + TIME_WAIT;
+    self.retransmit_at = None;
+  }
+
+  pub fn receive(&mut self, segment: &Segment, now: u64) {
+    match self.state {
+      State::Closed => { return; }
+      State::SynSent => {
+        self.receive_syn_sent(segment);
+        return;
+      }
+      _ => {}
+    }
+    let acceptable = segment.sequence == self.rcv_nxt;
+    if segment.flags & FLAG_RST != 0 {
+      if acceptable {
+        self.abort();
+      }
+      return;
+    }
+    if segment.flags & FLAG_SYN != 0 {
+      // Note: our SYN ACK was lost, the peer sent its SYN again
+      if self.state == State::SynReceived && segment.sequence.wrapping_add(1) == self.rcv_nxt {
+        self.emit(self.iss, FLAG_SYN | FLAG_ACK, &[]);
+      } else {
+        self.ack_pending = true;
+      }
+      return;
+    }
+    if !acceptable {
+      // Note: duplicate or out of order, the ACK tells the peer what we expect
+      if segment.length() != 0 {
+        self.ack_pending = true;
+      }
+      return;
+    }
+    if segment.flags & FLAG_ACK == 0 {
+      return;
+    }
+    self.acknowledge(segment.acknowledgment, segment.window, now);
+    if self.state == State::Closed {
+      return;
+    }
+    if !segment.payload.is_empty() {
+      if !matches!(self.state, State::Established | State::FinWait1 | State::FinWait2) {
+        return;
+      }
+      let n = core::cmp::min(segment.payload.len(), RX_BUFFER_SIZE - self.rx.len());
+      self.rx.extend(segment.payload[..n].iter());
+      self.rcv_nxt = self.rcv_nxt.wrapping_add(n as u32);
+      self.ack_pending = true;
+      if n < segment.payload.len() {
+        // Note: the rest (and the FIN) comes again once the window opens
+        return;
+      }
+    }
+    if segment.flags & FLAG_FIN != 0 {
+      self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+      self.fin_received = true;
+      self.ack_pending = true;
+      match self.state {
+        State::Established => { self.state = State::CloseWait; }
+        State::FinWait1 => { self.state = State::Closing; }
+        State::FinWait2 => { self.time_wait(now); }
+        _ => {}
+      }
+    }
+  }
+
+  fn receive_syn_sent(&mut self, segment: &Segment) {
+    let ack = segment.flags & FLAG_ACK != 0;
+    if ack && segment.acknowledgment != self.snd_nxt {
+      if segment.flags & FLAG_RST == 0 {
+        self.out.push(build(self.local, self.remote, segment.acknowledgment, 0, FLAG_RST, 0, &[]));
+      }
+      return;
+    }
+    if segment.flags & FLAG_RST != 0 {
+      // Note: connection refused
+      if ack {
+        self.abort();
+      }
+      return;
+    }
+    if segment.flags & FLAG_SYN == 0 {
+      return;
+    }
+    self.rcv_nxt = segment.sequence.wrapping_add(1);
+    self.snd_wnd = segment.window as usize;
+    self.mss = core::cmp::min(segment.mss.unwrap_or(DEFAULT_MSS), MSS);
+    if ack {
+      self.snd_una = segment.acknowledgment;
+      self.state = State::Established;
+      self.retransmit_at = None;
+      self.retries = 0;
+      self.ack_pending = true;
+    } else {
+      // Note: simultaneous open
+      self.state = State::SynReceived;
+      self.emit(self.iss, FLAG_SYN | FLAG_ACK, &[]);
+    }
+  }
+
+  fn acknowledge(&mut self, acknowledgment: u32, window: u16, now: u64) {
+    let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+    let mut acked = acknowledgment.wrapping_sub(self.snd_una) as usize;
+    if acked > in_flight {
+      // Note: acknowledges something not sent yet (or an old duplicate)
+      self.ack_pending = true;
+      return;
+    }
+    self.snd_wnd = window as usize;
+    if acked == 0 {
+      return;
+    }
+    if self.state == State::SynReceived {
+      self.state = State::Established;
+      acked -= 1;
+    }
+    let data = core::cmp::min(acked, self.sent);
+    self.tx.drain(..data);
+    self.sent -= data;
+    acked -= data;
+    self.snd_una = acknowledgment;
+    self.retries = 0;
+    self.retransmit_at = if self.snd_una == self.snd_nxt { None } else { Some(now + RETRANSMIT_TIMEOUT) };
+    if acked != 0 {
+      // Note: our FIN
+      match self.state {
+        State::FinWait1 => {
+          self.state = State::FinWait2;
+          self.fin_wait_since = now;
+        }
+        State::Closing => { self.time_wait(now); }
+        State::LastAck => { self.state = State::Closed; }
+        _ => {}
+      }
+    }
+  }
+
+  fn retransmit(&mut self, now: u64) {
+    self.retries += 1;
+    if self.retries > RETRANSMIT_LIMIT {
+      self.out.push(build(self.local, self.remote, self.snd_nxt, 0, FLAG_RST, 0, &[]));
+      self.abort();
+      return;
+    }
+    self.retransmit_at = Some(now + (RETRANSMIT_TIMEOUT << self.retries));
+    match self.state {
+      State::SynSent => { self.emit(self.iss, FLAG_SYN, &[]); }
+      State::SynReceived => { self.emit(self.iss, FLAG_SYN | FLAG_ACK, &[]); }
+      _ => {
+        let n = core::cmp::min(self.mss, self.sent);
+        let mut flags = FLAG_ACK;
+        if n != 0 {
+          flags |= FLAG_PSH;
+        }
+        if self.fin_sent && n == self.sent {
+          flags |= FLAG_FIN;
+        }
+        let data = self.tx_bytes(0, n);
+        self.emit(self.snd_una, flags, &data);
+      }
+    }
+  }
+
+  // Note: timers, then new data (and the FIN) within the peer's window, then a pending ACK
+  pub fn poll(&mut self, now: u64) {
+    match self.state {
+      State::Closed => { return; }
+      State::TimeWait => {
+        // Note: a retransmitted FIN of the peer is still acknowledged until then
+        if now >= self.time_wait_until {
+          self.state = State::Closed;
+          return;
+        }
+      }
+      _ => {}
+    }
+    if self.retransmit_at.map_or(false, |at| now >= at) {
+      self.retransmit(now);
+      if self.state == State::Closed {
+        return;
+      }
+    }
+    if matches!(self.state, State::Established | State::CloseWait) {
+      // Note: a closed window is probed with one byte
+      let window = core::cmp::max(self.snd_wnd, 1);
+      loop {
+        let n = core::cmp::min(core::cmp::min(self.mss, self.tx.len() - self.sent), window.saturating_sub(self.sent));
+        if n == 0 {
+          break;
+        }
+        let data = self.tx_bytes(self.sent, n);
+        let sequence = self.snd_una.wrapping_add(self.sent as u32);
+        self.emit(sequence, FLAG_ACK | FLAG_PSH, &data);
+        self.sent += n;
+        self.snd_nxt = sequence.wrapping_add(n as u32);
+        if self.retransmit_at.is_none() {
+          self.retransmit_at = Some(now + RETRANSMIT_TIMEOUT);
+        }
+      }
+      if self.closing && !self.fin_sent && self.sent == self.tx.len() {
+        self.emit(self.snd_nxt, FLAG_FIN | FLAG_ACK, &[]);
+        self.snd_nxt = self.snd_nxt.wrapping_add(1);
+        self.fin_sent = true;
+        self.state = if self.state == State::Established { State::FinWait1 } else { State::LastAck };
+        if self.retransmit_at.is_none() {
+          self.retransmit_at = Some(now + RETRANSMIT_TIMEOUT);
+        }
+      }
+    }
+    if self.ack_pending {
+      self.emit(self.snd_nxt, FLAG_ACK, &[]);
+    }
+  }
+
+  // Note: queued until acknowledged, data may be queued before the connection is established
+  pub fn send(&mut self, data: &[u8]) -> Result<usize, Error> {
+    if self.reset {
+      return Err(Error::ConnectionResetError);
+    }
+    if self.closing || !matches!(self.state, State::SynSent | State::SynReceived | State::Established | State::CloseWait) {
+      return Err(Error::NotConnectedError);
+    }
+    let n = core::cmp::min(data.len(), TX_BUFFER_SIZE - self.tx.len());
+    if n == 0 && !data.is_empty() {
+      return Err(Error::WouldBlockError);
+    }
+    self.tx.extend(data[..n].iter());
+    Ok(n)
+  }
+
+  // Note: 0 at end of stream, once the peer has closed its side and everything is read
+  pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+    if self.rx.is_empty() {
+      return if self.reset {
+        Err(Error::ConnectionResetError)
+      } else if self.fin_received {
+        Ok(0)
+      } else {
+        Err(Error::WouldBlockError)
+      };
+    }
+    let was_full = (self.window() as usize) < self.mss;
+    let n = core::cmp::min(buf.len(), self.rx.len());
+    for (b, c) in buf.iter_mut().zip(self.rx.drain(..n)) {
+      *b = c;
+    }
+    // Note: tell the peer the window opened again
+    if was_full && self.window() as usize >= self.mss {
+      self.ack_pending = true;
+    }
+    Ok(n)
+  }
+
+  // Note: the FIN goes after the queued data, a connection not yet established is dropped
+  pub fn close(&mut self) {
+    match self.state {
+      State::SynSent => { self.state = State::Closed; }
+      _ => { self.closing = true; }
+    }
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.state == State::Closed
+  }
+
+  // Note: the peer may never send its FIN, only the socket layer knows if anyone still waits for it
+  pub fn fin_wait_expired(&self, now: u64) -> bool {
+    self.state == State::FinWait2 && now.saturating_sub(self.fin_wait_since) >= FIN_WAIT_2_TIMEOUT
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CLIENT: Endpoint = Endpoint { address: [10, 0, 2, 15], port: 49152 };
+  const SERVER: Endpoint = Endpoint { address: [10, 0, 2, 2], port: 80 };
+
+  // Note: segments of `from` reach `to`, the number delivered
+  fn deliver(from: &mut Connection, to: &mut Connection, now: u64) -> usize {
+    from.poll(now);
+    let out = from.take_output();
+    for data in out.iter() {
+      let segment = parse(from.local.address, to.local.address, data).unwrap();
+      to.receive(&segment, now);
+    }
+    out.len()
+  }
+
+  fn pair() -> (Connection, Connection) {
+    let mut client = Connection::connect(CLIENT, SERVER, 1000, 0);
+    let syn_data = client.take_output().pop().unwrap();
+    let syn = parse(CLIENT.address, SERVER.address, &syn_data).unwrap();
+    assert_eq!(syn.flags, FLAG_SYN);
+    assert_eq!(syn.mss, Some(MSS));
+    let mut server = Connection::accept(SERVER, CLIENT, &syn, 5000, 0);
+    assert_eq!(deliver(&mut server, &mut client, 0), 1);
+    assert_eq!(client.state(), State::Established);
+    assert_eq!(deliver(&mut client, &mut server, 0), 1);
+    assert_eq!(server.state(), State::Established);
+    (client, server)
+  }
+
+  #[test]
+  fn segment() {
+    let data = build(CLIENT, SERVER, 1, 2, FLAG_ACK | FLAG_PSH, 1024, b"data");
+    let s = parse(CLIENT.address, SERVER.address, &data).unwrap();
+    assert_eq!((s.source_port, s.destination_port, s.sequence, s.acknowledgment), (49152, 80, 1, 2));
+    assert_eq!((s.flags, s.window, s.mss, s.payload), (FLAG_ACK | FLAG_PSH, 1024, None, &b"data"[..]));
+    assert!(parse(CLIENT.address, [10, 0, 2, 3], &data).is_none());
+    let rst = reset(SERVER, CLIENT, &s).unwrap();
+    let r = parse(SERVER.address, CLIENT.address, &rst).unwrap();
+    assert_eq!((r.flags, r.sequence), (FLAG_RST, 2));
+  }
+
+  #[test]
+  fn transfer_and_close() {
+    let (mut client, mut server) = pair();
+    assert_eq!(client.send(b"hello"), Ok(5));
+    deliver(&mut client, &mut server, 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(server.read(&mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(server.read(&mut buf), Err(Error::WouldBlockError));
+    client.close();
+    deliver(&mut server, &mut client, 0);
+    deliver(&mut client, &mut server, 0);
+    assert_eq!(client.state(), State::FinWait1);
+    assert_eq!(server.state(), State::CloseWait);
+    assert_eq!(server.read(&mut buf), Ok(0));
+    deliver(&mut server, &mut client, 0);
+    assert_eq!(client.state(), State::FinWait2);
+    assert!(!client.fin_wait_expired(FIN_WAIT_2_TIMEOUT - 1));
+    assert!(client.fin_wait_expired(FIN_WAIT_2_TIMEOUT));
+    server.close();
+    deliver(&mut server, &mut client, 0);
+    assert_eq!(server.state(), State::LastAck);
+    assert_eq!(client.state(), State::TimeWait);
+    deliver(&mut client, &mut server, 0);
+    assert!(server.is_closed());
+    client.poll(TIME_WAIT);
+    assert!(client.is_closed());
+  }
+
+  #[test]
+  fn retransmit() {
+    let (mut client, mut server) = pair();
+    client.send(b"lost").unwrap();
+    client.poll(0);
+    assert_eq!(client.take_output().len(), 1);
+    // Note: nothing until the timeout, then the same bytes again
+    assert_eq!(deliver(&mut client, &mut server, RETRANSMIT_TIMEOUT - 1), 0);
+    assert_eq!(deliver(&mut client, &mut server, RETRANSMIT_TIMEOUT), 1);
+    let mut buf = [0u8; 4];
+    assert_eq!(server.read(&mut buf), Ok(4));
+    assert_eq!(&buf, b"lost");
+    deliver(&mut server, &mut client, RETRANSMIT_TIMEOUT);
+    assert_eq!(client.retransmit_at, None);
+  }
+
+  #[test]
+  fn refused_and_timeout() {
+    let mut client = Connection::connect(CLIENT, SERVER, 1000, 0);
+    let syn_data = client.take_output().pop().unwrap();
+    let syn = parse(CLIENT.address, SERVER.address, &syn_data).unwrap();
+    let rst = reset(SERVER, CLIENT, &syn).unwrap();
+    client.receive(&parse(SERVER.address, CLIENT.address, &rst).unwrap(), 0);
+    assert!(client.is_closed());
+    assert_eq!(client.send(b"x"), Err(Error::ConnectionResetError));
+    let mut client = Connection::connect(CLIENT, SERVER, 1000, 0);
+    let mut now = 0;
+    for _ in 0..=RETRANSMIT_LIMIT {
+      now += RETRANSMIT_TIMEOUT << RETRANSMIT_LIMIT;
+      client.poll(now);
+    }
+    assert!(client.is_closed());
+    let mut buf = [0u8; 1];
+    assert_eq!(client.read(&mut buf), Err(Error::ConnectionResetError));
+  }
+}
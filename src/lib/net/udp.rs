@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+
+use super::{Endpoint, Ipv4Address};
+use super::ipv4::{checksum, PROTOCOL_UDP, pseudo_header_sum};
+
+pub const HEADER_SIZE: usize = 8;
+
+pub struct Datagram<'a> {
+  pub source_port: u16,
+  pub destination_port: u16,
+  pub payload: &'a [u8],
+}
+
+// Note: `data` is the IPv4 payload, a zero checksum means none was computed
+pub fn parse(source: Ipv4Address, destination: Ipv4Address, data: &[u8]) -> Option<Datagram> {
+  if data.len() < HEADER_SIZE {
+    return None;
+  }
+  let length = u16::from_be_bytes([data[4], data[5]]) as usize;
+  if length < HEADER_SIZE || length > data.len() {
+    return None;
+  }
+  let data = &data[..length];
+  if u16::from_be_bytes([data[6], data[7]]) != 0
+    && checksum(data, pseudo_header_sum(source, destination, PROTOCOL_UDP, length)) != 0 {
+    return None;
+  }
+  Some(Datagram {
+    source_port: u16::from_be_bytes([data[0], data[1]]),
+    destination_port: u16::from_be_bytes([data[2], data[3]]),
+    payload: &data[HEADER_SIZE..],
+  })
+}
+
+pub fn build(source: Endpoint, destination: Endpoint, payload: &[u8]) -> Vec<u8> {
+  let length = HEADER_SIZE + payload.len();
+  let mut data = Vec::with_capacity(length);
+  data.extend_from_slice(&source.port.to_be_bytes());
+  data.extend_from_slice(&destination.port.to_be_bytes());
+  data.extend_from_slice(&(length as u16).to_be_bytes());
+  data.extend_from_slice(&[0, 0]);
+  data.extend_from_slice(payload);
+  let sum = match checksum(&data, pseudo_header_sum(source.address, destination.address, PROTOCOL_UDP, length)) {
+    // Note: a computed zero is sent as all ones
+    0 => { 0xffff }
+    sum => { sum }
+  };
+  data[6..8].copy_from_slice(&sum.to_be_bytes());
+  data
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn datagram() {
+    let source = Endpoint { address: [10, 0, 2, 15], port: 5555 };
+    let destination = Endpoint { address: [10, 0, 2, 2], port: 53 };
+    let mut data = build(source, destination, b"hello");
+    let d = parse(source.address, destination.address, &data).unwrap();
+    assert_eq!((d.source_port, d.destination_port), (5555, 53));
+    assert_eq!(d.payload, b"hello");
+    // Note: the pseudo header covers the addresses
+    assert!(parse(source.address, [10, 0, 2, 3], &data).is_none());
+    data[6] = 0;
+    data[7] = 0;
+    assert!(parse(source.address, [10, 0, 2, 3], &data).is_some());
+  }
+}
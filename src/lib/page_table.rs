@@ -1,6 +1,12 @@
 use core::fmt::{Display, Formatter};
 
-use crate::arch::{Address, AddressSpaceId, PAGE_SHIFT};
+use crate::arch::{Address, AddressSpaceId, Arch, ArchTrait, PAGE_SHIFT, PAGE_SIZE};
+use crate::mm::PageFrame;
+
+// Note: leaf sizes above `PAGE_SIZE`, level 2 and level 1 blocks
+//       same on both architectures (4 KiB granule, 512 entries per table)
+pub const BLOCK_SIZE_2M: usize = 1 << 21;
+pub const BLOCK_SIZE_1G: usize = 1 << 30;
 
 pub trait PageTableEntryAttrTrait {
   fn writable(&self) -> bool;
@@ -20,6 +26,9 @@ pub trait PageTableEntryAttrTrait {
          copy_on_write: bool,
          shared: bool) -> Self;
   fn kernel_device() -> Self;
+  // Note: device memory handed to a process, e.g. the framebuffer, never copy on write
+  fn user_device() -> Self;
+  fn kernel_default() -> Self;
   fn user_default() -> Self;
   fn user_readonly() -> Self;
   fn filter(&self) -> Self;
@@ -93,6 +102,30 @@ impl PageTableEntryAttrTrait for EntryAttribute {
     }
   }
 
+  fn user_device() -> Self {
+    EntryAttribute {
+      writable: true,
+      user: true,
+      device: true,
+      k_executable: false,
+      u_executable: false,
+      copy_on_write: false,
+      shared: false,
+    }
+  }
+
+  fn kernel_default() -> Self {
+    EntryAttribute {
+      writable: true,
+      user: false,
+      device: false,
+      k_executable: false,
+      u_executable: false,
+      copy_on_write: false,
+      shared: false,
+    }
+  }
+
   fn user_default() -> Self {
     EntryAttribute {
       writable: true,
@@ -163,15 +196,76 @@ impl Display for Entry {
 #[derive(Copy, Clone, Debug)]
 pub enum Error {
   AddressNotMappedError,
+  FrameError(crate::mm::page_pool::Error),
+  // Note: a block over a next level table or over a leaf of another size
+  BlockConflictError,
+}
+
+impl core::convert::From<crate::mm::page_pool::Error> for Error {
+  fn from(e: crate::mm::page_pool::Error) -> Self {
+    Error::FrameError(e)
+  }
+}
+
+// Note: drop the reference a table entry holds on `pa` when a page table is destroyed
+//       device and kernel image pages are not counted
+//       nothing to unwind while tearing down, errors are reported and skipped
+pub fn release(pa: usize) {
+  if crate::mm::page_pool::managed(pa) {
+    if let Err(e) = crate::mm::page_pool::decrease_rc(PageFrame::new(pa)) {
+      error!("page_table: release 0x{:x}: {:?}", pa, e);
+    }
+  }
 }
 
 pub trait PageTableTrait {
-  fn new(directory: crate::mm::PageFrame) -> Self;
-  fn directory(&self) -> crate::mm::PageFrame;
+  fn new(directory: PageFrame) -> Self;
+  fn directory(&self) -> PageFrame;
   fn map(&self, va: usize, pa: usize, attr: EntryAttribute);
+  // Note: `size` is `BLOCK_SIZE_2M` or `BLOCK_SIZE_1G`, `va` and `pa` aligned to it
+  //       fails if the slot holds a next level table
+  fn map_block(&self, va: usize, pa: usize, size: usize, attr: EntryAttribute) -> Result<(), Error>;
+  // Note: clears the leaf covering `va`, a page or a whole block
   fn unmap(&self, va: usize);
-  fn insert_page(&self, va: usize, frame: crate::mm::PageFrame, attr: EntryAttribute) -> Result<(), Error>;
-  fn lookup_page(&self, va: usize) -> Option<Entry>;
+  fn insert_page(&self, va: usize, frame: PageFrame, attr: EntryAttribute) -> Result<(), Error>;
+
+  // Note: `frame` is the first frame of a block from `alloc_contiguous`, which holds the reference
+  fn insert_block(&self, va: usize, frame: PageFrame, size: usize, attr: EntryAttribute) -> Result<(), Error> {
+    let old = match self.lookup(va) {
+      Some((pte, s)) if s == size => { Some(pte.pa()) }
+      Some(_) => { return Err(Error::BlockConflictError); }
+      None => { None }
+    };
+    if old == Some(frame.pa()) {
+      // update attribute
+      self.map_block(va, frame.pa(), size, attr)?;
+      Arch::invalidate_tlb();
+      return Ok(());
+    }
+    crate::mm::page_pool::increase_rc(frame)?;
+    if let Err(e) = self.map_block(va, frame.pa(), size, attr) {
+      crate::mm::page_pool::decrease_rc(frame)?;
+      return Err(e);
+    }
+    Arch::invalidate_tlb();
+    if let Some(pa) = old {
+      // replace mapped block
+      crate::mm::page_pool::decrease_rc(PageFrame::new(pa))?;
+    }
+    Ok(())
+  }
+
+  // Note: the leaf covering `va` and its size
+  fn lookup(&self, va: usize) -> Option<(Entry, usize)>;
+
+  // Note: the page of `va`, also inside a block
+  fn lookup_page(&self, va: usize) -> Option<Entry> {
+    self.lookup(va).map(|(pte, size)| {
+      Entry::new(pte.attribute(), pte.pa() + crate::lib::round_down(va % size, PAGE_SIZE))
+    })
+  }
+
+  // Note: a block is removed as a whole
   fn remove_page(&self, va: usize) -> Result<(), Error>;
   fn recursive_map(&self, va: usize);
   fn destroy(&self);
@@ -180,4 +274,146 @@ pub trait PageTableTrait {
   fn kernel_page_table() -> Self;
   fn user_page_table() -> Self;
   fn set_user_page_table(pt: Self, asid: AddressSpaceId);
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use crate::arch::{Arch, ArchTrait, PAGE_SIZE, PageTable};
+  use crate::mm::page_pool;
+
+  use super::*;
+
+  // Note: a frame mapped twice in a user page table, accessed through the MMU
+  ktest!(user_mapping_translation {
+    let directory = page_pool::alloc();
+    directory.zero();
+    page_pool::increase_rc(directory).ok().unwrap();
+    let page_table = PageTable::new(directory);
+    let frame = page_pool::alloc();
+    frame.zero();
+    let (va, alias) = (0x1000_0000, 0x2000_0000 + PAGE_SIZE);
+    page_table.insert_page(va, frame, EntryAttribute::user_default()).ok().unwrap();
+    page_table.insert_page(alias, frame, EntryAttribute::user_readonly()).ok().unwrap();
+    assert_eq!(page_pool::rc(frame).ok(), Some(2));
+    let entry = page_table.lookup_page(alias).unwrap();
+    assert_eq!(entry.pa(), frame.pa());
+    assert!(!entry.attribute().writable());
+    assert!(page_table.lookup_page(va + PAGE_SIZE).is_none());
+
+    let saved = PageTable::user_page_table();
+    PageTable::set_user_page_table(page_table, 0);
+    Arch::invalidate_tlb();
+    Arch::user_access_begin();
+    unsafe {
+      core::intrinsics::volatile_store((va + 8) as *mut usize, 0x5a5a_1234);
+      assert_eq!(core::intrinsics::volatile_load((alias + 8) as *const usize), 0x5a5a_1234);
+    }
+    Arch::user_access_end();
+    PageTable::set_user_page_table(saved, 0);
+    Arch::invalidate_tlb();
+    assert_eq!(unsafe { core::intrinsics::volatile_load((frame.kva() + 8) as *const usize) }, 0x5a5a_1234);
+
+    page_table.remove_page(alias).ok().unwrap();
+    assert_eq!(page_pool::rc(frame).ok(), Some(1));
+    assert!(page_table.remove_page(alias).is_err());
+    page_table.destroy();
+    assert_eq!(page_pool::decrease_rc(directory).ok(), Some(0));
+  });
+
+  // Note: a 2 MiB block accessed past its first page through the MMU
+  ktest!(user_block_translation {
+    let directory = page_pool::alloc();
+    directory.zero();
+    page_pool::increase_rc(directory).ok().unwrap();
+    let page_table = PageTable::new(directory);
+    let order = (BLOCK_SIZE_2M / PAGE_SIZE).trailing_zeros() as usize;
+    let block = page_pool::alloc_contiguous(order).ok().unwrap();
+    let va = 0x1000_0000;
+    let offset = BLOCK_SIZE_2M - PAGE_SIZE + 8;
+    page_table.insert_block(va, block, BLOCK_SIZE_2M, EntryAttribute::user_default()).ok().unwrap();
+    assert_eq!(page_table.lookup(va + offset).map(|(e, size)| (e.pa(), size)), Some((block.pa(), BLOCK_SIZE_2M)));
+
+    let saved = PageTable::user_page_table();
+    PageTable::set_user_page_table(page_table, 0);
+    Arch::invalidate_tlb();
+    Arch::user_access_begin();
+    unsafe {
+      core::intrinsics::volatile_store((va + offset) as *mut usize, 0x5a5a_4321);
+    }
+    Arch::user_access_end();
+    PageTable::set_user_page_table(saved, 0);
+    Arch::invalidate_tlb();
+    assert_eq!(unsafe { core::intrinsics::volatile_load((block.kva() + offset) as *const usize) }, 0x5a5a_4321);
+
+    page_table.destroy();
+    assert_eq!(page_pool::rc(block).ok(), Some(0));
+    assert_eq!(page_pool::decrease_rc(directory).ok(), Some(0));
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::arch::{ArchPageTableEntry, ArchPageTableEntryTrait};
+
+  use super::*;
+
+  #[test]
+  fn filter_user_attribute() {
+    // Note: writable, user, device, k_executable, u_executable, copy_on_write, shared
+    let attr = EntryAttribute::new(true, false, true, true, true, false, false);
+    assert_eq!(attr.filter(), EntryAttribute::new(true, true, false, false, true, false, false));
+    let attr = EntryAttribute::new(false, true, false, true, false, true, true);
+    assert_eq!(attr.filter(), EntryAttribute::new(false, true, false, false, false, true, true));
+    assert_eq!(EntryAttribute::kernel_device().filter(), EntryAttribute::new(true, true, false, false, false, false, false));
+    assert_eq!(EntryAttribute::user_default().filter(), EntryAttribute::user_default());
+    // Note: only the kernel hands out device memory
+    assert!(EntryAttribute::user_device().device() && !EntryAttribute::user_device().filter().device());
+  }
+
+  #[test]
+  fn block_mapping() {
+    use crate::arch::PageTable;
+    use crate::mm::page_pool;
+    crate::mm::test_init();
+    let directory = page_pool::alloc();
+    page_pool::increase_rc(directory).ok().unwrap();
+    let page_table = PageTable::new(directory);
+    let order = (BLOCK_SIZE_2M / PAGE_SIZE).trailing_zeros() as usize;
+    let block = page_pool::alloc_contiguous(order).ok().unwrap();
+    let va = 0x4000_0000;
+    page_table.insert_block(va, block, BLOCK_SIZE_2M, EntryAttribute::user_default()).ok().unwrap();
+    assert_eq!(page_pool::rc(block).ok(), Some(1));
+    let (entry, size) = page_table.lookup(va + 0x12_3456).unwrap();
+    assert_eq!((entry.pa(), size), (block.pa(), BLOCK_SIZE_2M));
+    assert_eq!(page_table.lookup_page(va + 0x12_3456).map(|e| e.pa()), Some(block.pa() + 0x12_3000));
+
+    // Note: a page inside a block, a block over a page
+    let frame = page_pool::alloc();
+    let r = page_table.insert_page(va + PAGE_SIZE, frame, EntryAttribute::user_default());
+    assert!(matches!(r, Err(Error::BlockConflictError)));
+    page_table.insert_page(va + BLOCK_SIZE_2M + PAGE_SIZE, frame, EntryAttribute::user_default()).ok().unwrap();
+    let other = page_pool::alloc_contiguous(order).ok().unwrap();
+    let r = page_table.insert_block(va + BLOCK_SIZE_2M, other, BLOCK_SIZE_2M, EntryAttribute::user_default());
+    assert!(matches!(r, Err(Error::BlockConflictError)));
+    assert_eq!(page_pool::rc(other).ok(), Some(0));
+
+    // Note: removing any page of a block removes all of it
+    page_table.remove_page(va + PAGE_SIZE).ok().unwrap();
+    assert!(page_table.lookup(va).is_none());
+    assert_eq!(page_pool::rc(block).ok(), Some(0));
+    page_table.insert_block(va, other, BLOCK_SIZE_2M, EntryAttribute::user_readonly()).ok().unwrap();
+    page_table.destroy();
+    assert_eq!(page_pool::rc(other).ok(), Some(0));
+    assert_eq!(page_pool::rc(frame).ok(), Some(0));
+    assert_eq!(page_pool::decrease_rc(directory).ok(), Some(0));
+  }
+
+  #[test]
+  fn arch_entry_round_trip() {
+    let entry = Entry::new(EntryAttribute::user_default(), 0x1234_5000);
+    let pte = ArchPageTableEntry::from(entry);
+    let entry = Entry::from(ArchPageTableEntry::from_pte(pte.to_pte()));
+    assert_eq!(entry.pa(), 0x1234_5000);
+    assert_eq!(entry.attribute(), EntryAttribute::user_default());
+  }
+}
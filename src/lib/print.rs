@@ -1,29 +1,20 @@
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::arch::{Arch, ArchTrait};
-
-pub struct Writer;
-
-static mut WRITER: Writer = Writer;
-
-impl fmt::Write for Writer {
-  fn write_str(&mut self, s: &str) -> fmt::Result {
-    for b in s.bytes() {
-      crate::driver::uart::putc(b);
-    }
-    Ok(())
-  }
-}
+use crate::arch::{Arch, ArchTrait, CoreTrait};
 
+// Note: console output goes through the kernel log buffer (see `lib/log.rs`)
 pub fn print_arg(args: fmt::Arguments) {
-  use core::fmt::Write;
-  unsafe {
-    WRITER.write_fmt(args).unwrap();
-  }
+  crate::lib::log::print(args);
 }
 
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+// Note: host tests panic through std
+#[cfg(not(test))]
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo) -> ! {
+  crate::lib::log::emergency();
   if let Some(m) = info.message() {
     if let Some(l) = info.location() {
       println!("\nkernel panic: {} \n {}", m, l);
@@ -33,6 +24,18 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
   } else {
     println!("\nkernel panic!");
   }
+  // Note: do not dump again if dumping itself panics
+  if !PANICKED.swap(true, Ordering::SeqCst) {
+    let core = crate::lib::core::current();
+    if core.has_context() {
+      println!("trap context:");
+      print!("{}", core.context());
+    }
+    println!("backtrace:");
+    crate::lib::backtrace::print_kernel_backtrace(Arch::frame_pointer(), 0);
+  }
+  #[cfg(feature = "ktest")]
+  crate::lib::ktest::panicked();
   loop {
     Arch::wait_for_event();
   }
@@ -2,26 +2,81 @@ use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-use spin::Mutex;
+use spin::{Mutex, MutexGuard};
 
-use crate::arch::{PAGE_SIZE, PageTable};
-use crate::config::CONFIG_USER_STACK_TOP;
+use crate::arch::{ArchTrait, ContextFrameTrait, PAGE_SIZE, PageTable};
+use crate::config::{CONFIG_USER_STACK_GUARD, CONFIG_USER_STACK_LIMIT, CONFIG_USER_STACK_TOP};
 use crate::lib::bitmap::BitMap;
-use crate::lib::current_thread;
 use crate::lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::signal::{Signal, SignalState};
 use crate::lib::thread::Thread;
 
 pub type Pid = u16;
 
+#[derive(Copy, Clone, Debug)]
+pub enum ExitStatus {
+  Exited(i32),
+  Killed(Signal),
+}
+
+impl ExitStatus {
+  // Note: same encoding as POSIX `wait` status
+  //    exited: [15:8] exit code
+  //    killed: [6:0]  signal number
+  pub fn encode(&self) -> u32 {
+    match self {
+      ExitStatus::Exited(code) => { ((*code as u32) & 0xff) << 8 }
+      ExitStatus::Killed(sig) => { (*sig as u32) & 0x7f }
+    }
+  }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+  Alive,
+  Zombie(ExitStatus),
+}
+
+// Note: a thread blocked in `wait`
+//       `pid` 0 waits for any child
+#[derive(Debug)]
+struct Waiter {
+  thread: Thread,
+  pid: Pid,
+  status_va: usize,
+}
+
 #[derive(Debug)]
 pub struct ControlBlock {
   pid: Pid,
   threads: Mutex<Vec<Thread>>,
-  parent: Option<Process>,
+  parent: Mutex<Option<Pid>>,
+  children: Mutex<Vec<Process>>,
+  // Note: process group, the pid of its first member, `None` once that pid exited
+  group: Mutex<Option<Pid>>,
+  state: Mutex<State>,
+  waiter: Mutex<Option<Waiter>>,
   page_table: PageTable,
   exception_handler: Mutex<Option<(usize, usize)>>,
+  signal: Mutex<SignalState>,
 }
 
+// Note: the last reference is gone, no thread of the process is left to run on the directory
+//       and no core has it installed, see `Core::set_address_space`
+impl Drop for ControlBlock {
+  fn drop(&mut self) {
+    crate::lib::page_table::release(self.page_table.directory().pa());
+  }
+}
+
+// Note: the user stack grows on fault within [USER_STACK_BTM, CONFIG_USER_STACK_TOP)
+//       the guard below it is never mapped, a fault there is a stack overflow
+pub const USER_STACK_BTM: usize = CONFIG_USER_STACK_TOP - CONFIG_USER_STACK_LIMIT;
+const USER_STACK_GUARD_BTM: usize = USER_STACK_BTM - CONFIG_USER_STACK_GUARD;
+
+pub fn in_stack_guard(va: usize, len: usize) -> bool {
+  va < USER_STACK_BTM && va.saturating_add(len) > USER_STACK_GUARD_BTM
+}
 
 #[derive(Debug, Clone)]
 pub struct Process(Arc<ControlBlock>);
@@ -44,6 +99,13 @@ impl Process {
     r
   }
 
+  pub fn threads(&self) -> Vec<Thread> {
+    let lock = self.0.threads.lock();
+    let r = lock.clone();
+    drop(lock);
+    r
+  }
+
   pub fn set_main_thread(&self, t: Thread) {
     let mut lock = self.0.threads.lock();
     assert!(lock.is_empty());
@@ -64,28 +126,243 @@ impl Process {
     drop(lock);
   }
 
+  // Note: map a zeroed page at `va` if it is an unmapped page of the stack region
+  //       `Ok(false)` when `va` is not for the stack to grow into
+  pub fn grow_stack(&self, va: usize) -> Result<bool, crate::lib::page_table::Error> {
+    let va = crate::lib::round_down(va, PAGE_SIZE);
+    if va < USER_STACK_BTM || va >= CONFIG_USER_STACK_TOP {
+      return Ok(false);
+    }
+    let page_table = self.page_table();
+    if page_table.lookup_page(va).is_some() {
+      return Ok(false);
+    }
+    let frame = crate::mm::page_pool::try_alloc_tagged(crate::mm::page_pool::FRAME_USER, crate::mm::page_pool::Owner::Process(self.pid()))?;
+    page_table.insert_page(va, frame, EntryAttribute::user_default())?;
+    Ok(true)
+  }
+
+  pub fn signal(&self) -> MutexGuard<SignalState> {
+    self.0.signal.lock()
+  }
+
   pub fn page_table(&self) -> PageTable {
     self.0.page_table
   }
 
   pub fn parent(&self) -> Option<Process> {
-    match &self.0.parent {
-      None => {None},
-      Some(p) => {Some(p.clone())},
+    let lock = self.0.parent.lock();
+    let r = *lock;
+    drop(lock);
+    match r {
+      None => { None }
+      Some(pid) => { lookup(pid) }
     }
   }
 
+  pub fn group(&self) -> Option<Pid> {
+    let lock = self.0.group.lock();
+    let r = *lock;
+    drop(lock);
+    r
+  }
+
+  pub fn set_group(&self, group: Option<Pid>) {
+    let mut lock = self.0.group.lock();
+    *lock = group;
+    drop(lock);
+  }
+
+  fn set_parent(&self, parent: Option<Pid>) {
+    let mut lock = self.0.parent.lock();
+    *lock = parent;
+    drop(lock);
+  }
+
+  fn add_child(&self, child: Process) {
+    let mut lock = self.0.children.lock();
+    lock.push(child);
+    drop(lock);
+  }
+
+  pub fn is_zombie(&self) -> bool {
+    self.exit_status().is_some()
+  }
+
+  pub fn exit_status(&self) -> Option<ExitStatus> {
+    let lock = self.0.state.lock();
+    let r = match *lock {
+      State::Alive => { None }
+      State::Zombie(status) => { Some(status) }
+    };
+    drop(lock);
+    r
+  }
+
+  // Note: kill the process, reported as killed by SIGKILL
   pub fn destroy(&self) {
-    for t in self.0.threads.lock().iter() {
+    self.exit(ExitStatus::Killed(crate::lib::signal::SIGKILL));
+  }
+
+  // Note: release all resources but the pid and the page directory, the process stays a zombie
+  //       until its parent reaps it with `wait`
+  pub fn exit(&self, status: ExitStatus) {
+    let mut state = self.0.state.lock();
+    if let State::Zombie(_) = *state {
+      drop(state);
+      return;
+    }
+    *state = State::Zombie(status);
+    drop(state);
+    crate::lib::ipc::release_process(self);
+    crate::lib::net::release_process(self);
+    crate::lib::chardev::release_process(self);
+    let mut threads = self.0.threads.lock();
+    for t in threads.drain(..) {
       t.destroy();
     }
+    drop(threads);
+    let mut waiter = self.0.waiter.lock();
+    *waiter = None;
+    drop(waiter);
+    // Note: the directory stays, emptied of user mappings, exiting threads unwind on it
     self.0.page_table.destroy();
-    let frame = self.0.page_table.directory();
-    crate::mm::page_pool::decrease_rc(frame);
-    free(self);
-    if current_thread().is_none() {
-      crate::lib::scheduler::schedule();
+    crate::arch::Arch::invalidate_tlb();
+    crate::lib::framebuffer::release_process(self);
+    self.reparent_children();
+    self.dissolve_group();
+    match self.parent() {
+      Some(parent) => {
+        crate::lib::signal::raise(&parent, crate::lib::signal::SIGCHLD);
+        parent.child_exited(self);
+      }
+      None => {
+        // Note: nobody will reap it
+        free(self);
+      }
     }
+    // Note: if the current thread was destroyed, the trap handler switches away on its way out
+  }
+
+  // Note: the pid is recycled once reaped, a group named after it must not outlive it
+  fn dissolve_group(&self) {
+    let group = Some(self.pid());
+    for p in list() {
+      if p.group() == group {
+        p.set_group(None);
+      }
+    }
+    crate::lib::signal::forget_foreground(self.pid());
+  }
+
+  // Note: orphans are handed to the init process
+  fn reparent_children(&self) {
+    let mut lock = self.0.children.lock();
+    let children: Vec<Process> = lock.drain(..).collect();
+    drop(lock);
+    let init = init_process().filter(|init| init != self && !init.is_zombie());
+    for child in children {
+      match &init {
+        Some(init) => {
+          child.set_parent(Some(init.pid()));
+          init.add_child(child.clone());
+          if child.is_zombie() {
+            init.child_exited(&child);
+          }
+        }
+        None => {
+          child.set_parent(None);
+          if child.is_zombie() {
+            free(&child);
+          }
+        }
+      }
+    }
+  }
+
+  // Note: wake up a thread waiting for `child`, reap it if so
+  fn child_exited(&self, child: &Process) {
+    let mut lock = self.0.waiter.lock();
+    let matched = match &*lock {
+      Some(w) => { w.pid == 0 || w.pid == child.pid() }
+      None => { false }
+    };
+    if !matched {
+      drop(lock);
+      return;
+    }
+    let w = lock.take().unwrap();
+    drop(lock);
+    let status = child.exit_status().unwrap();
+    if w.status_va != 0 {
+      let _ = crate::lib::uaccess::write_user(self.page_table(), w.status_va, &status.encode());
+    }
+    self.reap(child);
+    w.thread.context().set_syscall_return_value(child.pid() as usize);
+    w.thread.set_status(crate::lib::thread::Status::TsRunnable);
+  }
+
+  fn reap(&self, child: &Process) {
+    let mut lock = self.0.children.lock();
+    lock.retain(|c| c != child);
+    drop(lock);
+    free(child);
+  }
+
+  // Note: find a zombie child matching `pid` (0 for any)
+  //       `Err` if there is no such child at all
+  fn zombie_child(&self, pid: Pid) -> Result<Option<Process>, Error> {
+    let lock = self.0.children.lock();
+    let mut found = false;
+    let mut r = None;
+    for c in lock.iter() {
+      if pid == 0 || c.pid() == pid {
+        found = true;
+        if c.is_zombie() {
+          r = Some(c.clone());
+          break;
+        }
+      }
+    }
+    drop(lock);
+    if found {
+      Ok(r)
+    } else {
+      Err(Error::NoChildError)
+    }
+  }
+
+  // Note: `t` gives up waiting (interrupted by a signal), `false` if it was not waiting
+  pub fn cancel_wait(&self, t: &Thread) -> bool {
+    let mut lock = self.0.waiter.lock();
+    let r = lock.as_ref().map_or(false, |w| &w.thread == t);
+    if r {
+      *lock = None;
+    }
+    drop(lock);
+    r
+  }
+
+  // Note: `Ok(None)` means `t` is blocked until a matching child exits
+  pub fn wait(&self, t: &Thread, pid: Pid, status_va: usize) -> Result<Option<Pid>, Error> {
+    if let Some(child) = self.zombie_child(pid)? {
+      let status = child.exit_status().unwrap();
+      if status_va != 0 {
+        crate::lib::uaccess::write_user(self.page_table(), status_va, &status.encode()).map_err(|_| Error::StatusAddressError)?;
+      }
+      self.reap(&child);
+      return Ok(Some(child.pid()));
+    }
+    let mut lock = self.0.waiter.lock();
+    *lock = Some(Waiter {
+      thread: t.clone(),
+      pid,
+      status_va,
+    });
+    drop(lock);
+    t.set_status(crate::lib::thread::Status::TsWaitForChild);
+    crate::lib::scheduler::schedule();
+    Ok(None)
   }
 }
 
@@ -97,11 +374,19 @@ struct ProcessPool {
 
 pub enum Error {
   ProcessNotFoundError,
+  NoChildError,
+  StatusAddressError,
 }
 
-fn make_user_page_table() -> PageTable {
-  let frame = crate::mm::page_pool::alloc();
-  crate::mm::page_pool::increase_rc(frame);
+fn make_user_page_table(pid: Pid) -> PageTable {
+  use crate::mm::page_pool::{FRAME_PAGE_TABLE, Owner};
+  let frame = crate::mm::page_pool::try_alloc_tagged(FRAME_PAGE_TABLE, Owner::Process(pid))
+    .and_then(|frame| crate::mm::page_pool::increase_rc(frame).map(|_| frame));
+  let frame = match frame {
+    Ok(frame) => { frame }
+    Err(e) => { panic!("process: make_user_page_table: {:?}", e) }
+  };
+  frame.zero();
   let page_table = PageTable::new(frame);
   page_table.recursive_map(crate::config::CONFIG_RECURSIVE_PAGE_TABLE_BTM);
   page_table
@@ -113,14 +398,23 @@ impl ProcessPool {
     let arc = Arc::new(ControlBlock {
       pid: id,
       threads: Mutex::new(Vec::new()),
-      parent,
-      page_table: make_user_page_table(),
+      parent: Mutex::new(parent.as_ref().map(|p| p.pid())),
+      children: Mutex::new(Vec::new()),
+      // Note: children start in the group of their parent, processes created by the kernel lead their own
+      group: Mutex::new(parent.as_ref().map_or(Some(id), |p| p.group())),
+      state: Mutex::new(State::Alive),
+      waiter: Mutex::new(None),
+      page_table: make_user_page_table(id),
       exception_handler: Mutex::new(None),
+      signal: Mutex::new(SignalState::new()),
     });
     let mut map = PROCESS_MAP.lock();
     map.insert(id, arc.clone());
     drop(map);
     self.alloced.push(Process(arc.clone()));
+    if let Some(parent) = parent {
+      parent.add_child(Process(arc.clone()));
+    }
     Process(arc)
   }
 
@@ -136,7 +430,6 @@ impl ProcessPool {
     }
   }
 
-  #[allow(dead_code)]
   fn list(&self) -> Vec<Process> {
     self.alloced.clone()
   }
@@ -147,6 +440,18 @@ lazy_static! {
   static ref PROCESS_MAP: Mutex<BTreeMap<Pid, Arc<ControlBlock>>> = Mutex::new(BTreeMap::new());
 }
 
+static INIT_PROCESS: Mutex<Option<Pid>> = Mutex::new(None);
+
+pub fn init_process() -> Option<Process> {
+  let lock = INIT_PROCESS.lock();
+  let r = *lock;
+  drop(lock);
+  match r {
+    None => { None }
+    Some(pid) => { lookup(pid) }
+  }
+}
+
 static PROCESS_POOL: Mutex<ProcessPool> = Mutex::new(ProcessPool {
   bitmap: BitMap::new(),
   alloced: Vec::new(),
@@ -163,12 +468,11 @@ pub fn free(p: &Process) {
   let mut pool = PROCESS_POOL.lock();
   match pool.free(p) {
     Ok(_) => {}
-    Err(_) => { println!("process_pool: free: process not found") }
+    Err(_) => { error!("process_pool: free: process not found") }
   }
   drop(pool);
 }
 
-#[allow(dead_code)]
 pub fn list() -> Vec<Process> {
   let pool = PROCESS_POOL.lock();
   let r = pool.list();
@@ -188,13 +492,123 @@ pub fn lookup(pid: Pid) -> Option<Process> {
 
 pub fn create(elf: &'static [u8], arg: usize) {
   let p = alloc(None);
+  // Note: the first process created by kernel is init
+  let mut init = INIT_PROCESS.lock();
+  if init.is_none() {
+    *init = Some(p.pid());
+  }
+  drop(init);
   let page_table = p.page_table();
   let pc = unsafe { crate::lib::elf::load_elf(elf, page_table) };
   let sp = CONFIG_USER_STACK_TOP;
-  match page_table.insert_page(sp - PAGE_SIZE, crate::mm::page_pool::alloc(), EntryAttribute::user_default()) {
+  let stack = crate::mm::page_pool::try_alloc_tagged(crate::mm::page_pool::FRAME_USER, crate::mm::page_pool::Owner::Process(p.pid()))
+    .map_err(crate::lib::page_table::Error::from)
+    .and_then(|frame| page_table.insert_page(sp - PAGE_SIZE, frame, EntryAttribute::user_default()));
+  match stack {
     Ok(_) => {}
     Err(_) => { panic!("process: load_image: page_table.insert_page failed") }
   }
   let t = crate::lib::thread::alloc_user(pc, sp, arg, p.clone());
   t.set_status(crate::lib::thread::Status::TsRunnable);
-  p.set_main_thread(t);}
\ No newline at end of file
+  p.set_main_thread(t);}
+
+#[cfg(test)]
+mod tests {
+  use crate::mm::page_pool;
+
+  use super::*;
+
+  #[test]
+  fn exit_status_encode() {
+    assert_eq!(ExitStatus::Exited(3).encode(), 0x300);
+    assert_eq!(ExitStatus::Exited(-1).encode(), 0xff00);
+    assert_eq!(ExitStatus::Killed(crate::lib::signal::SIGKILL).encode(), crate::lib::signal::SIGKILL as u32);
+  }
+
+  #[test]
+  fn alloc_lookup_free() {
+    crate::mm::test_init();
+    let p = alloc(None);
+    assert_eq!(lookup(p.pid()), Some(p.clone()));
+    assert!(list().contains(&p));
+    assert!(p.parent().is_none());
+    assert!(!p.is_zombie());
+    // Note: the directory is referenced by the process only
+    let directory = p.page_table().directory();
+    assert_eq!(page_pool::rc(directory).ok(), Some(1));
+    let d = page_pool::descriptor(directory).ok().unwrap();
+    assert_eq!(d.flags, page_pool::FRAME_PAGE_TABLE);
+    assert_eq!(d.owner, page_pool::Owner::Process(p.pid()));
+    let va = 0x1000;
+    let frame = page_pool::alloc();
+    p.page_table().insert_page(va, frame, EntryAttribute::user_default()).ok().unwrap();
+    assert_eq!(page_pool::rc(frame).ok(), Some(1));
+    assert_eq!(p.page_table().lookup_page(va).map(|e| e.pa()), Some(frame.pa()));
+    // Note: what `exit` releases, the directory goes with the last reference
+    p.page_table().destroy();
+    assert_eq!(page_pool::rc(frame).ok(), Some(0));
+    assert!(p.page_table().lookup_page(va).is_none());
+    free(&p);
+    assert!(lookup(p.pid()).is_none());
+    assert!(!list().contains(&p));
+    assert_eq!(page_pool::rc(directory).ok(), Some(1));
+    drop(p);
+    assert_eq!(page_pool::rc(directory).ok(), Some(0));
+  }
+
+  #[test]
+  fn stack_growth() {
+    crate::mm::test_init();
+    let p = alloc(None);
+    let va = CONFIG_USER_STACK_TOP - 3 * PAGE_SIZE;
+    assert_eq!(p.grow_stack(va + 8).ok(), Some(true));
+    let frame = p.page_table().lookup_page(va).unwrap().pa();
+    assert_eq!(page_pool::descriptor(crate::mm::PageFrame::new(frame)).ok().unwrap().owner, page_pool::Owner::Process(p.pid()));
+    // Note: mapped already, or out of the stack region
+    assert_eq!(p.grow_stack(va).ok(), Some(false));
+    assert_eq!(p.grow_stack(CONFIG_USER_STACK_TOP).ok(), Some(false));
+    assert_eq!(p.grow_stack(USER_STACK_BTM - PAGE_SIZE).ok(), Some(false));
+    assert_eq!(p.grow_stack(USER_STACK_BTM).ok(), Some(true));
+    assert!(in_stack_guard(USER_STACK_BTM - PAGE_SIZE, PAGE_SIZE));
+    assert!(in_stack_guard(USER_STACK_GUARD_BTM - PAGE_SIZE, 2 * PAGE_SIZE));
+    assert!(!in_stack_guard(USER_STACK_BTM, PAGE_SIZE));
+    assert!(!in_stack_guard(USER_STACK_GUARD_BTM - PAGE_SIZE, PAGE_SIZE));
+    p.page_table().destroy();
+    free(&p);
+  }
+
+  #[test]
+  fn parent_and_children() {
+    crate::mm::test_init();
+    let parent = alloc(None);
+    let child = alloc(Some(parent.clone()));
+    assert_eq!(child.parent(), Some(parent.clone()));
+    assert_eq!(parent.group(), Some(parent.pid()));
+    assert_eq!(child.group(), Some(parent.pid()));
+    assert!(matches!(parent.zombie_child(0), Ok(None)));
+    assert!(matches!(parent.zombie_child(child.pid()), Ok(None)));
+    assert!(matches!(parent.zombie_child(Pid::MAX), Err(Error::NoChildError)));
+    assert!(matches!(child.zombie_child(0), Err(Error::NoChildError)));
+    parent.reap(&child);
+    assert!(matches!(parent.zombie_child(0), Err(Error::NoChildError)));
+    assert!(lookup(child.pid()).is_none());
+    free(&parent);
+  }
+
+  #[test]
+  fn group_dissolved_on_exit() {
+    crate::mm::test_init();
+    let leader = alloc(None);
+    let member = alloc(Some(leader.clone()));
+    let other = alloc(None);
+    member.set_parent(None);
+    leader.exit(ExitStatus::Exited(0));
+    assert!(lookup(leader.pid()).is_none());
+    assert_eq!(member.group(), None);
+    assert_eq!(other.group(), Some(other.pid()));
+    for p in [member, other].iter() {
+      p.page_table().destroy();
+      free(p);
+    }
+  }
+}
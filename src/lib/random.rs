@@ -0,0 +1,207 @@
+use alloc::sync::Arc;
+
+use spin::Mutex;
+
+use crate::lib::chardev::{self, CharDevice};
+
+// Note: kernel entropy pool, a ChaCha20 (RFC 8439) key stirred by every input
+//       output is a ChaCha20 stream of the key, which is replaced right after (fast key erasure)
+//       seeded by the board random number generator (virtio-rng on riscv64),
+//       at boot, then every `RESEED_INTERVAL` when used
+const SEED_SIZE: usize = 32;
+// Note: nanoseconds
+const RESEED_INTERVAL: u64 = 60_000_000_000;
+
+// Note: third nonce word, keeps stirring and output apart
+const NONCE_OUTPUT: u32 = 0;
+const NONCE_STIR: u32 = 1;
+
+// Note: `flags` of `getrandom`, as on Linux, GRND_RANDOM changes nothing here
+pub const GRND_NONBLOCK: usize = 1;
+pub const GRND_RANDOM: usize = 2;
+pub const GRND_INSECURE: usize = 4;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+  NotSeededError,
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+  s[a] = s[a].wrapping_add(s[b]);
+  s[d] = (s[d] ^ s[a]).rotate_left(16);
+  s[c] = s[c].wrapping_add(s[d]);
+  s[b] = (s[b] ^ s[c]).rotate_left(12);
+  s[a] = s[a].wrapping_add(s[b]);
+  s[d] = (s[d] ^ s[a]).rotate_left(8);
+  s[c] = s[c].wrapping_add(s[d]);
+  s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u8; 64] {
+  // "expand 32-byte k"
+  let mut state = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+  state[4..12].copy_from_slice(key);
+  state[12] = counter;
+  state[13..16].copy_from_slice(nonce);
+  let mut s = state;
+  for _ in 0..10 {
+    quarter_round(&mut s, 0, 4, 8, 12);
+    quarter_round(&mut s, 1, 5, 9, 13);
+    quarter_round(&mut s, 2, 6, 10, 14);
+    quarter_round(&mut s, 3, 7, 11, 15);
+    quarter_round(&mut s, 0, 5, 10, 15);
+    quarter_round(&mut s, 1, 6, 11, 12);
+    quarter_round(&mut s, 2, 7, 8, 13);
+    quarter_round(&mut s, 3, 4, 9, 14);
+  }
+  let mut block = [0u8; 64];
+  for i in 0..16 {
+    block[i * 4..i * 4 + 4].copy_from_slice(&s[i].wrapping_add(state[i]).to_le_bytes());
+  }
+  block
+}
+
+fn key_from(bytes: &[u8]) -> [u32; 8] {
+  let mut key = [0u32; 8];
+  for (i, word) in key.iter_mut().enumerate() {
+    *word = u32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]]);
+  }
+  key
+}
+
+struct Pool {
+  key: [u32; 8],
+  // Note: stirs so far, the nonce of the next one
+  stirs: u64,
+  // Note: bytes from the hardware, seeded from `SEED_SIZE` on
+  credit: usize,
+  reseed_at: u64,
+}
+
+impl Pool {
+  const fn new() -> Self {
+    Pool {
+      key: [0; 8],
+      stirs: 0,
+      credit: 0,
+      reseed_at: 0,
+    }
+  }
+
+  fn seeded(&self) -> bool {
+    self.credit >= SEED_SIZE
+  }
+
+  // Note: `data` is xored into the key, a block of the key is the next key
+  fn stir(&mut self, data: &[u8]) {
+    for chunk in data.chunks(SEED_SIZE) {
+      for (i, b) in chunk.iter().enumerate() {
+        self.key[i / 4] ^= (*b as u32) << (8 * (i % 4));
+      }
+      self.stirs += 1;
+      let nonce = [self.stirs as u32, (self.stirs >> 32) as u32, NONCE_STIR];
+      self.key = key_from(&chacha20_block(&self.key, 0, &nonce));
+    }
+  }
+
+  // Note: block 0 becomes the next key, the output starts at block 1
+  fn fill(&mut self, buf: &mut [u8]) {
+    let nonce = [0, 0, NONCE_OUTPUT];
+    for (i, chunk) in buf.chunks_mut(64).enumerate() {
+      let block = chacha20_block(&self.key, i as u32 + 1, &nonce);
+      chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    self.key = key_from(&chacha20_block(&self.key, 0, &nonce));
+  }
+
+  // Note: the time goes in as well, it is not credited
+  fn reseed(&mut self, now: u64) {
+    let mut seed = [0u8; SEED_SIZE];
+    let n = crate::driver::rng::read(&mut seed);
+    self.stir(&now.to_le_bytes());
+    self.stir(&seed[..n]);
+    self.credit = self.credit.saturating_add(n);
+    self.reseed_at = now + RESEED_INTERVAL;
+  }
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool::new());
+
+// Note: the `random` character device, writes are stirred in without credit
+struct Random;
+
+impl CharDevice for Random {
+  fn read(&self, buf: &mut [u8]) -> Result<usize, chardev::Error> {
+    fill(buf, false).map_err(|_| chardev::Error::WouldBlockError)?;
+    Ok(buf.len())
+  }
+
+  fn write(&self, data: &[u8]) -> Result<usize, chardev::Error> {
+    POOL.lock().stir(data);
+    Ok(data.len())
+  }
+}
+
+pub fn init() {
+  crate::driver::rng::init();
+  let mut pool = POOL.lock();
+  pool.reseed(crate::lib::time::now());
+  let seeded = pool.seeded();
+  drop(pool);
+  if seeded {
+    info!("random: seeded");
+  } else {
+    warn!("random: no hardware generator, not seeded");
+  }
+  chardev::register("random", Arc::new(Random));
+}
+
+// Note: the hardware is asked again while the pool is not seeded, `insecure` fills it anyway
+pub fn fill(buf: &mut [u8], insecure: bool) -> Result<(), Error> {
+  let now = crate::lib::time::now();
+  let mut pool = POOL.lock();
+  if !pool.seeded() || now >= pool.reseed_at {
+    pool.reseed(now);
+  }
+  let r = if pool.seeded() || insecure {
+    pool.fill(buf);
+    Ok(())
+  } else {
+    Err(Error::NotSeededError)
+  };
+  drop(pool);
+  r
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn block() {
+    // Note: RFC 8439 2.3.2
+    let key: alloc::vec::Vec<u8> = (0..32).collect();
+    let block = chacha20_block(&key_from(&key), 1, &[0x0900_0000, 0x4a00_0000, 0]);
+    assert_eq!(block[..16], [0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4]);
+    assert_eq!(block[48..], [0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e]);
+  }
+
+  #[test]
+  fn pool() {
+    let mut a = Pool::new();
+    let mut b = Pool::new();
+    a.stir(b"seed");
+    b.stir(b"seed");
+    assert_eq!(a.key, b.key);
+    let (mut x, mut y) = ([0u8; 100], [0u8; 100]);
+    a.fill(&mut x);
+    b.fill(&mut y);
+    assert_eq!(x[..], y[..]);
+    // Note: the key is gone after use
+    a.fill(&mut y);
+    assert_ne!(x[..], y[..]);
+    b.stir(b"more");
+    assert_ne!(a.key, b.key);
+    assert!(!a.seeded());
+  }
+}
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 
-use crate::arch::{ArchTrait, CoreTrait};
-use crate::lib::current_core;
+use crate::arch::{Arch, ArchTrait, CoreTrait};
+use crate::lib::{current_core, current_thread};
 use crate::lib::thread::Thread;
 
 #[derive(Copy, Clone)]
@@ -10,26 +10,25 @@ pub struct RoundRobinScheduler {
 }
 
 pub trait SchedulerTrait {
-  fn schedule(&mut self);
+  fn pick(&mut self) -> Option<Thread>;
 }
 
 impl SchedulerTrait for RoundRobinScheduler {
-  fn schedule(&mut self) {
-    loop {
+  // Note: destroyed threads still parked in the kernel go first, see `leave_trap`
+  //       `None` if nothing is runnable, the core runs its idle thread then
+  fn pick(&mut self) -> Option<Thread> {
+    if let Some(t) = crate::lib::thread::take_exiting() {
+      return Some(t);
+    }
+    let candidates: Vec<Thread> = crate::lib::thread::list();
+    for _ in 0..candidates.len() {
       self.counter += 1;
-      let candidates: Vec<Thread> = crate::lib::thread::list();
-      if candidates.is_empty() {
-        crate::arch::Arch::wait_for_event();
-        continue;
-      }
-      let i = self.counter % candidates.len();
-      let t = candidates[i].clone();
+      let t = &candidates[self.counter % candidates.len()];
       if t.runnable() {
-        println!("\nscheduler: switch to [{}]", t.tid());
-        t.run();
-        return;
+        return Some(t.clone());
       }
     }
+    None
   }
 }
 
@@ -41,6 +40,60 @@ impl RoundRobinScheduler {
   }
 }
 
+// Note: may switch away, the caller resumes here once picked again
 pub fn schedule() {
+  let state = Arch::interrupt_save_disable();
   current_core().schedule();
-}
\ No newline at end of file
+  Arch::interrupt_restore(state);
+}
+
+// Note: a preemption point for long running kernel code (interrupts masked), no lock may be held
+//       a pending interrupt is served here as if taken, which may switch to another thread
+pub fn preempt_point() {
+  use crate::lib::isr::{InterruptServiceRoutine, Isr};
+  if Arch::interrupt_pending() {
+    Isr::interrupt_request();
+  }
+}
+
+// Note: called by trap handlers before returning to the trapped context
+//       no reference may be left on the kernel stack, a destroyed thread never comes back
+pub fn leave_trap() {
+  let exiting = current_thread().map_or(false, |t| t.exiting());
+  if exiting {
+    crate::lib::thread::retire_current();
+  }
+  if current_thread().is_none() {
+    schedule();
+  }
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use crate::lib::thread::Status;
+
+  use super::*;
+
+  fn idle(_arg: usize) {}
+
+  // Note: picked only, nothing is switched to at boot
+  ktest!(round_robin_runnable {
+    let mut scheduler = RoundRobinScheduler::new();
+    let a = crate::lib::thread::alloc_kernel(idle as usize, 0);
+    let b = crate::lib::thread::alloc_kernel(idle as usize, 1);
+    b.set_status(Status::TsRunnable);
+    for _ in 0..4 {
+      assert!(scheduler.pick() == Some(b.clone()));
+    }
+    a.set_status(Status::TsRunnable);
+    let first = scheduler.pick();
+    let second = scheduler.pick();
+    assert!(first != second);
+    a.set_status(Status::TsSleeping);
+    b.set_status(Status::TsSleeping);
+    // Note: nothing runnable, the core would go idle
+    assert!(scheduler.pick().is_none());
+    a.destroy();
+    b.destroy();
+  });
+}
@@ -0,0 +1,445 @@
+use core::mem::size_of;
+
+use spin::Mutex;
+
+use crate::arch::{ContextFrame, ContextFrameTrait, CoreTrait, PAGE_SIZE};
+use crate::config::CONFIG_USER_LIMIT;
+use crate::lib::{current_core, current_thread, round_down};
+use crate::lib::process::{ExitStatus, Pid, Process};
+use crate::lib::thread::Status;
+
+use self::Error::*;
+
+pub type Signal = usize;
+
+pub const SIGNAL_NUMBER: usize = 32;
+
+pub const SIGINT: Signal = 2;
+pub const SIGILL: Signal = 4;
+pub const SIGTRAP: Signal = 5;
+pub const SIGKILL: Signal = 9;
+pub const SIGSEGV: Signal = 11;
+#[allow(dead_code)]
+pub const SIGTERM: Signal = 15;
+pub const SIGCHLD: Signal = 17;
+
+// Note: special handler values
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+// Note: `how` of `signal_mask`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+  InvalidSignalError,
+  InvalidHandlerError,
+  BadFrameError,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Action {
+  Terminate,
+  Ignore,
+}
+
+fn default_action(sig: Signal) -> Action {
+  match sig {
+    SIGCHLD => Action::Ignore,
+    _ => Action::Terminate,
+  }
+}
+
+fn valid(sig: Signal) -> bool {
+  sig > 0 && sig < SIGNAL_NUMBER
+}
+
+fn mask(sig: Signal) -> u32 {
+  1u32 << sig as u32
+}
+
+// Note: SIGKILL can be neither caught nor blocked
+const UNBLOCKABLE: u32 = 1 << SIGKILL as u32;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SignalState {
+  handlers: [usize; SIGNAL_NUMBER],
+  pending: u32,
+  blocked: u32,
+}
+
+impl SignalState {
+  pub const fn new() -> Self {
+    SignalState {
+      handlers: [SIG_DFL; SIGNAL_NUMBER],
+      pending: 0,
+      blocked: 0,
+    }
+  }
+
+  // Note: used by `fork`, pending signals are not inherited
+  pub fn inherit(&self) -> Self {
+    SignalState {
+      handlers: self.handlers,
+      pending: 0,
+      blocked: self.blocked,
+    }
+  }
+
+  pub fn handler(&self, sig: Signal) -> usize {
+    self.handlers[sig]
+  }
+
+  pub fn set_handler(&mut self, sig: Signal, handler: usize) {
+    self.handlers[sig] = handler;
+  }
+
+  pub fn blocked(&self) -> u32 {
+    self.blocked
+  }
+
+  pub fn set_blocked(&mut self, blocked: u32) {
+    self.blocked = blocked & !UNBLOCKABLE;
+  }
+
+  pub fn raise(&mut self, sig: Signal) {
+    self.pending |= mask(sig);
+  }
+
+  // Note: a pending signal that is neither blocked nor ignored, it cuts a blocking system call short
+  fn interrupting(&self) -> bool {
+    (1..SIGNAL_NUMBER).any(|sig| {
+      let handler = self.handlers[sig];
+      self.pending & !self.blocked & mask(sig) != 0
+        && (sig == SIGKILL || (handler != SIG_IGN && (handler != SIG_DFL || default_action(sig) == Action::Terminate)))
+    })
+  }
+
+  // Note: take the lowest numbered pending and unblocked signal
+  fn take(&mut self) -> Option<Signal> {
+    let deliverable = self.pending & !self.blocked;
+    if deliverable == 0 {
+      return None;
+    }
+    let sig = deliverable.trailing_zeros() as Signal;
+    self.pending &= !mask(sig);
+    Some(sig)
+  }
+}
+
+// Note: frame pushed onto the user stack on delivery
+//       handler is called as `handler(sig, frame)`
+//       and must return via `signal_return(frame)`
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SignalFrame {
+  context: ContextFrame,
+  blocked: u32,
+  signal: u32,
+}
+
+// Note: SIGKILL destroys the process at once, other signals wait for the way back to user space
+//       threads blocked in a system call are woken up for them, see `interrupt`
+pub fn raise(p: &Process, sig: Signal) {
+  if sig == SIGKILL {
+    info!("process {} killed", p.pid());
+    p.destroy();
+    return;
+  }
+  let mut state = p.signal();
+  state.raise(sig);
+  let interrupting = state.interrupting();
+  drop(state);
+  if interrupting {
+    interrupt(p);
+  }
+}
+
+pub fn interrupting(p: &Process) -> bool {
+  let state = p.signal();
+  let r = state.interrupting();
+  drop(state);
+  r
+}
+
+// Note: blocked threads of `p` leave their wait queues and return `InterruptedError`
+//       a sleeping thread is only woken up, `timer::sleep_until` returns early then
+fn interrupt(p: &Process) {
+  for t in p.threads() {
+    let interrupted = match t.status() {
+      Status::TsWaitForReceive | Status::TsWaitForReply => {
+        crate::lib::ipc::release_thread(&t);
+        t.set_ipc_receive_va(None);
+        true
+      }
+      Status::TsWaitForChild => { p.cancel_wait(&t) }
+      Status::TsSleeping => {
+        t.wake(Status::TsSleeping);
+        false
+      }
+      _ => { false }
+    };
+    if interrupted {
+      t.context().set_syscall_return_value(-(crate::lib::syscall::Error::InterruptedError as isize) as usize);
+      t.set_status(Status::TsRunnable);
+    }
+  }
+}
+
+// Note: raise a signal caused by a synchronous fault
+//       it can not be ignored or blocked, otherwise the process would loop on the fault
+pub fn force(p: &Process, sig: Signal) {
+  let mut state = p.signal();
+  if state.handler(sig) == SIG_IGN || state.blocked() & mask(sig) != 0 {
+    state.set_handler(sig, SIG_DFL);
+    let blocked = state.blocked() & !mask(sig);
+    state.set_blocked(blocked);
+  }
+  state.raise(sig);
+  drop(state);
+}
+
+// Note: whether `sig` kills `p` when delivered
+pub fn is_fatal(p: &Process, sig: Signal) -> bool {
+  let state = p.signal();
+  let handler = state.handler(sig);
+  drop(state);
+  sig == SIGKILL || (handler == SIG_DFL && default_action(sig) == Action::Terminate)
+}
+
+pub fn set_action(p: &Process, sig: Signal, handler: usize) -> Result<usize, Error> {
+  if !valid(sig) || sig == SIGKILL {
+    return Err(InvalidSignalError);
+  }
+  if handler >= CONFIG_USER_LIMIT {
+    return Err(InvalidHandlerError);
+  }
+  let mut state = p.signal();
+  let old = state.handler(sig);
+  state.set_handler(sig, handler);
+  drop(state);
+  Ok(old)
+}
+
+pub fn set_mask(p: &Process, how: usize, set: u32) -> Result<u32, Error> {
+  let mut state = p.signal();
+  let old = state.blocked();
+  let new = match how {
+    SIG_BLOCK => old | set,
+    SIG_UNBLOCK => old & !set,
+    SIG_SETMASK => set,
+    _ => {
+      drop(state);
+      return Err(InvalidSignalError);
+    }
+  };
+  state.set_blocked(new);
+  drop(state);
+  Ok(old)
+}
+
+pub fn send(p: &Process, sig: Signal) -> Result<(), Error> {
+  if !valid(sig) {
+    return Err(InvalidSignalError);
+  }
+  raise(p, sig);
+  Ok(())
+}
+
+// Note: a process may signal itself, its children and the processes of its group
+//       the init process only gets the signals it catches, it would take the whole system down
+pub fn permitted(sender: &Process, target: &Process, sig: Signal) -> bool {
+  if crate::lib::process::init_process().as_ref() == Some(target) && !catches(target, sig) {
+    return false;
+  }
+  let group = sender.group();
+  sender == target || target.parent().as_ref() == Some(sender) || (group.is_some() && group == target.group())
+}
+
+fn catches(p: &Process, sig: Signal) -> bool {
+  if !valid(sig) {
+    return false;
+  }
+  let state = p.signal();
+  let handler = state.handler(sig);
+  drop(state);
+  sig != SIGKILL && handler != SIG_DFL && handler != SIG_IGN
+}
+
+// Note: process group getting Ctrl-C, `None` for the group of the init process
+static FOREGROUND: Mutex<Option<Pid>> = Mutex::new(None);
+
+pub fn foreground() -> Option<Pid> {
+  let lock = FOREGROUND.lock();
+  let r = *lock;
+  drop(lock);
+  r.or_else(|| crate::lib::process::init_process().and_then(|p| p.group()))
+}
+
+pub fn set_foreground(group: Pid) {
+  let mut lock = FOREGROUND.lock();
+  *lock = Some(group);
+  drop(lock);
+}
+
+// Note: the group is gone, Ctrl-C falls back to the group of the init process
+pub fn forget_foreground(group: Pid) {
+  let mut lock = FOREGROUND.lock();
+  if *lock == Some(group) {
+    *lock = None;
+  }
+  drop(lock);
+}
+
+// Note: Ctrl-C on console, SIGINT to the foreground process group
+//       the init process is spared, it would take the whole system down
+pub fn console_interrupt() {
+  let group = match foreground() {
+    Some(group) => { group }
+    None => { return; }
+  };
+  let init = crate::lib::process::init_process();
+  for p in crate::lib::process::list() {
+    if p.group() == Some(group) && Some(&p) != init.as_ref() && !p.is_zombie() {
+      raise(&p, SIGINT);
+    }
+  }
+}
+
+fn push_frame(p: &Process, ctx: &mut ContextFrame, sig: Signal, handler: usize, blocked: u32) -> Result<(), Error> {
+  let frame = SignalFrame {
+    context: *ctx,
+    blocked,
+    signal: sig as u32,
+  };
+  // Note: keep the stack 16 bytes aligned for both arch
+  let frame_va = round_down(ctx.stack_pointer().wrapping_sub(size_of::<SignalFrame>()), 16);
+  // Note: the frame may land below the mapped part of the stack, grow it as a fault would
+  for va in (round_down(frame_va, PAGE_SIZE)..frame_va.saturating_add(size_of::<SignalFrame>())).step_by(PAGE_SIZE) {
+    p.grow_stack(va).map_err(|_| BadFrameError)?;
+  }
+  crate::lib::uaccess::write_user(p.page_table(), frame_va, &frame).map_err(|_| BadFrameError)?;
+  ctx.set_exception_pc(handler);
+  ctx.set_stack_pointer(frame_va);
+  ctx.set_argument(sig);
+  ctx.set_syscall_argument(1, frame_va);
+  Ok(())
+}
+
+// Note: called on the way back to user space
+pub fn handle_pending() {
+  let t = match current_thread() {
+    None => { return; }
+    Some(t) => { t }
+  };
+  // Note: destroyed, unwinding its kernel stack, see `scheduler::leave_trap`
+  if t.exiting() {
+    return;
+  }
+  let p = match t.process() {
+    None => { return; }
+    Some(p) => { p }
+  };
+  loop {
+    let mut state = p.signal();
+    let sig = match state.take() {
+      None => {
+        drop(state);
+        return;
+      }
+      Some(sig) => { sig }
+    };
+    let handler = state.handler(sig);
+    let blocked = state.blocked();
+    if handler != SIG_DFL && handler != SIG_IGN && sig != SIGKILL {
+      // Note: block the signal itself while its handler runs
+      state.set_blocked(blocked | mask(sig));
+    }
+    drop(state);
+    match handler {
+      SIG_IGN if sig != SIGKILL => {}
+      SIG_DFL | SIG_IGN => {
+        if default_action(sig) == Action::Terminate {
+          info!("process {} terminated by signal {}", p.pid(), sig);
+          p.exit(ExitStatus::Killed(sig));
+          return;
+        }
+      }
+      _ => {
+        if sig == SIGKILL {
+          info!("process {} killed", p.pid());
+          p.exit(ExitStatus::Killed(sig));
+          return;
+        }
+        let ctx = current_core().context_mut();
+        if push_frame(&p, ctx, sig, handler, blocked).is_err() {
+          warn!("process {} bad signal frame, process killed", p.pid());
+          p.exit(ExitStatus::Killed(SIGSEGV));
+        }
+        return;
+      }
+    }
+  }
+}
+
+pub fn signal_return(p: &Process, frame_va: usize) -> Result<(), Error> {
+  let frame: SignalFrame = crate::lib::uaccess::read_user(p.page_table(), frame_va).map_err(|_| BadFrameError)?;
+  let ctx = current_core().context_mut();
+  ctx.restore_from_user(&frame.context);
+  let mut state = p.signal();
+  state.set_blocked(frame.blocked);
+  drop(state);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::lib::page_table::PageTableTrait;
+
+  use super::*;
+
+  #[test]
+  fn interrupting() {
+    let mut state = SignalState::new();
+    // Note: ignored by default
+    state.raise(SIGCHLD);
+    assert!(!state.interrupting());
+    state.raise(SIGINT);
+    assert!(state.interrupting());
+    state.set_blocked(mask(SIGINT));
+    assert!(!state.interrupting());
+    state.set_blocked(0);
+    state.set_handler(SIGINT, SIG_IGN);
+    assert!(!state.interrupting());
+    state.set_handler(SIGINT, 0x1000);
+    assert!(state.interrupting());
+  }
+
+  #[test]
+  fn permission() {
+    crate::mm::test_init();
+    let parent = crate::lib::process::alloc(None);
+    let child = crate::lib::process::alloc(Some(parent.clone()));
+    let other = crate::lib::process::alloc(None);
+    assert!(permitted(&parent, &parent, SIGTERM));
+    assert!(permitted(&parent, &child, SIGTERM));
+    // Note: same group as its parent until it moves
+    assert!(permitted(&child, &parent, SIGTERM));
+    child.set_group(Some(child.pid()));
+    assert!(!permitted(&child, &parent, SIGTERM));
+    assert!(permitted(&parent, &child, SIGTERM));
+    assert!(!permitted(&parent, &other, SIGTERM));
+    assert!(!permitted(&other, &child, SIGTERM));
+    // Note: out of any group once the leader is gone
+    other.set_group(None);
+    child.set_group(None);
+    assert!(!permitted(&other, &child, SIGTERM));
+    assert!(permitted(&parent, &child, SIGTERM));
+    for p in [child, parent, other].iter() {
+      p.page_table().destroy();
+      crate::lib::process::free(p);
+    }
+  }
+}
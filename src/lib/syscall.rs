@@ -2,7 +2,7 @@ use crate::arch::{ArchPageTableEntry, ArchPageTableEntryTrait, ContextFrameTrait
 use crate::config::CONFIG_USER_LIMIT;
 use crate::lib::{current_process, current_thread, round_down};
 use crate::lib::page_table::{Entry, PageTableEntryAttrTrait, PageTableTrait};
-use crate::lib::process::{Pid, Process};
+use crate::lib::process::{in_stack_guard, Pid, Process};
 
 use self::Error::*;
 
@@ -17,6 +17,17 @@ pub enum Error {
   MemoryNotMappedError,
   _IpcNotReceivingError,
   InternalError,
+  IpcEndpointNotFoundError,
+  IpcQueueFullError,
+  IpcInvalidMessageError,
+  ProcessNoChildError,
+  DeviceNotFoundError,
+  NetSocketError,
+  NetAddressInUseError,
+  NetConnectionError,
+  WouldBlockError,
+  InterruptedError,
+  DeviceBusyError,
 }
 
 impl core::convert::From<crate::mm::page_pool::Error> for Error {
@@ -29,19 +40,93 @@ impl core::convert::From<crate::mm::page_pool::Error> for Error {
 }
 
 impl core::convert::From<crate::lib::page_table::Error> for Error {
-  fn from(_: crate::lib::page_table::Error) -> Self {
-    InternalError
+  fn from(e: crate::lib::page_table::Error) -> Self {
+    match e {
+      crate::lib::page_table::Error::FrameError(e) => { Error::from(e) }
+      _ => { InternalError }
+    }
+  }
+}
+
+impl core::convert::From<crate::lib::ipc::Error> for Error {
+  fn from(e: crate::lib::ipc::Error) -> Self {
+    match e {
+      crate::lib::ipc::Error::EndpointNotFoundError => { IpcEndpointNotFoundError }
+      crate::lib::ipc::Error::QueueFullError => { IpcQueueFullError }
+      crate::lib::ipc::Error::InvalidMessageError => { IpcInvalidMessageError }
+      // Note: another process's endpoint is as good as none to the caller
+      crate::lib::ipc::Error::NotOwnerError => { IpcEndpointNotFoundError }
+    }
+  }
+}
+
+impl core::convert::From<crate::lib::signal::Error> for Error {
+  fn from(_: crate::lib::signal::Error) -> Self {
+    InvalidArgumentError
+  }
+}
+
+impl core::convert::From<crate::lib::uaccess::Error> for Error {
+  fn from(e: crate::lib::uaccess::Error) -> Self {
+    match e {
+      crate::lib::uaccess::Error::AddressLimitError => { MemoryLimitError }
+      crate::lib::uaccess::Error::OutOfMemoryError => { OutOfMemoryError }
+      _ => { MemoryNotMappedError }
+    }
   }
 }
 
 impl core::convert::From<crate::lib::process::Error> for Error {
   fn from(e: crate::lib::process::Error) -> Self {
     match e {
+      crate::lib::process::Error::NoChildError => { ProcessNoChildError }
+      crate::lib::process::Error::StatusAddressError => { MemoryNotMappedError }
       _ => { InternalError }
     }
   }
 }
 
+impl core::convert::From<crate::lib::net::Error> for Error {
+  fn from(e: crate::lib::net::Error) -> Self {
+    use crate::lib::net::Error as E;
+    match e {
+      E::NoDeviceError => { DeviceNotFoundError }
+      E::InvalidArgumentError => { InvalidArgumentError }
+      E::OutOfSocketError => { OutOfMemoryError }
+      E::SocketNotFoundError | E::InvalidStateError => { NetSocketError }
+      E::AddressInUseError => { NetAddressInUseError }
+      E::WouldBlockError => { WouldBlockError }
+      E::NotConnectedError | E::ConnectionResetError => { NetConnectionError }
+    }
+  }
+}
+
+impl core::convert::From<crate::lib::chardev::Error> for Error {
+  fn from(e: crate::lib::chardev::Error) -> Self {
+    match e {
+      crate::lib::chardev::Error::NotFoundError => { DeviceNotFoundError }
+      crate::lib::chardev::Error::NotOpenError => { InvalidArgumentError }
+      crate::lib::chardev::Error::WouldBlockError => { WouldBlockError }
+    }
+  }
+}
+
+impl core::convert::From<crate::lib::framebuffer::Error> for Error {
+  fn from(_: crate::lib::framebuffer::Error) -> Self {
+    DeviceBusyError
+  }
+}
+
+impl core::convert::From<crate::lib::random::Error> for Error {
+  fn from(_: crate::lib::random::Error) -> Self {
+    WouldBlockError
+  }
+}
+
+// Note: bytes copied from / to user space by one `net_send`, `net_receive`, `getrandom`, `chardev_read` or `chardev_write`
+const TRANSFER_LIMIT: usize = 0x1_0000;
+const CHARDEV_NAME_LIMIT: usize = 64;
+
 pub trait SystemCallTrait {
   fn putc(c: char);
   fn get_pid() -> u16;
@@ -57,6 +142,42 @@ pub trait SystemCallTrait {
   fn thread_set_status(pid: u16, status: crate::lib::thread::Status) -> Result<(), Error>;
   fn ipc_receive(dst_va: usize);
   fn ipc_can_send(pid: u16, value: usize, src_va: usize, perm: usize) -> Result<(), Error>;
+  fn endpoint_alloc() -> Result<u16, Error>;
+  fn endpoint_free(ep: u16) -> Result<(), Error>;
+  fn endpoint_send(ep: u16) -> Result<(), Error>;
+  fn endpoint_receive(ep: u16) -> Result<Option<u16>, Error>;
+  fn endpoint_call(ep: u16) -> Result<Option<u16>, Error>;
+  fn endpoint_reply_receive(ep: u16) -> Result<Option<u16>, Error>;
+  fn signal_action(sig: usize, handler: usize) -> Result<usize, Error>;
+  fn signal_mask(how: usize, set: usize) -> Result<usize, Error>;
+  fn signal_send(pid: u16, sig: usize) -> Result<(), Error>;
+  fn signal_return(frame: usize);
+  fn process_exit(code: i32);
+  fn process_wait(pid: u16, status_va: usize) -> Result<Option<u16>, Error>;
+  fn log_read(va: usize, len: usize) -> Result<usize, Error>;
+  fn trace_control(mask: usize) -> Result<usize, Error>;
+  fn trace_dump(format: usize) -> Result<(), Error>;
+  fn mem_alloc_large(pid: u16, va: usize, perm: usize) -> Result<(), Error>;
+  fn clock_gettime(clock: usize, ts_va: usize) -> Result<(), Error>;
+  fn nanosleep(ts_va: usize) -> Result<(), Error>;
+  fn core_usage(core_id: usize, va: usize) -> Result<(), Error>;
+  fn gettimeofday(tv_va: usize) -> Result<(), Error>;
+  fn framebuffer_map(va: usize, info_va: usize) -> Result<(), Error>;
+  fn net_socket(protocol: usize) -> Result<usize, Error>;
+  fn net_bind(sid: usize, port: usize) -> Result<(), Error>;
+  fn net_connect(sid: usize, endpoint_va: usize) -> Result<(), Error>;
+  fn net_listen(sid: usize) -> Result<(), Error>;
+  fn net_accept(sid: usize, endpoint_va: usize) -> Result<usize, Error>;
+  fn net_send(sid: usize, va: usize, len: usize, endpoint_va: usize) -> Result<usize, Error>;
+  fn net_receive(sid: usize, va: usize, len: usize, endpoint_va: usize) -> Result<usize, Error>;
+  fn net_close(sid: usize) -> Result<(), Error>;
+  fn getrandom(va: usize, len: usize, flags: usize) -> Result<usize, Error>;
+  fn chardev_open(name_va: usize, name_len: usize) -> Result<usize, Error>;
+  fn chardev_read(id: usize, va: usize, len: usize) -> Result<usize, Error>;
+  fn chardev_write(id: usize, va: usize, len: usize) -> Result<usize, Error>;
+  fn chardev_close(id: usize) -> Result<(), Error>;
+  fn process_set_group(pid: u16, group: u16) -> Result<(), Error>;
+  fn console_set_foreground(group: u16) -> Result<(), Error>;
 }
 
 pub struct SystemCall;
@@ -69,7 +190,9 @@ fn lookup_pid(pid: u16, check_parent: bool) -> Result<Process, Error> {
     }
   } else {
     if let Some(p) = crate::lib::process::lookup(pid) {
-      if check_parent {
+      if p.is_zombie() {
+        Err(ProcessPidNotFoundError)
+      } else if check_parent {
         if let Some(parent) = p.parent() {
           match current_process() {
             None => { Err(InternalError) }
@@ -132,11 +255,11 @@ impl SystemCallTrait for SystemCall {
   }
 
   fn mem_alloc(pid: u16, va: usize, attr: usize) -> Result<(), Error> {
-    if va >= CONFIG_USER_LIMIT {
+    if va >= CONFIG_USER_LIMIT || in_stack_guard(va, 1) {
       return Err(MemoryLimitError);
     }
     let p = lookup_pid(pid, true)?;
-    let frame = crate::mm::page_pool::try_alloc()?;
+    let frame = crate::mm::page_pool::try_alloc_tagged(crate::mm::page_pool::FRAME_USER, crate::mm::page_pool::Owner::Process(p.pid()))?;
     frame.zero();
     let page_table = p.page_table();
     let user_attr = Entry::from(ArchPageTableEntry::from_pte(attr)).attribute();
@@ -148,13 +271,17 @@ impl SystemCallTrait for SystemCall {
   fn mem_map(src_pid: u16, src_va: usize, dst_pid: u16, dst_va: usize, attr: usize) -> Result<(), Error> {
     let src_va = round_down(src_va, PAGE_SIZE);
     let dst_va = round_down(dst_va, PAGE_SIZE);
-    if src_va >= CONFIG_USER_LIMIT || dst_va >= CONFIG_USER_LIMIT {
+    if src_va >= CONFIG_USER_LIMIT || dst_va >= CONFIG_USER_LIMIT || in_stack_guard(dst_va, PAGE_SIZE) {
       return Err(MemoryLimitError);
     }
     let src_pid = lookup_pid(src_pid, true)?;
     let dst_pid = lookup_pid(dst_pid, true)?;
     let src_pt = src_pid.page_table();
-    if let Some(pte) = src_pt.lookup_page(src_va) {
+    if let Some((pte, size)) = src_pt.lookup(src_va) {
+      // Note: blocks are not shared page by page
+      if size != PAGE_SIZE {
+        return Err(InvalidArgumentError);
+      }
       let pa = pte.pa();
       let user_attr = Entry::from(ArchPageTableEntry::from_pte(attr)).attribute();
       let attr = user_attr.filter();
@@ -179,7 +306,9 @@ impl SystemCallTrait for SystemCall {
   fn process_alloc() -> Result<Pid, Error> {
     let t = current_thread().unwrap();
     let p = t.process().unwrap();
-    let child = crate::lib::process::alloc(Some(p));
+    let child = crate::lib::process::alloc(Some(p.clone()));
+    let state = p.signal().inherit();
+    *child.signal() = state;
     let mut ctx = *crate::lib::current_core().context();
     ctx.set_syscall_return_value(0);
     let child_thread = crate::lib::thread::alloc_user(0, 0, 0, child.clone());
@@ -199,7 +328,11 @@ impl SystemCallTrait for SystemCall {
       return Err(InvalidArgumentError);
     }
     let p = lookup_pid(pid, true)?;
-    p.main_thread().set_status(status);
+    let t = p.main_thread();
+    // Note: a thread blocked in a system call is woken by its waker only, never from here
+    if !t.transit(TsRunnable, status) && !t.transit(TsNotRunnable, status) {
+      return Err(InvalidArgumentError);
+    }
     Ok(())
   }
 
@@ -212,4 +345,468 @@ impl SystemCallTrait for SystemCall {
   fn ipc_can_send(pid: u16, value: usize, src_va: usize, attr: usize) -> Result<(), Error> {
     unimplemented!()
   }
-}
\ No newline at end of file
+
+  fn endpoint_alloc() -> Result<u16, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let ep = crate::lib::ipc::alloc(&p);
+    Ok(ep.id())
+  }
+
+  fn endpoint_free(ep: u16) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    crate::lib::ipc::free(p.pid(), ep)?;
+    Ok(())
+  }
+
+  // Note: message info and registers are read from the trapped context
+  //       see `lib/ipc.rs` for the register layout
+  fn endpoint_send(ep: u16) -> Result<(), Error> {
+    let t = current_thread().ok_or(InternalError)?;
+    let ep = crate::lib::ipc::lookup(ep)?;
+    let ctx = crate::lib::current_core().context();
+    crate::lib::ipc::send(&t, ctx, &ep)?;
+    Ok(())
+  }
+
+  fn endpoint_receive(ep: u16) -> Result<Option<u16>, Error> {
+    let t = current_thread().ok_or(InternalError)?;
+    let ep = crate::lib::ipc::lookup(ep)?;
+    let ctx = crate::lib::current_core().context_mut();
+    Ok(crate::lib::ipc::receive(&t, ctx, &ep)?)
+  }
+
+  fn endpoint_call(ep: u16) -> Result<Option<u16>, Error> {
+    let t = current_thread().ok_or(InternalError)?;
+    let ep = crate::lib::ipc::lookup(ep)?;
+    let ctx = crate::lib::current_core().context();
+    Ok(crate::lib::ipc::call(&t, ctx, &ep)?)
+  }
+
+  fn endpoint_reply_receive(ep: u16) -> Result<Option<u16>, Error> {
+    let t = current_thread().ok_or(InternalError)?;
+    let ep = crate::lib::ipc::lookup(ep)?;
+    let ctx = crate::lib::current_core().context_mut();
+    Ok(crate::lib::ipc::reply_receive(&t, ctx, &ep)?)
+  }
+
+  fn signal_action(sig: usize, handler: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::signal::set_action(&p, sig, handler)?)
+  }
+
+  fn signal_mask(how: usize, set: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::signal::set_mask(&p, how, set as u32)? as usize)
+  }
+
+  // Note: to the caller itself, its children and its process group only
+  //       the init process only gets the signals it catches
+  fn signal_send(pid: u16, sig: usize) -> Result<(), Error> {
+    let current = current_process().ok_or(InternalError)?;
+    let p = lookup_pid(pid, false)?;
+    if !crate::lib::signal::permitted(&current, &p, sig) {
+      return Err(ProcessParentMismatchedError);
+    }
+    crate::lib::signal::send(&p, sig)?;
+    Ok(())
+  }
+
+  // Note: no return value, the whole context is restored from the signal frame
+  fn signal_return(frame: usize) {
+    if let Some(p) = current_process() {
+      if crate::lib::signal::signal_return(&p, frame).is_err() {
+        crate::lib::signal::force(&p, crate::lib::signal::SIGSEGV);
+      }
+    }
+  }
+
+  fn process_exit(code: i32) {
+    if let Some(p) = current_process() {
+      p.exit(crate::lib::process::ExitStatus::Exited(code));
+    }
+  }
+
+  fn process_wait(pid: u16, status_va: usize) -> Result<Option<u16>, Error> {
+    let t = current_thread().ok_or(InternalError)?;
+    let p = t.process().ok_or(InternalError)?;
+    Ok(p.wait(&t, pid, status_va)?)
+  }
+
+  // Note: copy the most recent `len` bytes of the kernel log (dmesg), return bytes copied
+  fn log_read(va: usize, len: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let buf = crate::lib::log::read(len);
+    crate::lib::uaccess::copy_to_user(p.page_table(), va, &buf)?;
+    Ok(buf.len())
+  }
+
+  // Note: set trace enable mask (`TRACE_*` in `lib/trace.rs`), return the previous one
+  fn trace_control(mask: usize) -> Result<usize, Error> {
+    Ok(crate::lib::trace::set_mask(mask))
+  }
+
+  fn trace_dump(format: usize) -> Result<(), Error> {
+    if crate::lib::trace::dump(format) {
+      Ok(())
+    } else {
+      Err(InvalidArgumentError)
+    }
+  }
+
+  // Note: a zeroed 2 MiB block, opt in for large anonymous memory
+  //       unlike `mem_alloc` pages it cannot be shared by `mem_map` or IPC
+  fn mem_alloc_large(pid: u16, va: usize, attr: usize) -> Result<(), Error> {
+    use crate::lib::page_table::BLOCK_SIZE_2M;
+    use crate::mm::page_pool::{FRAME_USER, Owner};
+    if va % BLOCK_SIZE_2M != 0 {
+      return Err(InvalidArgumentError);
+    }
+    if va >= CONFIG_USER_LIMIT || CONFIG_USER_LIMIT - va < BLOCK_SIZE_2M || in_stack_guard(va, BLOCK_SIZE_2M) {
+      return Err(MemoryLimitError);
+    }
+    let p = lookup_pid(pid, true)?;
+    let order = (BLOCK_SIZE_2M / PAGE_SIZE).trailing_zeros() as usize;
+    let frame = crate::mm::page_pool::alloc_contiguous(order)?;
+    crate::mm::page_pool::tag(frame, FRAME_USER, Owner::Process(p.pid()))?;
+    // Note: held across zeroing and mapping, the block goes back to the pool on failure
+    crate::mm::page_pool::increase_rc(frame)?;
+    for i in 0..(BLOCK_SIZE_2M / PAGE_SIZE) {
+      crate::mm::PageFrame::new(frame.pa() + i * PAGE_SIZE).zero();
+      crate::lib::scheduler::preempt_point();
+    }
+    let user_attr = Entry::from(ArchPageTableEntry::from_pte(attr)).attribute();
+    let attr = user_attr.filter();
+    // Note: the process may have exited while preempted
+    let r = if p.is_zombie() {
+      Err(ProcessPidNotFoundError)
+    } else {
+      p.page_table().insert_block(va, frame, BLOCK_SIZE_2M, attr).map_err(Error::from)
+    };
+    crate::mm::page_pool::decrease_rc(frame)?;
+    r
+  }
+
+  fn clock_gettime(clock: usize, ts_va: usize) -> Result<(), Error> {
+    use crate::lib::time::{CLOCK_MONOTONIC, CLOCK_REALTIME, Timespec};
+    let ns = match clock {
+      CLOCK_REALTIME => { crate::lib::time::wall_clock() }
+      CLOCK_MONOTONIC => { crate::lib::time::now() }
+      _ => { return Err(InvalidArgumentError); }
+    };
+    let p = current_process().ok_or(InternalError)?;
+    crate::lib::uaccess::write_user(p.page_table(), ts_va, &Timespec::from_ns(ns))?;
+    Ok(())
+  }
+
+  // Note: relative duration, signals are delivered once the caller wakes up
+  fn nanosleep(ts_va: usize) -> Result<(), Error> {
+    use crate::lib::time::Timespec;
+    let t = current_thread().ok_or(InternalError)?;
+    let p = t.process().ok_or(InternalError)?;
+    let ts: Timespec = crate::lib::uaccess::read_user(p.page_table(), ts_va)?;
+    let duration = ts.to_ns().ok_or(InvalidArgumentError)?;
+    let deadline = crate::lib::time::now().saturating_add(duration);
+    if !crate::lib::timer::sleep_until(&t, deadline) {
+      return Err(InterruptedError);
+    }
+    Ok(())
+  }
+
+  // Note: idle and busy nanoseconds of a core since boot (`lib::core::Usage`)
+  fn core_usage(core_id: usize, va: usize) -> Result<(), Error> {
+    let core = crate::lib::core::get(core_id).ok_or(InvalidArgumentError)?;
+    let p = current_process().ok_or(InternalError)?;
+    crate::lib::uaccess::write_user(p.page_table(), va, &core.usage())?;
+    Ok(())
+  }
+
+  // Note: no time zone argument, always UTC
+  fn gettimeofday(tv_va: usize) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let tv = crate::lib::time::Timeval::from_ns(crate::lib::time::wall_clock());
+    crate::lib::uaccess::write_user(p.page_table(), tv_va, &tv)?;
+    Ok(())
+  }
+
+  // Note: the whole framebuffer at `va` (device memory, aligned accesses only),
+  //       its layout (`lib::framebuffer::Info`) at `info_va`
+  //       the range must be unmapped, the kernel stops drawing text on it until the caller exits
+  //       one process at a time, others get `DeviceBusyError`
+  fn framebuffer_map(va: usize, info_va: usize) -> Result<(), Error> {
+    use crate::arch::{Arch, ArchTrait};
+    use crate::lib::page_table::EntryAttribute;
+    let info = crate::lib::framebuffer::info().ok_or(DeviceNotFoundError)?;
+    let size = crate::lib::round_up(info.size, PAGE_SIZE);
+    if va % PAGE_SIZE != 0 {
+      return Err(InvalidArgumentError);
+    }
+    if va >= CONFIG_USER_LIMIT || CONFIG_USER_LIMIT - va < size || in_stack_guard(va, size) {
+      return Err(MemoryLimitError);
+    }
+    let p = current_process().ok_or(InternalError)?;
+    let page_table = p.page_table();
+    if (va..va + size).step_by(PAGE_SIZE).any(|va| page_table.lookup_page(va).is_some()) {
+      return Err(InvalidArgumentError);
+    }
+    crate::lib::uaccess::write_user(page_table, info_va, &info)?;
+    crate::lib::framebuffer::acquire(&p)?;
+    for offset in (0..size).step_by(PAGE_SIZE) {
+      page_table.map(va + offset, info.pa + offset, EntryAttribute::user_device());
+    }
+    Arch::invalidate_tlb();
+    Ok(())
+  }
+
+  // Note: `protocol` is `lib::net::SOCKET_UDP` or `SOCKET_TCP`, return the socket number
+  //       sockets never block, `WouldBlockError` means try again later
+  fn net_socket(protocol: usize) -> Result<usize, Error> {
+    use crate::lib::net::{Protocol, SOCKET_TCP, SOCKET_UDP};
+    let protocol = match protocol {
+      SOCKET_UDP => { Protocol::Udp }
+      SOCKET_TCP => { Protocol::Tcp }
+      _ => { return Err(InvalidArgumentError); }
+    };
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::net::socket(p.pid(), protocol)?)
+  }
+
+  // Note: port 0 takes an ephemeral port
+  fn net_bind(sid: usize, port: usize) -> Result<(), Error> {
+    if port > u16::MAX as usize {
+      return Err(InvalidArgumentError);
+    }
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::net::bind(p.pid(), sid, port as u16)?)
+  }
+
+  // Note: `lib::net::Endpoint` at `endpoint_va`, TCP returns once the SYN is out
+  fn net_connect(sid: usize, endpoint_va: usize) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let remote: crate::lib::net::Endpoint = crate::lib::uaccess::read_user(p.page_table(), endpoint_va)?;
+    Ok(crate::lib::net::connect(p.pid(), sid, remote)?)
+  }
+
+  fn net_listen(sid: usize) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::net::listen(p.pid(), sid)?)
+  }
+
+  // Note: a new socket for the connection, its peer written at `endpoint_va` unless 0
+  fn net_accept(sid: usize, endpoint_va: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let (connection, remote) = crate::lib::net::accept(p.pid(), sid)?;
+    if endpoint_va != 0 {
+      crate::lib::uaccess::write_user(p.page_table(), endpoint_va, &remote)?;
+    }
+    Ok(connection)
+  }
+
+  // Note: a UDP socket sends to the `lib::net::Endpoint` at `endpoint_va`, or to its peer if 0
+  //       return the bytes taken, TCP may take fewer
+  fn net_send(sid: usize, va: usize, len: usize, endpoint_va: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let to = if endpoint_va == 0 {
+      None
+    } else {
+      Some(crate::lib::uaccess::read_user(p.page_table(), endpoint_va)?)
+    };
+    let mut buf = alloc::vec![0u8; core::cmp::min(len, TRANSFER_LIMIT)];
+    crate::lib::uaccess::copy_from_user(p.page_table(), va, &mut buf)?;
+    Ok(crate::lib::net::send(p.pid(), sid, &buf, to)?)
+  }
+
+  // Note: the sender is written at `endpoint_va` unless 0, TCP returns 0 at end of stream
+  fn net_receive(sid: usize, va: usize, len: usize, endpoint_va: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let mut buf = alloc::vec![0u8; core::cmp::min(len, TRANSFER_LIMIT)];
+    let (n, from) = crate::lib::net::receive(p.pid(), sid, &mut buf)?;
+    crate::lib::uaccess::copy_to_user(p.page_table(), va, &buf[..n])?;
+    if endpoint_va != 0 {
+      crate::lib::uaccess::write_user(p.page_table(), endpoint_va, &from)?;
+    }
+    Ok(n)
+  }
+
+  fn net_close(sid: usize) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::net::close(p.pid(), sid)?)
+  }
+
+  // Note: `flags` of `lib::random::GRND_*`, return the bytes written
+  //       never blocks, `WouldBlockError` until the pool is seeded unless GRND_INSECURE
+  fn getrandom(va: usize, len: usize, flags: usize) -> Result<usize, Error> {
+    use crate::lib::random::{GRND_INSECURE, GRND_NONBLOCK, GRND_RANDOM};
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+      || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE {
+      return Err(InvalidArgumentError);
+    }
+    let p = current_process().ok_or(InternalError)?;
+    let mut buf = alloc::vec![0u8; core::cmp::min(len, TRANSFER_LIMIT)];
+    crate::lib::random::fill(&mut buf, flags & GRND_INSECURE != 0)?;
+    crate::lib::uaccess::copy_to_user(p.page_table(), va, &buf)?;
+    Ok(buf.len())
+  }
+
+  // Note: the character device named by the string at `name_va`, return its number
+  //       the number is the same for every process, the device is closed on exit
+  fn chardev_open(name_va: usize, name_len: usize) -> Result<usize, Error> {
+    if name_len > CHARDEV_NAME_LIMIT {
+      return Err(InvalidArgumentError);
+    }
+    let p = current_process().ok_or(InternalError)?;
+    let mut name = alloc::vec![0u8; name_len];
+    crate::lib::uaccess::copy_from_user(p.page_table(), name_va, &mut name)?;
+    let name = core::str::from_utf8(&name).map_err(|_| InvalidArgumentError)?;
+    Ok(crate::lib::chardev::open(p.pid(), name)?)
+  }
+
+  // Note: only on a device the caller opened
+  //       never blocks, `WouldBlockError` means try again later, 0 at end of stream
+  fn chardev_read(id: usize, va: usize, len: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let mut buf = alloc::vec![0u8; core::cmp::min(len, TRANSFER_LIMIT)];
+    let n = crate::lib::chardev::read(p.pid(), id, &mut buf)?;
+    crate::lib::uaccess::copy_to_user(p.page_table(), va, &buf[..n])?;
+    Ok(n)
+  }
+
+  // Note: return the bytes taken
+  fn chardev_write(id: usize, va: usize, len: usize) -> Result<usize, Error> {
+    let p = current_process().ok_or(InternalError)?;
+    let mut buf = alloc::vec![0u8; core::cmp::min(len, TRANSFER_LIMIT)];
+    crate::lib::uaccess::copy_from_user(p.page_table(), va, &mut buf)?;
+    Ok(crate::lib::chardev::write(p.pid(), id, &buf)?)
+  }
+
+  fn chardev_close(id: usize) -> Result<(), Error> {
+    let p = current_process().ok_or(InternalError)?;
+    Ok(crate::lib::chardev::close(p.pid(), id)?)
+  }
+
+  // Note: `pid` 0 is the caller, otherwise a child of it
+  //       `group` 0 makes `pid` lead a new group, any other must be the group of the caller
+  fn process_set_group(pid: u16, group: u16) -> Result<(), Error> {
+    let current = current_process().ok_or(InternalError)?;
+    let p = lookup_pid(pid, true)?;
+    let group = if group == 0 { p.pid() } else { group };
+    if group != p.pid() && Some(group) != current.group() {
+      return Err(InvalidArgumentError);
+    }
+    p.set_group(Some(group));
+    Ok(())
+  }
+
+  // Note: the group getting Ctrl-C, 0 for the group of the caller
+  //       any other must hold a child of the caller
+  fn console_set_foreground(group: u16) -> Result<(), Error> {
+    let current = current_process().ok_or(InternalError)?;
+    let group = if group == 0 { current.group().ok_or(InvalidArgumentError)? } else { group };
+    let member = crate::lib::process::list().into_iter().any(|p| {
+      p.group() == Some(group) && !p.is_zombie() && (p == current || p.parent().as_ref() == Some(&current))
+    });
+    if !member {
+      return Err(InvalidArgumentError);
+    }
+    crate::lib::signal::set_foreground(group);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn page_pool_error() {
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::OutOfFrameError), OutOfMemoryError));
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::UnmanagedFrameError), InternalError));
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::FreeUnallocatedFrameError), InternalError));
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::FreeReferencedFrameError), InternalError));
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::RefCountOverflowError), InternalError));
+    assert!(matches!(Error::from(crate::mm::page_pool::Error::RefCountUnderflowError), InternalError));
+  }
+
+  #[test]
+  fn page_table_error() {
+    use crate::lib::page_table::Error::*;
+    assert!(matches!(Error::from(AddressNotMappedError), InternalError));
+    assert!(matches!(Error::from(FrameError(crate::mm::page_pool::Error::OutOfFrameError)), OutOfMemoryError));
+    assert!(matches!(Error::from(FrameError(crate::mm::page_pool::Error::RefCountOverflowError)), InternalError));
+  }
+
+  #[test]
+  fn uaccess_error() {
+    assert!(matches!(Error::from(crate::lib::uaccess::Error::AddressLimitError), MemoryLimitError));
+    assert!(matches!(Error::from(crate::lib::uaccess::Error::AddressNotMappedError), MemoryNotMappedError));
+    assert!(matches!(Error::from(crate::lib::uaccess::Error::PermissionDeniedError), MemoryNotMappedError));
+    assert!(matches!(Error::from(crate::lib::uaccess::Error::OutOfMemoryError), OutOfMemoryError));
+  }
+
+  #[test]
+  fn net_error() {
+    use crate::lib::net::Error as E;
+    assert!(matches!(Error::from(E::NoDeviceError), DeviceNotFoundError));
+    assert!(matches!(Error::from(E::InvalidStateError), NetSocketError));
+    assert!(matches!(Error::from(E::WouldBlockError), WouldBlockError));
+    assert!(matches!(Error::from(E::ConnectionResetError), NetConnectionError));
+    // Note: arguments are checked before the caller is looked up
+    assert!(matches!(SystemCall::net_socket(2), Err(InvalidArgumentError)));
+    assert!(matches!(SystemCall::net_bind(0, 0x1_0000), Err(InvalidArgumentError)));
+  }
+
+  #[test]
+  fn chardev_error() {
+    use crate::lib::random::{GRND_INSECURE, GRND_RANDOM};
+    assert!(matches!(Error::from(crate::lib::chardev::Error::NotFoundError), DeviceNotFoundError));
+    assert!(matches!(Error::from(crate::lib::chardev::Error::NotOpenError), InvalidArgumentError));
+    assert!(matches!(Error::from(crate::lib::chardev::Error::WouldBlockError), WouldBlockError));
+    assert!(matches!(Error::from(crate::lib::random::Error::NotSeededError), WouldBlockError));
+    assert!(matches!(Error::from(crate::lib::framebuffer::Error::BusyError), DeviceBusyError));
+    // Note: arguments are checked before the caller is looked up
+    assert!(matches!(SystemCall::getrandom(0, 0, 8), Err(InvalidArgumentError)));
+    assert!(matches!(SystemCall::getrandom(0, 0, GRND_RANDOM | GRND_INSECURE), Err(InvalidArgumentError)));
+    assert!(matches!(SystemCall::chardev_open(0, CHARDEV_NAME_LIMIT + 1), Err(InvalidArgumentError)));
+  }
+
+  #[test]
+  fn error_number() {
+    // Note: user space sees `-(Error as isize)`, numbers must not move
+    assert_eq!(InvalidArgumentError as isize, 1);
+    assert_eq!(OutOfMemoryError as isize, 3);
+    assert_eq!(MemoryLimitError as isize, 7);
+    assert_eq!(ProcessNoChildError as isize, 14);
+    assert_eq!(WouldBlockError as isize, 19);
+    assert_eq!(InterruptedError as isize, 20);
+    assert_eq!(DeviceBusyError as isize, 21);
+  }
+
+  #[test]
+  fn memory_argument_limit() {
+    assert!(matches!(SystemCall::mem_alloc(0, CONFIG_USER_LIMIT, 0), Err(MemoryLimitError)));
+    assert!(matches!(SystemCall::mem_map(0, CONFIG_USER_LIMIT, 0, 0, 0), Err(MemoryLimitError)));
+    assert!(matches!(SystemCall::mem_map(0, 0, 0, CONFIG_USER_LIMIT + PAGE_SIZE, 0), Err(MemoryLimitError)));
+    assert!(matches!(SystemCall::mem_unmap(0, usize::MAX), Err(MemoryLimitError)));
+    // Note: the user stack guard stays unmapped
+    let guard = crate::lib::process::USER_STACK_BTM - PAGE_SIZE;
+    assert!(matches!(SystemCall::mem_alloc(0, guard, 0), Err(MemoryLimitError)));
+    assert!(matches!(SystemCall::mem_map(0, 0, 0, guard, 0), Err(MemoryLimitError)));
+  }
+
+  #[test]
+  fn pid_lookup() {
+    crate::mm::test_init();
+    // Note: no thread is running on the host
+    assert!(matches!(lookup_pid(0, false), Err(InternalError)));
+    assert!(matches!(lookup_pid(Pid::MAX, false), Err(ProcessPidNotFoundError)));
+    // Note: pid 0 stands for the caller, the process holding it cannot be looked up
+    let a = crate::lib::process::alloc(None);
+    let b = crate::lib::process::alloc(None);
+    let p = if a.pid() != 0 { a.clone() } else { b.clone() };
+    assert!(lookup_pid(p.pid(), false).ok() == Some(p.clone()));
+    assert!(matches!(lookup_pid(p.pid(), true), Err(ProcessParentNotFoundError)));
+    assert!(matches!(SystemCall::process_destroy(p.pid()), Err(ProcessParentNotFoundError)));
+    crate::lib::process::free(&a);
+    crate::lib::process::free(&b);
+    assert!(matches!(lookup_pid(p.pid(), false), Err(ProcessPidNotFoundError)));
+  }
+}
@@ -1,14 +1,17 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use spin::{Mutex, MutexGuard};
 
-use crate::arch::{AddressSpaceId, ArchTrait, ContextFrame, ContextFrameTrait, CoreTrait};
+use crate::arch::{AddressSpaceId, Arch, ArchTrait, ContextFrame, ContextFrameTrait, CoreTrait};
 use crate::lib::bitmap::BitMap;
 use crate::lib::current_thread;
 use crate::lib::page_table::PageTableTrait;
 use crate::lib::process::Process;
+use crate::mm::kernel_stack::KernelStack;
 
 pub type Tid = u16;
 
@@ -18,18 +21,30 @@ pub enum Type {
   Kernel,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Status {
   TsRunnable = 1,
   TsNotRunnable = 2,
+  TsWaitForReceive = 3,
+  TsWaitForReply = 4,
+  TsWaitForChild = 5,
+  TsSleeping = 6,
 }
 
+// Note: the trap frame (user context) sits at the top of the kernel stack
+//       a kernel thread's frame only holds its entry until it first runs
 #[derive(Debug)]
 pub struct ControlBlock {
   tid: u16,
   t: Type,
   status: Mutex<Status>,
-  context: Mutex<ContextFrame>,
+  stack: KernelStack,
+  context: Mutex<()>,
+  // Note: written by `switch_context` while the thread is switched out
+  kernel_sp: AtomicUsize,
+  started: AtomicBool,
+  exiting: AtomicBool,
+  ipc: Mutex<crate::lib::ipc::ThreadState>,
 }
 
 pub enum Error {
@@ -39,9 +54,29 @@ pub enum Error {
 #[derive(Debug, Clone)]
 pub struct Thread(Arc<ControlBlock>);
 
+// Note: a destroyed thread may still be unwinding while its tid is reused
 impl PartialEq for Thread {
   fn eq(&self, other: &Self) -> bool {
-    self.0.tid == other.0.tid
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+pub struct ContextGuard<'a> {
+  _lock: MutexGuard<'a, ()>,
+  frame: *mut ContextFrame,
+}
+
+impl<'a> core::ops::Deref for ContextGuard<'a> {
+  type Target = ContextFrame;
+
+  fn deref(&self) -> &ContextFrame {
+    unsafe { &*self.frame }
+  }
+}
+
+impl<'a> core::ops::DerefMut for ContextGuard<'a> {
+  fn deref_mut(&mut self) -> &mut ContextFrame {
+    unsafe { &mut *self.frame }
   }
 }
 
@@ -56,6 +91,29 @@ impl Thread {
     drop(lock);
   }
 
+  pub fn status(&self) -> Status {
+    let lock = self.0.status.lock();
+    let r = *lock;
+    drop(lock);
+    r
+  }
+
+  // Note: make runnable only if still blocked in `from`, `false` otherwise
+  pub fn wake(&self, from: Status) -> bool {
+    self.transit(from, Status::TsRunnable)
+  }
+
+  // Note: set `to` only if the status is still `from`, `false` otherwise
+  pub fn transit(&self, from: Status, to: Status) -> bool {
+    let mut lock = self.0.status.lock();
+    let r = *lock == from;
+    if r {
+      *lock = to;
+    }
+    drop(lock);
+    r
+  }
+
   pub fn runnable(&self) -> bool {
     let lock = self.0.status.lock();
     let r = *lock == Status::TsRunnable;
@@ -74,47 +132,107 @@ impl Thread {
     }
   }
 
-  pub fn context(&self) -> MutexGuard<ContextFrame> {
-    self.0.context.lock()
+  pub fn context(&self) -> ContextGuard {
+    ContextGuard {
+      _lock: self.0.context.lock(),
+      frame: (self.0.stack.top() - size_of::<ContextFrame>()) as *mut ContextFrame,
+    }
+  }
+
+  pub fn exiting(&self) -> bool {
+    self.0.exiting.load(Ordering::Acquire)
+  }
+
+  pub fn ipc_receive_va(&self) -> Option<usize> {
+    let lock = self.0.ipc.lock();
+    let r = lock.receive_va();
+    drop(lock);
+    r
+  }
+
+  pub fn set_ipc_receive_va(&self, va: Option<usize>) {
+    let mut lock = self.0.ipc.lock();
+    lock.set_receive_va(va);
+    drop(lock);
+  }
+
+  pub fn take_ipc_reply_to(&self) -> Option<Thread> {
+    let mut lock = self.0.ipc.lock();
+    let r = lock.take_reply_to();
+    drop(lock);
+    r
+  }
+
+  pub fn set_ipc_reply_to(&self, t: Option<Thread>) {
+    let mut lock = self.0.ipc.lock();
+    lock.set_reply_to(t);
+    drop(lock);
+  }
+
+  pub fn forget_ipc_reply_to(&self, client: &Thread) {
+    let mut lock = self.0.ipc.lock();
+    lock.forget_reply_to(client);
+    drop(lock);
   }
 
-  pub fn run(&self) {
-    println!("run thread {}", self.tid());
+  // Note: switch to this thread on the current core, returns once the caller is switched back
+  //       no lock may be held, the caller's stack is parked in here meanwhile
+  pub fn run(self) {
     let core = crate::lib::core::current();
-    if let Some(t) = current_thread() {
-      // Note: normal switch
-      let mut old = t.context();
-      *old = *core.context();
-      drop(old);
-      let new = self.context();
-      *core.context_mut() = *new;
-      drop(new);
-    } else {
-      if core.has_context() {
-        // Note: previous process has been destroyed
-        let new = self.context();
-        *core.context_mut() = *new;
-        drop(new);
-      } else {
-        // Note: this is first run
-        // `main` prepare the context to stack
-      }
+    let prev = current_thread();
+    if prev.as_ref() == Some(&self) {
+      return;
     }
+    // Note: an exiting thread is only referenced by the core, it must unwind before switching
+    if prev.as_ref().map_or(false, |t| t.exiting()) {
+      return;
+    }
+    trace!("run thread {}", self.tid());
+    crate::lib::trace::context_switch(self.tid());
     core.set_running_thread(Some(self.clone()));
     if let Some(p) = self.process() {
-      println!("run process {}", self.process().unwrap().pid());
+      debug!("run process {}", p.pid());
+      // Note: a zombie's directory is still held by its threads, see `process::ControlBlock::drop`
       crate::arch::PageTable::set_user_page_table(p.page_table(), p.pid() as AddressSpaceId);
+      core.set_address_space(p);
     }
     crate::arch::Arch::invalidate_tlb();
+    // Note: the core context points into the kernel stack switched out, restored when back
+    let ctx = if core.has_context() { Some(core.context_mut() as *mut ContextFrame) } else { None };
+    // Note: no previous thread (boot, or destroyed by itself), its stack is abandoned
+    let mut abandoned = 0usize;
+    let from = match &prev {
+      Some(t) => { &t.0.kernel_sp as *const AtomicUsize as *mut usize }
+      None => { &mut abandoned as *mut usize }
+    };
+    let to = self.0.kernel_sp.load(Ordering::Acquire);
+    // Note: references left on a parked stack keep threads alive, drop them first
+    drop(prev);
+    drop(self);
+    Arch::switch_context(from, to);
+    // Note: may be resumed on another core
+    let core = crate::lib::core::current();
+    core.reap();
+    match ctx {
+      Some(ctx) => { core.set_context(ctx) }
+      None => { core.clear_context() }
+    }
   }
 
+  // Note: the current thread is destroyed at once, its kernel stack is freed after switching away
+  //       a thread parked in the kernel is resumed to unwind first, see `scheduler::leave_trap`
   pub fn destroy(&self) {
-    if let Some(t) = current_thread() {
-      if self.0.tid == t.tid() {
-        crate::lib::core::current().set_running_thread(None);
-      }
+    crate::lib::ipc::release_thread(self);
+    self.set_ipc_reply_to(None);
+    free(self);
+    if current_thread().as_ref() == Some(self) {
+      retire_current();
+    } else if self.0.started.load(Ordering::Acquire) {
+      self.0.exiting.store(true, Ordering::Release);
+      let mut exiting = EXITING.lock();
+      exiting.push(self.clone());
+      drop(exiting);
     }
-    free(self)
   }
 }
 
@@ -123,29 +241,42 @@ struct ThreadPool {
   alloced: Vec<Thread>,
 }
 
+fn kernel_stack() -> KernelStack {
+  match KernelStack::new() {
+    Ok(stack) => { stack }
+    Err(e) => { panic!("thread_pool: kernel stack: {:?}", e) }
+  }
+}
+
 impl ThreadPool {
   fn alloc_user(&mut self, pc: usize, sp: usize, arg: usize, p: Process) -> Thread {
-    let id = self.bitmap.alloc() as Tid;
-    let arc = Arc::new(ControlBlock {
-      tid: id,
-      t: Type::User(p),
-      status: Mutex::new(Status::TsNotRunnable),
-      context: Mutex::new(ContextFrame::new(pc, sp, arg, false)),
-    });
-    let mut map = THREAD_MAP.lock();
-    map.insert(id, arc.clone());
-    drop(map);
-    self.alloced.push(Thread(arc.clone()));
-    Thread(arc)
+    let stack = kernel_stack();
+    self.alloc(Type::User(p), stack, ContextFrame::new(pc, sp, arg, false))
   }
 
-  fn alloc_kernel(&mut self, pc: usize, sp: usize, arg: usize) -> Thread {
+  // Note: a kernel thread runs on its kernel stack, from the top
+  fn alloc_kernel(&mut self, pc: usize, arg: usize) -> Thread {
+    let stack = kernel_stack();
+    let ctx = ContextFrame::new(pc, stack.top(), arg, true);
+    self.alloc(Type::Kernel, stack, ctx)
+  }
+
+  fn alloc(&mut self, t: Type, stack: KernelStack, ctx: ContextFrame) -> Thread {
     let id = self.bitmap.alloc() as Tid;
+    unsafe {
+      core::ptr::write((stack.top() - size_of::<ContextFrame>()) as *mut ContextFrame, ctx);
+    }
+    let sp = Arch::kernel_stack_init(stack.top());
     let arc = Arc::new(ControlBlock {
       tid: id,
-      t: Type::Kernel,
+      t,
       status: Mutex::new(Status::TsNotRunnable),
-      context: Mutex::new(ContextFrame::new(pc, sp, arg, true)),
+      stack,
+      context: Mutex::new(()),
+      kernel_sp: AtomicUsize::new(sp),
+      started: AtomicBool::new(false),
+      exiting: AtomicBool::new(false),
+      ipc: Mutex::new(crate::lib::ipc::ThreadState::new()),
     });
     let mut map = THREAD_MAP.lock();
     map.insert(id, arc.clone());
@@ -181,6 +312,9 @@ static THREAD_POOL: Mutex<ThreadPool> = Mutex::new(ThreadPool {
   alloced: Vec::new(),
 });
 
+// Note: destroyed threads parked in the kernel, to be resumed to unwind
+static EXITING: Mutex<Vec<Thread>> = Mutex::new(Vec::new());
+
 pub fn alloc_user(pc: usize, sp: usize, arg: usize, p: Process) -> Thread {
   let mut pool = THREAD_POOL.lock();
   let r = pool.alloc_user(pc, sp, arg, p);
@@ -188,9 +322,10 @@ pub fn alloc_user(pc: usize, sp: usize, arg: usize, p: Process) -> Thread {
   r
 }
 
-pub fn alloc_kernel(pc: usize, sp: usize, arg: usize) -> Thread {
+// Note: `pc` must not return
+pub fn alloc_kernel(pc: usize, arg: usize) -> Thread {
   let mut pool = THREAD_POOL.lock();
-  let r = pool.alloc_kernel(pc, sp, arg);
+  let r = pool.alloc_kernel(pc, arg);
   drop(pool);
   r
 }
@@ -199,7 +334,7 @@ pub fn free(t: &Thread) {
   let mut pool = THREAD_POOL.lock();
   match pool.free(t) {
     Ok(_) => {}
-    Err(_) => { println!("thread_pool: free: thread not found") }
+    Err(_) => { error!("thread_pool: free: thread not found") }
   }
   drop(pool);
 }
@@ -211,6 +346,34 @@ pub fn list() -> Vec<Thread> {
   r
 }
 
+pub fn take_exiting() -> Option<Thread> {
+  let mut exiting = EXITING.lock();
+  let r = exiting.pop();
+  drop(exiting);
+  r
+}
+
+// Note: stop running the current thread, the core keeps it until switched away
+pub fn retire_current() {
+  let core = crate::lib::core::current();
+  if let Some(t) = core.running_thread() {
+    core.set_running_thread(None);
+    core.retire(t);
+  }
+}
+
+// Note: a new thread lands here on its first switch, see `thread_entry` in `arch/*/exception.S`
+//       its trap frame is popped on return
+#[no_mangle]
+extern "C" fn thread_start() {
+  let core = crate::lib::core::current();
+  core.reap();
+  core.clear_context();
+  if let Some(t) = core.running_thread() {
+    t.0.started.store(true, Ordering::Release);
+  }
+}
+
 #[allow(dead_code)]
 pub fn lookup(tid: Tid) -> Option<Thread> {
   let map = THREAD_MAP.lock();
@@ -221,3 +384,60 @@ pub fn lookup(tid: Tid) -> Option<Thread> {
   drop(map);
   r
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn kernel_thread_lifecycle() {
+    crate::mm::test_init();
+    let t = alloc_kernel(0x1000, 7);
+    assert_eq!(lookup(t.tid()), Some(t.clone()));
+    assert!(list().contains(&t));
+    assert!(t.process().is_none());
+    assert!(!t.runnable());
+    let ctx = *t.context();
+    assert_eq!(ctx.exception_pc(), 0x1000);
+    assert_eq!(ctx.stack_pointer(), t.0.stack.top());
+    assert_eq!(ctx.syscall_argument(0), 7);
+    t.set_status(Status::TsRunnable);
+    assert!(t.runnable());
+    assert!(t.transit(Status::TsRunnable, Status::TsNotRunnable));
+    assert!(!t.transit(Status::TsWaitForReceive, Status::TsRunnable));
+    assert_eq!(t.status(), Status::TsNotRunnable);
+    t.destroy();
+    assert!(lookup(t.tid()).is_none());
+    assert!(!list().contains(&t));
+  }
+
+  #[test]
+  fn user_thread_lifecycle() {
+    crate::mm::test_init();
+    let p = crate::lib::process::alloc(None);
+    let t = alloc_user(0x4000, 0x8000, 0, p.clone());
+    assert_eq!(t.process(), Some(p.clone()));
+    assert_eq!(lookup(t.tid()), Some(t.clone()));
+    assert_eq!(t.context().stack_pointer(), 0x8000);
+    t.destroy();
+    assert!(lookup(t.tid()).is_none());
+    crate::lib::process::free(&p);
+  }
+
+  #[test]
+  fn kernel_stack_per_thread() {
+    crate::mm::test_init();
+    let a = alloc_kernel(0x1000, 0);
+    let b = alloc_kernel(0x1000, 1);
+    let (a_top, b_top) = (a.0.stack.top(), b.0.stack.top());
+    assert!(a_top <= b.0.stack.bottom() || b_top <= a.0.stack.bottom());
+    // Note: the context lives on the stack of its thread
+    a.context().set_argument(3);
+    assert_eq!(a.context().syscall_argument(0), 3);
+    assert_eq!(b.context().syscall_argument(0), 1);
+    // Note: never ran, nothing to unwind
+    a.destroy();
+    assert!(!a.exiting());
+    b.destroy();
+  }
+}
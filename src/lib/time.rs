@@ -0,0 +1,166 @@
+// Note: the clocksource is the free running counter of the timer driver
+//       `CNTPCT_EL0` / `CNTFRQ_EL0` on aarch64, `time` CSR and device tree `timebase-frequency` on riscv64
+//       kernel time is in nanoseconds since the counter started
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// Note: clock ids as `clock_gettime` takes them (same numbers as POSIX)
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+// Note: wall clock = monotonic clock + this offset, taken from the RTC at boot
+static WALL_CLOCK_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Timeval {
+  pub sec: u64,
+  pub usec: u64,
+}
+
+impl Timeval {
+  pub fn from_ns(ns: u64) -> Self {
+    Timeval {
+      sec: ns / NANOS_PER_SEC,
+      usec: ns % NANOS_PER_SEC / 1000,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Timespec {
+  pub sec: u64,
+  pub nsec: u64,
+}
+
+impl Timespec {
+  pub fn from_ns(ns: u64) -> Self {
+    Timespec {
+      sec: ns / NANOS_PER_SEC,
+      nsec: ns % NANOS_PER_SEC,
+    }
+  }
+
+  // Note: `None` if `nsec` is out of range or the value does not fit
+  pub fn to_ns(&self) -> Option<u64> {
+    if self.nsec >= NANOS_PER_SEC {
+      return None;
+    }
+    self.sec.checked_mul(NANOS_PER_SEC).and_then(|ns| ns.checked_add(self.nsec))
+  }
+}
+
+pub fn counter_to_ns(count: u64) -> u64 {
+  (count as u128 * NANOS_PER_SEC as u128 / crate::driver::timer::frequency() as u128) as u64
+}
+
+pub fn ns_to_counter(ns: u64) -> u64 {
+  (ns as u128 * crate::driver::timer::frequency() as u128 / NANOS_PER_SEC as u128) as u64
+}
+
+// Note: monotonic
+pub fn now() -> u64 {
+  counter_to_ns(crate::driver::timer::counter())
+}
+
+// Note: nanoseconds since the Unix epoch
+pub fn wall_clock() -> u64 {
+  now().saturating_add(WALL_CLOCK_OFFSET.load(Ordering::Relaxed))
+}
+
+// Note: proleptic Gregorian calendar, UTC
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DateTime {
+  pub year: u32,
+  pub month: u32,
+  pub day: u32,
+  pub hour: u32,
+  pub minute: u32,
+  pub second: u32,
+}
+
+impl DateTime {
+  // Note: days to civil from http://howardhinnant.github.io/date_algorithms.html
+  pub fn from_unix(sec: u64) -> Self {
+    let days = sec / 86400;
+    let rem = sec % 86400;
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    DateTime {
+      year: year as u32,
+      month: month as u32,
+      day: day as u32,
+      hour: (rem / 3600) as u32,
+      minute: (rem % 3600 / 60) as u32,
+      second: (rem % 60) as u32,
+    }
+  }
+}
+
+impl core::fmt::Display for DateTime {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+    write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", self.year, self.month, self.day, self.hour, self.minute, self.second)
+  }
+}
+
+// Note: the RTC is read once, the counter keeps time afterwards
+pub fn init() {
+  match crate::driver::rtc::read() {
+    Some(rtc) => {
+      WALL_CLOCK_OFFSET.store(rtc.saturating_sub(now()), Ordering::Relaxed);
+      info!("wall clock: {}", DateTime::from_unix(wall_clock() / NANOS_PER_SEC));
+    }
+    None => {
+      warn!("wall clock: no RTC, counting from the epoch");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn timespec() {
+    let t = Timespec::from_ns(3 * NANOS_PER_SEC + 7);
+    assert_eq!(t, Timespec { sec: 3, nsec: 7 });
+    assert_eq!(t.to_ns(), Some(3 * NANOS_PER_SEC + 7));
+    assert_eq!(Timespec { sec: 0, nsec: NANOS_PER_SEC }.to_ns(), None);
+    assert_eq!(Timespec { sec: u64::MAX, nsec: 0 }.to_ns(), None);
+  }
+
+  #[test]
+  fn date_time() {
+    assert_eq!(DateTime::from_unix(0), DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 });
+    // Note: leap day
+    assert_eq!(DateTime::from_unix(951_782_400 + 3661), DateTime { year: 2000, month: 2, day: 29, hour: 1, minute: 1, second: 1 });
+    assert_eq!(DateTime::from_unix(1_704_067_199), DateTime { year: 2023, month: 12, day: 31, hour: 23, minute: 59, second: 59 });
+    assert_eq!(Timeval::from_ns(2 * NANOS_PER_SEC + 1_500), Timeval { sec: 2, usec: 1 });
+  }
+
+  #[test]
+  fn wall() {
+    init();
+    // Note: the host RTC is the system clock, well after 2020
+    assert!(DateTime::from_unix(wall_clock() / NANOS_PER_SEC).year >= 2020);
+  }
+
+  #[test]
+  fn monotonic() {
+    let a = now();
+    let b = now();
+    assert!(b >= a);
+    // Note: the host clocksource counts nanoseconds
+    assert_eq!(ns_to_counter(counter_to_ns(12345)), 12345);
+  }
+}
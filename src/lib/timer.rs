@@ -0,0 +1,184 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait};
+use crate::board::BOARD_CORE_NUMBER;
+use crate::config::{CONFIG_IDLE_POLL_INTERVAL, CONFIG_TIME_SLICE};
+use crate::lib::thread::{Status, Thread};
+
+// Note: kernel timeouts ordered by deadline (nanoseconds, see `lib/time.rs`)
+//       callbacks run in interrupt context with the queue unlocked, they must not block
+
+pub type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TimerId {
+  deadline: u64,
+  sequence: u64,
+}
+
+impl TimerId {
+  #[allow(dead_code)]
+  pub fn deadline(&self) -> u64 {
+    self.deadline
+  }
+}
+
+struct TimerQueue {
+  sequence: u64,
+  timers: BTreeMap<TimerId, Callback>,
+}
+
+lazy_static! {
+  static ref QUEUE: Mutex<TimerQueue> = Mutex::new(TimerQueue {
+    sequence: 0,
+    timers: BTreeMap::new(),
+  });
+}
+
+const STOPPED: AtomicU64 = AtomicU64::new(u64::MAX);
+// Note: deadline each core's one shot timer is armed with, `u64::MAX` if stopped
+static ARMED: [AtomicU64; BOARD_CORE_NUMBER] = [STOPPED; BOARD_CORE_NUMBER];
+
+fn arm_at(deadline: u64) {
+  ARMED[Arch::core_id()].store(deadline, Ordering::Relaxed);
+  if deadline == u64::MAX {
+    crate::driver::timer::set_deadline(None);
+  } else {
+    crate::driver::timer::set_deadline(Some(crate::lib::time::ns_to_counter(deadline)));
+  }
+}
+
+// Note: called by the scheduler on every pick, no periodic tick
+//       a busy core is interrupted at the end of its time slice, an idle one only for timers
+pub fn arm(busy: bool) {
+  let now = crate::lib::time::now();
+  let limit = now.saturating_add(if busy { CONFIG_TIME_SLICE } else { CONFIG_IDLE_POLL_INTERVAL });
+  let deadline = match next_deadline() {
+    Some(deadline) if deadline < limit => { deadline }
+    _ => { limit }
+  };
+  arm_at(deadline);
+}
+
+// Note: the timer is pulled in if the new deadline comes first
+pub fn add(deadline: u64, callback: Callback) -> TimerId {
+  let mut queue = QUEUE.lock();
+  queue.sequence += 1;
+  let id = TimerId { deadline, sequence: queue.sequence };
+  queue.timers.insert(id, callback);
+  drop(queue);
+  if deadline < ARMED[Arch::core_id()].load(Ordering::Relaxed) {
+    arm_at(deadline);
+  }
+  id
+}
+
+// Note: `false` if the timer has fired already (or is firing)
+pub fn cancel(id: TimerId) -> bool {
+  let mut queue = QUEUE.lock();
+  let callback = queue.timers.remove(&id);
+  drop(queue);
+  callback.is_some()
+}
+
+pub fn next_deadline() -> Option<u64> {
+  let queue = QUEUE.lock();
+  let r = queue.timers.keys().next().map(|id| id.deadline);
+  drop(queue);
+  r
+}
+
+// Note: run every callback due at `now`, return how many ran
+pub fn expire(now: u64) -> usize {
+  let mut n = 0;
+  loop {
+    let mut queue = QUEUE.lock();
+    let due = match queue.timers.keys().next() {
+      Some(id) if id.deadline <= now => { Some(*id) }
+      _ => { None }
+    };
+    let callback = due.and_then(|id| queue.timers.remove(&id));
+    drop(queue);
+    match callback {
+      None => { return n; }
+      Some(callback) => {
+        callback();
+        n += 1;
+      }
+    }
+  }
+}
+
+// Note: timer interrupt, fire due timers, the scheduler rearms the timer right after
+pub fn interrupt() {
+  expire(crate::lib::time::now());
+}
+
+// Note: block `t`, the current thread, until `deadline` has passed, it is destroyed
+//       or a signal interrupts it, `false` if woken up early
+pub fn sleep_until(t: &Thread, deadline: u64) -> bool {
+  let state = Arch::interrupt_save_disable();
+  let interrupted = || t.process().map_or(false, |p| crate::lib::signal::interrupting(&p));
+  while crate::lib::time::now() < deadline && !t.exiting() && !interrupted() {
+    t.set_status(Status::TsSleeping);
+    let sleeper = t.clone();
+    let id = add(deadline, Box::new(move || { sleeper.wake(Status::TsSleeping); }));
+    crate::lib::scheduler::schedule();
+    // Note: woken up by someone else, the timer is still queued
+    cancel(id);
+  }
+  Arch::interrupt_restore(state);
+  crate::lib::time::now() >= deadline
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::sync::Arc;
+  use core::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+
+  // Note: the queue is global and host tests run in parallel, use deadlines no other test does
+  #[test]
+  fn expire_in_order() {
+    let base = u64::MAX / 2;
+    let fired = Arc::new(AtomicUsize::new(0));
+    let order = |expect: usize| -> Callback {
+      let fired = fired.clone();
+      Box::new(move || { assert_eq!(fired.fetch_add(1, Ordering::SeqCst), expect); })
+    };
+    let late = add(base + 20, order(1));
+    let early = add(base + 10, order(0));
+    // Note: the earliest timer pulls the one shot timer in
+    assert!(ARMED[0].load(Ordering::SeqCst) <= base + 10);
+    let cancelled = add(base + 15, order(usize::MAX));
+    assert_eq!(late.deadline(), base + 20);
+    assert!(early < late);
+    assert!(cancel(cancelled));
+    assert!(!cancel(cancelled));
+    assert_eq!(expire(base + 10), 1);
+    assert_eq!(fired.load(Ordering::SeqCst), 1);
+    assert!(!cancel(early));
+    assert_eq!(expire(base + 30), 1);
+    assert_eq!(fired.load(Ordering::SeqCst), 2);
+    let now = crate::lib::time::now();
+    arm(true);
+    assert!(ARMED[0].load(Ordering::SeqCst) >= now + CONFIG_TIME_SLICE);
+  }
+
+  #[test]
+  fn sleep_past_deadline() {
+    crate::mm::test_init();
+    let t = crate::lib::thread::alloc_kernel(0x1000, 0);
+    // Note: nothing to wait for, the thread is not parked
+    sleep_until(&t, 0);
+    assert!(!t.runnable());
+    assert!(!t.wake(Status::TsSleeping));
+    t.destroy();
+  }
+}
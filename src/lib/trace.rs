@@ -0,0 +1,210 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait};
+use crate::board::BOARD_CORE_NUMBER;
+use crate::config::{CONFIG_TRACE_BUFFER_LENGTH, CONFIG_TRACE_MASK};
+use crate::lib::log::Console;
+use crate::lib::thread::Tid;
+
+// Note: bits of the enable mask
+pub const TRACE_SYSCALL: usize = 1 << 0;
+pub const TRACE_CONTEXT_SWITCH: usize = 1 << 1;
+pub const TRACE_PAGE_FAULT: usize = 1 << 2;
+pub const TRACE_IRQ: usize = 1 << 3;
+
+// Note: `format` of `dump`
+pub const TRACE_FORMAT_BINARY: usize = 0;
+pub const TRACE_FORMAT_JSON: usize = 1;
+
+// Note: tid of events without a running thread
+const NO_TID: u16 = 0xffff;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u16)]
+enum Kind {
+  SyscallEnter = 1,
+  SyscallExit = 2,
+  ContextSwitch = 3,
+  PageFault = 4,
+  Irq = 5,
+}
+
+// Note: binary dump record, 32 bytes little endian
+//       SyscallEnter  a: number
+//       SyscallExit   a: number     b: return value
+//       ContextSwitch a: next tid   (tid is the previous one)
+//       PageFault     a: address
+//       Irq           (the source is not told apart, every interrupt taken)
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Event {
+  timestamp: u64,
+  kind: u16,
+  tid: u16,
+  _reserved: u32,
+  a: u64,
+  b: u64,
+}
+
+impl Event {
+  const fn empty() -> Self {
+    Event {
+      timestamp: 0,
+      kind: 0,
+      tid: NO_TID,
+      _reserved: 0,
+      a: 0,
+      b: 0,
+    }
+  }
+
+  fn to_bytes(&self) -> [u8; 32] {
+    let mut r = [0u8; 32];
+    r[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+    r[8..10].copy_from_slice(&self.kind.to_le_bytes());
+    r[10..12].copy_from_slice(&self.tid.to_le_bytes());
+    r[16..24].copy_from_slice(&self.a.to_le_bytes());
+    r[24..32].copy_from_slice(&self.b.to_le_bytes());
+    r
+  }
+}
+
+// Note: per core ring, oldest events are overwritten
+struct TraceBuffer {
+  events: [Event; CONFIG_TRACE_BUFFER_LENGTH],
+  recorded: usize,
+}
+
+impl TraceBuffer {
+  const fn new() -> Self {
+    TraceBuffer {
+      events: [Event::empty(); CONFIG_TRACE_BUFFER_LENGTH],
+      recorded: 0,
+    }
+  }
+
+  fn push(&mut self, event: Event) {
+    self.events[self.recorded % CONFIG_TRACE_BUFFER_LENGTH] = event;
+    self.recorded += 1;
+  }
+
+  fn for_each<F: FnMut(&Event)>(&self, mut f: F) {
+    let first = self.recorded.saturating_sub(CONFIG_TRACE_BUFFER_LENGTH);
+    for i in first..self.recorded {
+      f(&self.events[i % CONFIG_TRACE_BUFFER_LENGTH]);
+    }
+  }
+
+  fn len(&self) -> usize {
+    core::cmp::min(self.recorded, CONFIG_TRACE_BUFFER_LENGTH)
+  }
+}
+
+static MASK: AtomicUsize = AtomicUsize::new(CONFIG_TRACE_MASK);
+
+static BUFFERS: [Mutex<TraceBuffer>; BOARD_CORE_NUMBER] = [Mutex::new(TraceBuffer::new()); BOARD_CORE_NUMBER];
+
+fn record(kind: Kind, mask: usize, tid: Option<Tid>, a: u64, b: u64) {
+  if MASK.load(Ordering::Relaxed) & mask == 0 {
+    return;
+  }
+  let event = Event {
+    timestamp: crate::driver::timer::counter(),
+    kind: kind as u16,
+    tid: tid.unwrap_or(NO_TID),
+    _reserved: 0,
+    a,
+    b,
+  };
+  // Note: events are also recorded in irq context
+  let irq = Arch::interrupt_save_disable();
+  let mut buffer = BUFFERS[Arch::core_id()].lock();
+  buffer.push(event);
+  drop(buffer);
+  Arch::interrupt_restore(irq);
+}
+
+fn current_tid() -> Option<Tid> {
+  crate::lib::current_thread().map(|t| t.tid())
+}
+
+pub fn syscall_enter(number: usize) {
+  record(Kind::SyscallEnter, TRACE_SYSCALL, current_tid(), number as u64, 0);
+}
+
+// Note: `tid` is the caller, which may not be running anymore
+pub fn syscall_exit(tid: Option<Tid>, number: usize, result: isize) {
+  record(Kind::SyscallExit, TRACE_SYSCALL, tid, number as u64, result as u64);
+}
+
+pub fn context_switch(next: Tid) {
+  record(Kind::ContextSwitch, TRACE_CONTEXT_SWITCH, current_tid(), next as u64, 0);
+}
+
+pub fn page_fault(address: usize) {
+  record(Kind::PageFault, TRACE_PAGE_FAULT, current_tid(), address as u64, 0);
+}
+
+pub fn irq() {
+  record(Kind::Irq, TRACE_IRQ, current_tid(), 0, 0);
+}
+
+// Note: return the previous mask
+pub fn set_mask(mask: usize) -> usize {
+  MASK.swap(mask, Ordering::SeqCst)
+}
+
+// Note: binary layout
+//       header: "RPTRACE1", u32 core number, u32 event size, u64 counter frequency
+//       per core: u32 core id, u32 event count, events (oldest first)
+fn dump_binary(console: &mut Console) {
+  console.write_bytes(b"RPTRACE1");
+  console.write_bytes(&(BOARD_CORE_NUMBER as u32).to_le_bytes());
+  console.write_bytes(&32u32.to_le_bytes());
+  console.write_bytes(&crate::driver::timer::frequency().to_le_bytes());
+  for (core, buffer) in BUFFERS.iter().enumerate() {
+    let buffer = buffer.lock();
+    console.write_bytes(&(core as u32).to_le_bytes());
+    console.write_bytes(&(buffer.len() as u32).to_le_bytes());
+    buffer.for_each(|e| console.write_bytes(&e.to_bytes()));
+    drop(buffer);
+  }
+}
+
+// Note: Chrome trace event format (chrome://tracing, Perfetto)
+//       pid is the core, tid the kernel thread
+fn dump_json(console: &mut Console) {
+  let frequency = crate::driver::timer::frequency() as u128;
+  let mut first = true;
+  let _ = writeln!(console, "{{\"traceEvents\":[");
+  for (core, buffer) in BUFFERS.iter().enumerate() {
+    let buffer = buffer.lock();
+    buffer.for_each(|e| {
+      let ns = e.timestamp as u128 * 1_000_000_000 / frequency;
+      let tid = if e.tid == NO_TID { -1 } else { e.tid as i32 };
+      let _ = write!(console, "{}{{\"pid\":{},\"tid\":{},\"ts\":{}.{:03},", if first { "" } else { ",\n" }, core, tid, ns / 1000, ns % 1000);
+      first = false;
+      let _ = match e.kind {
+        k if k == Kind::SyscallEnter as u16 => { write!(console, "\"ph\":\"B\",\"name\":\"syscall {}\"}}", e.a) }
+        k if k == Kind::SyscallExit as u16 => { write!(console, "\"ph\":\"E\",\"name\":\"syscall {}\",\"args\":{{\"result\":{}}}}}", e.a, e.b as i64) }
+        k if k == Kind::ContextSwitch as u16 => { write!(console, "\"ph\":\"i\",\"s\":\"p\",\"name\":\"switch\",\"args\":{{\"next\":{}}}}}", e.a) }
+        k if k == Kind::PageFault as u16 => { write!(console, "\"ph\":\"i\",\"s\":\"t\",\"name\":\"page fault\",\"args\":{{\"address\":\"{:#x}\"}}}}", e.a) }
+        _ => { write!(console, "\"ph\":\"i\",\"s\":\"p\",\"name\":\"irq\"}}") }
+      };
+    });
+    drop(buffer);
+  }
+  let _ = writeln!(console, "\n]}}");
+}
+
+pub fn dump(format: usize) -> bool {
+  match format {
+    TRACE_FORMAT_BINARY => { crate::lib::log::console_exclusive(dump_binary); }
+    TRACE_FORMAT_JSON => { crate::lib::log::console_exclusive(dump_json); }
+    _ => { return false; }
+  }
+  true
+}
@@ -0,0 +1,124 @@
+use core::mem::size_of;
+
+use crate::arch::{PAGE_SIZE, PageTable};
+use crate::config::CONFIG_USER_LIMIT;
+use crate::lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::lib::round_down;
+use crate::mm::PageFrame;
+
+use self::Error::*;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+  AddressLimitError,
+  AddressNotMappedError,
+  PermissionDeniedError,
+  OutOfMemoryError,
+}
+
+// Note: resolve a user page for kernel write access
+//       copy on write pages are broken here (same as user space `fork` handler does)
+//       `force` ignores the writable bit (debugger writes into text)
+//       a read only frame mapped elsewhere too (e.g. text after `fork`) is copied first, the copy stays read only
+fn writable_frame(page_table: PageTable, va: usize, force: bool) -> Result<PageFrame, Error> {
+  let pte = page_table.lookup_page(va).ok_or(AddressNotMappedError)?;
+  let attr = pte.attribute();
+  let frame = PageFrame::new(pte.pa());
+  if attr.u_copy_on_write() {
+    let attr = EntryAttribute::new(true, true, false, false, attr.u_executable(), false, attr.u_shared());
+    private_copy(page_table, va, frame, attr)
+  } else if attr.writable() && attr.u_readable() {
+    Ok(frame)
+  } else if force && attr.u_readable() {
+    match crate::mm::page_pool::rc(frame) {
+      Ok(rc) if rc > 1 => {
+        let attr = EntryAttribute::new(false, true, false, false, attr.u_executable(), false, false);
+        private_copy(page_table, va, frame, attr)
+      }
+      // Note: only mapped here, or not from the page pool (device memory)
+      _ => { Ok(frame) }
+    }
+  } else {
+    Err(PermissionDeniedError)
+  }
+}
+
+fn private_copy(page_table: PageTable, va: usize, src: PageFrame, attr: EntryAttribute) -> Result<PageFrame, Error> {
+  let frame = crate::mm::page_pool::try_alloc_tagged(crate::mm::page_pool::FRAME_USER, crate::mm::page_pool::Owner::Unowned)
+    .map_err(|_| OutOfMemoryError)?;
+  frame.copy_from(&src);
+  page_table.insert_page(va, frame, attr).map_err(|_| AddressNotMappedError)?;
+  Ok(frame)
+}
+
+fn readable_frame(page_table: PageTable, va: usize) -> Result<PageFrame, Error> {
+  let pte = page_table.lookup_page(va).ok_or(AddressNotMappedError)?;
+  if pte.attribute().u_readable() {
+    Ok(PageFrame::new(pte.pa()))
+  } else {
+    Err(PermissionDeniedError)
+  }
+}
+
+fn check_range(va: usize, len: usize) -> Result<(), Error> {
+  match va.checked_add(len) {
+    Some(end) if end <= CONFIG_USER_LIMIT => Ok(()),
+    _ => Err(AddressLimitError),
+  }
+}
+
+pub fn copy_to_user(page_table: PageTable, va: usize, src: &[u8]) -> Result<(), Error> {
+  write(page_table, va, src, false)
+}
+
+// Note: write user memory regardless of page permission, for the debugger
+//       caller syncs the instruction cache when code is written
+pub fn poke_user(page_table: PageTable, va: usize, src: &[u8]) -> Result<(), Error> {
+  write(page_table, va, src, true)
+}
+
+fn write(page_table: PageTable, va: usize, src: &[u8], force: bool) -> Result<(), Error> {
+  check_range(va, src.len())?;
+  let mut copied = 0;
+  while copied < src.len() {
+    let addr = va + copied;
+    let page = round_down(addr, PAGE_SIZE);
+    let frame = writable_frame(page_table, page, force)?;
+    let offset = addr - page;
+    let n = core::cmp::min(PAGE_SIZE - offset, src.len() - copied);
+    unsafe {
+      core::intrinsics::volatile_copy_memory((frame.kva() + offset) as *mut u8, src[copied..].as_ptr(), n);
+    }
+    copied += n;
+  }
+  Ok(())
+}
+
+pub fn copy_from_user(page_table: PageTable, va: usize, dst: &mut [u8]) -> Result<(), Error> {
+  check_range(va, dst.len())?;
+  let mut copied = 0;
+  while copied < dst.len() {
+    let addr = va + copied;
+    let page = round_down(addr, PAGE_SIZE);
+    let frame = readable_frame(page_table, page)?;
+    let offset = addr - page;
+    let n = core::cmp::min(PAGE_SIZE - offset, dst.len() - copied);
+    unsafe {
+      core::intrinsics::volatile_copy_memory(dst[copied..].as_mut_ptr(), (frame.kva() + offset) as *const u8, n);
+    }
+    copied += n;
+  }
+  Ok(())
+}
+
+pub fn write_user<T: Copy>(page_table: PageTable, va: usize, value: &T) -> Result<(), Error> {
+  let src = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+  copy_to_user(page_table, va, src)
+}
+
+pub fn read_user<T: Copy>(page_table: PageTable, va: usize) -> Result<T, Error> {
+  let mut value = core::mem::MaybeUninit::<T>::uninit();
+  let dst = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+  copy_from_user(page_table, va, dst)?;
+  Ok(unsafe { value.assume_init() })
+}
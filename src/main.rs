@@ -1,5 +1,7 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+// Note: `cargo test` builds the kernel for the host, boot path is unused there
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 #![feature(global_asm)]
 #![feature(alloc_error_handler)]
 #![feature(panic_info_message)]
@@ -12,6 +14,7 @@
 extern crate alloc;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(not(test))]
 extern crate rlibc;
 
 use arch::*;
@@ -29,6 +32,55 @@ macro_rules! println {
     })
 }
 
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ($crate::lib::log::log($level, module_path!(), format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log!($crate::lib::log::Level::Error, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log!($crate::lib::log::Level::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log!($crate::lib::log::Level::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => ($crate::log!($crate::lib::log::Level::Debug, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => ($crate::log!($crate::lib::log::Level::Trace, $($arg)*));
+}
+
+// Note: register a kernel test, only built with feature `ktest`
+//       e.g. `ktest!(name { assert_eq!(1 + 1, 2); });`
+#[macro_export]
+macro_rules! ktest {
+    ($name:ident $body:block) => {
+        #[allow(non_upper_case_globals)]
+        #[used]
+        #[link_section = ".ktest"]
+        static $name: $crate::lib::ktest::KernelTest = $crate::lib::ktest::KernelTest {
+            name: stringify!($name),
+            module: module_path!(),
+            run: {
+                fn run() $body
+                run
+            },
+        };
+    };
+}
+
 mod arch;
 mod board;
 mod driver;
@@ -36,6 +88,7 @@ mod lib;
 mod mm;
 mod config;
 
+#[cfg(not(test))]
 fn clear_bss() {
   extern "C" {
     fn BSS_START();
@@ -46,6 +99,7 @@ fn clear_bss() {
   unsafe { core::intrinsics::volatile_set_memory(start as *mut u8, 0, end - start); }
 }
 
+#[cfg(not(test))]
 fn static_check() {
   use core::intrinsics::size_of;
   #[allow(unused_unsafe)]
@@ -61,20 +115,36 @@ fn static_check() {
   }
 }
 
+#[cfg(not(test))]
 fn kthread_test(arg: usize) {
   loop {
     print!("{}", arg);
   }
 }
 
+#[cfg(not(test))]
+#[cfg_attr(feature = "ktest", allow(unreachable_code))]
 #[no_mangle]
 pub unsafe fn main() -> ! {
   clear_bss();
+  board::description::save();
+  mm::heap::init();
   board::init();
+  board::description::report();
   static_check();
-  mm::heap::init();
+  mm::heap::extend();
   mm::page_pool::init();
+  lib::framebuffer::init();
+  lib::net::init();
+  driver::port::init();
   board::init_per_core();
+  lib::time::init();
+  lib::random::init();
+  #[cfg(feature = "ktest")]
+  {
+    arch::Arch::exception_init();
+    lib::ktest::run();
+  }
   // Note: `arg` is used to start different programs:
   //    0 - fktest: a `fork` test
   //    1 - pingpong: an IPC test
@@ -83,19 +153,14 @@ pub unsafe fn main() -> ! {
     lib::process::create(&lib::user_image::_binary_user_aarch64_elf_start, 0);
   #[cfg(target_arch = "riscv64")]
     lib::process::create(&lib::user_image::_binary_user_riscv64_elf_start, 0);
-  let t = lib::thread::alloc_kernel(kthread_test as usize, mm::page_pool::alloc().kva() + PAGE_SIZE, 0);
+  let t = lib::thread::alloc_kernel(kthread_test as usize, 0);
   t.set_status(lib::thread::Status::TsRunnable);
-  // let u = lib::thread::alloc_kernel(kthread_test as usize, mm::page_pool::alloc().kva() + PAGE_SIZE, 1);
+  // let u = lib::thread::alloc_kernel(kthread_test as usize, 1);
   // u.set_status(lib::thread::Status::TsRunnable);
+  drop(t);
   arch::Arch::exception_init();
   driver::timer::init(0);
+  // Note: switch away from the boot stack for good
   lib::scheduler::schedule();
-  extern {
-    fn pop_context_first(ctx: usize) -> !;
-  }
-  let t = lib::current_thread().unwrap();
-  let lock = t.context();
-  let ctx = *lock;
-  drop(lock);
-  pop_context_first(&ctx as *const _ as usize);
+  panic!("main: back on the boot stack");
 }
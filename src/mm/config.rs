@@ -1,22 +1,82 @@
+use alloc::vec::Vec;
 use core::ops::Range;
 
 use crate::arch::{Address, PAGE_SIZE};
-use crate::lib::round_up;
+use crate::lib::{round_down, round_up};
 
 // non paged memory in kernel (kernel heap memory)
-pub const CONFIG_NON_PAGED_MEMORY_SIZE: usize = 0xf00_0000;
+// Note: the boot part is enough to parse the device tree
+//       the heap is then extended to 1/`CONFIG_HEAP_RATIO` of RAM, within min and max
+pub const CONFIG_HEAP_BOOT_SIZE: usize = 0x10_0000;
+pub const CONFIG_HEAP_MIN_SIZE: usize = 0x100_0000;
+pub const CONFIG_HEAP_MAX_SIZE: usize = 0xf00_0000;
+pub const CONFIG_HEAP_RATIO: usize = 16;
 
-pub fn paged_range() -> Range<usize> {
+// Note: memory regions clipped to the kernel linear map, page aligned
+#[cfg(not(test))]
+fn memory() -> Vec<Range<usize>> {
+  crate::board::description::get().memory.iter()
+    .map(|r| round_up(r.start, PAGE_SIZE)..round_down(core::cmp::min(r.end, crate::board::BOARD_PHYSICAL_ADDRESS_LIMIT), PAGE_SIZE))
+    .filter(|r| r.start < r.end)
+    .collect()
+}
+
+#[cfg(not(test))]
+fn kernel_end() -> usize {
   extern "C" {
     // Note: link-time label, see kernel.aarch64.lds
     fn KERNEL_END();
   }
-  let kernel_end = round_up((KERNEL_END as usize).kva2pa(), PAGE_SIZE);
-  let normal_range = crate::board::BOARD_NORMAL_MEMORY_RANGE;
-  kernel_end..(normal_range.end - CONFIG_NON_PAGED_MEMORY_SIZE)
+  round_up((KERNEL_END as usize).kva2pa(), PAGE_SIZE)
 }
 
+// Note: right after the kernel image, which is in RAM whatever the board layout is
+//       sized once the device tree is parsed, `heap_boot_range` before that
+//       never reaches into a reserved range, the boot heap must be clear of them
+#[cfg(not(test))]
 pub fn heap_range() -> Range<usize> {
+  let start = kernel_end();
+  let total: usize = memory().iter().map(|r| r.end - r.start).sum();
+  let end = memory().iter()
+    .find(|r| r.contains(&start))
+    .map_or(start + CONFIG_HEAP_BOOT_SIZE, |r| r.end);
+  let end = crate::board::description::get().reserved.iter()
+    .filter(|r| r.end > start)
+    .fold(end, |end, r| core::cmp::min(end, round_down(r.start, PAGE_SIZE)));
+  if end < start + CONFIG_HEAP_BOOT_SIZE {
+    panic!("mm: reserved memory within the boot heap");
+  }
+  // Note: at most half of what is left in the region of the kernel
+  let left = end - start;
+  let size = core::cmp::min(core::cmp::max(total / CONFIG_HEAP_RATIO, CONFIG_HEAP_MIN_SIZE), CONFIG_HEAP_MAX_SIZE);
+  let size = core::cmp::max(core::cmp::min(size, left / 2), CONFIG_HEAP_BOOT_SIZE);
+  start..(start + round_down(size, PAGE_SIZE))
+}
+
+#[cfg(not(test))]
+pub fn heap_boot_range() -> Range<usize> {
+  let start = kernel_end();
+  start..(start + CONFIG_HEAP_BOOT_SIZE)
+}
+
+// Note: zones of the page pool, every memory region except what is below the
+//       heap end in the region of the kernel (firmware, boot stack, kernel image and heap)
+//       reserved ranges are left to the page pool
+#[cfg(not(test))]
+pub fn paged_ranges() -> Vec<Range<usize>> {
+  let kernel_end = kernel_end();
+  let heap_end = heap_range().end;
+  memory().into_iter()
+    .map(|r| if r.contains(&kernel_end) { heap_end..r.end } else { r })
+    .filter(|r| r.start < r.end)
+    .collect()
+}
+
+// Note: no kernel image on the host, the first page is kept unused
+#[cfg(test)]
+pub fn paged_ranges() -> Vec<Range<usize>> {
   let normal_range = crate::board::BOARD_NORMAL_MEMORY_RANGE;
-  (normal_range.end - CONFIG_NON_PAGED_MEMORY_SIZE)..normal_range.end
+  let mut r = Vec::new();
+  r.push((normal_range.start + PAGE_SIZE)..(normal_range.start + 0x100_0000));
+  r
 }
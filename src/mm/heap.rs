@@ -6,13 +6,26 @@ use crate::arch::Address;
 #[global_allocator]
 static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
+// Note: only the boot part, the board layout is not known yet
 pub fn init() {
-  let range = super::config::heap_range();
+  let range = super::config::heap_boot_range();
   unsafe {
     HEAP_ALLOCATOR.lock().init(range.start.pa2kva(), range.end - range.start)
   }
 }
 
+// Note: grow to the size derived from the amount of RAM
+pub fn extend() {
+  let boot = super::config::heap_boot_range();
+  let range = super::config::heap_range();
+  if range.end > boot.end {
+    unsafe {
+      HEAP_ALLOCATOR.lock().add_to_heap(boot.end.pa2kva(), range.end.pa2kva());
+    }
+  }
+  info!("heap {:#x}..{:#x}", range.start, range.end);
+}
+
 #[alloc_error_handler]
 fn alloc_error_handler(_: core::alloc::Layout) -> ! {
   panic!("alloc_error_handler: heap panic");
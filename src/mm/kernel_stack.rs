@@ -0,0 +1,115 @@
+use spin::Mutex;
+
+use crate::arch::{Arch, ArchTrait, PAGE_SIZE, PageTable};
+use crate::config::{CONFIG_KERNEL_STACK_PAGES, CONFIG_KERNEL_STACK_WINDOW_SIZE};
+use crate::lib::bitmap::BitMap;
+use crate::lib::page_table::{EntryAttribute, PageTableEntryAttrTrait, PageTableTrait};
+use crate::mm::page_pool::{FRAME_KERNEL, Owner};
+use crate::mm::PageFrame;
+
+const STACK_SIZE: usize = CONFIG_KERNEL_STACK_PAGES * PAGE_SIZE;
+// Note: a slot is a guard (never mapped) followed by the stack, both `STACK_SIZE`
+//       so an address in the window is in a guard iff its `STACK_SIZE` bit is clear
+//       which is what trap entry checks before pushing a trap frame
+const SLOT_SIZE: usize = 2 * STACK_SIZE;
+const SLOT_NUMBER: usize = CONFIG_KERNEL_STACK_WINDOW_SIZE / SLOT_SIZE;
+
+#[derive(Debug)]
+pub enum Error {
+  OutOfSlotError,
+  FrameError(crate::mm::page_pool::Error),
+}
+
+impl core::convert::From<crate::mm::page_pool::Error> for Error {
+  fn from(e: crate::mm::page_pool::Error) -> Self {
+    Error::FrameError(e)
+  }
+}
+
+static SLOTS: Mutex<BitMap> = Mutex::new(BitMap::new());
+
+// Note: owns its frames and window slot, both given back on drop
+#[derive(Debug)]
+pub struct KernelStack {
+  slot: usize,
+  frame: PageFrame,
+}
+
+impl KernelStack {
+  pub fn new() -> Result<Self, Error> {
+    let order = CONFIG_KERNEL_STACK_PAGES.trailing_zeros() as usize;
+    let frame = crate::mm::page_pool::alloc_contiguous(order)?;
+    crate::mm::page_pool::increase_rc(frame)?;
+    if let Err(e) = crate::mm::page_pool::tag(frame, FRAME_KERNEL, Owner::Unowned) {
+      let _ = crate::mm::page_pool::decrease_rc(frame);
+      return Err(e.into());
+    }
+    let mut slots = SLOTS.lock();
+    let slot = slots.alloc();
+    if slot >= SLOT_NUMBER {
+      slots.clear(slot);
+    }
+    drop(slots);
+    if slot >= SLOT_NUMBER {
+      let _ = crate::mm::page_pool::decrease_rc(frame);
+      return Err(Error::OutOfSlotError);
+    }
+    let stack = KernelStack { slot, frame };
+    let page_table = PageTable::kernel_page_table();
+    for i in 0..CONFIG_KERNEL_STACK_PAGES {
+      page_table.map(stack.bottom() + i * PAGE_SIZE, frame.pa() + i * PAGE_SIZE, EntryAttribute::kernel_default());
+    }
+    Ok(stack)
+  }
+
+  // Note: the mock arch has no MMU, stacks are used through the linear map (no guard)
+  #[cfg(test)]
+  pub fn bottom(&self) -> usize {
+    self.frame.kva()
+  }
+
+  #[cfg(not(test))]
+  pub fn bottom(&self) -> usize {
+    crate::config::CONFIG_KERNEL_STACK_BTM + self.slot * SLOT_SIZE + STACK_SIZE
+  }
+
+  pub fn top(&self) -> usize {
+    self.bottom() + STACK_SIZE
+  }
+}
+
+// Note: `addr` is in the guard of a kernel stack slot
+#[cfg(not(test))]
+pub fn is_guard(addr: usize) -> bool {
+  let btm = crate::config::CONFIG_KERNEL_STACK_BTM;
+  addr >= btm && addr < btm + CONFIG_KERNEL_STACK_WINDOW_SIZE && (addr - btm) % SLOT_SIZE < STACK_SIZE
+}
+
+// Note: `addr` is in a kernel stack slot, out of its guard
+#[cfg(not(test))]
+pub fn in_stack(addr: usize) -> bool {
+  let btm = crate::config::CONFIG_KERNEL_STACK_BTM;
+  addr >= btm && addr < btm + CONFIG_KERNEL_STACK_WINDOW_SIZE && !is_guard(addr)
+}
+
+// Note: no window on the host, stacks are in the linear map
+#[cfg(test)]
+pub fn in_stack(_addr: usize) -> bool {
+  false
+}
+
+impl Drop for KernelStack {
+  fn drop(&mut self) {
+    let page_table = PageTable::kernel_page_table();
+    for i in 0..CONFIG_KERNEL_STACK_PAGES {
+      page_table.unmap(self.bottom() + i * PAGE_SIZE);
+    }
+    Arch::invalidate_tlb();
+    if let Err(e) = crate::mm::page_pool::decrease_rc(self.frame) {
+      error!("kernel_stack: free 0x{:x}: {:?}", self.frame.pa(), e);
+    }
+    let mut slots = SLOTS.lock();
+    slots.clear(self.slot);
+    drop(slots);
+  }
+}
@@ -2,5 +2,15 @@ pub use self::page_frame::*;
 
 mod page_frame;
 pub mod page_pool;
+pub mod kernel_stack;
+// Note: host tests use the std allocator
+#[cfg(not(test))]
 pub mod heap;
 pub mod config;
+
+// Note: host tests share the global page pool, whichever test runs first sets it up
+#[cfg(test)]
+pub fn test_init() {
+  static INIT: std::sync::Once = std::sync::Once::new();
+  INIT.call_once(page_pool::init);
+}
@@ -15,136 +15,346 @@ pub enum Error {
   FreeUnallocatedFrameError,
   FreeReferencedFrameError,
   RefCountOverflowError,
+  RefCountUnderflowError,
 }
 
-struct PagePool {
+// Note: what a frame is used for, set by its user and cleared when it is freed
+pub const FRAME_KERNEL: u16 = 1 << 0;
+pub const FRAME_USER: u16 = 1 << 1;
+pub const FRAME_PAGE_TABLE: u16 = 1 << 2;
+pub const FRAME_CACHE: u16 = 1 << 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Owner {
+  Unowned,
+  Process(crate::lib::process::Pid),
+  // Note: an object keyed by address, e.g. a page cache entry
+  Object(usize),
+}
+
+// Note: per-frame metadata (like `struct page`), kept on the first frame of a block
+#[derive(Copy, Clone, Debug)]
+pub struct FrameDescriptor {
+  pub rc: u32,
+  pub flags: u16,
+  pub owner: Owner,
+}
+
+impl FrameDescriptor {
+  const fn new() -> Self {
+    FrameDescriptor {
+      rc: 0,
+      flags: 0,
+      owner: Owner::Unowned,
+    }
+  }
+}
+
+// Note: largest block is 2^`MAX_ORDER` frames
+pub const MAX_ORDER: usize = 10;
+
+const NONE: u32 = u32::max_value();
+
+// Note: `state` of the first frame of a block, 0 for the other frames
+const STATE_FREE: u8 = 0x80;
+const STATE_ALLOCATED: u8 = 0x40;
+const STATE_ORDER_MASK: u8 = 0x3f;
+
+// Note: a discontiguous piece of RAM, metadata is per zone
+//       buddy allocator, blocks of 2^order frames aligned to their size in physical
+//       address, free lists are linked through `next`/`prev` indexed by frame
+//       descriptor of a block is kept on its first frame
+struct Zone {
   start: usize,
   end: usize,
-  rc: Vec<u8>,
-  free: Vec<usize>,
-  allocated: Vec<usize>,
+  frames: Vec<FrameDescriptor>,
+  state: Vec<u8>,
+  next: Vec<u32>,
+  prev: Vec<u32>,
+  heads: [u32; MAX_ORDER + 1],
+  free_pages: usize,
+}
+
+impl Zone {
+  // Note: frames overlapping `reserved` are managed but never handed out
+  fn new(range: Range<usize>, reserved: &[Range<usize>]) -> Self {
+    assert_eq!(range.start % PAGE_SIZE, 0);
+    assert_eq!(range.end % PAGE_SIZE, 0);
+    let len = (range.end - range.start) / PAGE_SIZE;
+    let mut zone = Zone {
+      start: range.start,
+      end: range.end,
+      frames: Vec::new(),
+      state: Vec::new(),
+      next: Vec::new(),
+      prev: Vec::new(),
+      heads: [NONE; MAX_ORDER + 1],
+      free_pages: 0,
+    };
+    zone.frames.resize(len, FrameDescriptor::new());
+    zone.state.resize(len, 0);
+    zone.next.resize(len, NONE);
+    zone.prev.resize(len, NONE);
+    let usable = |i: usize| {
+      let pa = range.start + i * PAGE_SIZE;
+      !reserved.iter().any(|r| r.start < pa + PAGE_SIZE && pa < r.end)
+    };
+    // Note: largest aligned blocks covering each run of usable frames
+    let mut i = 0;
+    while i < len {
+      if !usable(i) {
+        i += 1;
+        continue;
+      }
+      let mut order = 0;
+      while order < MAX_ORDER
+        && zone.pfn(i) % (1 << (order + 1)) == 0
+        && (i..i + (1 << (order + 1))).all(|j| j < len && usable(j)) {
+        order += 1;
+      }
+      zone.push(i, order);
+      i += 1 << order;
+    }
+    zone
+  }
+
+  fn contains(&self, frame: PageFrame) -> bool {
+    self.start <= frame.pa() && frame.pa() < self.end
+  }
+
+  fn index(&self, frame: PageFrame) -> usize {
+    (frame.pa() - self.start) / PAGE_SIZE
+  }
+
+  fn pfn(&self, i: usize) -> usize {
+    self.start / PAGE_SIZE + i
+  }
+
+  fn push(&mut self, i: usize, order: usize) {
+    let head = self.heads[order];
+    self.state[i] = STATE_FREE | order as u8;
+    self.prev[i] = NONE;
+    self.next[i] = head;
+    if head != NONE {
+      self.prev[head as usize] = i as u32;
+    }
+    self.heads[order] = i as u32;
+    self.free_pages += 1 << order;
+  }
+
+  fn remove(&mut self, i: usize, order: usize) {
+    let (prev, next) = (self.prev[i], self.next[i]);
+    if prev == NONE {
+      self.heads[order] = next;
+    } else {
+      self.next[prev as usize] = next;
+    }
+    if next != NONE {
+      self.prev[next as usize] = prev;
+    }
+    self.state[i] = 0;
+    self.free_pages -= 1 << order;
+  }
+
+  // Note: split the smallest free block large enough, upper halves go back to the lists
+  fn allocate(&mut self, order: usize) -> Option<usize> {
+    let found = (order..=MAX_ORDER).find(|k| self.heads[*k] != NONE)?;
+    let i = self.heads[found] as usize;
+    self.remove(i, found);
+    for k in (order..found).rev() {
+      self.push(i + (1 << k), k);
+    }
+    self.state[i] = STATE_ALLOCATED | order as u8;
+    self.frames[i] = FrameDescriptor::new();
+    Some(self.start + i * PAGE_SIZE)
+  }
+
+  // Note: merge with free buddies, O(MAX_ORDER)
+  fn free(&mut self, i: usize) -> Result<(), Error> {
+    if self.state[i] & STATE_ALLOCATED == 0 {
+      return Err(FreeUnallocatedFrameError);
+    }
+    let mut order = (self.state[i] & STATE_ORDER_MASK) as usize;
+    let mut i = i;
+    self.state[i] = 0;
+    self.frames[i] = FrameDescriptor::new();
+    while order < MAX_ORDER {
+      let buddy_pfn = self.pfn(i) ^ (1 << order);
+      if buddy_pfn < self.pfn(0) {
+        break;
+      }
+      let buddy = buddy_pfn - self.pfn(0);
+      if buddy >= self.state.len() || self.state[buddy] != STATE_FREE | order as u8 {
+        break;
+      }
+      self.remove(buddy, order);
+      i = core::cmp::min(i, buddy);
+      order += 1;
+    }
+    self.push(i, order);
+    Ok(())
+  }
+
+  fn allocated_pages(&self) -> usize {
+    self.state.iter()
+      .filter(|s| **s & STATE_ALLOCATED != 0)
+      .map(|s| 1usize << (*s & STATE_ORDER_MASK))
+      .sum()
+  }
+}
+
+struct PagePool {
+  zones: Vec<Zone>,
 }
 
 pub trait PagePoolTrait {
-  fn init(&mut self, range: Range<usize>);
+  fn init(&mut self, zones: &[Range<usize>], reserved: &[Range<usize>]);
   fn allocate(&mut self) -> Result<PageFrame, Error>;
+  fn allocate_contiguous(&mut self, order: usize) -> Result<PageFrame, Error>;
   fn free(&mut self, frame: PageFrame) -> Result<(), Error>;
-  fn increase_rc(&mut self, frame: PageFrame) -> Result<u8, Error>;
-  fn decrease_rc(&mut self, frame: PageFrame) -> Result<u8, Error>;
-  fn rc(&self, frame: PageFrame) -> Result<u8, Error>;
-  fn ppn(&self, frame: PageFrame) -> usize;
+  fn increase_rc(&mut self, frame: PageFrame) -> Result<u32, Error>;
+  fn decrease_rc(&mut self, frame: PageFrame) -> Result<u32, Error>;
+  fn rc(&self, frame: PageFrame) -> Result<u32, Error>;
+  fn descriptor(&self, frame: PageFrame) -> Result<FrameDescriptor, Error>;
+  fn tag(&mut self, frame: PageFrame, flags: u16, owner: Owner) -> Result<(), Error>;
+  fn reclaimable(&self) -> Vec<PageFrame>;
   fn in_pool(&self, frame: PageFrame) -> bool;
   fn report(&self);
 }
 
 impl PagePoolTrait for PagePool {
-  fn init(&mut self, range: Range<usize>) {
-    assert_eq!(range.start % PAGE_SIZE, 0);
-    assert_eq!(range.end % PAGE_SIZE, 0);
-    self.start = range.start;
-    self.end = range.end;
-    for pa in range.step_by(PAGE_SIZE) {
-      self.rc.push(0);
-      self.free.push(pa);
+  fn init(&mut self, zones: &[Range<usize>], reserved: &[Range<usize>]) {
+    for range in zones {
+      self.zones.push(Zone::new(range.clone(), reserved));
     }
   }
 
   fn allocate(&mut self) -> Result<PageFrame, Error> {
-    if let Some(pa) = self.free.pop() {
-      self.allocated.push(pa);
-      Ok(PageFrame::new(pa))
-    } else {
-      Err(OutOfFrameError)
+    self.allocate_contiguous(0)
+  }
+
+  // Note: lower zones are used up first
+  fn allocate_contiguous(&mut self, order: usize) -> Result<PageFrame, Error> {
+    if order > MAX_ORDER {
+      return Err(OutOfFrameError);
+    }
+    for zone in self.zones.iter_mut() {
+      if let Some(pa) = zone.allocate(order) {
+        return Ok(PageFrame::new(pa));
+      }
     }
+    Err(OutOfFrameError)
   }
 
   fn free(&mut self, frame: PageFrame) -> Result<(), Error> {
-    if !self.in_pool(frame) {
-      return Err(UnmanagedFrameError);
-    }
     if let Ok(0) = self.rc(frame) {
-      if let Some(pa) = self.allocated.remove_item(&frame.pa()) {
-        self.free.push(pa);
-        Ok(())
-      } else {
-        Err(FreeUnallocatedFrameError)
-      }
+      let zone = self.zone_mut(frame)?;
+      let i = zone.index(frame);
+      zone.free(i)
+    } else if !self.in_pool(frame) {
+      Err(UnmanagedFrameError)
     } else {
       Err(FreeReferencedFrameError)
     }
   }
 
-  fn increase_rc(&mut self, frame: PageFrame) -> Result<u8, Error> {
-    if !self.in_pool(frame) {
-      return Err(UnmanagedFrameError);
-    }
-    let ppn = self.ppn(frame);
-    let val = self.rc[ppn];
-    if val == 255 {
-      return Err(RefCountOverflowError);
-    }
-    self.rc[ppn] += 1;
-    Ok(val + 1)
+  fn increase_rc(&mut self, frame: PageFrame) -> Result<u32, Error> {
+    let zone = self.zone_mut(frame)?;
+    let i = zone.index(frame);
+    let val = zone.frames[i].rc.checked_add(1).ok_or(RefCountOverflowError)?;
+    zone.frames[i].rc = val;
+    Ok(val)
   }
 
-  fn decrease_rc(&mut self, frame: PageFrame) -> Result<u8, Error> {
-    if !self.in_pool(frame) {
-      return Err(UnmanagedFrameError);
-    }
-    let ppn = self.ppn(frame);
-    self.rc[ppn] -= 1;
-    if self.rc[ppn] == 0 {
+  fn decrease_rc(&mut self, frame: PageFrame) -> Result<u32, Error> {
+    let zone = self.zone_mut(frame)?;
+    let i = zone.index(frame);
+    let val = zone.frames[i].rc.checked_sub(1).ok_or(RefCountUnderflowError)?;
+    zone.frames[i].rc = val;
+    if val == 0 {
       self.free(frame)?;
-      return Ok(0);
     }
-    Ok(self.rc[ppn])
+    Ok(val)
   }
 
-  fn rc(&self, frame: PageFrame) -> Result<u8, Error> {
-    if !self.in_pool(frame) {
-      Err(UnmanagedFrameError)
-    } else {
-      Ok(self.rc[self.ppn(frame)])
-    }
+  fn rc(&self, frame: PageFrame) -> Result<u32, Error> {
+    self.descriptor(frame).map(|d| d.rc)
   }
 
-  fn ppn(&self, frame: PageFrame) -> usize {
-    assert!(self.in_pool(frame));
-    (frame.pa() - self.start) / PAGE_SIZE
+  fn descriptor(&self, frame: PageFrame) -> Result<FrameDescriptor, Error> {
+    let zone = self.zone(frame)?;
+    Ok(zone.frames[zone.index(frame)])
+  }
+
+  // Note: flags are added to the ones already set, the owner is replaced
+  fn tag(&mut self, frame: PageFrame, flags: u16, owner: Owner) -> Result<(), Error> {
+    let zone = self.zone_mut(frame)?;
+    let i = zone.index(frame);
+    zone.frames[i].flags |= flags;
+    zone.frames[i].owner = owner;
+    Ok(())
+  }
+
+  // Note: cache frames referenced by the cache only, can be dropped under memory pressure
+  fn reclaimable(&self) -> Vec<PageFrame> {
+    let mut r = Vec::new();
+    for zone in &self.zones {
+      for (i, d) in zone.frames.iter().enumerate() {
+        if zone.state[i] & STATE_ALLOCATED != 0 && d.flags & FRAME_CACHE != 0 && d.rc == 1 {
+          r.push(PageFrame::new(zone.start + i * PAGE_SIZE));
+        }
+      }
+    }
+    r
   }
 
   fn in_pool(&self, frame: PageFrame) -> bool {
-    self.start <= frame.pa() && frame.pa() < self.end
+    self.zone(frame).is_ok()
   }
 
   fn report(&self) {
     println!("page_pool report");
-    println!("free:      0x{:08x}", self.free.len());
-    println!("allocated: 0x{:08x}", self.allocated.len());
+    for zone in &self.zones {
+      println!("zone 0x{:08x}..0x{:08x} free: 0x{:08x} allocated: 0x{:08x}",
+               zone.start, zone.end, zone.free_pages, zone.allocated_pages());
+    }
   }
 }
 
 impl PagePool {
   const fn new() -> Self {
     PagePool {
-      start: 0,
-      end: 0,
-      rc: Vec::new(),
-      free: Vec::new(),
-      allocated: Vec::new(),
+      zones: Vec::new(),
     }
   }
+
+  fn zone(&self, frame: PageFrame) -> Result<&Zone, Error> {
+    self.zones.iter().find(|z| z.contains(frame)).ok_or(UnmanagedFrameError)
+  }
+
+  fn zone_mut(&mut self, frame: PageFrame) -> Result<&mut Zone, Error> {
+    self.zones.iter_mut().find(|z| z.contains(frame)).ok_or(UnmanagedFrameError)
+  }
 }
 
 static PAGE_POOL: Mutex<PagePool> = Mutex::new(PagePool::new());
 
 pub fn init() {
-  let range = super::config::paged_range();
+  let zones = super::config::paged_ranges();
+  let reserved = &crate::board::description::get().reserved;
   let mut pool = PAGE_POOL.lock();
-  pool.init(range);
+  pool.init(&zones, reserved);
   drop(pool);
 }
 
+// Note: whether `pa` is a frame of the pool, e.g. not a device or kernel image page
+pub fn managed(pa: usize) -> bool {
+  let pool = PAGE_POOL.lock();
+  let r = pool.in_pool(PageFrame::new(pa));
+  drop(pool);
+  r
+}
+
 pub fn alloc() -> PageFrame {
   let mut pool = PAGE_POOL.lock();
   if let Ok(frame) = pool.allocate() {
@@ -155,6 +365,7 @@ pub fn alloc() -> PageFrame {
   }
 }
 
+#[allow(dead_code)]
 pub fn try_alloc() -> Result<PageFrame, Error> {
   let mut pool = PAGE_POOL.lock();
   let r = pool.allocate();
@@ -162,16 +373,67 @@ pub fn try_alloc() -> Result<PageFrame, Error> {
   r
 }
 
-pub fn increase_rc(frame: PageFrame) {
+// Note: a frame tagged with `flags` and `owner`, not referenced yet
+pub fn try_alloc_tagged(flags: u16, owner: Owner) -> Result<PageFrame, Error> {
   let mut pool = PAGE_POOL.lock();
-  let _r = pool.increase_rc(frame);
+  let r = pool.allocate().and_then(|frame| pool.tag(frame, flags, owner).map(|_| frame));
   drop(pool);
+  r
 }
 
-pub fn decrease_rc(frame: PageFrame) {
+// Note: 2^`order` physically contiguous frames, aligned to their size
+//       the block is freed when the reference count of its first frame drops to 0
+pub fn alloc_contiguous(order: usize) -> Result<PageFrame, Error> {
   let mut pool = PAGE_POOL.lock();
-  let _r = pool.decrease_rc(frame);
+  let r = pool.allocate_contiguous(order);
   drop(pool);
+  r
+}
+
+pub fn increase_rc(frame: PageFrame) -> Result<u32, Error> {
+  let mut pool = PAGE_POOL.lock();
+  let r = pool.increase_rc(frame);
+  drop(pool);
+  r
+}
+
+// Note: the frame is freed when the last reference is dropped
+pub fn decrease_rc(frame: PageFrame) -> Result<u32, Error> {
+  let mut pool = PAGE_POOL.lock();
+  let r = pool.decrease_rc(frame);
+  drop(pool);
+  r
+}
+
+#[allow(dead_code)]
+pub fn rc(frame: PageFrame) -> Result<u32, Error> {
+  let pool = PAGE_POOL.lock();
+  let r = pool.rc(frame);
+  drop(pool);
+  r
+}
+
+#[allow(dead_code)]
+pub fn descriptor(frame: PageFrame) -> Result<FrameDescriptor, Error> {
+  let pool = PAGE_POOL.lock();
+  let r = pool.descriptor(frame);
+  drop(pool);
+  r
+}
+
+pub fn tag(frame: PageFrame, flags: u16, owner: Owner) -> Result<(), Error> {
+  let mut pool = PAGE_POOL.lock();
+  let r = pool.tag(frame, flags, owner);
+  drop(pool);
+  r
+}
+
+#[allow(dead_code)]
+pub fn reclaimable() -> Vec<PageFrame> {
+  let pool = PAGE_POOL.lock();
+  let r = pool.reclaimable();
+  drop(pool);
+  r
 }
 
 #[allow(dead_code)]
@@ -179,4 +441,240 @@ pub fn report() {
   let pool = PAGE_POOL.lock();
   pool.report();
   drop(pool);
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "ktest")]
+mod ktests {
+  use super::*;
+
+  ktest!(alloc_write_free {
+    let frame = alloc();
+    increase_rc(frame).ok().unwrap();
+    assert_eq!(rc(frame).ok(), Some(1));
+    frame.zero();
+    let words = PAGE_SIZE / MACHINE_SIZE;
+    let p = frame.kva() as *mut usize;
+    for i in 0..words {
+      unsafe { core::intrinsics::volatile_store(p.add(i), i ^ frame.pa()); }
+    }
+    for i in 0..words {
+      assert_eq!(unsafe { core::intrinsics::volatile_load(p.add(i)) }, i ^ frame.pa());
+    }
+    decrease_rc(frame).ok().unwrap();
+    assert_eq!(rc(frame).ok(), Some(0));
+    // Note: the frame is back in the free lists
+    let mut pool = PAGE_POOL.lock();
+    assert!(matches!(pool.free(frame), Err(FreeUnallocatedFrameError)));
+    drop(pool);
+  });
+
+  ktest!(contiguous_block {
+    let order = 4;
+    let frame = alloc_contiguous(order).ok().unwrap();
+    assert_eq!(frame.pa() % (PAGE_SIZE << order), 0);
+    increase_rc(frame).ok().unwrap();
+    for i in 0..(1 << order) {
+      let f = PageFrame::new(frame.pa() + i * PAGE_SIZE);
+      assert!(managed(f.pa()));
+      f.zero();
+    }
+    let single = alloc();
+    assert!(single.pa() < frame.pa() || single.pa() >= frame.pa() + (PAGE_SIZE << order));
+    increase_rc(single).ok().unwrap();
+    decrease_rc(single).ok().unwrap();
+    decrease_rc(frame).ok().unwrap();
+    assert!(alloc_contiguous(MAX_ORDER + 1).is_err());
+  });
+
+  ktest!(distinct_frames {
+    let mut frames = Vec::new();
+    for _ in 0..64 {
+      let frame = try_alloc().ok().unwrap();
+      assert!(managed(frame.pa()));
+      assert!(frames.iter().all(|f: &PageFrame| f.pa() != frame.pa()));
+      increase_rc(frame).ok().unwrap();
+      frames.push(frame);
+    }
+    for frame in frames {
+      decrease_rc(frame).ok().unwrap();
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Note: a private pool of 4 frames, not the global one
+  fn pool() -> PagePool {
+    let mut pool = PagePool::new();
+    pool.init(&[0x1000..0x5000], &[]);
+    pool
+  }
+
+  #[test]
+  fn reserved_frames() {
+    let mut pool = PagePool::new();
+    pool.init(&[0x1000..0x5000], &[0x2800..0x3001]);
+    let mut frames = Vec::new();
+    while let Ok(frame) = pool.allocate() {
+      frames.push(frame.pa());
+    }
+    frames.sort();
+    assert_eq!(frames, vec![0x1000, 0x4000]);
+    assert!(pool.in_pool(PageFrame::new(0x2000)));
+    assert!(matches!(pool.free(PageFrame::new(0x2000)), Err(FreeUnallocatedFrameError)));
+  }
+
+  #[test]
+  fn discontiguous_zones() {
+    let mut pool = PagePool::new();
+    pool.init(&[0x1000..0x3000, 0x10_0000..0x10_1000], &[]);
+    assert!(!pool.in_pool(PageFrame::new(0x3000)));
+    assert!(!pool.in_pool(PageFrame::new(0x8000)));
+    let frames: Vec<usize> = (0..3).map(|_| pool.allocate().ok().unwrap().pa()).collect();
+    assert!(frames[..2].iter().all(|pa| *pa < 0x3000));
+    assert_eq!(frames[2], 0x10_0000);
+    assert!(matches!(pool.allocate(), Err(OutOfFrameError)));
+    let frame = PageFrame::new(0x10_0000);
+    pool.increase_rc(frame).ok().unwrap();
+    assert_eq!(pool.decrease_rc(frame).ok(), Some(0));
+    assert_eq!(pool.zones[1].free_pages, 1);
+    assert_eq!(pool.rc(PageFrame::new(0x2000)).ok(), Some(0));
+  }
+
+  #[test]
+  fn buddy_split_and_merge() {
+    let mut pool = PagePool::new();
+    pool.init(&[0x1_0000..0x2_0000], &[]);
+    // Note: 16 frames aligned to 16, a single order 4 block
+    assert_eq!(pool.zones[0].heads[4], 0);
+    let a = pool.allocate().ok().unwrap();
+    assert_eq!(a.pa(), 0x1_0000);
+    let b = pool.allocate_contiguous(2).ok().unwrap();
+    assert_eq!(b.pa(), 0x1_4000);
+    let c = pool.allocate_contiguous(3).ok().unwrap();
+    assert_eq!(c.pa(), 0x1_8000);
+    assert!(matches!(pool.allocate_contiguous(2), Err(OutOfFrameError)));
+    assert_eq!(pool.zones[0].free_pages, 3);
+    assert_eq!(pool.zones[0].allocated_pages(), 13);
+    for frame in &[b, a, c] {
+      pool.free(*frame).ok().unwrap();
+    }
+    assert_eq!(pool.zones[0].heads[4], 0);
+    assert_eq!(pool.zones[0].free_pages, 16);
+    assert!(matches!(pool.free(a), Err(FreeUnallocatedFrameError)));
+    assert!(matches!(pool.allocate_contiguous(MAX_ORDER + 1), Err(OutOfFrameError)));
+  }
+
+  #[test]
+  fn unaligned_zone_blocks() {
+    let mut pool = PagePool::new();
+    // Note: frames 3..13 around a reserved frame 8
+    pool.init(&[0x3000..0xd000], &[0x8000..0x9000]);
+    let zone = &pool.zones[0];
+    assert_eq!(zone.free_pages, 9);
+    let blocks: Vec<(usize, u8)> = (0..zone.state.len())
+      .filter(|i| zone.state[*i] & STATE_FREE != 0)
+      .map(|i| (zone.start + i * PAGE_SIZE, zone.state[i] & STATE_ORDER_MASK))
+      .collect();
+    assert_eq!(blocks, vec![(0x3000, 0), (0x4000, 2), (0x9000, 0), (0xa000, 1), (0xc000, 0)]);
+    let big = pool.allocate_contiguous(2).ok().unwrap();
+    assert_eq!(big.pa(), 0x4000);
+    pool.free(big).ok().unwrap();
+    assert!(pool.allocate_contiguous(3).is_err());
+  }
+
+  #[test]
+  fn allocate_until_exhausted() {
+    let mut pool = pool();
+    let mut frames = Vec::new();
+    for _ in 0..4 {
+      let frame = pool.allocate().ok().unwrap();
+      assert!(pool.in_pool(frame));
+      assert!(!frames.contains(&frame.pa()));
+      frames.push(frame.pa());
+    }
+    assert!(matches!(pool.allocate(), Err(OutOfFrameError)));
+    pool.free(PageFrame::new(frames[0])).ok().unwrap();
+    assert_eq!(pool.allocate().ok().unwrap().pa(), frames[0]);
+  }
+
+  #[test]
+  fn free_errors() {
+    let mut pool = pool();
+    assert!(matches!(pool.free(PageFrame::new(0x5000)), Err(UnmanagedFrameError)));
+    assert!(matches!(pool.free(PageFrame::new(0x1000)), Err(FreeUnallocatedFrameError)));
+    let frame = pool.allocate().ok().unwrap();
+    pool.increase_rc(frame).ok().unwrap();
+    assert!(matches!(pool.free(frame), Err(FreeReferencedFrameError)));
+  }
+
+  #[test]
+  fn reference_count() {
+    let mut pool = pool();
+    let frame = pool.allocate().ok().unwrap();
+    assert_eq!(pool.rc(frame).ok(), Some(0));
+    assert_eq!(pool.increase_rc(frame).ok(), Some(1));
+    assert_eq!(pool.increase_rc(frame).ok(), Some(2));
+    assert_eq!(pool.decrease_rc(frame).ok(), Some(1));
+    assert_eq!(pool.zones[0].allocated_pages(), 1);
+    // Note: last reference frees the frame
+    assert_eq!(pool.decrease_rc(frame).ok(), Some(0));
+    assert_eq!(pool.zones[0].allocated_pages(), 0);
+    assert_eq!(pool.zones[0].free_pages, 4);
+    assert!(matches!(pool.rc(PageFrame::new(0x8000)), Err(UnmanagedFrameError)));
+  }
+
+  #[test]
+  fn reference_count_overflow() {
+    let mut pool = pool();
+    let frame = pool.allocate().ok().unwrap();
+    // Note: a page shared by far more than 255 mappings
+    for _ in 0..1000 {
+      pool.increase_rc(frame).ok().unwrap();
+    }
+    assert_eq!(pool.rc(frame).ok(), Some(1000));
+    let i = pool.zones[0].index(frame);
+    pool.zones[0].frames[i].rc = u32::max_value();
+    assert!(matches!(pool.increase_rc(frame), Err(RefCountOverflowError)));
+    assert_eq!(pool.rc(frame).ok(), Some(u32::max_value()));
+  }
+
+  #[test]
+  fn reference_count_underflow() {
+    let mut pool = pool();
+    let frame = pool.allocate().ok().unwrap();
+    pool.increase_rc(frame).ok().unwrap();
+    assert_eq!(pool.decrease_rc(frame).ok(), Some(0));
+    assert!(matches!(pool.decrease_rc(frame), Err(RefCountUnderflowError)));
+    assert_eq!(pool.zones[0].free_pages, 4);
+  }
+
+  #[test]
+  fn frame_descriptor() {
+    let mut pool = pool();
+    let cache = pool.allocate().ok().unwrap();
+    let user = pool.allocate().ok().unwrap();
+    pool.tag(cache, FRAME_KERNEL, Owner::Unowned).ok().unwrap();
+    pool.tag(cache, FRAME_CACHE, Owner::Object(0x1234)).ok().unwrap();
+    pool.tag(user, FRAME_USER, Owner::Process(3)).ok().unwrap();
+    let d = pool.descriptor(cache).ok().unwrap();
+    assert_eq!(d.flags, FRAME_KERNEL | FRAME_CACHE);
+    assert_eq!(d.owner, Owner::Object(0x1234));
+    assert_eq!(pool.descriptor(user).ok().unwrap().owner, Owner::Process(3));
+    assert!(matches!(pool.tag(PageFrame::new(0x8000), FRAME_USER, Owner::Unowned), Err(UnmanagedFrameError)));
+    // Note: only cache frames no one else maps are reclaimable
+    pool.increase_rc(cache).ok().unwrap();
+    pool.increase_rc(user).ok().unwrap();
+    let reclaimable: Vec<usize> = pool.reclaimable().iter().map(|f| f.pa()).collect();
+    assert_eq!(reclaimable, vec![cache.pa()]);
+    pool.increase_rc(cache).ok().unwrap();
+    assert!(pool.reclaimable().is_empty());
+    // Note: metadata is cleared once the frame is freed
+    pool.decrease_rc(cache).ok().unwrap();
+    pool.decrease_rc(cache).ok().unwrap();
+    let d = pool.descriptor(cache).ok().unwrap();
+    assert_eq!((d.rc, d.flags, d.owner), (0, 0, Owner::Unowned));
+  }
+}